@@ -150,7 +150,23 @@ async fn call_event_loop(
                     }
                 });
             }
-            CallEvent::Terminated => return Ok(()),
+            CallEvent::RemoteHold | CallEvent::RemoteResume => {
+                // the media backend takes care of the changed media directions
+            }
+            CallEvent::ReferReceived(_refer) => {
+                // dropping the REFER declines the transfer
+            }
+            CallEvent::InfoReceived(_info) => {
+                // dropping the INFO rejects it
+            }
+            CallEvent::Dtmf(..) | CallEvent::Media(MediaEvent::Dtmf(..)) => {
+                // telephone-events are reported as CallEvent::Dtmf
+            }
+            CallEvent::TransferProgress(..)
+            | CallEvent::ReInviteCompleted { .. }
+            | CallEvent::SessionRefreshed { .. }
+            | CallEvent::Record(..) => {}
+            CallEvent::Terminated(..) => return Ok(()),
         }
     }
 }
@@ -93,6 +93,10 @@ impl RtpTransport {
         self.ports = Some(ports)
     }
 
+    pub(crate) fn ports(&self) -> Option<&RtpTransportPorts> {
+        self.ports.as_ref()
+    }
+
     #[track_caller]
     pub(crate) fn require_ports(&self) -> &RtpTransportPorts {
         self.ports
@@ -11,7 +11,10 @@ use super::{
 };
 use crate::{
     OpenSslContext,
-    rtp_session::{RtpOutboundStream, RtpSession, RtpSessionEvent, SendRtpPacket},
+    rtp_session::{
+        RtpInboundStats, RtpOutboundStats, RtpOutboundStream, RtpSession, RtpSessionEvent,
+        SendRtpPacket,
+    },
     rtp_transport::RtpOrRtcp,
     sdp::{
         local_media::LocalMedia,
@@ -25,7 +28,7 @@ use ice::{
     Component, IceAgent, IceConnectionState, IceCredentials, IceGatheringState, ReceivedPkt,
 };
 use openssl::hash::MessageDigest;
-use rtp::{RtpExtensions, RtpPacket, rtcp_types::Compound};
+use rtp::{RtpExtensions, RtpPacket, Ssrc, rtcp_types::Compound};
 use sdp_types::{
     Connection, Fingerprint, FingerprintAlgorithm, Fmtp, Group, IceCandidate, IceOptions,
     IcePassword, IceUsernameFragment, MediaDescription, Origin, Rtcp, RtpMap, Time,
@@ -33,6 +36,7 @@ use sdp_types::{
 };
 use slotmap::{SlotMap, new_key_type};
 use std::{
+    borrow::Cow,
     collections::{BTreeSet, HashMap, VecDeque},
     mem::{replace, take},
     net::{IpAddr, SocketAddr},
@@ -1595,6 +1599,48 @@ impl SdpSession {
         )
     }
 
+    /// Returns the addresses and RTP statistics of the given media
+    pub fn media_stats(&mut self, id: MediaId) -> Option<MediaStats> {
+        let media = self.media.iter().find(|m| m.id == id)?;
+        let transport = &mut self.transports[media.transport_id];
+
+        let (local_address, remote_address) = match transport.transport.connectivity() {
+            Connectivity::Static {
+                remote_rtp_address, ..
+            } => (
+                transport
+                    .transport
+                    .ports()
+                    .map(|ports| SocketAddr::new(self.address, ports.rtp)),
+                Some(*remote_rtp_address),
+            ),
+            Connectivity::Ice(ice_agent) => ice_agent
+                .discovered_addr(Component::Rtp)
+                .map(|(local, remote)| (Some(local), Some(remote)))
+                .unwrap_or_default(),
+        };
+
+        let outbound = media.streams.tx.and_then(|ssrc| {
+            let stats = transport.rtp_session.tx_stream(ssrc)?.stats();
+            Some((ssrc, stats))
+        });
+
+        let inbound = media.streams.rx.and_then(|ssrc| {
+            let stats = transport.rtp_session.rx_stream(ssrc)?.stats();
+            Some((ssrc, stats))
+        });
+
+        Some(MediaStats {
+            pt: media.codec_pt,
+            codec: media.codec.name.clone(),
+            clock_rate: media.codec.clock_rate,
+            local_address,
+            remote_address,
+            outbound,
+            inbound,
+        })
+    }
+
     /// Returns a temporary [`MediaWriter`] which can be used to send RTP packets
     pub fn writer(&mut self, id: MediaId) -> Option<MediaWriter<'_>> {
         let media = self.media.iter_mut().find(|m| m.id == id)?;
@@ -1684,6 +1730,25 @@ impl From<Direction> for DirectionBools {
     }
 }
 
+/// Addresses and RTP statistics of a media, returned by [`SdpSession::media_stats`]
+#[derive(Debug, Clone)]
+pub struct MediaStats {
+    /// Negotiated payload type of the codec
+    pub pt: u8,
+    pub codec: Cow<'static, str>,
+    pub clock_rate: u32,
+
+    /// Local RTP address, if known
+    pub local_address: Option<SocketAddr>,
+    /// Remote RTP address, if known
+    pub remote_address: Option<SocketAddr>,
+
+    /// SSRC and statistics of the outbound RTP stream, if the media is sending
+    pub outbound: Option<(Ssrc, RtpOutboundStats)>,
+    /// SSRC and statistics of the inbound RTP stream, if RTP has been received
+    pub inbound: Option<(Ssrc, RtpInboundStats)>,
+}
+
 pub struct MediaWriter<'a> {
     media: &'a Media,
     stream: &'a mut RtpOutboundStream,
@@ -7,7 +7,7 @@ use sip_types::header::typed::{
     QopResponse, Username,
 };
 use sip_types::print::{AppendCtx, PrintCtx, UriContext};
-use sip_types::{Headers, Name};
+use sip_types::{Headers, Method, Name};
 use std::collections::HashMap;
 
 #[derive(Debug, thiserror::Error)]
@@ -153,24 +153,14 @@ impl ClientAuthenticator for DigestAuthenticator {
                         .find(|(realm, _)| realm == digest_realm)
                         .expect("qop_entry must be some");
 
-                    match qop_response.qop {
-                        QopOption::Auth | QopOption::AuthInt => {
-                            let hash = (qop_entry.hash)(
-                                format!(
-                                    "{}:{}:{:08X}:{}:auth:{}",
-                                    qop_entry.ha1,
-                                    response.header.nonce,
-                                    qop_response.nc,
-                                    qop_response.cnonce,
-                                    qop_entry.ha2
-                                )
-                                .as_bytes(),
-                            );
-
-                            response.header.response = hash.into();
-                        }
-                        QopOption::Other(_) => unreachable!(),
-                    };
+                    response.header.response = calculate_response(
+                        qop_entry.hash,
+                        &qop_entry.ha1,
+                        &response.header.nonce,
+                        Some(qop_response),
+                        &qop_entry.ha2,
+                    )
+                    .into();
                 }
             }
 
@@ -332,35 +322,18 @@ impl DigestAuthenticator {
         digest_challenge: DigestChallenge,
         request_parts: RequestParts<'_>,
     ) -> Result<DigestResponse, DigestError> {
-        let algorithm_value = match digest_challenge.algorithm.clone() {
-            Algorithm::AkaNamespace((_, av)) => av,
-            Algorithm::AlgorithmValue(av) => av,
-        };
-
-        let (hash, is_session): (HashFn, bool) = match algorithm_value {
-            AlgorithmValue::MD5 => {
-                if self.reject_md5 {
-                    return Err(DigestError::UnsupportedAlgorithm(BytesStr::from_static(
-                        "MD5",
-                    )));
-                } else {
-                    (hash_md5, false)
-                }
+        let (hash, is_session) = match algorithm_hash(&digest_challenge.algorithm) {
+            Some(_) if self.reject_md5 && is_md5(&digest_challenge.algorithm) => {
+                return Err(DigestError::UnsupportedAlgorithm(BytesStr::from_static(
+                    "MD5",
+                )));
             }
-            AlgorithmValue::MD5Sess => {
-                if self.reject_md5 {
-                    return Err(DigestError::UnsupportedAlgorithm(BytesStr::from_static(
-                        "MD5",
-                    )));
-                } else {
-                    (hash_md5, true)
-                }
+            Some(hash) => hash,
+            None => {
+                return Err(DigestError::UnsupportedAlgorithm(
+                    digest_challenge.algorithm.to_string().into(),
+                ));
             }
-            AlgorithmValue::SHA256 => (hash_sha256, false),
-            AlgorithmValue::SHA256Sess => (hash_sha256, true),
-            AlgorithmValue::SHA512256 => (hash_sha512_trunc256, false),
-            AlgorithmValue::SHA512256Sess => (hash_sha512_trunc256, true),
-            AlgorithmValue::Other(other) => return Err(DigestError::UnsupportedAlgorithm(other)),
         };
 
         let response = self.digest_respond(digest_challenge, request_parts, is_session, hash)?;
@@ -383,17 +356,15 @@ impl DigestAuthenticator {
 
         let cnonce = BytesStr::from(uuid::Uuid::new_v4().simple().to_string());
 
-        let mut ha1 = hash(
-            [
-                format!("{}:{}:", digest_user.user, challenge.realm).as_bytes(),
-                &digest_user.password,
-            ]
-            .concat()
-            .as_slice(),
+        let mut ha1 = calculate_ha1(
+            hash,
+            &digest_user.user,
+            &challenge.realm,
+            &digest_user.password,
         );
 
         if is_session {
-            ha1 = format!("{}:{}:{}", ha1, challenge.nonce, cnonce);
+            ha1 = calculate_session_ha1(hash, &ha1, &challenge.nonce, &cnonce);
         }
 
         let ctx = PrintCtx {
@@ -408,71 +379,32 @@ impl DigestAuthenticator {
             challenge.qop.push(QopOption::Auth)
         }
 
-        let (response, qop_response) = if !challenge.qop.is_empty() {
-            if challenge.qop.contains(&QopOption::AuthInt) {
-                let ha2 = hash(
-                    format!(
-                        "{}:{}:{}",
-                        &request_parts.line.method,
-                        uri,
-                        hash(request_parts.body)
-                    )
-                    .as_bytes(),
-                );
-
-                let nc = 1;
-
-                let response = hash(
-                    format!(
-                        "{}:{}:{:08X}:{}:auth-int:{}",
-                        ha1, challenge.nonce, nc, cnonce, ha2
-                    )
-                    .as_bytes(),
-                );
-
-                self.save_qop_response(challenge.realm.clone(), ha1, ha2, hash);
-
-                let qop_response = QopResponse {
-                    qop: QopOption::AuthInt,
-                    cnonce,
-                    nc,
-                };
-
-                (response, Some(qop_response))
-            } else if challenge.qop.contains(&QopOption::Auth) {
-                let a2 = format!("{}:{}", &request_parts.line.method, uri);
-                let ha2 = hash(a2.as_bytes());
-
-                let nc = 1;
-
-                let response = hash(
-                    format!(
-                        "{}:{}:{:08X}:{}:auth:{}",
-                        ha1, challenge.nonce, nc, cnonce, ha2
-                    )
-                    .as_bytes(),
-                );
+        let qop = if challenge.qop.is_empty() {
+            None
+        } else if challenge.qop.contains(&QopOption::AuthInt) {
+            Some(QopOption::AuthInt)
+        } else if challenge.qop.contains(&QopOption::Auth) {
+            Some(QopOption::Auth)
+        } else {
+            return Err(DigestError::UnsupportedQop);
+        };
 
-                self.save_qop_response(challenge.realm.clone(), ha1, ha2, hash);
+        let ha2 = calculate_ha2(
+            hash,
+            &request_parts.line.method,
+            &uri,
+            qop.as_ref(),
+            request_parts.body,
+        );
 
-                let qop_response = QopResponse {
-                    qop: QopOption::Auth,
-                    cnonce,
-                    nc,
-                };
+        let qop_response = qop.map(|qop| QopResponse { qop, cnonce, nc: 1 });
 
-                (response, Some(qop_response))
-            } else {
-                return Err(DigestError::UnsupportedQop);
-            }
-        } else {
-            let a2 = format!("{}:{}", &request_parts.line.method, uri);
+        let response =
+            calculate_response(hash, &ha1, &challenge.nonce, qop_response.as_ref(), &ha2);
 
-            (
-                hash(format!("{}:{}:{}", ha1, challenge.nonce, hash(a2.as_bytes())).as_bytes()),
-                None,
-            )
-        };
+        if qop_response.is_some() {
+            self.save_qop_response(challenge.realm.clone(), ha1, ha2, hash);
+        }
 
         let username = if challenge.userhash {
             // Hash the username when the challenge sets `userhash` (RFC7616 Section 3.4.4)
@@ -522,23 +454,94 @@ impl DigestAuthenticator {
     }
 }
 
-fn hash_md5(i: &[u8]) -> String {
+/// Returns the hash function of the algorithm and if it's a `-sess` variant
+pub(crate) fn algorithm_hash(algorithm: &Algorithm) -> Option<(HashFn, bool)> {
+    let algorithm_value = match algorithm {
+        Algorithm::AkaNamespace((_, av)) => av,
+        Algorithm::AlgorithmValue(av) => av,
+    };
+
+    match algorithm_value {
+        AlgorithmValue::MD5 => Some((hash_md5, false)),
+        AlgorithmValue::MD5Sess => Some((hash_md5, true)),
+        AlgorithmValue::SHA256 => Some((hash_sha256, false)),
+        AlgorithmValue::SHA256Sess => Some((hash_sha256, true)),
+        AlgorithmValue::SHA512256 => Some((hash_sha512_trunc256, false)),
+        AlgorithmValue::SHA512256Sess => Some((hash_sha512_trunc256, true)),
+        AlgorithmValue::Other(_) => None,
+    }
+}
+
+fn is_md5(algorithm: &Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::AlgorithmValue(AlgorithmValue::MD5 | AlgorithmValue::MD5Sess)
+            | Algorithm::AkaNamespace((_, AlgorithmValue::MD5 | AlgorithmValue::MD5Sess))
+    )
+}
+
+/// `H(username:realm:password)`
+pub(crate) fn calculate_ha1(hash: HashFn, user: &str, realm: &str, password: &[u8]) -> String {
+    hash(
+        [format!("{user}:{realm}:").as_bytes(), password]
+            .concat()
+            .as_slice(),
+    )
+}
+
+/// `H(H(username:realm:password):nonce:cnonce)` used by the `-sess` algorithms
+pub(crate) fn calculate_session_ha1(hash: HashFn, ha1: &str, nonce: &str, cnonce: &str) -> String {
+    hash(format!("{ha1}:{nonce}:{cnonce}").as_bytes())
+}
+
+/// `H(method:uri)` or `H(method:uri:H(body))` when using qop `auth-int`
+pub(crate) fn calculate_ha2(
+    hash: HashFn,
+    method: &Method,
+    uri: &str,
+    qop: Option<&QopOption>,
+    body: &[u8],
+) -> String {
+    if qop == Some(&QopOption::AuthInt) {
+        hash(format!("{method}:{uri}:{}", hash(body)).as_bytes())
+    } else {
+        hash(format!("{method}:{uri}").as_bytes())
+    }
+}
+
+/// Calculate the `response` value of a digest authorization
+pub(crate) fn calculate_response(
+    hash: HashFn,
+    ha1: &str,
+    nonce: &str,
+    qop_response: Option<&QopResponse>,
+    ha2: &str,
+) -> String {
+    match qop_response {
+        Some(QopResponse { qop, cnonce, nc }) => {
+            hash(format!("{ha1}:{nonce}:{nc:08x}:{cnonce}:{qop}:{ha2}").as_bytes())
+        }
+        None => hash(format!("{ha1}:{nonce}:{ha2}").as_bytes()),
+    }
+}
+
+pub(crate) fn hash_md5(i: &[u8]) -> String {
     format!("{:x}", md5::compute(i))
 }
 
-fn hash_sha256(i: &[u8]) -> String {
+pub(crate) fn hash_sha256(i: &[u8]) -> String {
     let mut hasher = sha2::Sha256::new();
     hasher.update(i);
     format!("{:x}", hasher.finalize())
 }
 
-fn hash_sha512_trunc256(i: &[u8]) -> String {
+pub(crate) fn hash_sha512_trunc256(i: &[u8]) -> String {
     let mut hasher = sha2::Sha512_256::new();
     hasher.update(i);
     format!("{:x}", hasher.finalize())
 }
 
-type HashFn = fn(&[u8]) -> String;
+pub(crate) type HashFn = fn(&[u8]) -> String;
 
 #[cfg(test)]
 mod test {
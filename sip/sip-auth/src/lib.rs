@@ -4,8 +4,10 @@ use std::error::Error;
 use std::fmt::Debug;
 
 mod digest;
+mod server;
 
 pub use digest::{DigestAuthenticator, DigestCredentials, DigestError, DigestUser};
+pub use server::{CredentialStore, ServerAuthError, ServerAuthenticator};
 
 /// SIP request authenticator
 pub trait ClientAuthenticator {
@@ -0,0 +1,633 @@
+use crate::RequestParts;
+use crate::digest::{
+    algorithm_hash, calculate_ha1, calculate_ha2, calculate_response, calculate_session_ha1,
+    hash_sha256,
+};
+use bytesstr::BytesStr;
+use sip_types::header::HeaderError;
+use sip_types::header::typed::{
+    Algorithm, AlgorithmValue, AuthChallenge, AuthResponse, DigestChallenge, DigestResponse,
+    QopOption, Username,
+};
+use sip_types::uri::SipUri;
+use sip_types::{Headers, Name, StatusCode};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of the hex encoded timestamp at the start of every nonce
+const TIMESTAMP_LEN: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerAuthError {
+    #[error("request contains no digest authorization for realm {0}")]
+    MissingAuthorization(BytesStr),
+    #[error("nonce was not issued by this authenticator")]
+    InvalidNonce,
+    #[error("nonce is stale")]
+    StaleNonce,
+    #[error("nonce count {0} has already been used")]
+    Replay(u32),
+    #[error("encountered unsupported algorithm {0}")]
+    UnsupportedAlgorithm(BytesStr),
+    #[error("unsupported qop")]
+    UnsupportedQop,
+    #[error("unknown user {0}")]
+    UnknownUser(BytesStr),
+    #[error("wrong credentials for user {0}")]
+    WrongCredentials(BytesStr),
+    #[error("digest uri {0} does not match the request-uri")]
+    UriMismatch(BytesStr),
+    #[error(transparent)]
+    Header(HeaderError),
+}
+
+impl ServerAuthError {
+    /// Returns if the credentials were correct but the nonce expired
+    ///
+    /// The new challenge should then be sent with `stale=true`, so the client retries without
+    /// asking the user for credentials.
+    pub fn is_stale(&self) -> bool {
+        matches!(self, ServerAuthError::StaleNonce)
+    }
+}
+
+/// Provides the passwords of users authenticated by the [`ServerAuthenticator`]
+pub trait CredentialStore {
+    /// Returns the password of `username` in `realm`, or `None` if the user is unknown
+    fn password(&self, realm: &str, username: &str) -> Option<Vec<u8>>;
+}
+
+impl<F> CredentialStore for F
+where
+    F: Fn(&str, &str) -> Option<Vec<u8>>,
+{
+    fn password(&self, realm: &str, username: &str) -> Option<Vec<u8>> {
+        self(realm, username)
+    }
+}
+
+/// Issues Digest challenges and verifies the authorization of incoming requests
+///
+/// Nonces are stateless, they contain their creation time and are signed with a secret. Only the highest
+/// nonce-count received for every nonce is remembered until it expires to detect replayed requests.
+///
+/// Challenges with `userhash=true` are not supported.
+pub struct ServerAuthenticator<S> {
+    realm: BytesStr,
+    store: S,
+    secret: String,
+    nonce_counts: Mutex<HashMap<BytesStr, NonceCount>>,
+
+    /// Algorithms offered in challenges, in order of preference. Defaults to SHA-256 and MD5
+    pub algorithms: Vec<AlgorithmValue>,
+    /// Qop options offered in challenges. Defaults to `auth`
+    ///
+    /// Authorizations without qop are only accepted when this is empty.
+    pub qop: Vec<QopOption>,
+    /// Time after which a nonce is considered stale. Is 5 minutes by default
+    pub nonce_lifetime: Duration,
+    /// Use `Proxy-Authenticate` / `Proxy-Authorization` headers instead of `WWW-Authenticate` / `Authorization`. Is false by default
+    pub proxy: bool,
+}
+
+struct NonceCount {
+    nc: u32,
+    expires: u64,
+}
+
+impl<S: CredentialStore> ServerAuthenticator<S> {
+    pub fn new<R>(realm: R, store: S) -> Self
+    where
+        R: Into<BytesStr>,
+    {
+        Self {
+            realm: realm.into(),
+            store,
+            secret: uuid::Uuid::new_v4().simple().to_string(),
+            nonce_counts: Mutex::new(HashMap::new()),
+            algorithms: vec![AlgorithmValue::SHA256, AlgorithmValue::MD5],
+            qop: vec![QopOption::Auth],
+            nonce_lifetime: Duration::from_secs(300),
+            proxy: false,
+        }
+    }
+
+    /// Use the given `secret` to sign nonces instead of a random one
+    ///
+    /// Authenticators sharing the same realm and secret accept each other's nonces.
+    pub fn with_secret<T>(mut self, secret: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.secret = secret.into();
+        self
+    }
+
+    pub fn realm(&self) -> &BytesStr {
+        &self.realm
+    }
+
+    /// Status code of the response that must carry the challenge (`401` or `407`)
+    pub fn challenge_code(&self) -> StatusCode {
+        if self.proxy {
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED
+        } else {
+            StatusCode::UNAUTHORIZED
+        }
+    }
+
+    /// Add a challenge for every configured algorithm to the headers of a rejection response
+    ///
+    /// `stale` must be set when [`ServerAuthError::is_stale`] returned true.
+    pub fn challenge(&self, headers: &mut Headers, stale: bool) {
+        let name = if self.proxy {
+            Name::PROXY_AUTHENTICATE
+        } else {
+            Name::WWW_AUTHENTICATE
+        };
+
+        let nonce = self.create_nonce(unix_time());
+
+        for algorithm in &self.algorithms {
+            let challenge = AuthChallenge::Digest(DigestChallenge {
+                realm: self.realm.clone(),
+                domain: None,
+                nonce: nonce.clone(),
+                opaque: None,
+                stale,
+                algorithm: Algorithm::AlgorithmValue(algorithm.clone()),
+                qop: self.qop.clone(),
+                userhash: false,
+                other: vec![],
+            });
+
+            headers.insert_type(name.clone(), &challenge);
+        }
+    }
+
+    /// Verify the authorization of a request, returns the name of the authenticated user
+    pub fn verify(&self, request: RequestParts<'_>) -> Result<BytesStr, ServerAuthError> {
+        let name = if self.proxy {
+            Name::PROXY_AUTHORIZATION
+        } else {
+            Name::AUTHORIZATION
+        };
+
+        let responses = request
+            .headers
+            .try_get::<Vec<AuthResponse>>(name)
+            .map(|val| val.map_err(ServerAuthError::Header))
+            .transpose()?
+            .unwrap_or_default();
+
+        let response = responses
+            .into_iter()
+            .find_map(|response| match response {
+                AuthResponse::Digest(digest) if digest.realm == self.realm => Some(digest),
+                _ => None,
+            })
+            .ok_or_else(|| ServerAuthError::MissingAuthorization(self.realm.clone()))?;
+
+        self.verify_response(request, response)
+    }
+
+    fn verify_response(
+        &self,
+        request: RequestParts<'_>,
+        response: DigestResponse,
+    ) -> Result<BytesStr, ServerAuthError> {
+        let timestamp = self
+            .verify_nonce(&response.nonce)
+            .ok_or(ServerAuthError::InvalidNonce)?;
+
+        // The credentials must have been created for this request and not be taken from another one
+        // (RFC2617 Section 3.2.2.5)
+        let uri_matches = response
+            .uri
+            .parse::<SipUri>()
+            .is_ok_and(|uri| uri.compare(&request.line.uri));
+
+        if !uri_matches {
+            return Err(ServerAuthError::UriMismatch(response.uri));
+        }
+
+        let offered = match &response.algorithm {
+            Algorithm::AlgorithmValue(algorithm) => self.algorithms.contains(algorithm),
+            Algorithm::AkaNamespace(_) => false,
+        };
+
+        let hash = algorithm_hash(&response.algorithm)
+            .filter(|_| offered)
+            .ok_or_else(|| {
+                ServerAuthError::UnsupportedAlgorithm(response.algorithm.to_string().into())
+            })?;
+
+        // Refuse to downgrade to no qop or any qop that wasn't offered
+        match &response.qop_response {
+            Some(qop_response) if self.qop.contains(&qop_response.qop) => {}
+            None if self.qop.is_empty() => {}
+            _ => return Err(ServerAuthError::UnsupportedQop),
+        }
+
+        let username = decode_username(&response.username)
+            .ok_or_else(|| ServerAuthError::UnknownUser(response.username.to_string().into()))?;
+
+        if response.userhash {
+            return Err(ServerAuthError::UnknownUser(username));
+        }
+
+        let password = self
+            .store
+            .password(&self.realm, &username)
+            .ok_or_else(|| ServerAuthError::UnknownUser(username.clone()))?;
+
+        let (hash, is_session) = hash;
+
+        let mut ha1 = calculate_ha1(hash, &username, &self.realm, &password);
+
+        if is_session {
+            let Some(qop_response) = &response.qop_response else {
+                return Err(ServerAuthError::UnsupportedQop);
+            };
+
+            ha1 = calculate_session_ha1(hash, &ha1, &response.nonce, &qop_response.cnonce);
+        }
+
+        let ha2 = calculate_ha2(
+            hash,
+            &request.line.method,
+            &response.uri,
+            response
+                .qop_response
+                .as_ref()
+                .map(|qop_response| &qop_response.qop),
+            request.body,
+        );
+
+        let expected = calculate_response(
+            hash,
+            &ha1,
+            &response.nonce,
+            response.qop_response.as_ref(),
+            &ha2,
+        );
+
+        if !constant_time_eq(expected.as_bytes(), response.response.as_bytes()) {
+            return Err(ServerAuthError::WrongCredentials(username));
+        }
+
+        let now = unix_time();
+        let expires = timestamp.saturating_add(self.nonce_lifetime.as_secs());
+
+        if now > expires {
+            return Err(ServerAuthError::StaleNonce);
+        }
+
+        if let Some(qop_response) = &response.qop_response {
+            self.check_nonce_count(&response.nonce, qop_response.nc, now, expires)?;
+        }
+
+        Ok(username)
+    }
+
+    /// Remember the highest nonce-count of the nonce, returns an error if `nc` has been seen before
+    fn check_nonce_count(
+        &self,
+        nonce: &BytesStr,
+        nc: u32,
+        now: u64,
+        expires: u64,
+    ) -> Result<(), ServerAuthError> {
+        let mut nonce_counts = self.nonce_counts.lock().expect("lock poisoned");
+
+        nonce_counts.retain(|_, count| count.expires >= now);
+
+        let count = nonce_counts
+            .entry(nonce.clone())
+            .or_insert(NonceCount { nc: 0, expires });
+
+        if nc <= count.nc {
+            return Err(ServerAuthError::Replay(nc));
+        }
+
+        count.nc = nc;
+
+        Ok(())
+    }
+
+    /// Nonce consisting of the hex encoded timestamp followed by its signature
+    fn create_nonce(&self, timestamp: u64) -> BytesStr {
+        let timestamp = format!("{timestamp:0TIMESTAMP_LEN$x}");
+        let signature = self.sign(&timestamp);
+
+        format!("{timestamp}{signature}").into()
+    }
+
+    /// Verify the nonce's signature and return its timestamp
+    fn verify_nonce(&self, nonce: &str) -> Option<u64> {
+        if !nonce.is_char_boundary(TIMESTAMP_LEN) {
+            return None;
+        }
+
+        let (timestamp, signature) = nonce.split_at(TIMESTAMP_LEN);
+
+        if !constant_time_eq(self.sign(timestamp).as_bytes(), signature.as_bytes()) {
+            return None;
+        }
+
+        u64::from_str_radix(timestamp, 16).ok()
+    }
+
+    fn sign(&self, timestamp: &str) -> String {
+        hash_sha256(format!("{timestamp}:{}:{}", self.realm, self.secret).as_bytes())
+    }
+}
+
+/// Returns the plain username, decoding the `username*` encoding of non ASCII usernames (RFC5987)
+fn decode_username(username: &Username) -> Option<BytesStr> {
+    let encoded = match username {
+        Username::Username(username) => return Some(username.clone()),
+        Username::UsernameNonASCII(encoded) => encoded,
+    };
+
+    let (charset, rest) = encoded.split_once('\'')?;
+    let (_language, value) = rest.split_once('\'')?;
+
+    if !charset.eq_ignore_ascii_case("UTF-8") {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(b);
+        }
+    }
+
+    String::from_utf8(decoded).ok().map(BytesStr::from)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ClientAuthenticator, DigestAuthenticator, DigestCredentials, DigestUser, ResponseParts,
+    };
+    use sip_types::Method;
+    use sip_types::msg::{RequestLine, StatusLine};
+
+    fn test_server() -> ServerAuthenticator<impl CredentialStore> {
+        ServerAuthenticator::new("example.org", |realm: &str, username: &str| {
+            (realm == "example.org" && username == "user123").then(|| b"password123".to_vec())
+        })
+    }
+
+    fn test_client(password: &str) -> DigestAuthenticator {
+        let mut credentials = DigestCredentials::new();
+        credentials.add_for_realm("example.org", DigestUser::new("user123", password));
+
+        DigestAuthenticator::new(credentials)
+    }
+
+    fn request_line() -> RequestLine {
+        RequestLine {
+            method: Method::MESSAGE,
+            uri: "sip:bob@example.org".parse::<SipUri>().unwrap(),
+        }
+    }
+
+    /// Let the client respond to the server's challenge
+    fn authorize<S: CredentialStore>(
+        server: &ServerAuthenticator<S>,
+        client: &mut DigestAuthenticator,
+        stale: bool,
+    ) {
+        let mut challenge = Headers::new();
+        server.challenge(&mut challenge, stale);
+
+        client
+            .handle_rejection(
+                RequestParts {
+                    line: &request_line(),
+                    headers: &Headers::new(),
+                    body: b"hello",
+                },
+                ResponseParts {
+                    line: &StatusLine {
+                        code: server.challenge_code(),
+                        reason: None,
+                    },
+                    headers: &challenge,
+                    body: &[],
+                },
+            )
+            .unwrap();
+    }
+
+    fn verify<S: CredentialStore>(
+        server: &ServerAuthenticator<S>,
+        client: &mut DigestAuthenticator,
+    ) -> Result<BytesStr, ServerAuthError> {
+        let mut headers = Headers::new();
+        client.authorize_request(&mut headers);
+
+        server.verify(RequestParts {
+            line: &request_line(),
+            headers: &headers,
+            body: b"hello",
+        })
+    }
+
+    #[test]
+    fn verify_algorithms_and_qop() {
+        let algorithms = [
+            AlgorithmValue::MD5,
+            AlgorithmValue::SHA256,
+            AlgorithmValue::SHA512256,
+            AlgorithmValue::SHA256Sess,
+        ];
+
+        for algorithm in algorithms {
+            for qop in [vec![], vec![QopOption::Auth], vec![QopOption::AuthInt]] {
+                // The -sess algorithms require a cnonce, which is only sent with qop
+                if algorithm == AlgorithmValue::SHA256Sess && qop.is_empty() {
+                    continue;
+                }
+
+                let mut server = test_server();
+                server.algorithms = vec![algorithm.clone()];
+                server.qop = qop.clone();
+
+                let mut client = test_client("password123");
+                authorize(&server, &mut client, false);
+
+                let username = verify(&server, &mut client)
+                    .unwrap_or_else(|e| panic!("{algorithm} {qop:?} failed: {e}"));
+                assert_eq!(username, "user123");
+
+                // The next request of the client uses the next nonce-count
+                if !qop.is_empty() {
+                    verify(&server, &mut client).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reject_wrong_password() {
+        let server = test_server();
+
+        let mut client = test_client("password456");
+        authorize(&server, &mut client, false);
+
+        assert!(matches!(
+            verify(&server, &mut client),
+            Err(ServerAuthError::WrongCredentials(_))
+        ));
+    }
+
+    #[test]
+    fn reject_missing_authorization() {
+        let server = test_server();
+
+        assert!(matches!(
+            verify(&server, &mut test_client("password123")),
+            Err(ServerAuthError::MissingAuthorization(_))
+        ));
+    }
+
+    #[test]
+    fn reject_other_request_uri() {
+        let server = test_server();
+
+        let mut client = test_client("password123");
+        authorize(&server, &mut client, false);
+
+        let mut headers = Headers::new();
+        client.authorize_request(&mut headers);
+
+        let other_line = RequestLine {
+            method: Method::MESSAGE,
+            uri: "sip:carol@example.org".parse::<SipUri>().unwrap(),
+        };
+
+        assert!(matches!(
+            server.verify(RequestParts {
+                line: &other_line,
+                headers: &headers,
+                body: b"hello",
+            }),
+            Err(ServerAuthError::UriMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn reject_replayed_nonce_count() {
+        let server = test_server();
+
+        let mut client = test_client("password123");
+        authorize(&server, &mut client, false);
+
+        let mut headers = Headers::new();
+        client.authorize_request(&mut headers);
+
+        let request = RequestParts {
+            line: &request_line(),
+            headers: &headers,
+            body: b"hello",
+        };
+
+        server.verify(request).unwrap();
+        assert!(matches!(
+            server.verify(request),
+            Err(ServerAuthError::Replay(1))
+        ));
+    }
+
+    #[test]
+    fn reject_stale_nonce() {
+        let server = test_server();
+
+        let mut challenge = Headers::new();
+        challenge.insert_type(
+            Name::WWW_AUTHENTICATE,
+            &AuthChallenge::Digest(DigestChallenge {
+                realm: "example.org".into(),
+                domain: None,
+                nonce: server.create_nonce(unix_time() - 600),
+                opaque: None,
+                stale: false,
+                algorithm: Algorithm::AlgorithmValue(AlgorithmValue::SHA256),
+                qop: vec![QopOption::Auth],
+                userhash: false,
+                other: vec![],
+            }),
+        );
+
+        let mut client = test_client("password123");
+        client
+            .handle_rejection(
+                RequestParts {
+                    line: &request_line(),
+                    headers: &Headers::new(),
+                    body: b"hello",
+                },
+                ResponseParts {
+                    line: &StatusLine {
+                        code: StatusCode::UNAUTHORIZED,
+                        reason: None,
+                    },
+                    headers: &challenge,
+                    body: &[],
+                },
+            )
+            .unwrap();
+
+        let error = verify(&server, &mut client).unwrap_err();
+        assert!(error.is_stale());
+
+        // The client must accept the new nonce
+        authorize(&server, &mut client, true);
+        verify(&server, &mut client).unwrap();
+    }
+
+    #[test]
+    fn reject_foreign_nonce() {
+        let server = test_server();
+        let other = test_server();
+
+        let mut client = test_client("password123");
+        authorize(&other, &mut client, false);
+
+        assert!(matches!(
+            verify(&server, &mut client),
+            Err(ServerAuthError::InvalidNonce)
+        ));
+    }
+
+    #[test]
+    fn decode_non_ascii_username() {
+        let username = Username::new("jäger".into());
+
+        assert_eq!(decode_username(&username).unwrap(), "jäger");
+    }
+}
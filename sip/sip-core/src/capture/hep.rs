@@ -0,0 +1,201 @@
+use super::{CaptureProtocol, CaptureSink, CapturedMessage};
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::UNIX_EPOCH;
+
+const HEP_PROTOCOL_SIP: u8 = 1;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+
+/// [`CaptureSink`] sending all captured SIP messages to a HEPv3 collector (e.g. Homer) over UDP
+///
+/// STUN messages are not sent.
+pub struct HepSender {
+    socket: UdpSocket,
+    collector: SocketAddr,
+    agent_id: u32,
+    password: Option<String>,
+}
+
+impl HepSender {
+    /// Create a sender for the given collector address, binding a new UDP socket
+    pub fn new(collector: SocketAddr) -> io::Result<Self> {
+        let bind: SocketAddr = if collector.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+
+        let socket = UdpSocket::bind(bind)?;
+
+        // Capturing happens inside async tasks, sending must never block
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            collector,
+            agent_id: 0,
+            password: None,
+        })
+    }
+
+    /// Set the capture agent id included in every packet
+    pub fn with_agent_id(mut self, agent_id: u32) -> Self {
+        self.agent_id = agent_id;
+        self
+    }
+
+    /// Set the password (auth key) expected by the collector
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+}
+
+impl CaptureSink for HepSender {
+    fn capture(&self, message: &CapturedMessage<'_>) {
+        if message.protocol != CaptureProtocol::Sip {
+            return;
+        }
+
+        let packet = encode(message, self.agent_id, self.password.as_deref());
+
+        if let Err(e) = self.socket.send_to(&packet, self.collector) {
+            log::debug!("Failed to send HEP packet to {}, {e}", self.collector);
+        }
+    }
+}
+
+/// Encode the message as HEPv3 packet
+fn encode(message: &CapturedMessage<'_>, agent_id: u32, password: Option<&str>) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(128 + message.data.len());
+
+    packet.extend_from_slice(b"HEP3");
+    // total length, set below
+    packet.extend_from_slice(&[0, 0]);
+
+    let (src, dst) = message.ip_addresses();
+
+    let protocol = if message.reliable {
+        IPPROTO_TCP
+    } else {
+        IPPROTO_UDP
+    };
+
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            push_chunk(&mut packet, 0x01, &[AF_INET]);
+            push_chunk(&mut packet, 0x02, &[protocol]);
+            push_chunk(&mut packet, 0x03, &src.octets());
+            push_chunk(&mut packet, 0x04, &dst.octets());
+        }
+        (src, dst) => {
+            push_chunk(&mut packet, 0x01, &[AF_INET6]);
+            push_chunk(&mut packet, 0x02, &[protocol]);
+            push_chunk(&mut packet, 0x05, &super::to_ipv6(src).octets());
+            push_chunk(&mut packet, 0x06, &super::to_ipv6(dst).octets());
+        }
+    }
+
+    let timestamp = message
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    push_chunk(&mut packet, 0x07, &message.source().port().to_be_bytes());
+    push_chunk(
+        &mut packet,
+        0x08,
+        &message.destination().port().to_be_bytes(),
+    );
+    push_chunk(
+        &mut packet,
+        0x09,
+        &(timestamp.as_secs() as u32).to_be_bytes(),
+    );
+    push_chunk(&mut packet, 0x0a, &timestamp.subsec_micros().to_be_bytes());
+    push_chunk(&mut packet, 0x0b, &[HEP_PROTOCOL_SIP]);
+    push_chunk(&mut packet, 0x0c, &agent_id.to_be_bytes());
+
+    if let Some(password) = password {
+        push_chunk(&mut packet, 0x0e, password.as_bytes());
+    }
+
+    let max_payload_len = usize::from(u16::MAX) - 6 - packet.len();
+    let payload = &message.data[..message.data.len().min(max_payload_len)];
+
+    push_chunk(&mut packet, 0x0f, payload);
+
+    let len = packet.len() as u16;
+    packet[4..6].copy_from_slice(&len.to_be_bytes());
+
+    packet
+}
+
+/// Append a chunk with the generic vendor id
+fn push_chunk(packet: &mut Vec<u8>, chunk_type: u16, value: &[u8]) {
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.extend_from_slice(&chunk_type.to_be_bytes());
+    packet.extend_from_slice(&((6 + value.len()) as u16).to_be_bytes());
+    packet.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::CaptureDirection;
+    use std::time::Duration;
+
+    #[test]
+    fn encode_ipv4() {
+        let message = CapturedMessage {
+            timestamp: UNIX_EPOCH + Duration::from_micros(1_500_000),
+            direction: CaptureDirection::Received,
+            protocol: CaptureProtocol::Sip,
+            transport: "UDP",
+            reliable: false,
+            local: "192.168.0.1:5060".parse().unwrap(),
+            remote: "10.0.0.1:5080".parse().unwrap(),
+            data: b"OPTIONS",
+        };
+
+        let packet = encode(&message, 42, None);
+
+        assert_eq!(&packet[..4], b"HEP3");
+        assert_eq!(
+            usize::from(u16::from_be_bytes([packet[4], packet[5]])),
+            packet.len()
+        );
+
+        let mut chunks = vec![];
+        let mut rest = &packet[6..];
+
+        while !rest.is_empty() {
+            let chunk_type = u16::from_be_bytes([rest[2], rest[3]]);
+            let len = usize::from(u16::from_be_bytes([rest[4], rest[5]]));
+            chunks.push((chunk_type, &rest[6..len]));
+            rest = &rest[len..];
+        }
+
+        assert_eq!(
+            chunks,
+            [
+                (0x01, &[AF_INET][..]),
+                (0x02, &[IPPROTO_UDP]),
+                (0x03, &[10, 0, 0, 1]),
+                (0x04, &[192, 168, 0, 1]),
+                (0x07, &5080u16.to_be_bytes()),
+                (0x08, &5060u16.to_be_bytes()),
+                (0x09, &1u32.to_be_bytes()),
+                (0x0a, &500_000u32.to_be_bytes()),
+                (0x0b, &[HEP_PROTOCOL_SIP]),
+                (0x0c, &42u32.to_be_bytes()),
+                (0x0f, b"OPTIONS"),
+            ]
+        );
+    }
+}
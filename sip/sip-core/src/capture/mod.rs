@@ -0,0 +1,132 @@
+//! Capture every SIP (and STUN) message sent or received by the endpoint for debugging purposes
+//!
+//! Captured messages are passed to all [`CaptureSink`]s added using
+//! [`EndpointBuilder::add_capture_sink`](crate::EndpointBuilder::add_capture_sink). Provided sinks are
+//! [`PcapngWriter`] to write a pcapng file readable by Wireshark and [`HepSender`] to send messages to a
+//! HEPv3 collector like Homer.
+
+use crate::transport::TpHandle;
+use std::net::{IpAddr, SocketAddr};
+use std::time::SystemTime;
+
+mod hep;
+mod pcapng;
+
+pub use hep::HepSender;
+pub use pcapng::PcapngWriter;
+
+/// Direction of a captured message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Sent,
+    Received,
+}
+
+/// Protocol of a captured message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureProtocol {
+    Sip,
+    Stun,
+}
+
+/// Message sent or received by the endpoint
+#[derive(Debug, Clone, Copy)]
+pub struct CapturedMessage<'a> {
+    /// Time the message was sent or received at
+    pub timestamp: SystemTime,
+    pub direction: CaptureDirection,
+    pub protocol: CaptureProtocol,
+    /// Name of the transport (e.g. UDP, TCP, TLS, WS)
+    pub transport: &'static str,
+    /// Is the transport reliable (connection oriented)
+    pub reliable: bool,
+    /// Local address of the transport
+    pub local: SocketAddr,
+    /// Address of the peer
+    pub remote: SocketAddr,
+    /// Message as sent or received on the wire, for secure transports this is the decrypted message
+    pub data: &'a [u8],
+}
+
+impl CapturedMessage<'_> {
+    /// Address the message was sent from
+    pub fn source(&self) -> SocketAddr {
+        match self.direction {
+            CaptureDirection::Sent => self.local,
+            CaptureDirection::Received => self.remote,
+        }
+    }
+
+    /// Address the message was sent to
+    pub fn destination(&self) -> SocketAddr {
+        match self.direction {
+            CaptureDirection::Sent => self.remote,
+            CaptureDirection::Received => self.local,
+        }
+    }
+
+    /// Source and destination IP addresses of the same family, IPv4 addresses are mapped to IPv6 if
+    /// the other address is IPv6
+    fn ip_addresses(&self) -> (IpAddr, IpAddr) {
+        match (self.source().ip(), self.destination().ip()) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => (src.into(), dst.into()),
+            (src, dst) => (to_ipv6(src).into(), to_ipv6(dst).into()),
+        }
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+/// Receiver of captured messages
+///
+/// Called synchronously whenever a message is sent or received, implementations should not block.
+pub trait CaptureSink: Send + Sync + 'static {
+    fn capture(&self, message: &CapturedMessage<'_>);
+}
+
+/// All capture sinks of an endpoint
+#[derive(Default)]
+pub(crate) struct Capture {
+    sinks: Box<[Box<dyn CaptureSink>]>,
+}
+
+impl Capture {
+    pub(crate) fn new(sinks: Vec<Box<dyn CaptureSink>>) -> Self {
+        Self {
+            sinks: sinks.into_boxed_slice(),
+        }
+    }
+
+    pub(crate) fn capture(
+        &self,
+        direction: CaptureDirection,
+        protocol: CaptureProtocol,
+        transport: &TpHandle,
+        remote: SocketAddr,
+        data: &[u8],
+    ) {
+        if self.sinks.is_empty() {
+            return;
+        }
+
+        let message = CapturedMessage {
+            timestamp: SystemTime::now(),
+            direction,
+            protocol,
+            transport: transport.name(),
+            reliable: transport.reliable(),
+            local: transport.bound(),
+            remote,
+            data,
+        };
+
+        for sink in self.sinks.iter() {
+            sink.capture(&message);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use super::{CaptureDirection, CaptureProtocol, CaptureSink, CapturedMessage};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+/// Link type of raw IPv4/IPv6 packets without link layer header
+const LINKTYPE_RAW: u16 = 101;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// Largest payload which fits into an IP packet, larger messages are truncated
+const MAX_PAYLOAD_LEN: usize = u16::MAX as usize - 60;
+
+/// [`CaptureSink`] writing all captured messages into a pcapng file
+///
+/// Messages are written as raw IP packets with synthesized UDP headers for messages of unreliable transports
+/// and TCP headers for all other transports (including TLS and WebSocket, which are written unencrypted and
+/// unframed).
+///
+/// Writing happens on a separate thread, which flushes the writer whenever it has written all queued
+/// messages and when the `PcapngWriter` is dropped.
+pub struct PcapngWriter<W> {
+    sender: Option<mpsc::Sender<OwnedMessage>>,
+    thread: Option<JoinHandle<W>>,
+    stun: bool,
+}
+
+/// Copy of a [`CapturedMessage`] sent to the writer thread
+struct OwnedMessage {
+    message: CapturedMessage<'static>,
+    data: Vec<u8>,
+}
+
+impl PcapngWriter<BufWriter<File>> {
+    /// Create a pcapng file at the given path
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Send + 'static> PcapngWriter<W> {
+    /// Write the pcapng section header into the writer and start the writer thread
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        write_section_header(&mut writer)?;
+        write_interface_description(&mut writer)?;
+        writer.flush()?;
+
+        let (sender, receiver) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("pcapng-writer".into())
+            .spawn(move || write_messages(writer, receiver))?;
+
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            stun: false,
+        })
+    }
+
+    /// Also write STUN messages, which are not written by default
+    pub fn with_stun(mut self) -> Self {
+        self.stun = true;
+        self
+    }
+}
+
+impl<W> PcapngWriter<W> {
+    /// Stop the writer thread after it has written all queued messages, returns the flushed writer
+    fn finish(&mut self) -> Option<W> {
+        drop(self.sender.take());
+
+        self.thread.take()?.join().ok()
+    }
+}
+
+impl<W> Drop for PcapngWriter<W> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl<W: Write + Send + 'static> CaptureSink for PcapngWriter<W> {
+    fn capture(&self, message: &CapturedMessage<'_>) {
+        if message.protocol == CaptureProtocol::Stun && !self.stun {
+            return;
+        }
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let message = OwnedMessage {
+            message: CapturedMessage {
+                data: &[],
+                ..*message
+            },
+            data: message.data.to_vec(),
+        };
+
+        // Sending only fails if the writer thread stopped after a write error
+        let _ = sender.send(message);
+    }
+}
+
+/// Write all received messages, flushing the writer once no more messages are queued.
+///
+/// Returns the writer when all senders are gone or writing failed.
+fn write_messages<W: Write>(mut writer: W, receiver: mpsc::Receiver<OwnedMessage>) -> W {
+    // Next sequence number of every TCP flow, keyed by source and destination
+    let mut tcp_seq = HashMap::<(SocketAddr, SocketAddr), u32>::new();
+
+    while let Ok(message) = receiver.recv() {
+        let mut result = write_message(&mut writer, &mut tcp_seq, message);
+
+        while result.is_ok()
+            && let Ok(message) = receiver.try_recv()
+        {
+            result = write_message(&mut writer, &mut tcp_seq, message);
+        }
+
+        if let Err(e) = result.and_then(|_| writer.flush()) {
+            log::warn!("Failed to write pcapng capture, stopping capture, {e}");
+            break;
+        }
+    }
+
+    writer
+}
+
+fn write_message<W: Write>(
+    writer: &mut W,
+    tcp_seq: &mut HashMap<(SocketAddr, SocketAddr), u32>,
+    OwnedMessage { message, data }: OwnedMessage,
+) -> io::Result<()> {
+    let message = CapturedMessage {
+        data: &data,
+        ..message
+    };
+
+    let seq = if message.reliable {
+        let seq = tcp_seq
+            .entry((message.source(), message.destination()))
+            .or_insert(0);
+
+        let current = *seq;
+        *seq = seq.wrapping_add(message.data.len() as u32);
+        Some(current)
+    } else {
+        None
+    };
+
+    let packet = ip_packet(&message, seq);
+
+    write_enhanced_packet(writer, &message, &packet)
+}
+
+fn write_section_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    let mut block = Vec::with_capacity(28);
+    block.extend_from_slice(&0x0A0D0D0Au32.to_le_bytes());
+    block.extend_from_slice(&28u32.to_le_bytes());
+    // byte-order magic
+    block.extend_from_slice(&0x1A2B3C4Du32.to_le_bytes());
+    // version 1.0
+    block.extend_from_slice(&1u16.to_le_bytes());
+    block.extend_from_slice(&0u16.to_le_bytes());
+    // section length is not specified
+    block.extend_from_slice(&(-1i64).to_le_bytes());
+    block.extend_from_slice(&28u32.to_le_bytes());
+
+    writer.write_all(&block)
+}
+
+fn write_interface_description<W: Write>(writer: &mut W) -> io::Result<()> {
+    let mut block = Vec::with_capacity(20);
+    block.extend_from_slice(&1u32.to_le_bytes());
+    block.extend_from_slice(&20u32.to_le_bytes());
+    block.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+    block.extend_from_slice(&0u16.to_le_bytes());
+    // no snap length
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&20u32.to_le_bytes());
+
+    writer.write_all(&block)
+}
+
+fn write_enhanced_packet<W: Write>(
+    writer: &mut W,
+    message: &CapturedMessage<'_>,
+    packet: &[u8],
+) -> io::Result<()> {
+    let padding = (4 - packet.len() % 4) % 4;
+
+    // header (28) + packet + options (12) + trailing length (4)
+    let total_len = (28 + packet.len() + padding + 12 + 4) as u32;
+
+    // timestamps are in microseconds, the default resolution
+    let timestamp = message
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+
+    let flags: u32 = match message.direction {
+        CaptureDirection::Received => 1,
+        CaptureDirection::Sent => 2,
+    };
+
+    let mut block = Vec::with_capacity(total_len as usize);
+    block.extend_from_slice(&6u32.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+    // interface id
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+    block.extend_from_slice(&(timestamp as u32).to_le_bytes());
+    block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    block.extend_from_slice(packet);
+    block.resize(block.len() + padding, 0);
+    // epb_flags option with the direction
+    block.extend_from_slice(&2u16.to_le_bytes());
+    block.extend_from_slice(&4u16.to_le_bytes());
+    block.extend_from_slice(&flags.to_le_bytes());
+    // opt_endofopt
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+
+    writer.write_all(&block)
+}
+
+/// Synthesize an IP packet containing the message, using a TCP header if `tcp_seq` is set or an UDP header otherwise
+fn ip_packet(message: &CapturedMessage<'_>, tcp_seq: Option<u32>) -> Vec<u8> {
+    let (src, dst) = message.ip_addresses();
+    let src_port = message.source().port();
+    let dst_port = message.destination().port();
+
+    let payload = &message.data[..message.data.len().min(MAX_PAYLOAD_LEN)];
+
+    let (protocol, mut segment) = match tcp_seq {
+        Some(seq) => {
+            let mut segment = Vec::with_capacity(20 + payload.len());
+            segment.extend_from_slice(&src_port.to_be_bytes());
+            segment.extend_from_slice(&dst_port.to_be_bytes());
+            segment.extend_from_slice(&seq.to_be_bytes());
+            // acknowledgment number
+            segment.extend_from_slice(&0u32.to_be_bytes());
+            // data offset of 5 words, flags PSH + ACK
+            segment.extend_from_slice(&[0x50, 0x18]);
+            // window
+            segment.extend_from_slice(&u16::MAX.to_be_bytes());
+            // checksum & urgent pointer
+            segment.extend_from_slice(&[0, 0, 0, 0]);
+            segment.extend_from_slice(payload);
+
+            (IPPROTO_TCP, segment)
+        }
+        None => {
+            let mut segment = Vec::with_capacity(8 + payload.len());
+            segment.extend_from_slice(&src_port.to_be_bytes());
+            segment.extend_from_slice(&dst_port.to_be_bytes());
+            segment.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+            // checksum
+            segment.extend_from_slice(&[0, 0]);
+            segment.extend_from_slice(payload);
+
+            (IPPROTO_UDP, segment)
+        }
+    };
+
+    // Calculate the checksum of the segment including the pseudo header
+    let mut pseudo_header = Vec::with_capacity(40);
+    push_ip(&mut pseudo_header, src);
+    push_ip(&mut pseudo_header, dst);
+
+    match src {
+        IpAddr::V4(_) => {
+            pseudo_header.extend_from_slice(&[0, protocol]);
+            pseudo_header.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        }
+        IpAddr::V6(_) => {
+            pseudo_header.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+    }
+
+    let mut checksum = internet_checksum(&[&pseudo_header, &segment]);
+
+    let checksum_offset = if protocol == IPPROTO_TCP {
+        16
+    } else {
+        // An UDP checksum of zero means no checksum
+        if checksum == 0 {
+            checksum = 0xFFFF;
+        }
+
+        6
+    };
+
+    segment[checksum_offset..checksum_offset + 2].copy_from_slice(&checksum.to_be_bytes());
+
+    let mut packet = Vec::with_capacity(40 + segment.len());
+
+    match src {
+        IpAddr::V4(_) => {
+            packet.extend_from_slice(&[0x45, 0]);
+            packet.extend_from_slice(&((20 + segment.len()) as u16).to_be_bytes());
+            // identification, don't fragment
+            packet.extend_from_slice(&[0, 0, 0x40, 0]);
+            // ttl, protocol & checksum
+            packet.extend_from_slice(&[64, protocol, 0, 0]);
+            push_ip(&mut packet, src);
+            push_ip(&mut packet, dst);
+
+            let checksum = internet_checksum(&[&packet]);
+            packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        IpAddr::V6(_) => {
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+            // next header & hop limit
+            packet.extend_from_slice(&[protocol, 64]);
+            push_ip(&mut packet, src);
+            push_ip(&mut packet, dst);
+        }
+    }
+
+    packet.extend_from_slice(&segment);
+    packet
+}
+
+fn push_ip(buffer: &mut Vec<u8>, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => buffer.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => buffer.extend_from_slice(&ip.octets()),
+    }
+}
+
+/// One's complement sum of all 16 bit words (RFC1071), all parts except the last must have an even length
+fn internet_checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+
+    for part in parts {
+        let mut chunks = part.chunks_exact(2);
+
+        for chunk in &mut chunks {
+            sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+        }
+
+        if let [last] = chunks.remainder() {
+            sum += u32::from(u16::from_be_bytes([*last, 0]));
+        }
+    }
+
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn message(reliable: bool, data: &[u8]) -> CapturedMessage<'_> {
+        CapturedMessage {
+            timestamp: UNIX_EPOCH + Duration::from_micros(0x1_0000_0002),
+            direction: CaptureDirection::Sent,
+            protocol: CaptureProtocol::Sip,
+            transport: if reliable { "TCP" } else { "UDP" },
+            reliable,
+            local: "192.168.0.1:5060".parse().unwrap(),
+            remote: "10.0.0.1:5080".parse().unwrap(),
+            data,
+        }
+    }
+
+    #[test]
+    fn udp_packet() {
+        let packet = ip_packet(&message(false, b"OPTIONS"), None);
+
+        assert_eq!(packet.len(), 20 + 8 + 7);
+        assert_eq!(packet[9], IPPROTO_UDP);
+        assert_eq!(&packet[12..16], &[192, 168, 0, 1]);
+        assert_eq!(&packet[16..20], &[10, 0, 0, 1]);
+        assert_eq!(&packet[20..24], &[0x13, 0xC4, 0x13, 0xD8]);
+        assert_eq!(&packet[28..], b"OPTIONS");
+
+        // A valid checksum sums up to zero
+        assert_eq!(internet_checksum(&[&packet[..20]]), 0);
+    }
+
+    #[test]
+    fn tcp_packet_ipv6() {
+        let mut message = message(true, b"OPTIONS");
+        message.remote = "[2001:db8::1]:5060".parse().unwrap();
+
+        let packet = ip_packet(&message, Some(7));
+
+        assert_eq!(packet.len(), 40 + 20 + 7);
+        assert_eq!(packet[0] >> 4, 6);
+        assert_eq!(packet[6], IPPROTO_TCP);
+        assert_eq!(&packet[44..48], &7u32.to_be_bytes());
+        assert_eq!(&packet[60..], b"OPTIONS");
+    }
+
+    #[test]
+    fn writer() {
+        let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+
+        writer.capture(&message(false, b"OPTIONS"));
+
+        let mut stun = message(false, b"STUN");
+        stun.protocol = CaptureProtocol::Stun;
+        writer.capture(&stun);
+
+        let buffer = writer.finish().unwrap();
+
+        // section header, interface description, single enhanced packet block
+        let epb = &buffer[28 + 20..];
+        assert_eq!(&epb[..4], &6u32.to_le_bytes());
+        assert_eq!(epb.len(), 44 + 36);
+        assert_eq!(&epb[12..16], &1u32.to_le_bytes());
+        assert_eq!(&epb[16..20], &2u32.to_le_bytes());
+    }
+
+    /// Writer appending to a shared buffer
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let shared = Shared::default();
+
+        let writer = PcapngWriter::new(BufWriter::new(shared.clone())).unwrap();
+
+        writer.capture(&message(true, b"OPTIONS"));
+        drop(writer);
+
+        // section header, interface description, enhanced packet block of the TCP packet (47 bytes, padded to 48)
+        assert_eq!(shared.0.lock().unwrap().len(), 28 + 20 + 44 + 48);
+    }
+}
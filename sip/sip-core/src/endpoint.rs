@@ -1,10 +1,14 @@
+use crate::capture::{Capture, CaptureDirection, CaptureProtocol, CaptureSink};
+use crate::hook::Hooks;
 use crate::transaction::{ClientInvTsx, ClientTsx, ServerInvTsx, ServerTsx, TsxKey};
 use crate::transaction::{Transactions, TsxMessage};
 use crate::transport::{
-    Direction, Factory, OutgoingParts, OutgoingRequest, OutgoingResponse, ReceivedMessage,
-    TargetTransportInfo, TpHandle, Transports, TransportsBuilder,
+    Direction, DnsOverrides, Factory, OutgoingParts, OutgoingRequest, OutgoingResponse,
+    ReceivedMessage, ServerEntry, TargetTransportInfo, TpHandle, Transports, TransportsBuilder,
+};
+use crate::{
+    BaseHeaders, IncomingRequest, Layer, MayTake, MessageHook, Request, Response, Result, StunError,
 };
-use crate::{BaseHeaders, IncomingRequest, Layer, MayTake, Request, Response, Result, StunError};
 use bytes::{Bytes, BytesMut};
 use bytesstr::BytesStr;
 use sip_types::header::typed::{Accept, Allow, Supported, Via};
@@ -18,6 +22,7 @@ use std::fmt::Write;
 use std::mem::take;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fmt, io};
 use stun_types::Message;
 use tokio::sync::broadcast;
@@ -45,6 +50,7 @@ impl fmt::Debug for Endpoint {
 
 struct Inner {
     // capabilities
+    accept: Vec<Accept>,
     allow: Vec<Allow>,
     supported: Vec<Supported>,
     user_agent: Option<BytesStr>,
@@ -53,6 +59,8 @@ struct Inner {
     transactions: Transactions,
 
     layer: Box<[Box<dyn Layer>]>,
+    hooks: Hooks,
+    capture: Arc<Capture>,
 }
 
 impl Endpoint {
@@ -91,6 +99,11 @@ impl Endpoint {
         ServerInvTsx::new(request)
     }
 
+    /// Returns all ACCEPT headers this endpoint supports
+    pub fn accepted(&self) -> &Vec<Accept> {
+        &self.inner.accept
+    }
+
     /// Returns all ALLOW headers this endpoint supports
     pub fn allowed(&self) -> &Vec<Allow> {
         &self.inner.allow
@@ -121,6 +134,41 @@ impl Endpoint {
         self.transports().select(self, uri).await
     }
 
+    /// Resolve all servers the uri may be reached at, in the order they should be tried
+    /// ([RFC3263](https://datatracker.ietf.org/doc/html/rfc3263))
+    pub async fn resolve_servers(&self, uri: &SipUri) -> Result<Vec<ServerEntry>> {
+        Ok(self.transports().resolve_uri(uri).await?)
+    }
+
+    /// Find or create a suitable transport for a server returned by [`Endpoint::resolve_servers`]
+    pub async fn select_transport_for_server(
+        &self,
+        uri: &SipUri,
+        server: &ServerEntry,
+    ) -> Result<TpHandle> {
+        self.transports()
+            .select_for_server(self, uri, server)
+            .await
+            .ok_or_else(|| {
+                io::Error::other(format!("Failed to select transport for {}", server.address))
+                    .into()
+            })
+    }
+
+    /// Blacklist a server returned by [`Endpoint::resolve_servers`] after it failed, so it's tried last
+    /// when resolving a target
+    ///
+    /// The server is blacklisted for the configured blacklist duration, or `retry_after` if it is longer.
+    /// Client transactions blacklist servers on their own when failing over.
+    pub fn blacklist_server(&self, server: &ServerEntry, retry_after: Option<Duration>) {
+        self.transports().blacklist(server, retry_after)
+    }
+
+    /// Returns if the server has been blacklisted after it failed
+    pub fn is_server_blacklisted(&self, server: &ServerEntry) -> bool {
+        self.transports().is_blacklisted(server)
+    }
+
     /// Takes a request and converts it into an `Outgoing`.
     /// To do so it calculates the destination and retrieves a suitable transport
     pub async fn create_outgoing(
@@ -156,6 +204,14 @@ impl Endpoint {
         }
 
         if message.parts.buffer.is_empty() {
+            if !self.inner.hooks.outgoing_request(message) {
+                log::debug!(
+                    "Outgoing request {} dropped by hook",
+                    message.msg.line.method
+                );
+                return Ok(());
+            }
+
             let mut buffer = BytesMut::new();
 
             let ctx = PrintCtx {
@@ -191,12 +247,30 @@ impl Endpoint {
             .parts
             .transport
             .send(&message.parts.buffer, message.parts.destination)
-            .await
+            .await?;
+
+        self.inner.capture.capture(
+            CaptureDirection::Sent,
+            CaptureProtocol::Sip,
+            &message.parts.transport,
+            message.parts.destination,
+            &message.parts.buffer,
+        );
+
+        Ok(())
     }
 
     /// Print the request to its buffer (if needed) and send it via the transport
     pub async fn send_outgoing_response(&self, message: &mut OutgoingResponse) -> io::Result<()> {
         if message.parts.buffer.is_empty() {
+            if !self.inner.hooks.outgoing_response(message) {
+                log::debug!(
+                    "Outgoing response {} dropped by hook",
+                    message.msg.line.code.into_u16()
+                );
+                return Ok(());
+            }
+
             let mut buffer = BytesMut::new();
 
             let ctx = PrintCtx {
@@ -232,7 +306,17 @@ impl Endpoint {
             .parts
             .transport
             .send(&message.parts.buffer, message.parts.destination)
-            .await
+            .await?;
+
+        self.inner.capture.capture(
+            CaptureDirection::Sent,
+            CaptureProtocol::Sip,
+            &message.parts.transport,
+            message.parts.destination,
+            &message.parts.buffer,
+        );
+
+        Ok(())
     }
 
     /// Create a response to an incoming request with a given status code and optional reason
@@ -303,8 +387,21 @@ impl Endpoint {
     /// Pass a received message to the endpoint for further processing
     ///
     /// Spawns a task internally which will let every registered layer have a look at the message
-    /// and let it decide if it is going to handle it.
-    pub fn receive(&self, message: ReceivedMessage) {
+    /// and let it decide if it is going to handle it. Registered [`MessageHook`]s are called beforehand.
+    pub fn receive(&self, mut message: ReceivedMessage) {
+        self.inner.capture.capture(
+            CaptureDirection::Received,
+            CaptureProtocol::Sip,
+            &message.tp_info.transport,
+            message.tp_info.source,
+            &message.tp_info.buffer,
+        );
+
+        if !self.inner.hooks.incoming(&mut message) {
+            log::debug!("Received message {message} dropped by hook");
+            return;
+        }
+
         tokio::spawn(self.clone().do_receive(message));
     }
 
@@ -443,6 +540,14 @@ impl Endpoint {
 
     /// Pass a received STUN message to the endpoint for further processing
     pub fn receive_stun(&self, message: Message, source: SocketAddr, transport: TpHandle) {
+        self.inner.capture.capture(
+            CaptureDirection::Received,
+            CaptureProtocol::Stun,
+            &transport,
+            source,
+            message.buffer(),
+        );
+
         let this = self.clone();
         tokio::spawn(async move {
             this.transports()
@@ -462,6 +567,18 @@ impl Endpoint {
             .await
     }
 
+    /// Send a keep-alive ping over a transport and wait for the pong, a double-CRLF or a WebSocket ping
+    /// ([RFC5626 Section 4.4.1](https://datatracker.ietf.org/doc/html/rfc5626#section-4.4.1))
+    ///
+    /// Returns an error if the ping couldn't be sent, the transport was closed or no pong was received within 10 seconds.
+    pub async fn send_keep_alive(
+        &self,
+        transport: &TpHandle,
+        target: SocketAddr,
+    ) -> io::Result<()> {
+        self.transports().send_keep_alive(transport, target).await
+    }
+
     pub(crate) fn transactions(&self) -> &Transactions {
         &self.inner.transactions
     }
@@ -507,6 +624,8 @@ pub struct EndpointBuilder {
 
     transports: TransportsBuilder,
     layer: Vec<Box<dyn Layer>>,
+    hooks: Vec<Box<dyn MessageHook>>,
+    capture_sinks: Vec<Box<dyn CaptureSink>>,
 }
 
 impl Default for EndpointBuilder {
@@ -527,6 +646,8 @@ impl EndpointBuilder {
             user_agent: None,
             transports: Default::default(),
             layer: Default::default(),
+            hooks: Default::default(),
+            capture_sinks: Default::default(),
         }
     }
 
@@ -535,12 +656,21 @@ impl EndpointBuilder {
     where
         A: Into<Accept>,
     {
-        self.accept.push(accepted.into())
+        let accepted = accepted.into();
+
+        if !self.accept.contains(&accepted) {
+            self.accept.push(accepted)
+        }
     }
 
     /// Add an ALLOW header to the endpoints capabilities
     pub fn add_allow(&mut self, allowed: Method) {
-        self.allow.push(Allow(allowed))
+        let allowed = Allow(allowed);
+
+        // Multiple layers may allow the same method
+        if !self.allow.contains(&allowed) {
+            self.allow.push(allowed)
+        }
     }
 
     /// Add an SUPPORTED header to the endpoints capabilities
@@ -548,7 +678,12 @@ impl EndpointBuilder {
     where
         S: Into<BytesStr>,
     {
-        self.supported.push(Supported(supported.into()))
+        let supported = Supported(supported.into());
+
+        // Multiple layers may support the same extension
+        if !self.supported.contains(&supported) {
+            self.supported.push(supported)
+        }
     }
 
     /// Set the User-Agent header to be sent with every request
@@ -573,11 +708,26 @@ impl EndpointBuilder {
 
     /// Set a `trust-dns-resolver` DNS resolver for the endpoint to use.
     ///
-    /// Uses the system config by default.
+    /// Uses the system config by default. Results of lookups, including the absence of records,
+    /// are cached by the endpoint according to their TTL.
     pub fn set_dns_resolver(&mut self, dns_resolver: hickory_resolver::TokioResolver) {
         self.transports.set_dns_resolver(dns_resolver)
     }
 
+    /// Set static DNS records which are used instead of querying DNS for the names they cover.
+    ///
+    /// See [`DnsOverrides`] for details.
+    pub fn set_dns_overrides(&mut self, dns_overrides: DnsOverrides) {
+        self.transports.set_dns_overrides(dns_overrides)
+    }
+
+    /// Set how long a server which failed to respond is tried last when resolving a target.
+    ///
+    /// Defaults to 60 seconds.
+    pub fn set_blacklist_duration(&mut self, duration: Duration) {
+        self.transports.set_blacklist_duration(duration)
+    }
+
     /// Add a implementation of [`Layer`] to the endpoint.
     ///
     /// Note that the insertion order is relevant in how the SIP Stack may react to requests,
@@ -591,6 +741,25 @@ impl EndpointBuilder {
         self.layer.push(Box::new(layer));
     }
 
+    /// Add a [`MessageHook`] which is able to inspect, modify or drop every message sent or received by the endpoint.
+    ///
+    /// Hooks are called in insertion order.
+    pub fn add_message_hook<H>(&mut self, hook: H)
+    where
+        H: MessageHook,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Add a [`CaptureSink`] which receives every SIP and STUN message sent or received by the endpoint
+    /// (e.g. [`PcapngWriter`](crate::capture::PcapngWriter) or [`HepSender`](crate::capture::HepSender)).
+    pub fn add_capture_sink<S>(&mut self, sink: S)
+    where
+        S: CaptureSink,
+    {
+        self.capture_sinks.push(Box::new(sink));
+    }
+
     /// "Subscribe" to the creation of the endpoint.
     ///
     /// The broadcast channel will receive the endpoint on successful creation or error if the
@@ -606,13 +775,18 @@ impl EndpointBuilder {
             layer.init(self);
         }
 
+        let capture = Arc::new(Capture::new(take(&mut self.capture_sinks)));
+
         let inner = Inner {
+            accept: take(&mut self.accept),
             allow: take(&mut self.allow),
             supported: take(&mut self.supported),
             user_agent: take(&mut self.user_agent),
-            transports: self.transports.build(),
+            transports: self.transports.build(capture.clone()),
             transactions: Default::default(),
             layer,
+            hooks: Hooks::new(take(&mut self.hooks)),
+            capture,
         };
 
         let inner = Arc::new(inner);
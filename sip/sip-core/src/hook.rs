@@ -0,0 +1,312 @@
+use crate::transport::{OutgoingRequest, OutgoingResponse, ReceivedMessage};
+use bytes::BytesMut;
+use sip_types::Name;
+use sip_types::print::{AppendCtx, PrintCtx};
+use std::fmt::Write;
+
+/// What to do with a message after it has been passed to a [`MessageHook`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    /// The message has not been modified
+    Continue,
+    /// The message has been modified, received messages are printed again to reflect the changes
+    Modified,
+    /// Discard the message, it will not be sent or processed
+    Drop,
+}
+
+/// Hooks allow inspecting and rewriting every SIP message sent or received by the endpoint
+/// (e.g. to work around interoperability issues by rewriting headers).
+///
+/// They can be added to the endpoint in the building stage by calling
+/// [`EndpointBuilder::add_message_hook`](crate::EndpointBuilder::add_message_hook) and are called in insertion order.
+/// Once a hook drops a message the remaining hooks are not called.
+pub trait MessageHook: Send + Sync + 'static {
+    /// Called for every request before it is printed and sent.
+    ///
+    /// Retransmissions of the request reuse the printed buffer and are not passed to the hook again.
+    /// A dropped request has no printed buffer, so every retransmission of it is passed to the hooks again.
+    fn outgoing_request(&self, _request: &mut OutgoingRequest) -> HookAction {
+        HookAction::Continue
+    }
+
+    /// Called for every response before it is printed and sent.
+    ///
+    /// Retransmissions of the response reuse the printed buffer and are not passed to the hook again.
+    /// A dropped response has no printed buffer, so every retransmission of it is passed to the hooks again.
+    fn outgoing_response(&self, _response: &mut OutgoingResponse) -> HookAction {
+        HookAction::Continue
+    }
+
+    /// Called for every message received from a transport, before it is passed to transactions and layers
+    fn incoming(&self, _message: &mut ReceivedMessage) -> HookAction {
+        HookAction::Continue
+    }
+}
+
+/// All hooks of an endpoint
+pub(crate) struct Hooks {
+    hooks: Box<[Box<dyn MessageHook>]>,
+}
+
+impl Hooks {
+    pub(crate) fn new(hooks: Vec<Box<dyn MessageHook>>) -> Self {
+        Self {
+            hooks: hooks.into_boxed_slice(),
+        }
+    }
+
+    /// Pass the request to all hooks, returns false if it has been dropped
+    pub(crate) fn outgoing_request(&self, request: &mut OutgoingRequest) -> bool {
+        self.run(|hook| hook.outgoing_request(request)).is_some()
+    }
+
+    /// Pass the response to all hooks, returns false if it has been dropped
+    pub(crate) fn outgoing_response(&self, response: &mut OutgoingResponse) -> bool {
+        self.run(|hook| hook.outgoing_response(response)).is_some()
+    }
+
+    /// Pass the message to all hooks, returns false if it has been dropped
+    ///
+    /// Prints the message into a new buffer if any hook modified it.
+    pub(crate) fn incoming(&self, message: &mut ReceivedMessage) -> bool {
+        match self.run(|hook| hook.incoming(message)) {
+            Some(true) => {
+                reprint(message);
+                true
+            }
+            Some(false) => true,
+            None => false,
+        }
+    }
+
+    /// Returns if any hook modified the message, or `None` if it was dropped
+    fn run(&self, mut f: impl FnMut(&dyn MessageHook) -> HookAction) -> Option<bool> {
+        let mut modified = false;
+
+        for hook in self.hooks.iter() {
+            match f(&**hook) {
+                HookAction::Continue => {}
+                HookAction::Modified => modified = true,
+                HookAction::Drop => return None,
+            }
+        }
+
+        Some(modified)
+    }
+}
+
+fn reprint(message: &mut ReceivedMessage) {
+    let mut buffer = BytesMut::new();
+
+    let ctx = PrintCtx {
+        method: message.line.request_method(),
+        uri: None,
+    };
+
+    message.headers.remove(&Name::CONTENT_LENGTH);
+    message
+        .headers
+        .insert(Name::CONTENT_LENGTH, message.body.len().to_string());
+
+    // Writing into BytesMut cannot fail
+    let _ = write!(
+        buffer,
+        "{}\r\n{}\r\n",
+        message.line.print_ctx(ctx),
+        message.headers
+    );
+
+    buffer.extend_from_slice(&message.body);
+
+    message.tp_info.buffer = buffer.freeze();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Request;
+    use crate::transport::{Direction, OutgoingParts, TpHandle, Transport};
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use sip_types::Method;
+    use sip_types::header::typed::ContentLength;
+    use sip_types::msg::{MessageLine, RequestLine};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{fmt, io};
+
+    const ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5060);
+
+    #[derive(Debug)]
+    struct TestTransport;
+
+    impl fmt::Display for TestTransport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("test")
+        }
+    }
+
+    #[async_trait]
+    impl Transport for TestTransport {
+        fn name(&self) -> &'static str {
+            "UDP"
+        }
+
+        fn secure(&self) -> bool {
+            false
+        }
+
+        fn reliable(&self) -> bool {
+            false
+        }
+
+        fn bound(&self) -> SocketAddr {
+            ADDR
+        }
+
+        fn sent_by(&self) -> SocketAddr {
+            ADDR
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::None
+        }
+
+        async fn send(&self, _: &[u8], _: SocketAddr) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Hook returning `action` for every message, counting how often it was called
+    struct TestHook {
+        action: HookAction,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl TestHook {
+        fn counting(action: HookAction) -> (Box<dyn MessageHook>, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+
+            let hook = Self {
+                action,
+                calls: calls.clone(),
+            };
+
+            (Box::new(hook), calls)
+        }
+
+        fn call(&self) -> HookAction {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.action
+        }
+    }
+
+    impl MessageHook for TestHook {
+        fn outgoing_request(&self, _request: &mut OutgoingRequest) -> HookAction {
+            self.call()
+        }
+
+        fn outgoing_response(&self, _response: &mut OutgoingResponse) -> HookAction {
+            self.call()
+        }
+
+        fn incoming(&self, _message: &mut ReceivedMessage) -> HookAction {
+            self.call()
+        }
+    }
+
+    /// Hook replacing the body of received messages
+    struct ReplaceBody(&'static str);
+
+    impl MessageHook for ReplaceBody {
+        fn incoming(&self, message: &mut ReceivedMessage) -> HookAction {
+            message.body = Bytes::from_static(self.0.as_bytes());
+            HookAction::Modified
+        }
+    }
+
+    fn outgoing_request() -> OutgoingRequest {
+        OutgoingRequest {
+            msg: Request::new(Method::OPTIONS, "sip:bob@example.com".parse().unwrap()),
+            parts: OutgoingParts {
+                transport: TpHandle::new(TestTransport),
+                destination: ADDR,
+                buffer: Bytes::new(),
+            },
+        }
+    }
+
+    fn received_message() -> ReceivedMessage {
+        let buffer = "MESSAGE sip:bob@example.com SIP/2.0\r\nContent-Length: 5\r\n\r\nhello";
+
+        let mut headers = sip_types::Headers::new();
+        headers.insert(Name::CONTENT_LENGTH, "5");
+
+        ReceivedMessage::new(
+            ADDR,
+            Bytes::from_static(buffer.as_bytes()),
+            TpHandle::new(TestTransport),
+            MessageLine::Request(RequestLine {
+                method: Method::MESSAGE,
+                uri: "sip:bob@example.com".parse().unwrap(),
+            }),
+            headers,
+            Bytes::from_static(b"hello"),
+        )
+    }
+
+    #[test]
+    fn drop_skips_remaining_hooks() {
+        let (first, first_calls) = TestHook::counting(HookAction::Continue);
+        let (dropping, dropping_calls) = TestHook::counting(HookAction::Drop);
+        let (last, last_calls) = TestHook::counting(HookAction::Continue);
+
+        let hooks = Hooks::new(vec![first, dropping, last]);
+
+        assert!(!hooks.outgoing_request(&mut outgoing_request()));
+
+        assert_eq!(first_calls.load(Ordering::Relaxed), 1);
+        assert_eq!(dropping_calls.load(Ordering::Relaxed), 1);
+        assert_eq!(last_calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn drop_incoming() {
+        let (dropping, _) = TestHook::counting(HookAction::Drop);
+
+        let hooks = Hooks::new(vec![dropping]);
+
+        assert!(!hooks.incoming(&mut received_message()));
+    }
+
+    #[test]
+    fn unmodified_incoming_is_not_printed() {
+        let (hook, calls) = TestHook::counting(HookAction::Continue);
+
+        let hooks = Hooks::new(vec![hook]);
+
+        let mut message = received_message();
+        let buffer = message.tp_info.buffer.clone();
+
+        assert!(hooks.incoming(&mut message));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(message.tp_info.buffer, buffer);
+    }
+
+    #[test]
+    fn modified_incoming_is_printed() {
+        let hooks = Hooks::new(vec![Box::new(ReplaceBody("hello world"))]);
+
+        let mut message = received_message();
+
+        assert!(hooks.incoming(&mut message));
+
+        assert_eq!(message.headers.get_named::<ContentLength>().unwrap().0, 11);
+        assert_eq!(
+            message.tp_info.buffer,
+            "MESSAGE sip:bob@example.com SIP/2.0\r\nContent-Length: 11\r\n\r\nhello world"
+        );
+    }
+}
@@ -19,7 +19,9 @@ use transport::MessageTpInfo;
 
 #[macro_use]
 mod error;
+pub mod capture;
 mod endpoint;
+mod hook;
 mod may_take;
 pub mod transaction;
 pub mod transport;
@@ -27,6 +29,7 @@ pub mod transport;
 pub use endpoint::Endpoint;
 pub use endpoint::EndpointBuilder;
 pub use error::{Error, Result, StunError};
+pub use hook::{HookAction, MessageHook};
 pub use may_take::MayTake;
 
 /// Basic Response
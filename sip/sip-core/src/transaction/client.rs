@@ -1,4 +1,5 @@
 use super::consts::{T1, T2};
+use super::failover::{self, Failover};
 use super::key::TsxKey;
 use super::{TsxRegistration, TsxResponse};
 use crate::error::Error;
@@ -6,8 +7,7 @@ use crate::transaction::consts::T4;
 use crate::transport::{OutgoingRequest, TargetTransportInfo};
 use crate::{Endpoint, Request, Result};
 use sip_types::{CodeKind, Method};
-use std::time::Instant;
-use tokio::time::{timeout, timeout_at};
+use tokio::time::{Instant, timeout, timeout_at};
 
 /// Client non-INVITE transaction. Used to receive responses to a sent request.
 ///
@@ -22,6 +22,7 @@ pub struct ClientTsx {
     request: OutgoingRequest,
     timeout: Instant,
     state: State,
+    failover: Option<Failover>,
 }
 
 #[derive(Debug)]
@@ -46,17 +47,29 @@ impl ClientTsx {
             "tried to create client transaction from {method} request"
         );
 
-        let mut request = endpoint.create_outgoing(request, target).await?;
+        let (registration, request, failover) =
+            failover::send_request(endpoint, request, target).await?;
 
-        let registration = TsxRegistration::create(endpoint, TsxKey::client(&method));
+        let timeout = Instant::now() + T1 * 64;
 
-        let via = registration.endpoint.create_via(
-            &request.parts.transport,
-            &registration.tsx_key,
-            target.via_host_port.clone(),
-        );
+        Ok(Self {
+            registration: Some(registration),
+            request,
+            timeout,
+            state: State::Init,
+            failover,
+        })
+    }
+
+    /// Internal: Used by [ClientInvTsx::cancel](super::ClientInvTsx::cancel)
+    pub(crate) async fn send_cancel(
+        endpoint: Endpoint,
+        invite_key: &TsxKey,
+        mut request: OutgoingRequest,
+    ) -> Result<Self> {
+        let registration =
+            TsxRegistration::create(endpoint, invite_key.with_method(&Method::CANCEL));
 
-        request.msg.headers.insert_named_front(&via);
         registration
             .endpoint
             .send_outgoing_request(&mut request)
@@ -69,6 +82,7 @@ impl ClientTsx {
             request,
             timeout,
             state: State::Init,
+            failover: None,
         })
     }
 
@@ -81,10 +95,49 @@ impl ClientTsx {
     ///
     /// Must be called until a final response or error is returned.
     ///
+    /// If the request-uri resolved to multiple servers, the request is sent to the next server when
+    /// the current one doesn't respond, the transport fails or it responds with 503. The transport and
+    /// destination of [`ClientTsx::request`] are updated accordingly.
+    ///
     /// # Panics
     /// After receiving the final response this function will panic if called again.
     /// This is due to it needing to move out some internal state to a new task.
     pub async fn receive(&mut self) -> Result<TsxResponse> {
+        loop {
+            let result = self.receive_current().await;
+
+            let should_failover = match &result {
+                Ok(response) => Failover::on_response(response.line.code),
+                Err(e) => matches!(self.state, State::Init) && Failover::on_error(e),
+            };
+
+            if should_failover && self.failover().await {
+                continue;
+            }
+
+            return result;
+        }
+    }
+
+    /// Send the request to the next server, returns false if there are none left
+    async fn failover(&mut self) -> bool {
+        let Some(failover) = &mut self.failover else {
+            return false;
+        };
+
+        let Some((registration, request)) = failover.next().await else {
+            return false;
+        };
+
+        self.registration = Some(registration);
+        self.request = request;
+        self.timeout = Instant::now() + T1 * 64;
+        self.state = State::Init;
+
+        true
+    }
+
+    async fn receive_current(&mut self) -> Result<TsxResponse> {
         let registration = if let Some(registration) = &mut self.registration {
             registration
         } else {
@@ -97,7 +150,7 @@ impl ClientTsx {
                 loop {
                     let receive = timeout(T2, registration.receive_response());
 
-                    match timeout_at(self.timeout.into(), receive).await {
+                    match timeout_at(self.timeout, receive).await {
                         Ok(Ok(msg)) => return self.handle_msg(msg),
                         Ok(Err(_)) => {
                             // retransmit
@@ -111,7 +164,7 @@ impl ClientTsx {
                 }
             }
             State::Init | State::Proceeding => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => self.handle_msg(msg),
                     Err(_) => Err(Error::RequestTimedOut),
                 }
@@ -154,10 +207,7 @@ impl ClientTsx {
                     tokio::spawn(async move {
                         let timeout = Instant::now() + T4;
 
-                        while timeout_at(timeout.into(), registration.receive())
-                            .await
-                            .is_ok()
-                        {
+                        while timeout_at(timeout, registration.receive()).await.is_ok() {
                             // toss incoming messages, just keep registration alive
                         }
                     });
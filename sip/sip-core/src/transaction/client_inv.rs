@@ -1,17 +1,17 @@
 use super::consts::T1;
-use super::key::TsxKey;
-use super::{TsxRegistration, TsxResponse};
+use super::failover::{self, Failover};
+use super::{ClientTsx, TsxRegistration, TsxResponse};
 use crate::Result;
 use crate::error::Error;
 use crate::transport::{OutgoingParts, OutgoingRequest, TargetTransportInfo};
 use crate::{Endpoint, Request};
 use bytes::Bytes;
 use sip_types::header::HeaderError;
-use sip_types::header::typed::{CSeq, MaxForwards};
+use sip_types::header::typed::{CSeq, MaxForwards, Via};
 use sip_types::msg::RequestLine;
 use sip_types::{CodeKind, Headers, Method, Name};
-use std::time::{Duration, Instant};
-use tokio::time::{timeout, timeout_at};
+use std::time::Duration;
+use tokio::time::{Instant, timeout, timeout_at};
 
 /// Client INVITE transaction. Used to receives responses to a INVITE request.
 ///
@@ -26,7 +26,9 @@ pub struct ClientInvTsx {
     registration: Option<TsxRegistration>,
     request: OutgoingRequest,
     timeout: Instant,
+    proceeding_timeout: Duration,
     state: State,
+    failover: Option<Failover>,
 }
 
 #[derive(Debug)]
@@ -57,21 +59,8 @@ impl ClientInvTsx {
             request.line.method
         );
 
-        let mut request = endpoint.create_outgoing(request, target).await?;
-
-        let registration = TsxRegistration::create(endpoint, TsxKey::client(&Method::INVITE));
-
-        let via = registration.endpoint.create_via(
-            &request.parts.transport,
-            &registration.tsx_key,
-            target.via_host_port.clone(),
-        );
-
-        request.msg.headers.insert_named_front(&via);
-        registration
-            .endpoint
-            .send_outgoing_request(&mut request)
-            .await?;
+        let (registration, request, failover) =
+            failover::send_request(endpoint, request, target).await?;
 
         let timeout = Instant::now() + T1 * 64;
 
@@ -79,7 +68,9 @@ impl ClientInvTsx {
             registration: Some(registration),
             request,
             timeout,
+            proceeding_timeout: T1 * 240, // 2 minutes
             state: State::Init,
+            failover,
         })
     }
 
@@ -88,6 +79,26 @@ impl ClientInvTsx {
         &self.request
     }
 
+    /// Set how long to wait for a final response once a provisional response has been received. Every provisional
+    /// response restarts the timeout. Is 2 minutes by default
+    pub fn set_proceeding_timeout(&mut self, timeout: Duration) {
+        self.proceeding_timeout = timeout;
+    }
+
+    /// Send a CANCEL request for this INVITE transaction
+    ///
+    /// The CANCEL is sent using the same branch, transport and destination as the INVITE. It must only
+    /// be sent after receiving a provisional response (RFC3261 Section 9.1).
+    pub async fn cancel(&self) -> Result<ClientTsx> {
+        let Some(registration) = &self.registration else {
+            return Err(Error::RequestTimedOut);
+        };
+
+        let cancel = create_cancel(&self.request)?;
+
+        ClientTsx::send_cancel(registration.endpoint.clone(), &registration.tsx_key, cancel).await
+    }
+
     /// Receive one or more responses.
     ///
     /// The return type differs from [`ClientTsx::receive`](super::ClientTsx::receive)
@@ -96,8 +107,51 @@ impl ClientInvTsx {
     /// INVITE transaction terminated and will no longer be able to receive any responses.
     ///
     /// This behavior SHOULD only apply if an INVITE is sent outside a dialog.
+    ///
+    /// If the request-uri resolved to multiple servers, the INVITE is sent to the next server when the
+    /// current one doesn't respond, the transport fails or it responds with 503. The transport and
+    /// destination of [`ClientInvTsx::request`] are updated accordingly.
     #[tracing::instrument(name = "tsx_inv_receive", level = "debug", skip(self))]
     pub async fn receive(&mut self) -> Result<Option<TsxResponse>> {
+        loop {
+            let result = self.receive_current().await;
+
+            let should_failover = match &result {
+                Ok(Some(response)) => {
+                    matches!(self.state, State::Completed | State::Terminated)
+                        && Failover::on_response(response.line.code)
+                }
+                Ok(None) => false,
+                Err(e) => matches!(self.state, State::Init) && Failover::on_error(e),
+            };
+
+            if should_failover && self.failover().await {
+                continue;
+            }
+
+            return result;
+        }
+    }
+
+    /// Send the INVITE to the next server, returns false if there are none left
+    async fn failover(&mut self) -> bool {
+        let Some(failover) = &mut self.failover else {
+            return false;
+        };
+
+        let Some((registration, request)) = failover.next().await else {
+            return false;
+        };
+
+        self.registration = Some(registration);
+        self.request = request;
+        self.timeout = Instant::now() + T1 * 64;
+        self.state = State::Init;
+
+        true
+    }
+
+    async fn receive_current(&mut self) -> Result<Option<TsxResponse>> {
         let registration = match &mut self.registration {
             Some(registration) => registration,
             None => return Ok(None),
@@ -110,7 +164,7 @@ impl ClientInvTsx {
                 loop {
                     let receive = timeout(n, registration.receive_response());
 
-                    match timeout_at(self.timeout.into(), receive).await {
+                    match timeout_at(self.timeout, receive).await {
                         Ok(Ok(msg)) => return self.handle_msg(msg).await,
                         Ok(Err(_)) => {
                             // retransmit
@@ -126,13 +180,13 @@ impl ClientInvTsx {
                 }
             }
             State::Init | State::Proceeding => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => self.handle_msg(msg).await,
                     Err(_) => Err(Error::RequestTimedOut),
                 }
             }
             State::Accepted => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => Ok(Some(msg)),
                     Err(_) => {
                         self.state = State::Terminated;
@@ -147,7 +201,7 @@ impl ClientInvTsx {
     async fn handle_msg(&mut self, msg: TsxResponse) -> Result<Option<TsxResponse>> {
         match msg.line.code.kind() {
             CodeKind::Provisional => {
-                self.timeout = Instant::now() + T1 * 240; // 2 minutes
+                self.timeout = Instant::now() + self.proceeding_timeout;
                 self.state = State::Proceeding;
             }
             CodeKind::Success => {
@@ -172,10 +226,7 @@ impl ClientInvTsx {
                     tokio::spawn(async move {
                         let timeout = Instant::now() + Duration::from_secs(32);
 
-                        while timeout_at(timeout.into(), registration.receive())
-                            .await
-                            .is_ok()
-                        {
+                        while timeout_at(timeout, registration.receive()).await.is_ok() {
                             registration
                                 .endpoint
                                 .send_outgoing_request(&mut ack)
@@ -191,6 +242,53 @@ impl ClientInvTsx {
     }
 }
 
+fn create_cancel(request: &OutgoingRequest) -> Result<OutgoingRequest, HeaderError> {
+    let mut headers = Headers::with_capacity(6);
+
+    let via = request
+        .msg
+        .headers
+        .get_named::<Vec<Via>>()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| HeaderError::missing(Name::VIA))?;
+
+    headers.insert_named(&via);
+    request.msg.headers.clone_into(&mut headers, Name::FROM)?;
+    request.msg.headers.clone_into(&mut headers, Name::TO)?;
+    request
+        .msg
+        .headers
+        .clone_into(&mut headers, Name::CALL_ID)?;
+    headers.insert_named(&MaxForwards(70));
+
+    let cseq = request.msg.headers.get_named::<CSeq>()?;
+
+    headers.insert_named(&CSeq {
+        cseq: cseq.cseq,
+        method: Method::CANCEL,
+    });
+
+    // The route set must be the same as the INVITE's
+    let _ = request.msg.headers.clone_into(&mut headers, Name::ROUTE);
+
+    Ok(OutgoingRequest {
+        msg: Request {
+            line: RequestLine {
+                method: Method::CANCEL,
+                uri: request.msg.line.uri.clone(),
+            },
+            headers,
+            body: Bytes::new(),
+        },
+        parts: OutgoingParts {
+            transport: request.parts.transport.clone(),
+            destination: request.parts.destination,
+            buffer: Default::default(),
+        },
+    })
+}
+
 fn create_ack(
     request: &OutgoingRequest,
     response: &TsxResponse,
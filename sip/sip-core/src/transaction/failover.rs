@@ -0,0 +1,409 @@
+use super::TsxRegistration;
+use super::key::TsxKey;
+use crate::error::Error;
+use crate::transport::{
+    OutgoingParts, OutgoingRequest, ServerEntry, TargetTransportInfo, TpHandle,
+};
+use crate::{Endpoint, Request, Result};
+use sip_types::StatusCode;
+use sip_types::host::HostPort;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+/// Remaining servers of the request's target a client transaction may fail over to
+/// ([RFC3263 Section 4.3](https://datatracker.ietf.org/doc/html/rfc3263#section-4.3))
+#[derive(Debug)]
+pub(crate) struct Failover {
+    endpoint: Endpoint,
+    /// The request without a Via header, used to create a new transaction to the next server
+    request: Request,
+    via_host_port: Option<HostPort>,
+    /// Server the current transaction was sent to
+    current: ServerEntry,
+    targets: VecDeque<ServerEntry>,
+}
+
+impl Failover {
+    /// Returns if the transaction should fail over after receiving the given response code
+    pub(crate) fn on_response(code: StatusCode) -> bool {
+        code == StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    /// Returns if the transaction should fail over after the given error
+    pub(crate) fn on_error(error: &Error) -> bool {
+        matches!(error, Error::RequestTimedOut | Error::Io(_))
+    }
+
+    /// Blacklist the server of the current transaction and send the request in a new transaction to the next server.
+    ///
+    /// Returns `None` if there are no servers left.
+    pub(crate) async fn next(&mut self) -> Option<(TsxRegistration, OutgoingRequest)> {
+        let transports = self.endpoint.transports();
+
+        transports.blacklist(&self.current, None);
+
+        loop {
+            let failed = self.current.address;
+
+            let (transport, server) = transports
+                .select_target(&self.endpoint, &self.request.line.uri, &mut self.targets)
+                .await?;
+
+            self.current = server;
+
+            log::debug!("Failing over from {failed} to {}", server.address);
+
+            match send(
+                &self.endpoint,
+                self.request.clone(),
+                transport,
+                server.address,
+                self.via_host_port.clone(),
+            )
+            .await
+            {
+                Ok(sent) => return Some(sent),
+                Err(e) => {
+                    log::debug!("Failed to send request to {}, {e}", server.address);
+                    transports.blacklist(&server, None);
+                }
+            }
+        }
+    }
+}
+
+/// Send the request in a new client transaction.
+///
+/// If the target has no transport set, the request-uri is resolved to all its servers and the returned
+/// [`Failover`] contains the servers which can be tried if the transaction to the first one fails.
+pub(super) async fn send_request(
+    endpoint: Endpoint,
+    request: Request,
+    target: &mut TargetTransportInfo,
+) -> Result<(TsxRegistration, OutgoingRequest, Option<Failover>)> {
+    if let Some((transport, destination)) = &target.transport {
+        let (registration, request) = send(
+            &endpoint,
+            request,
+            transport.clone(),
+            *destination,
+            target.via_host_port.clone(),
+        )
+        .await?;
+
+        return Ok((registration, request, None));
+    }
+
+    let transports = endpoint.transports();
+
+    let mut targets = transports.resolve_targets(&request.line.uri).await?;
+
+    let (transport, server) = transports
+        .select_target(&endpoint, &request.line.uri, &mut targets)
+        .await
+        .ok_or_else(|| {
+            Error::from(std::io::Error::other(format!(
+                "Failed to select transport for {:?}",
+                request.line.uri
+            )))
+        })?;
+
+    let mut failover = if targets.is_empty() {
+        None
+    } else {
+        Some(Failover {
+            endpoint: endpoint.clone(),
+            request: request.clone(),
+            via_host_port: target.via_host_port.clone(),
+            current: server,
+            targets,
+        })
+    };
+
+    let sent = send(
+        &endpoint,
+        request,
+        transport,
+        server.address,
+        target.via_host_port.clone(),
+    )
+    .await;
+
+    let (registration, request) = match (sent, &mut failover) {
+        (Ok(sent), _) => sent,
+        (Err(e), Some(failover)) if Failover::on_error(&e) => {
+            log::debug!("Failed to send request to {}, {e}", server.address);
+
+            failover.next().await.ok_or(e)?
+        }
+        (Err(e), _) => return Err(e),
+    };
+
+    target.transport = Some((request.parts.transport.clone(), request.parts.destination));
+
+    Ok((registration, request, failover))
+}
+
+async fn send(
+    endpoint: &Endpoint,
+    request: Request,
+    transport: TpHandle,
+    destination: SocketAddr,
+    via_host_port: Option<HostPort>,
+) -> Result<(TsxRegistration, OutgoingRequest)> {
+    let registration =
+        TsxRegistration::create(endpoint.clone(), TsxKey::client(&request.line.method));
+
+    let via = endpoint.create_via(&transport, &registration.tsx_key, via_host_port);
+
+    let mut request = OutgoingRequest {
+        msg: request,
+        parts: OutgoingParts {
+            transport,
+            destination,
+            buffer: Default::default(),
+        },
+    };
+
+    request.msg.headers.insert_named_front(&via);
+    endpoint.send_outgoing_request(&mut request).await?;
+
+    Ok((registration, request))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::transport::udp::Udp;
+    use crate::transport::{DnsOverrides, TargetTransportInfo};
+    use crate::{Endpoint, Request};
+    use hickory_resolver::TokioResolver;
+    use hickory_resolver::config::ResolverConfig;
+    use hickory_resolver::name_server::TokioConnectionProvider;
+    use sip_types::header::typed::{CSeq, CallID, FromTo};
+    use sip_types::uri::{NameAddr, SipUri};
+    use sip_types::{CodeKind, Method, Name, StatusCode};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+    use tokio::sync::mpsc;
+
+    /// Server answering every request except ACK with `code`, or never answering if it's `None`.
+    ///
+    /// Sends the method of every received request to the returned receiver.
+    async fn server(code: Option<u16>) -> (SocketAddr, mpsc::UnboundedReceiver<String>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut buffer = vec![0; 65535];
+
+            loop {
+                let (len, source) = socket.recv_from(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..len]).into_owned();
+
+                let method = request.split(' ').next().unwrap().to_owned();
+
+                if tx.send(method.clone()).is_err() {
+                    return;
+                }
+
+                if let Some(code) = code
+                    && method != "ACK"
+                {
+                    socket
+                        .send_to(response(&request, code).as_bytes(), source)
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        (addr, rx)
+    }
+
+    fn response(request: &str, code: u16) -> String {
+        let mut response = format!("SIP/2.0 {code} Test\r\n");
+
+        for line in request.lines() {
+            if line.starts_with("To:") {
+                response += &format!("{line};tag=server\r\n");
+            } else if ["Via:", "From:", "Call-ID:", "CSeq:"]
+                .iter()
+                .any(|name| line.starts_with(name))
+            {
+                response += &format!("{line}\r\n");
+            }
+        }
+
+        response + "Content-Length: 0\r\n\r\n"
+    }
+
+    /// Endpoint resolving `sip:example.com` to the primary and backup server
+    async fn endpoint(primary: SocketAddr, backup: SocketAddr) -> Endpoint {
+        let mut overrides = DnsOverrides::new();
+
+        overrides
+            .add_srv(
+                "_sip._udp.example.com",
+                10,
+                0,
+                primary.port(),
+                "primary.example.com.",
+            )
+            .add_srv(
+                "_sip._udp.example.com",
+                20,
+                0,
+                backup.port(),
+                "backup.example.com.",
+            )
+            .add_ip("primary.example.com", primary.ip())
+            .add_ip("backup.example.com", backup.ip());
+
+        let mut builder = Endpoint::builder();
+
+        // Resolver without any name servers, every query not answered by the overrides fails
+        builder.set_dns_resolver(
+            TokioResolver::builder_with_config(
+                ResolverConfig::new(),
+                TokioConnectionProvider::default(),
+            )
+            .build(),
+        );
+        builder.set_dns_overrides(overrides);
+
+        Udp::spawn(&mut builder, "127.0.0.1:0").await.unwrap();
+
+        builder.build()
+    }
+
+    fn uri() -> SipUri {
+        "sip:example.com".parse().unwrap()
+    }
+
+    fn request(method: Method) -> Request {
+        let uri = uri();
+
+        let mut request = Request::new(method.clone(), uri.clone());
+
+        request.headers.insert_type(
+            Name::FROM,
+            &FromTo::new(NameAddr::uri(uri.clone()), Some("client".into())),
+        );
+        request
+            .headers
+            .insert_type(Name::TO, &FromTo::new(NameAddr::uri(uri), None));
+        request
+            .headers
+            .insert_named(&CallID::new(format!("failover-{method}")));
+        request.headers.insert_named(&CSeq::new(1, method));
+
+        request
+    }
+
+    async fn send_request(endpoint: &Endpoint) -> StatusCode {
+        let mut transaction = endpoint
+            .send_request(
+                request(Method::OPTIONS),
+                &mut TargetTransportInfo::default(),
+            )
+            .await
+            .unwrap();
+
+        transaction.receive_final().await.unwrap().line.code
+    }
+
+    async fn send_invite(endpoint: &Endpoint) -> StatusCode {
+        let mut transaction = endpoint
+            .send_invite(request(Method::INVITE), &mut TargetTransportInfo::default())
+            .await
+            .unwrap();
+
+        loop {
+            let response = transaction.receive().await.unwrap().unwrap();
+
+            if response.line.code.kind() != CodeKind::Provisional {
+                return response.line.code;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_fails_over_on_503() {
+        let (primary, mut primary_requests) = server(Some(503)).await;
+        let (backup, mut backup_requests) = server(Some(200)).await;
+
+        let endpoint = endpoint(primary, backup).await;
+
+        assert_eq!(send_request(&endpoint).await, StatusCode::OK);
+
+        assert_eq!(primary_requests.recv().await.unwrap(), "OPTIONS");
+        assert_eq!(backup_requests.recv().await.unwrap(), "OPTIONS");
+
+        // The blacklisted primary server is tried last by the next request
+        assert_eq!(send_request(&endpoint).await, StatusCode::OK);
+
+        assert_eq!(backup_requests.recv().await.unwrap(), "OPTIONS");
+        assert!(primary_requests.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_fails_over_on_timeout() {
+        let (primary, mut primary_requests) = server(None).await;
+        let (backup, mut backup_requests) = server(Some(200)).await;
+
+        let endpoint = endpoint(primary, backup).await;
+
+        assert_eq!(send_request(&endpoint).await, StatusCode::OK);
+
+        assert_eq!(primary_requests.recv().await.unwrap(), "OPTIONS");
+        assert_eq!(backup_requests.recv().await.unwrap(), "OPTIONS");
+    }
+
+    #[tokio::test]
+    async fn invite_fails_over_on_503() {
+        let (primary, mut primary_requests) = server(Some(503)).await;
+        let (backup, mut backup_requests) = server(Some(486)).await;
+
+        let endpoint = endpoint(primary, backup).await;
+
+        assert_eq!(send_invite(&endpoint).await, StatusCode::BUSY_HERE);
+
+        assert_eq!(primary_requests.recv().await.unwrap(), "INVITE");
+        assert_eq!(primary_requests.recv().await.unwrap(), "ACK");
+        assert_eq!(backup_requests.recv().await.unwrap(), "INVITE");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn invite_fails_over_on_timeout() {
+        let (primary, mut primary_requests) = server(None).await;
+        let (backup, mut backup_requests) = server(Some(486)).await;
+
+        let endpoint = endpoint(primary, backup).await;
+
+        assert_eq!(send_invite(&endpoint).await, StatusCode::BUSY_HERE);
+
+        assert_eq!(primary_requests.recv().await.unwrap(), "INVITE");
+        assert_eq!(backup_requests.recv().await.unwrap(), "INVITE");
+    }
+
+    #[tokio::test]
+    async fn blacklist_ignores_shorter_retry_after() {
+        let (primary, _) = server(None).await;
+        let (backup, _) = server(None).await;
+
+        let endpoint = endpoint(primary, backup).await;
+
+        let servers = endpoint.resolve_servers(&uri()).await.unwrap();
+
+        endpoint.blacklist_server(&servers[0], Some(Duration::ZERO));
+
+        assert!(endpoint.is_server_blacklisted(&servers[0]));
+        assert!(!endpoint.is_server_blacklisted(&servers[1]));
+
+        // Only the server with the same transport and address is blacklisted
+        assert!(!endpoint.is_server_blacklisted(&primary.into()));
+    }
+}
@@ -97,6 +97,16 @@ impl TsxKey {
         }))
     }
 
+    /// Create a client key for a request which belongs to the same branch as `self` (e.g. CANCEL)
+    #[inline]
+    pub(crate) fn with_method(&self, method: &Method) -> Self {
+        TsxKey(Repr::RFC3261(Rfc3261 {
+            role: Role::Client,
+            branch: self.branch().clone(),
+            method: filter_method(method),
+        }))
+    }
+
     #[inline]
     pub fn branch(&self) -> &BytesStr {
         match &self.0 {
@@ -12,6 +12,7 @@ use tokio::sync::mpsc;
 
 mod client;
 mod client_inv;
+mod failover;
 mod key;
 mod registration;
 mod server;
@@ -1,6 +1,6 @@
 use self::managed::{DropNotifier, ManagedTransportState, MangedTransport, RefOwner, WeakRefOwner};
-use self::resolver::ServerEntry;
 use self::stun_user::StunUser;
+use crate::capture::Capture;
 use crate::{Endpoint, Request, Response, Result};
 use bytes::Bytes;
 use parking_lot::Mutex;
@@ -9,13 +9,13 @@ use sip_types::host::{Host, HostPort};
 use sip_types::msg::MessageLine;
 use sip_types::print::AppendCtx;
 use sip_types::uri::SipUri;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Display};
 use std::mem::take;
 use std::net::SocketAddr;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 use std::{fmt, io};
 use stun::StunEndpoint;
 use stun_types::Message;
@@ -33,13 +33,17 @@ pub mod native_tls;
 pub mod rustls;
 pub mod tcp;
 pub mod udp;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use resolver::{DnsOverrides, ServerEntry};
 
 /// Abstraction over a transport factory.
 ///
 /// It is used to created connection oriented transports
 #[async_trait::async_trait]
 pub trait Factory: Send + Sync + 'static {
-    /// Must return the name of the transport this factory produces. (e.g. UDP, TCP, TLS ...)
+    /// Must return the name of the transport this factory produces. (e.g. UDP, TCP, TLS, WS ...)
     fn name(&self) -> &'static str;
 
     /// Checks if the factory is eligible for the transport specified inside an uri.
@@ -94,6 +98,13 @@ pub trait Transport: Debug + Display + Send + Sync + 'static {
     ///
     /// Connection oriented transports may discard the `target` parameter.
     async fn send(&self, message: &[u8], target: SocketAddr) -> io::Result<()>;
+
+    /// Send a keep-alive ping to `target`, which must be answered with a pong (RFC5626 Section 4.4)
+    ///
+    /// Sends a double-CRLF by default, transports with their own ping mechanism (e.g. WebSocket) override this.
+    async fn send_keep_alive(&self, target: SocketAddr) -> io::Result<()> {
+        self.send(b"\r\n\r\n", target).await
+    }
 }
 
 /// Wrapper over implementations of [`Transport`].
@@ -294,15 +305,29 @@ impl TpKey {
     }
 }
 
+/// Time to wait for the response to a double-CRLF keep-alive request
+const KEEP_ALIVE_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time a server which failed is tried last when selecting targets
+const DEFAULT_BLACKLIST_DURATION: Duration = Duration::from_secs(60);
+
 pub(crate) struct Transports {
     unmanaged: Box<[TpHandle]>,
     factories: Box<[Arc<dyn Factory>]>,
 
     transports: Mutex<HashMap<TpKey, MangedTransport>>,
 
+    /// Pending keep-alive requests waiting for a pong on a transport
+    keep_alive_waiters: Mutex<HashMap<TpKey, Vec<oneshot::Sender<()>>>>,
+
     stun: StunEndpoint<StunUser>,
 
-    dns_resolver: hickory_resolver::TokioResolver,
+    resolver: resolver::Resolver,
+
+    /// Servers which recently failed, keyed by their transport and address, mapped to the instant they are
+    /// no longer blacklisted
+    blacklist: Mutex<HashMap<(Option<&'static str>, SocketAddr), Instant>>,
+    blacklist_duration: Duration,
 }
 
 impl Transports {
@@ -310,11 +335,12 @@ impl Transports {
         match host {
             Host::IP6(ip) => Ok(vec![ServerEntry::from((*ip, port))]),
             Host::IP4(ip) => Ok(vec![ServerEntry::from((*ip, port))]),
-            Host::Name(name) => resolver::resolve_host(&self.dns_resolver, name, port).await,
+            Host::Name(name) => resolver::resolve_host(&self.resolver, name, port).await,
         }
     }
 
-    async fn resolve_uri(&self, uri: &SipUri) -> io::Result<Vec<ServerEntry>> {
+    /// Resolve the uri to all servers it may be reached at, ordered by preference
+    pub(crate) async fn resolve_uri(&self, uri: &SipUri) -> io::Result<Vec<ServerEntry>> {
         let port = match uri.host_port.port {
             Some(port) => port,
             None if uri.sips => 5061,
@@ -324,6 +350,19 @@ impl Transports {
         self.resolve_host_port(&uri.host_port.host, port).await
     }
 
+    /// Resolve the uri to all servers it may be reached at, blacklisted servers are moved to the end of the list
+    pub(crate) async fn resolve_targets(&self, uri: &SipUri) -> io::Result<VecDeque<ServerEntry>> {
+        let servers = self.resolve_uri(uri).await?;
+
+        let (mut targets, blacklisted): (VecDeque<_>, VecDeque<_>) = servers
+            .into_iter()
+            .partition(|server| !self.is_blacklisted(server));
+
+        targets.extend(blacklisted);
+
+        Ok(targets)
+    }
+
     /// Will try to find or create a suitable transport the given Uri
     #[tracing::instrument(name = "select_transport", level = "trace", skip(self, endpoint))]
     pub(crate) async fn select(
@@ -334,29 +373,80 @@ impl Transports {
         log::trace!("select transport for {:?}", uri);
 
         // Resolve host_port to possible remote addresses
-        let servers = self.resolve_uri(uri).await?;
-
-        for server in servers {
-            // Search unmanaged ones (connectionless, e.g. udp)
-            if let Some(transport) = self.find_matching_unmanaged_transport(uri, &server) {
-                log::trace!("selected connectionless: {transport}");
+        let mut targets = self.resolve_targets(uri).await?;
+
+        self.select_target(endpoint, uri, &mut targets)
+            .await
+            .map(|(transport, server)| (transport, server.address))
+            .ok_or_else(|| {
+                io::Error::other(format!("Failed to select transport for {uri:?}")).into()
+            })
+    }
 
-                return Ok((transport.clone(), server.address));
+    /// Take servers from the front of `targets` until a transport to one of them could be found or created
+    pub(crate) async fn select_target(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+        targets: &mut VecDeque<ServerEntry>,
+    ) -> Option<(TpHandle, ServerEntry)> {
+        while let Some(server) = targets.pop_front() {
+            if let Some(transport) = self.select_for_server(endpoint, uri, &server).await {
+                return Some((transport, server));
             }
+        }
 
-            // Search managed idling transports (connections, e.g. tcp / tls)
-            if let Some(found) = self.find_matching_idling_transport(uri, &server) {
-                return Ok((found, server.address));
-            }
+        None
+    }
 
-            // No existing transport found, try and connect a new one
+    /// Blacklist the server after it failed, so it's only used after all other servers of a target have failed
+    ///
+    /// The server is blacklisted for the configured duration, or `retry_after` if it is longer.
+    pub(crate) fn blacklist(&self, server: &ServerEntry, retry_after: Option<Duration>) {
+        log::debug!("Blacklisting server {}", server.address);
 
-            if let Some(found) = self.connect(endpoint, uri, &server).await {
-                return Ok((found, server.address));
-            }
+        let duration = retry_after.map_or(self.blacklist_duration, |retry_after| {
+            retry_after.max(self.blacklist_duration)
+        });
+
+        let mut blacklist = self.blacklist.lock();
+        let until = blacklist
+            .entry((server.transport(), server.address))
+            .or_insert_with(Instant::now);
+
+        *until = (*until).max(Instant::now() + duration);
+    }
+
+    pub(crate) fn is_blacklisted(&self, server: &ServerEntry) -> bool {
+        let mut blacklist = self.blacklist.lock();
+
+        let now = Instant::now();
+        blacklist.retain(|_, until| *until > now);
+
+        blacklist.contains_key(&(server.transport(), server.address))
+    }
+
+    /// Find or create a suitable transport to reach the given server of the uri
+    pub(crate) async fn select_for_server(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+        server: &ServerEntry,
+    ) -> Option<TpHandle> {
+        // Search unmanaged ones (connectionless, e.g. udp)
+        if let Some(transport) = self.find_matching_unmanaged_transport(uri, server) {
+            log::trace!("selected connectionless: {transport}");
+
+            return Some(transport.clone());
         }
 
-        Err(io::Error::other(format!("Failed to select transport for {uri:?}")).into())
+        // Search managed idling transports (connections, e.g. tcp / tls)
+        if let Some(found) = self.find_matching_idling_transport(uri, server) {
+            return Some(found);
+        }
+
+        // No existing transport found, try and connect a new one
+        self.connect(endpoint, uri, server).await
     }
 
     fn find_matching_unmanaged_transport(
@@ -442,6 +532,8 @@ impl Transports {
         uri: &SipUri,
         server: &ServerEntry,
     ) -> Option<TpHandle> {
+        let mut failed = false;
+
         // Try to build new transport with a factory
         for factory in self.factories.iter() {
             if let Some(transport) = server.transport
@@ -473,10 +565,16 @@ impl Transports {
                         server.address,
                         factory.name()
                     );
+
+                    failed = true;
                 }
             }
         }
 
+        if failed {
+            self.blacklist(server, None);
+        }
+
         None
     }
 
@@ -561,6 +659,71 @@ impl Transports {
         log::trace!("drop transport {tp_key:?}");
 
         self.transports.lock().remove(tp_key);
+
+        // Wake up all waiting keep-alive requests, they will never receive a response
+        self.keep_alive_waiters.lock().remove(tp_key);
+    }
+
+    /// Send a keep-alive ping over the transport and wait for the pong
+    pub(crate) async fn send_keep_alive(
+        &self,
+        transport: &TpHandle,
+        target: SocketAddr,
+    ) -> io::Result<()> {
+        let tp_key = transport.key();
+        let (tx, rx) = oneshot::channel();
+
+        self.keep_alive_waiters
+            .lock()
+            .entry(tp_key)
+            .or_default()
+            .push(tx);
+
+        if let Err(e) = transport.send_keep_alive(target).await {
+            drop(rx);
+            self.remove_closed_keep_alive_waiters(&tp_key);
+            return Err(e);
+        }
+
+        // RFC5626 Section 4.4.1: the pong must be received within 10 seconds
+        match tokio::time::timeout(KEEP_ALIVE_PONG_TIMEOUT, rx).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "transport closed while waiting for keep-alive response",
+            )),
+            Err(_) => {
+                // The receiver has been dropped with the timeout, other waiters of the transport remain
+                self.remove_closed_keep_alive_waiters(&tp_key);
+
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "keep-alive response timed out",
+                ))
+            }
+        }
+    }
+
+    /// Remove all keep-alive waiters of the transport which are no longer waiting for a response
+    fn remove_closed_keep_alive_waiters(&self, tp_key: &TpKey) {
+        let mut keep_alive_waiters = self.keep_alive_waiters.lock();
+
+        if let Some(waiters) = keep_alive_waiters.get_mut(tp_key) {
+            waiters.retain(|waiter| !waiter.is_closed());
+
+            if waiters.is_empty() {
+                keep_alive_waiters.remove(tp_key);
+            }
+        }
+    }
+
+    /// Called by transports when a keep-alive pong was received
+    pub(crate) fn receive_keep_alive_response(&self, tp_key: &TpKey) {
+        if let Some(waiters) = self.keep_alive_waiters.lock().remove(tp_key) {
+            for waiter in waiters {
+                let _ = waiter.send(());
+            }
+        }
     }
 
     pub(crate) async fn receive_stun(
@@ -578,6 +741,8 @@ pub(crate) struct TransportsBuilder {
     unmanaged: Vec<TpHandle>,
     factories: Vec<Arc<dyn Factory>>,
     dns_resolver: Option<hickory_resolver::TokioResolver>,
+    dns_overrides: DnsOverrides,
+    blacklist_duration: Option<Duration>,
 }
 
 impl TransportsBuilder {
@@ -595,7 +760,15 @@ impl TransportsBuilder {
         self.dns_resolver = Some(dns_resolver);
     }
 
-    pub(crate) fn build(&mut self) -> Transports {
+    pub(crate) fn set_dns_overrides(&mut self, dns_overrides: DnsOverrides) {
+        self.dns_overrides = dns_overrides;
+    }
+
+    pub(crate) fn set_blacklist_duration(&mut self, duration: Duration) {
+        self.blacklist_duration = Some(duration);
+    }
+
+    pub(crate) fn build(&mut self, capture: Arc<Capture>) -> Transports {
         let dns_resolver = self.dns_resolver.take().unwrap_or_else(|| {
             hickory_resolver::TokioResolver::builder_tokio()
                 .expect("Failed to create default system DNS resolver")
@@ -605,9 +778,14 @@ impl TransportsBuilder {
         Transports {
             unmanaged: take(&mut self.unmanaged).into_boxed_slice(),
             factories: take(&mut self.factories).into_boxed_slice(),
-            stun: StunEndpoint::new(StunUser),
+            stun: StunEndpoint::new(StunUser { capture }),
             transports: Default::default(),
-            dns_resolver,
+            keep_alive_waiters: Default::default(),
+            resolver: resolver::Resolver::new(dns_resolver, take(&mut self.dns_overrides)),
+            blacklist: Default::default(),
+            blacklist_duration: self
+                .blacklist_duration
+                .unwrap_or(DEFAULT_BLACKLIST_DURATION),
         }
     }
 }
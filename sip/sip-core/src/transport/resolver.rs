@@ -1,17 +1,42 @@
-use hickory_resolver::ResolveError;
-use hickory_resolver::proto::rr::rdata::{NAPTR, SRV};
-use hickory_resolver::proto::rr::{RData, RecordType};
-use hickory_resolver::{Name, TokioResolver};
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::proto::ProtoErrorKind;
+use hickory_resolver::proto::op::Query;
+use hickory_resolver::proto::rr::rdata::{A, AAAA, NAPTR, SRV};
+use hickory_resolver::proto::rr::{RData, Record, RecordType};
+use hickory_resolver::{Name, ResolveError, TokioResolver};
 use multimap::MultiMap;
+use parking_lot::Mutex;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Copy)]
-pub(super) struct ServerEntry {
-    pub(super) address: SocketAddr,
+/// Time a missing record is cached for if the DNS response didn't specify a negative TTL
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+/// Upper bound of the time a missing record is cached for
+const MAX_NEGATIVE_TTL: Duration = Duration::from_secs(300);
+
+/// TTL of records created from [`DnsOverrides`]
+const OVERRIDE_TTL: u32 = 3600;
+
+/// Server resolved from an URI ([RFC3263](https://datatracker.ietf.org/doc/html/rfc3263))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerEntry {
+    /// Address of the server
+    pub address: SocketAddr,
     pub(super) transport: Option<Transport>,
 }
 
+impl ServerEntry {
+    /// Name of the transport to use for this server, if it was specified by a NAPTR or SRV record
+    pub fn transport(&self) -> Option<&'static str> {
+        self.transport.map(|transport| transport.as_str())
+    }
+}
+
 impl<S> From<S> for ServerEntry
 where
     SocketAddr: From<S>,
@@ -24,7 +49,7 @@ where
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(super) enum Transport {
     /// SIP+D2U
     Udp,
@@ -34,6 +59,10 @@ pub(super) enum Transport {
     TlsOverTcp,
     /// SIP+D2S
     Sctp,
+    /// SIP+D2W
+    Ws,
+    /// SIPS+D2W
+    Wss,
 }
 
 impl Transport {
@@ -43,6 +72,8 @@ impl Transport {
             Transport::Tcp => "TCP",
             Transport::TlsOverTcp => "TLS",
             Transport::Sctp => "SCTP",
+            Transport::Ws => "WS",
+            Transport::Wss => "WSS",
         }
     }
 
@@ -52,6 +83,8 @@ impl Transport {
             b"SIP+D2T" => Some(Self::Tcp),
             b"SIPS+D2T" => Some(Self::TlsOverTcp),
             b"SIP+D2S" => Some(Self::Sctp),
+            b"SIP+D2W" => Some(Self::Ws),
+            b"SIPS+D2W" => Some(Self::Wss),
             _ => None,
         }
     }
@@ -62,13 +95,246 @@ impl Transport {
             Transport::Tcp => 5060,
             Transport::TlsOverTcp => 5061,
             Transport::Sctp => 5060,
+            Transport::Ws => 80,
+            Transport::Wss => 443,
         }
     }
 }
 
-#[tracing::instrument(err, skip(dns_resolver, uri_port))]
+/// Static DNS records used instead of querying DNS, similar to a hosts file
+///
+/// Names which have records in the overrides are never looked up using DNS, record types which weren't
+/// added for them resolve to no records. This includes the domain of every SRV name (e.g. `example.com` for
+/// `_sip._udp.example.com`) and all other SRV names of that domain. This allows resolving SIP URIs without
+/// any DNS server, e.g. to test failover between multiple local servers.
+#[derive(Debug, Default, Clone)]
+pub struct DnsOverrides {
+    srv: HashMap<String, Vec<SRV>>,
+    ip: HashMap<String, Vec<IpAddr>>,
+
+    /// All names and domains that are answered from the overrides
+    covered: HashSet<String>,
+}
+
+impl DnsOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an A or AAAA record for `name`
+    pub fn add_ip(&mut self, name: &str, ip: IpAddr) -> &mut Self {
+        let name = normalize(name);
+
+        self.ip.entry(name.clone()).or_default().push(ip);
+        self.covered.insert(name);
+        self
+    }
+
+    /// Add an SRV record for `name` (e.g. `_sip._udp.example.com`) pointing to `target`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` is not a valid domain name
+    pub fn add_srv(
+        &mut self,
+        name: &str,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &str,
+    ) -> &mut Self {
+        let target = Name::from_utf8(target).expect("SRV target must be a valid domain name");
+        let name = normalize(name);
+
+        if let Some(domain) = srv_domain(&name) {
+            self.covered.insert(domain.to_owned());
+        }
+
+        self.srv
+            .entry(name.clone())
+            .or_default()
+            .push(SRV::new(priority, weight, port, target));
+        self.covered.insert(name);
+        self
+    }
+
+    /// Returns the overridden records if the name is covered by the overrides
+    fn lookup(&self, name: &Name, query: QueryType) -> Option<Option<Lookup>> {
+        let normalized = normalize(&name.to_utf8());
+
+        let is_covered = self.covered.contains(&normalized)
+            || srv_domain(&normalized).is_some_and(|domain| self.covered.contains(domain));
+
+        if !is_covered {
+            return None;
+        }
+
+        let records: Vec<Record> = match query {
+            QueryType::Naptr => vec![],
+            QueryType::Srv => self
+                .srv
+                .get(&normalized)
+                .into_iter()
+                .flatten()
+                .map(|srv| Record::from_rdata(name.clone(), OVERRIDE_TTL, RData::SRV(srv.clone())))
+                .collect(),
+            QueryType::Ip => self
+                .ip
+                .get(&normalized)
+                .into_iter()
+                .flatten()
+                .map(|ip| {
+                    let rdata = match ip {
+                        IpAddr::V4(ip) => RData::A(A(*ip)),
+                        IpAddr::V6(ip) => RData::AAAA(AAAA(*ip)),
+                    };
+
+                    Record::from_rdata(name.clone(), OVERRIDE_TTL, rdata)
+                })
+                .collect(),
+        };
+
+        if records.is_empty() {
+            return Some(None);
+        }
+
+        let lookup = Lookup::new_with_max_ttl(
+            Query::query(name.clone(), query.record_type()),
+            Arc::from(records),
+        );
+
+        Some(Some(lookup))
+    }
+}
+
+/// Lowercase the name and remove the trailing dot of fully qualified names
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Returns the domain of an SRV name (`_service._proto.domain`)
+fn srv_domain(name: &str) -> Option<&str> {
+    let mut labels = name.splitn(3, '.');
+
+    let service = labels.next()?;
+    let proto = labels.next()?;
+    let domain = labels.next()?;
+
+    (service.starts_with('_') && proto.starts_with('_')).then_some(domain)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum QueryType {
+    Naptr,
+    Srv,
+    /// A and/or AAAA records, using the lookup strategy of the DNS resolver
+    Ip,
+}
+
+impl QueryType {
+    fn record_type(self) -> RecordType {
+        match self {
+            QueryType::Naptr => RecordType::NAPTR,
+            QueryType::Srv => RecordType::SRV,
+            QueryType::Ip => RecordType::A,
+        }
+    }
+}
+
+/// DNS resolver which caches the results of lookups and answers names covered by [`DnsOverrides`]
+pub(super) struct Resolver {
+    dns_resolver: TokioResolver,
+    overrides: DnsOverrides,
+
+    /// Results of previous lookups, `None` if no records exist
+    cache: Mutex<HashMap<(Name, QueryType), CacheEntry>>,
+}
+
+struct CacheEntry {
+    lookup: Option<Lookup>,
+    valid_until: Instant,
+}
+
+impl Resolver {
+    pub(super) fn new(dns_resolver: TokioResolver, overrides: DnsOverrides) -> Self {
+        Self {
+            dns_resolver,
+            overrides,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lookup records of the given type, returns `None` if none exist
+    async fn lookup(&self, name: Name, query: QueryType) -> Result<Option<Lookup>, ResolveError> {
+        if let Some(lookup) = self.overrides.lookup(&name, query) {
+            return Ok(lookup);
+        }
+
+        let now = Instant::now();
+
+        if let Some(entry) = self.cache.lock().get(&(name.clone(), query))
+            && entry.valid_until > now
+        {
+            log::trace!("Using cached {query:?} lookup for \"{name}\"");
+            return Ok(entry.lookup.clone());
+        }
+
+        let result = match query {
+            QueryType::Naptr | QueryType::Srv => {
+                self.dns_resolver
+                    .lookup(name.clone(), query.record_type())
+                    .await
+            }
+            QueryType::Ip => self
+                .dns_resolver
+                .lookup_ip(name.clone())
+                .await
+                .map(Lookup::from),
+        };
+
+        let entry = match result {
+            Ok(lookup) => CacheEntry {
+                valid_until: lookup.valid_until(),
+                lookup: Some(lookup),
+            },
+            Err(e) => {
+                let Some(negative_ttl) = negative_ttl(&e) else {
+                    return Err(e);
+                };
+
+                CacheEntry {
+                    lookup: None,
+                    valid_until: now + negative_ttl,
+                }
+            }
+        };
+
+        let lookup = entry.lookup.clone();
+
+        let mut cache = self.cache.lock();
+        cache.retain(|_, entry| entry.valid_until > now);
+        cache.insert((name, query), entry);
+
+        Ok(lookup)
+    }
+}
+
+/// Returns the time to cache the absence of records for, if the error reports that no records exist
+fn negative_ttl(e: &ResolveError) -> Option<Duration> {
+    match e.proto()?.kind() {
+        ProtoErrorKind::NoRecordsFound { negative_ttl, .. } => Some(
+            negative_ttl
+                .map(|ttl| Duration::from_secs(ttl.into()))
+                .unwrap_or(DEFAULT_NEGATIVE_TTL)
+                .min(MAX_NEGATIVE_TTL),
+        ),
+        _ => None,
+    }
+}
+
+#[tracing::instrument(err, skip(resolver, uri_port))]
 pub(super) async fn resolve_host(
-    dns_resolver: &TokioResolver,
+    resolver: &Resolver,
     name: &str,
     uri_port: u16,
 ) -> io::Result<Vec<ServerEntry>> {
@@ -79,7 +345,7 @@ pub(super) async fn resolve_host(
     let mut entries: Vec<ServerEntry> = vec![];
 
     // First find NAPTR DNS records
-    resolve_naptr_records(dns_resolver, name.clone(), &mut entries).await?;
+    resolve_naptr_records(resolver, name.clone(), &mut entries).await?;
 
     // If there are none, look for SRV entries directly
     if entries.is_empty() {
@@ -93,13 +359,13 @@ pub(super) async fn resolve_host(
         ];
 
         for (name, transport) in records {
-            resolve_srv_records(dns_resolver, name, Some(transport), &mut entries).await?;
+            resolve_srv_records(resolver, name, Some(transport), &mut entries).await?;
         }
     }
 
     // Neither NAPTR nor SRV entries exist - just resolve A/AAAA records
     if entries.is_empty() {
-        resolve_a_records(dns_resolver, name.clone(), None, uri_port, &mut entries).await?;
+        resolve_a_records(resolver, name.clone(), None, uri_port, &mut entries).await?;
     }
 
     if entries.is_empty() {
@@ -112,16 +378,14 @@ pub(super) async fn resolve_host(
 }
 
 async fn resolve_naptr_records(
-    dns_resolver: &TokioResolver,
+    resolver: &Resolver,
     name: Name,
     entries: &mut Vec<ServerEntry>,
 ) -> Result<(), ResolveError> {
     log::debug!("Resolving NAPTR records for \"{name}\"");
 
     // Fetch records
-    let Some(lookup) =
-        filter_no_records(dns_resolver.lookup(name.clone(), RecordType::NAPTR).await)?
-    else {
+    let Some(lookup) = resolver.lookup(name.clone(), QueryType::Naptr).await? else {
         log::debug!("No NAPTR records exist for \"{name}\"");
         return Ok(());
     };
@@ -155,7 +419,7 @@ async fn resolve_naptr_records(
         match record.flags() {
             b"s" => {
                 resolve_srv_records(
-                    dns_resolver,
+                    resolver,
                     record.replacement().clone(),
                     Some(transport),
                     entries,
@@ -164,7 +428,7 @@ async fn resolve_naptr_records(
             }
             b"a" => {
                 resolve_a_records(
-                    dns_resolver,
+                    resolver,
                     record.replacement().clone(),
                     Some(transport),
                     transport.default_port(),
@@ -191,28 +455,28 @@ async fn resolve_naptr_records(
 }
 
 async fn resolve_srv_records(
-    dns_resolver: &TokioResolver,
+    resolver: &Resolver,
     name: Name,
     transport: Option<Transport>,
     entries: &mut Vec<ServerEntry>,
 ) -> Result<(), ResolveError> {
     log::debug!("Resolving SRV records for \"{name}\"");
 
-    let Some(lookup) = filter_no_records(dns_resolver.lookup(name.clone(), RecordType::SRV).await)?
-    else {
+    let Some(lookup) = resolver.lookup(name.clone(), QueryType::Srv).await? else {
         log::debug!("No SRV records exist for \"{name}\"");
         return Ok(());
     };
 
-    // Order SRV records by priority
-    let mut srv_records: Vec<&SRV> = lookup
+    // Order SRV records by priority and weight
+    let srv_records: Vec<&SRV> = lookup
         .record_iter()
         .filter_map(|record| match record.data() {
             RData::SRV(srv) => Some(srv),
             _ => None,
         })
         .collect();
-    srv_records.sort_unstable_by_key(|srv| srv.priority());
+    let srv_records =
+        order_by_priority_and_weight(srv_records, |srv| (srv.priority(), srv.weight()));
 
     log::debug!("Got {} SRV records for \"{name}\"", srv_records.len());
 
@@ -236,7 +500,7 @@ async fn resolve_srv_records(
                 transport,
             }));
         } else {
-            resolve_a_records(dns_resolver, target.clone(), transport, port, entries).await?;
+            resolve_a_records(resolver, target.clone(), transport, port, entries).await?;
         };
     }
 
@@ -244,7 +508,7 @@ async fn resolve_srv_records(
 }
 
 async fn resolve_a_records(
-    dns_resolver: &TokioResolver,
+    resolver: &Resolver,
     name: Name,
     transport: Option<Transport>,
     port: u16,
@@ -252,29 +516,307 @@ async fn resolve_a_records(
 ) -> Result<(), ResolveError> {
     log::debug!("Resolving A/AAAA records for \"{name}\"");
 
-    let Some(lookup) = filter_no_records(dns_resolver.lookup_ip(name.clone()).await)? else {
+    let Some(lookup) = resolver.lookup(name.clone(), QueryType::Ip).await? else {
         log::debug!("No A/AAAA records exist for \"{name}\"");
         return Ok(());
     };
 
     log::debug!(
         "Got {} A/AAAA records for \"{name}\"",
-        lookup.as_lookup().records().len()
+        lookup.records().len()
     );
 
-    entries.extend(lookup.iter().map(|ip| ServerEntry {
-        address: SocketAddr::new(ip, port),
-        transport,
+    entries.extend(lookup.iter().filter_map(|rdata| {
+        let ip = match rdata {
+            RData::A(a) => IpAddr::from(a.0),
+            RData::AAAA(aaaa) => IpAddr::from(aaaa.0),
+            _ => return None,
+        };
+
+        Some(ServerEntry {
+            address: SocketAddr::new(ip, port),
+            transport,
+        })
     }));
 
     Ok(())
 }
 
-/// Filter out errors where no records for a given name weren't found and instead return an Ok(None)
-fn filter_no_records<T>(e: Result<T, ResolveError>) -> Result<Option<T>, ResolveError> {
-    match e {
-        Ok(t) => Ok(Some(t)),
-        Err(e) if e.proto().is_some_and(|p| p.is_no_records_found()) => Ok(None),
-        Err(e) => Err(e),
+/// Order records by ascending priority, records with the same priority are ordered using a weighted random selection
+/// ([RFC2782](https://datatracker.ietf.org/doc/html/rfc2782))
+fn order_by_priority_and_weight<T>(mut records: Vec<T>, key: impl Fn(&T) -> (u16, u16)) -> Vec<T> {
+    // Sort by priority, records with a weight of 0 are placed first inside their priority group
+    records.sort_by_key(|record| {
+        let (priority, weight) = key(record);
+        (priority, weight != 0)
+    });
+
+    let mut ordered = Vec::with_capacity(records.len());
+    let mut rng = rand::rng();
+
+    while !records.is_empty() {
+        let priority = key(&records[0]).0;
+        let group_len = records
+            .iter()
+            .take_while(|record| key(record).0 == priority)
+            .count();
+
+        let mut group: Vec<T> = records.drain(..group_len).collect();
+
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|record| u32::from(key(record).1)).sum();
+            let selected = rng.random_range(0..=total);
+
+            let mut running_sum = 0;
+            let index = group
+                .iter()
+                .position(|record| {
+                    running_sum += u32::from(key(record).1);
+                    running_sum >= selected
+                })
+                .unwrap_or(0);
+
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hickory_resolver::config::{NameServerConfig, ResolverConfig};
+    use hickory_resolver::name_server::TokioConnectionProvider;
+    use hickory_resolver::proto::op::{Message, MessageType};
+    use hickory_resolver::proto::rr::rdata::SOA;
+    use hickory_resolver::proto::xfer::Protocol;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::UdpSocket;
+
+    fn overrides() -> DnsOverrides {
+        let mut overrides = DnsOverrides::new();
+
+        overrides
+            .add_srv("_sip._udp.example.com", 20, 0, 5080, "backup.example.com.")
+            .add_srv("_sip._udp.example.com", 10, 0, 5060, "primary.example.com.")
+            .add_ip("primary.example.com", IpAddr::from([127, 0, 0, 1]))
+            .add_ip("Backup.Example.com", IpAddr::from([127, 0, 0, 2]));
+
+        overrides
+    }
+
+    #[test]
+    fn overrides_cover_srv_domain() {
+        let overrides = overrides();
+
+        let lookup = |name: &str, query| overrides.lookup(&Name::from_utf8(name).unwrap(), query);
+
+        assert!(matches!(
+            lookup("example.com", QueryType::Naptr),
+            Some(None)
+        ));
+        assert!(matches!(
+            lookup("_sips._tcp.example.com", QueryType::Srv),
+            Some(None)
+        ));
+        assert!(matches!(
+            lookup("_sip._udp.example.com", QueryType::Srv),
+            Some(Some(_))
+        ));
+        assert!(matches!(
+            lookup("primary.example.com.", QueryType::Ip),
+            Some(Some(_))
+        ));
+        assert!(lookup("example.org", QueryType::Naptr).is_none());
+        assert!(lookup("_sip._udp.example.org", QueryType::Srv).is_none());
+    }
+
+    #[tokio::test]
+    async fn resolve_host_with_overrides() {
+        // Resolver without any name servers, every query not answered by the overrides fails
+        let dns_resolver = TokioResolver::builder_with_config(
+            ResolverConfig::new(),
+            TokioConnectionProvider::default(),
+        )
+        .build();
+
+        let resolver = Resolver::new(dns_resolver, overrides());
+
+        let entries = resolve_host(&resolver, "example.com", 5060).await.unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                ServerEntry {
+                    address: SocketAddr::from(([127, 0, 0, 1], 5060)),
+                    transport: Some(Transport::Udp),
+                },
+                ServerEntry {
+                    address: SocketAddr::from(([127, 0, 0, 2], 5080)),
+                    transport: Some(Transport::Udp),
+                },
+            ]
+        );
+
+        assert!(resolve_host(&resolver, "example.org", 5060).await.is_err());
+    }
+
+    /// Stub DNS server answering every query for `host.*` with an A record valid for 1 second.
+    /// Other names have no records, `soa-<ttl>.*` responses carry a SOA record specifying the negative TTL.
+    async fn dns_server() -> (Resolver, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn({
+            let queries = queries.clone();
+
+            async move {
+                let mut buf = vec![0; 65535];
+
+                loop {
+                    let (len, remote) = socket.recv_from(&mut buf).await.unwrap();
+                    let request = Message::from_vec(&buf[..len]).unwrap();
+                    let query = request.queries()[0].clone();
+                    let name = query.name().clone();
+                    let label = name.iter().next().unwrap().to_vec();
+                    let label = String::from_utf8(label).unwrap();
+
+                    queries.fetch_add(1, Ordering::SeqCst);
+
+                    let mut response = Message::new();
+                    response
+                        .set_id(request.id())
+                        .set_message_type(MessageType::Response)
+                        .set_recursion_desired(true)
+                        .set_recursion_available(true)
+                        .add_query(query.clone());
+
+                    if label == "host" && query.query_type() == RecordType::A {
+                        response.add_answer(Record::from_rdata(
+                            name,
+                            1,
+                            RData::A(A::new(127, 0, 0, 1)),
+                        ));
+                    } else if let Some(ttl) = label.strip_prefix("soa-") {
+                        let ttl = ttl.parse().unwrap();
+                        let zone = name.base_name();
+                        let soa = SOA::new(zone.clone(), zone.clone(), 1, 3600, 600, 86400, ttl);
+
+                        response.add_name_server(Record::from_rdata(zone, ttl, RData::SOA(soa)));
+                    }
+
+                    let response = response.to_vec().unwrap();
+                    socket.send_to(&response, remote).await.unwrap();
+                }
+            }
+        });
+
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig::new(addr, Protocol::Udp));
+
+        let mut builder =
+            TokioResolver::builder_with_config(config, TokioConnectionProvider::default());
+        // Disable the resolver's own cache, so every uncached lookup reaches the server
+        builder.options_mut().cache_size = 0;
+
+        (Resolver::new(builder.build(), DnsOverrides::new()), queries)
+    }
+
+    fn name(name: &str) -> Name {
+        Name::from_utf8(name).unwrap()
+    }
+
+    /// Remaining time the lookup of `name` is cached for
+    fn cached_for(resolver: &Resolver, name: &str) -> Duration {
+        resolver.cache.lock()[&(self::name(name), QueryType::Srv)]
+            .valid_until
+            .saturating_duration_since(Instant::now())
+    }
+
+    #[tokio::test]
+    async fn cache_expires_after_ttl() {
+        let (resolver, queries) = dns_server().await;
+
+        let lookup = resolver.lookup(name("host.test."), QueryType::Ip).await;
+        assert!(lookup.unwrap().is_some());
+
+        let lookup = resolver.lookup(name("soa-1.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // Both answers are served from the cache while valid
+        let lookup = resolver.lookup(name("host.test."), QueryType::Ip).await;
+        assert!(lookup.unwrap().is_some());
+
+        let lookup = resolver.lookup(name("soa-1.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let lookup = resolver.lookup(name("host.test."), QueryType::Ip).await;
+        assert!(lookup.unwrap().is_some());
+
+        let lookup = resolver.lookup(name("soa-1.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+        assert_eq!(queries.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn negative_ttl_is_clamped() {
+        let (resolver, queries) = dns_server().await;
+
+        // Without a SOA record the default negative TTL is used
+        let lookup = resolver.lookup(name("missing.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+
+        let ttl = cached_for(&resolver, "missing.test.");
+        assert!(ttl > DEFAULT_NEGATIVE_TTL - Duration::from_secs(5) && ttl <= DEFAULT_NEGATIVE_TTL);
+
+        // Negative TTLs within the limit are used as is
+        let lookup = resolver.lookup(name("soa-60.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+
+        let ttl = cached_for(&resolver, "soa-60.test.");
+        assert!(ttl > Duration::from_secs(55) && ttl <= Duration::from_secs(60));
+
+        // Longer negative TTLs are capped
+        let lookup = resolver.lookup(name("soa-600.test."), QueryType::Srv).await;
+        assert!(lookup.unwrap().is_none());
+
+        let ttl = cached_for(&resolver, "soa-600.test.");
+        assert!(ttl > MAX_NEGATIVE_TTL - Duration::from_secs(5) && ttl <= MAX_NEGATIVE_TTL);
+
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn order_by_priority() {
+        let records = vec![(20, 0, "c"), (10, 5, "a"), (30, 1, "d"), (10, 5, "b")];
+
+        let ordered =
+            order_by_priority_and_weight(records, |(priority, weight, _)| (*priority, *weight));
+        let priorities: Vec<u16> = ordered.iter().map(|(priority, ..)| *priority).collect();
+
+        assert_eq!(priorities, [10, 10, 20, 30]);
+    }
+
+    #[test]
+    fn order_by_weight() {
+        let mut heavy_first = 0;
+
+        for _ in 0..1000 {
+            let records = vec![(10, 1, "light"), (10, 99, "heavy")];
+            let ordered =
+                order_by_priority_and_weight(records, |(priority, weight, _)| (*priority, *weight));
+
+            if ordered[0].2 == "heavy" {
+                heavy_first += 1;
+            }
+        }
+
+        assert!(heavy_first > 900);
     }
 }
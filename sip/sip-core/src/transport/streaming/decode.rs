@@ -8,6 +8,9 @@ use std::io;
 use std::str::{Utf8Error, from_utf8};
 use tokio_util::codec::Decoder;
 
+/// Maximum size of a received message
+pub(crate) const MAX_MESSAGE_SIZE: usize = 65535;
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
     #[error(transparent)]
@@ -71,7 +74,7 @@ impl Decoder for StreamingDecoder {
         }
 
         // limit message size
-        if src.len() > 65535 {
+        if src.len() > MAX_MESSAGE_SIZE {
             src.clear();
 
             return Err(Error::MessageTooLarge);
@@ -138,44 +141,127 @@ impl Decoder for StreamingDecoder {
         // reset state
         self.head_progress = 0;
 
-        // reset parser
-        parser = PullParser::new(&src_bytes, 0);
+        parse_message(src_bytes, Some(content_len))
+            .map(|message| Some(Item::DecodedMessage(message)))
+    }
+}
 
-        // Now properly parse the message
-        let mut message_line = None;
-        let mut headers = Headers::new();
+/// Decode a message received as a single frame of a message based transport (e.g. WebSocket)
+///
+/// The frame must contain exactly one message, everything after the message head is its body.
+/// Frames only containing a double or single CRLF are keep-alive requests and responses.
+#[cfg(feature = "websocket")]
+pub(crate) fn decode_frame(frame: Bytes) -> Result<Option<Item>, Error> {
+    if frame.len() > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge);
+    }
 
-        for item in &mut parser {
-            let item = item.expect("got error when input was already checked");
+    let whitespace_count = frame.iter().take_while(|b| b.is_ascii_whitespace()).count();
 
-            let line = from_utf8(item)?;
+    if whitespace_count == frame.len() {
+        return if frame.starts_with(b"\r\n\r\n") {
+            Ok(Some(Item::KeepAliveRequest))
+        } else if frame.starts_with(b"\r\n") {
+            Ok(Some(Item::KeepAliveResponse))
+        } else {
+            Ok(None)
+        };
+    }
 
-            if message_line.is_none() {
-                match MessageLine::parse(&src_bytes)(line) {
-                    Ok((_, line)) => message_line = Some(line),
-                    Err(_) => return Err(Error::Malformed),
-                }
-            } else {
-                match Line::parse(&src_bytes, line).finish() {
-                    Ok((_, line)) => headers.insert(line.name, line.value),
-                    Err(e) => {
-                        log::error!("Incoming SIP message has malformed header line, {e}");
-                    }
+    parse_message(frame.slice(whitespace_count..), None)
+        .map(|message| Some(Item::DecodedMessage(message)))
+}
+
+/// Parse a complete message from the buffer
+///
+/// The body is `content_len` bytes after the message head, or the remaining buffer if it is `None`.
+fn parse_message(buffer: Bytes, content_len: Option<usize>) -> Result<DecodedMessage, Error> {
+    let mut parser = PullParser::new(&buffer, 0);
+
+    let mut message_line = None;
+    let mut headers = Headers::new();
+
+    for item in &mut parser {
+        // The head of messages received as frames is not validated before
+        let item = item.map_err(|_| Error::Malformed)?;
+
+        let line = from_utf8(item)?;
+
+        if message_line.is_none() {
+            match MessageLine::parse(&buffer)(line) {
+                Ok((_, line)) => message_line = Some(line),
+                Err(_) => return Err(Error::Malformed),
+            }
+        } else {
+            match Line::parse(&buffer, line).finish() {
+                Ok((_, line)) => headers.insert(line.name, line.value),
+                Err(e) => {
+                    log::error!("Incoming SIP message has malformed header line, {e}");
                 }
             }
         }
+    }
 
-        let head_end = parser.head_end();
+    let head_end = parser.head_end();
 
-        // slice remaining bytes
-        let body = src_bytes.slice(head_end..head_end + content_len);
-        assert_eq!(content_len, body.len());
+    // slice remaining bytes
+    let body = match content_len {
+        Some(content_len) => {
+            let body = buffer.slice(head_end..head_end + content_len);
+            assert_eq!(content_len, body.len());
+            body
+        }
+        None => buffer.slice(head_end..),
+    };
+
+    Ok(DecodedMessage {
+        line: message_line.ok_or(Error::Malformed)?,
+        headers,
+        body,
+        buffer,
+    })
+}
+
+#[cfg(all(test, feature = "websocket"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_without_content_length() {
+        let frame =
+            Bytes::from_static(b"MESSAGE sip:bob@example.com SIP/2.0\r\nCall-ID: abc\r\n\r\nHello");
+
+        let Ok(Some(Item::DecodedMessage(message))) = decode_frame(frame) else {
+            panic!("expected message");
+        };
+
+        assert!(message.line.is_request());
+        assert_eq!(
+            message
+                .headers
+                .get_named::<sip_types::header::typed::CallID>()
+                .unwrap()
+                .0,
+            "abc"
+        );
+        assert_eq!(message.body, "Hello");
+    }
 
-        Ok(Some(Item::DecodedMessage(DecodedMessage {
-            line: message_line.ok_or(Error::Malformed)?,
-            headers,
-            body,
-            buffer: src_bytes,
-        })))
+    #[test]
+    fn keep_alive_frames() {
+        assert!(matches!(
+            decode_frame(Bytes::from_static(b"\r\n\r\n")),
+            Ok(Some(Item::KeepAliveRequest))
+        ));
+        assert!(matches!(
+            decode_frame(Bytes::from_static(b"\r\n")),
+            Ok(Some(Item::KeepAliveResponse))
+        ));
+        assert!(matches!(
+            decode_frame(Bytes::from_static(
+                b"INVITE sip:bob@example.com SIP/2.0\r\nVia"
+            )),
+            Err(Error::Malformed)
+        ));
     }
 }
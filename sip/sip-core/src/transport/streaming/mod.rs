@@ -3,6 +3,7 @@ use crate::transport::{Direction, Factory, ReceivedMessage, TpHandle, TpKey, Tra
 use crate::{Endpoint, EndpointBuilder};
 use decode::{Item, StreamingDecoder};
 use sip_types::uri::SipUri;
+use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -12,10 +13,10 @@ use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf, split
 use tokio::net::ToSocketAddrs;
 use tokio::sync::{Mutex, broadcast, oneshot};
 use tokio::time::{Sleep, interval, sleep};
-use tokio_stream::StreamExt;
+use tokio_stream::{Stream, StreamExt};
 use tokio_util::codec::FramedRead;
 
-mod decode;
+pub(crate) mod decode;
 
 /// Helper trait to implement the transport specific behavior of binding to an address
 #[async_trait::async_trait]
@@ -37,7 +38,7 @@ pub trait StreamingListenerBuilder: Sized + Send + Sync + 'static {
 
         log::info!(
             "Accepting {} connections on {}",
-            Self::Transport::NAME,
+            <Self::Transport as StreamingTransport>::NAME,
             bound
         );
 
@@ -77,15 +78,87 @@ pub trait StreamingListener: Send + Sync {
     async fn accept(&mut self) -> io::Result<(Self::Transport, SocketAddr)>;
 }
 
-pub struct StreamingWrite<T> {
+/// Connection which sends and receives complete SIP messages
+///
+/// Implemented for all [`StreamingTransport`]s, which delimit messages using the Content-Length header,
+/// and by message based transports like WebSocket, which send one message per frame.
+pub(crate) trait Connection: Send + 'static {
+    type Read: Stream<Item = Result<Item, decode::Error>> + Send + Unpin + 'static;
+    type Write: ConnectionWrite;
+
+    const NAME: &'static str;
+    const SECURE: bool;
+
+    fn matches_transport_param(name: &str) -> bool;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    fn split(self) -> (Self::Read, Self::Write);
+}
+
+/// Sending half of a [`Connection`]
+#[async_trait::async_trait]
+pub(crate) trait ConnectionWrite: Send + 'static {
+    /// Send a complete message
+    async fn write_message(&mut self, message: &[u8]) -> io::Result<()>;
+
+    /// Send a keep-alive request, a double-CRLF by default
+    async fn write_keep_alive_request(&mut self) -> io::Result<()> {
+        self.write_message(b"\r\n\r\n").await
+    }
+
+    /// Respond to a received keep-alive request, a single-CRLF by default
+    async fn write_keep_alive_response(&mut self) -> io::Result<()> {
+        self.write_message(b"\r\n").await
+    }
+}
+
+impl<T: StreamingTransport> Connection for T {
+    type Read = FramedRead<ReadHalf<T>, StreamingDecoder>;
+    type Write = WriteHalf<T>;
+
+    const NAME: &'static str = T::NAME;
+    const SECURE: bool = T::SECURE;
+
+    fn matches_transport_param(name: &str) -> bool {
+        <T as StreamingTransport>::matches_transport_param(name)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        StreamingTransport::local_addr(self)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        StreamingTransport::peer_addr(self)
+    }
+
+    fn split(self) -> (Self::Read, Self::Write) {
+        let (read, write) = split(self);
+
+        (FramedRead::new(read, StreamingDecoder::default()), write)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: StreamingTransport> ConnectionWrite for WriteHalf<T> {
+    async fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        self.write_all(message).await?;
+        self.flush().await
+    }
+}
+
+/// Sending half of a connection based transport (e.g. TCP, TLS or WebSocket)
+pub struct StreamingWrite<C> {
     bound: SocketAddr,
     remote: SocketAddr,
     incoming: bool,
 
-    write_half: Arc<Mutex<WriteHalf<T>>>,
+    write_half: Arc<Mutex<dyn ConnectionWrite>>,
+    connection: PhantomData<fn() -> C>,
 }
 
-impl<T: StreamingTransport> fmt::Debug for StreamingWrite<T> {
+impl<T: Connection> fmt::Debug for StreamingWrite<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("StreamingWrite")
             .field("bound", &self.bound)
@@ -95,7 +168,7 @@ impl<T: StreamingTransport> fmt::Debug for StreamingWrite<T> {
     }
 }
 
-impl<T: StreamingTransport> fmt::Display for StreamingWrite<T> {
+impl<T: Connection> fmt::Display for StreamingWrite<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}:bound={}:remote={}", T::NAME, self.bound, self.remote,)
     }
@@ -104,7 +177,7 @@ impl<T: StreamingTransport> fmt::Display for StreamingWrite<T> {
 #[async_trait::async_trait]
 impl<T> Transport for StreamingWrite<T>
 where
-    T: StreamingTransport,
+    T: Connection,
 {
     fn name(&self) -> &'static str {
         T::NAME
@@ -139,10 +212,15 @@ where
     }
 
     async fn send(&self, bytes: &[u8], _target: SocketAddr) -> io::Result<()> {
-        let mut socket = self.write_half.lock().await;
-        socket.write_all(bytes).await?;
-        socket.flush().await?;
-        Ok(())
+        self.write_half.lock().await.write_message(bytes).await
+    }
+
+    async fn send_keep_alive(&self, _target: SocketAddr) -> io::Result<()> {
+        self.write_half
+            .lock()
+            .await
+            .write_keep_alive_request()
+            .await
     }
 }
 
@@ -152,15 +230,15 @@ where
     T: StreamingFactory,
 {
     fn name(&self) -> &'static str {
-        T::Transport::NAME
+        <T::Transport as StreamingTransport>::NAME
     }
 
     fn secure(&self) -> bool {
-        T::Transport::SECURE
+        <T::Transport as StreamingTransport>::SECURE
     }
 
     fn matches_transport_param(&self, name: &str) -> bool {
-        T::Transport::matches_transport_param(name)
+        <T::Transport as StreamingTransport>::matches_transport_param(name)
     }
 
     async fn create(
@@ -172,36 +250,44 @@ where
         log::trace!("{} trying to connect to {}", self.name(), addr);
 
         let stream = self.connect::<SocketAddr>(uri, addr).await?;
-        let local = stream.local_addr()?;
-        let remote = stream.peer_addr()?;
 
-        let (read, write) = split(stream);
+        add_outgoing(&endpoint, stream)
+    }
+}
 
-        let write_half = Arc::new(Mutex::new(write));
+/// Add an established outgoing connection to the endpoint's transports and start receiving from it
+pub(crate) fn add_outgoing<C: Connection>(
+    endpoint: &Endpoint,
+    connection: C,
+) -> io::Result<TpHandle> {
+    let local = connection.local_addr()?;
+    let remote = connection.peer_addr()?;
 
-        let transport = StreamingWrite {
-            bound: local,
-            remote,
-            write_half: write_half.clone(),
-            incoming: false,
-        };
+    let (read, write) = connection.split();
 
-        let framed = FramedRead::new(read, StreamingDecoder::default());
+    let write_half = Arc::new(Mutex::new(write));
 
-        let (transport, notifier) = endpoint.transports().add_managed_used(transport);
+    let transport = StreamingWrite::<C> {
+        bound: local,
+        remote,
+        write_half: write_half.clone(),
+        connection: PhantomData,
+        incoming: false,
+    };
 
-        tokio::spawn(receive_task(
-            endpoint.clone(),
-            framed,
-            write_half,
-            ReceiveTaskState::InUse(notifier),
-            local,
-            remote,
-            false,
-        ));
+    let (transport, notifier) = endpoint.transports().add_managed_used(transport);
 
-        return Ok(transport);
-    }
+    tokio::spawn(receive_task::<C>(
+        endpoint.clone(),
+        read,
+        write_half,
+        ReceiveTaskState::InUse(notifier),
+        local,
+        remote,
+        false,
+    ));
+
+    Ok(transport)
 }
 
 async fn task_accept<I>(mut endpoint: broadcast::Receiver<Endpoint>, mut incoming: I)
@@ -215,45 +301,49 @@ where
 
     loop {
         match incoming.accept().await {
-            Ok((stream, remote)) => {
-                let local = match stream.local_addr() {
-                    Ok(local) => local,
-                    Err(e) => {
-                        log::error!("Could not retrieve local addr for incoming stream {e}");
-                        continue;
-                    }
-                };
-
-                log::trace!("Connection accepted from {remote} on {local}");
+            Ok((stream, remote)) => add_incoming(&endpoint, stream, remote),
+            Err(e) => log::error!("Error accepting connection, {e}"),
+        }
+    }
+}
 
-                let (read, write) = split(stream);
+/// Add an accepted connection to the endpoint's transports and start receiving from it
+///
+/// The connection is closed if it isn't used within 32 seconds.
+pub(crate) fn add_incoming<C: Connection>(endpoint: &Endpoint, connection: C, remote: SocketAddr) {
+    let local = match connection.local_addr() {
+        Ok(local) => local,
+        Err(e) => {
+            log::error!("Could not retrieve local addr for incoming stream {e}");
+            return;
+        }
+    };
 
-                let write_half = Arc::new(Mutex::new(write));
+    log::trace!("Connection accepted from {remote} on {local}");
 
-                let transport = StreamingWrite {
-                    bound: local,
-                    remote,
-                    write_half: write_half.clone(),
-                    incoming: true,
-                };
+    let (read, write) = connection.split();
 
-                let rx = endpoint.transports().add_managed_unused(transport);
+    let write_half = Arc::new(Mutex::new(write));
 
-                let framed = FramedRead::new(read, StreamingDecoder::default());
+    let transport = StreamingWrite::<C> {
+        bound: local,
+        remote,
+        write_half: write_half.clone(),
+        connection: PhantomData,
+        incoming: true,
+    };
 
-                tokio::spawn(receive_task(
-                    endpoint.clone(),
-                    framed,
-                    write_half,
-                    ReceiveTaskState::Unused(Box::pin(sleep(Duration::from_secs(32))), rx),
-                    local,
-                    remote,
-                    true,
-                ));
-            }
-            Err(e) => log::error!("Error accepting connection, {e}"),
-        }
-    }
+    let rx = endpoint.transports().add_managed_unused(transport);
+
+    tokio::spawn(receive_task::<C>(
+        endpoint.clone(),
+        read,
+        write_half,
+        ReceiveTaskState::Unused(Box::pin(sleep(Duration::from_secs(32))), rx),
+        local,
+        remote,
+        true,
+    ));
 }
 
 enum ReceiveTaskState {
@@ -263,14 +353,14 @@ enum ReceiveTaskState {
 
 async fn receive_task<T>(
     endpoint: Endpoint,
-    mut framed: FramedRead<ReadHalf<T>, StreamingDecoder>,
-    write_half: Arc<Mutex<WriteHalf<T>>>,
+    mut framed: T::Read,
+    write_half: Arc<Mutex<T::Write>>,
     mut state: ReceiveTaskState,
     local: SocketAddr,
     remote: SocketAddr,
     incoming: bool,
 ) where
-    T: StreamingTransport,
+    T: Connection,
 {
     let tp_key = TpKey {
         name: T::NAME,
@@ -301,7 +391,7 @@ async fn receive_task<T>(
                         continue;
                     }
                     _ = keep_alive_request_interval.tick() => {
-                        if let Err(e) = write_half.lock().await.write(b"\r\n\r\n").await {
+                        if let Err(e) = write_half.lock().await.write_keep_alive_request().await {
                             log::debug!("Failed to send keep alive request, {e}");
                         }
                         continue;
@@ -322,7 +412,7 @@ async fn receive_task<T>(
                         }
                     }
                     _ = keep_alive_request_interval.tick() => {
-                        if let Err(e) = write_half.lock().await.write(b"\r\n\r\n").await {
+                        if let Err(e) = write_half.lock().await.write_keep_alive_request().await {
                             log::debug!("Failed to send keep alive request, {e}");
                         }
                         continue;
@@ -340,14 +430,14 @@ async fn receive_task<T>(
         let message = match item {
             Some(Ok(Item::DecodedMessage(item))) => item,
             Some(Ok(Item::KeepAliveRequest)) => {
-                if let Err(e) = write_half.lock().await.write(b"\r\n").await {
+                if let Err(e) = write_half.lock().await.write_keep_alive_response().await {
                     log::debug!("Failed to respond to keep alive request, {e}");
                 }
 
                 continue;
             }
             Some(Ok(Item::KeepAliveResponse)) => {
-                // discard responses for now
+                endpoint.transports().receive_keep_alive_response(&tp_key);
                 continue;
             }
             Some(Err(e)) => {
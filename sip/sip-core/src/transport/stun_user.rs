@@ -1,12 +1,16 @@
 use super::{TpHandle, Transports};
+use crate::capture::{Capture, CaptureDirection, CaptureProtocol};
 use crate::{Result, StunError};
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use stun::{IncomingMessage, StunEndpointUser};
 use stun_types::attributes::{MappedAddress, Software, XorMappedAddress};
 use stun_types::{Class, MessageBuilder, Method, TransactionId};
 
-pub(crate) struct StunUser;
+pub(crate) struct StunUser {
+    pub(crate) capture: Arc<Capture>,
+}
 
 #[async_trait::async_trait]
 impl StunEndpointUser for StunUser {
@@ -18,7 +22,17 @@ impl StunEndpointUser for StunUser {
         target: SocketAddr,
         transport: &Self::Transport,
     ) -> io::Result<()> {
-        transport.send(bytes, target).await
+        transport.send(bytes, target).await?;
+
+        self.capture.capture(
+            CaptureDirection::Sent,
+            CaptureProtocol::Stun,
+            transport,
+            target,
+            bytes,
+        );
+
+        Ok(())
     }
 
     async fn receive(&self, _message: IncomingMessage<Self::Transport>) {
@@ -119,7 +119,9 @@ async fn handle_msg(
             inner.socket.send_to(b"\r\n", remote).await?;
         }
         Ok(CompleteItem::KeepAliveResponse) => {
-            // ignore for now
+            endpoint
+                .transports()
+                .receive_keep_alive_response(&handle.key());
         }
         Ok(CompleteItem::Stun(message)) => {
             endpoint.receive_stun(message, remote, handle.clone());
@@ -0,0 +1,390 @@
+//! SIP over WebSocket ([RFC7118](https://datatracker.ietf.org/doc/html/rfc7118))
+//!
+//! WebSocket connections are established on top of another streaming transport,
+//! using TCP results in the `WS` transport, using TLS in the `WSS` transport.
+
+use super::streaming::decode::{self, Item, MAX_MESSAGE_SIZE};
+use super::streaming::{
+    self, Connection, ConnectionWrite, StreamingFactory, StreamingListener,
+    StreamingListenerBuilder, StreamingTransport,
+};
+use super::{Factory, TpHandle};
+use crate::{Endpoint, EndpointBuilder};
+use bytes::Bytes;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use sip_types::uri::SipUri;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::from_utf8;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::net::ToSocketAddrs;
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+use tokio_stream::Stream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{HeaderValue, StatusCode, header};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// WebSocket subprotocol which must be negotiated to exchange SIP messages
+const SUBPROTOCOL: &str = "sip";
+
+/// Time an accepted connection has to complete the WebSocket handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(32);
+
+fn config() -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_SIZE))
+        .max_frame_size(Some(MAX_MESSAGE_SIZE))
+}
+
+// ==== Connector
+
+/// Factory for outgoing WebSocket connections, established using the given streaming factory
+/// (e.g. [`TcpConnector`](super::tcp::TcpConnector) for `WS`)
+pub struct WsConnector<F> {
+    connector: F,
+    path: String,
+}
+
+impl<F> WsConnector<F>
+where
+    F: StreamingFactory,
+    F::Transport: Unpin,
+{
+    pub fn new(connector: F) -> Self {
+        Self::new_with_path(connector, "/")
+    }
+
+    /// Create a connector which requests the given resource path in the WebSocket handshake
+    pub fn new_with_path(connector: F, path: impl Into<String>) -> Self {
+        Self {
+            connector,
+            path: path.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<F> Factory for WsConnector<F>
+where
+    F: StreamingFactory,
+    F::Transport: Unpin,
+{
+    fn name(&self) -> &'static str {
+        WsConnection::<F::Transport>::NAME
+    }
+
+    fn secure(&self) -> bool {
+        WsConnection::<F::Transport>::SECURE
+    }
+
+    fn matches_transport_param(&self, name: &str) -> bool {
+        WsConnection::<F::Transport>::matches_transport_param(name)
+    }
+
+    async fn create(
+        &self,
+        endpoint: Endpoint,
+        uri: &SipUri,
+        addr: SocketAddr,
+    ) -> io::Result<TpHandle> {
+        log::trace!("{} trying to connect to {}", self.name(), addr);
+
+        let stream = self.connector.connect::<SocketAddr>(uri, addr).await?;
+
+        let scheme = if <F::Transport as StreamingTransport>::SECURE {
+            "wss"
+        } else {
+            "ws"
+        };
+
+        let mut request = format!(
+            "{scheme}://{}:{}{}",
+            uri.host_port.host,
+            addr.port(),
+            self.path
+        )
+        .into_client_request()
+        .map_err(io::Error::other)?;
+
+        request.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+
+        // The handshake fails if the server doesn't accept the requested subprotocol
+        let (stream, _) =
+            tokio_tungstenite::client_async_with_config(request, stream, Some(config()))
+                .await
+                .map_err(io::Error::other)?;
+
+        streaming::add_outgoing(&endpoint, WsConnection::new(stream)?)
+    }
+}
+
+// ==== Listener
+
+/// Listener accepting WebSocket connections, using the given streaming listener
+/// (e.g. [`TcpListener`](super::tcp::TcpListener) for `WS`)
+pub struct WsListener<B> {
+    listener: B,
+}
+
+impl<B> WsListener<B>
+where
+    B: StreamingListenerBuilder,
+    B::Transport: Unpin,
+{
+    pub fn new(listener: B) -> Self {
+        Self { listener }
+    }
+
+    pub async fn spawn<A: ToSocketAddrs + Send>(
+        self,
+        endpoint: &mut EndpointBuilder,
+        addr: A,
+    ) -> io::Result<()> {
+        let (listener, bound) = self.listener.bind(addr).await?;
+
+        log::info!(
+            "Accepting {} connections on {}",
+            WsConnection::<B::Transport>::NAME,
+            bound
+        );
+
+        tokio::spawn(task_accept(endpoint.subscribe(), listener));
+
+        Ok(())
+    }
+}
+
+async fn task_accept<I>(mut endpoint: broadcast::Receiver<Endpoint>, mut incoming: I)
+where
+    I: StreamingListener,
+    I::Transport: Unpin,
+{
+    let endpoint = match endpoint.recv().await.ok() {
+        Some(endpoint) => endpoint,
+        None => return,
+    };
+
+    loop {
+        match incoming.accept().await {
+            Ok((stream, remote)) => {
+                // Perform the handshake in a separate task to not block accepting other connections
+                tokio::spawn(accept(endpoint.clone(), stream, remote));
+            }
+            Err(e) => log::error!("Error accepting connection, {e}"),
+        }
+    }
+}
+
+async fn accept<T>(endpoint: Endpoint, stream: T, remote: SocketAddr)
+where
+    T: StreamingTransport + Unpin,
+{
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(
+        stream,
+        negotiate_subprotocol,
+        Some(config()),
+    );
+
+    let stream = match timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            log::debug!("WebSocket handshake with {remote} failed, {e}");
+            return;
+        }
+        Err(_) => {
+            log::debug!("WebSocket handshake with {remote} timed out");
+            return;
+        }
+    };
+
+    match WsConnection::new(stream) {
+        Ok(connection) => streaming::add_incoming(&endpoint, connection, remote),
+        Err(e) => log::error!("Could not retrieve local addr for incoming stream {e}"),
+    }
+}
+
+/// Reject WebSocket handshakes which don't offer the `sip` subprotocol, select it otherwise
+#[allow(clippy::result_large_err)] // signature is given by tungstenite's `Callback`
+fn negotiate_subprotocol(
+    request: &Request,
+    mut response: Response,
+) -> Result<Response, ErrorResponse> {
+    let offers_sip = request
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == SUBPROTOCOL);
+
+    if !offers_sip {
+        let mut response = ErrorResponse::new(Some("sip subprotocol required".into()));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return Err(response);
+    }
+
+    response.headers_mut().insert(
+        header::SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(SUBPROTOCOL),
+    );
+
+    Ok(response)
+}
+
+// ==== Transport
+
+/// WebSocket connection over a streaming transport, carrying one SIP message per frame
+pub(crate) struct WsConnection<T> {
+    stream: WebSocketStream<T>,
+    local: SocketAddr,
+    remote: SocketAddr,
+}
+
+impl<T: StreamingTransport + Unpin> WsConnection<T> {
+    fn new(stream: WebSocketStream<T>) -> io::Result<Self> {
+        let local = StreamingTransport::local_addr(stream.get_ref())?;
+        let remote = StreamingTransport::peer_addr(stream.get_ref())?;
+
+        Ok(Self {
+            stream,
+            local,
+            remote,
+        })
+    }
+}
+
+impl<T: StreamingTransport + Unpin> Connection for WsConnection<T> {
+    type Read = WsRead<T>;
+    type Write = SplitSink<WebSocketStream<T>, Message>;
+
+    const NAME: &'static str = if <T as StreamingTransport>::SECURE {
+        "WSS"
+    } else {
+        "WS"
+    };
+    const SECURE: bool = <T as StreamingTransport>::SECURE;
+
+    fn matches_transport_param(name: &str) -> bool {
+        // `transport=ws` is used for both WS and WSS
+        name.eq_ignore_ascii_case("ws") || name.eq_ignore_ascii_case(Self::NAME)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.remote)
+    }
+
+    fn split(self) -> (Self::Read, Self::Write) {
+        let (write, read) = self.stream.split();
+
+        (WsRead { read }, write)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: StreamingTransport + Unpin> ConnectionWrite for SplitSink<WebSocketStream<T>, Message> {
+    async fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        // Messages are sent as text frames unless they contain a binary body
+        let message = match from_utf8(message) {
+            Ok(text) => Message::text(text),
+            Err(_) => Message::binary(Bytes::copy_from_slice(message)),
+        };
+
+        self.send(message).await.map_err(io::Error::other)
+    }
+
+    async fn write_keep_alive_request(&mut self) -> io::Result<()> {
+        self.send(Message::Ping(Bytes::new()))
+            .await
+            .map_err(io::Error::other)
+    }
+}
+
+/// Decodes the received text and binary frames of a WebSocket connection
+pub(crate) struct WsRead<T> {
+    read: SplitStream<WebSocketStream<T>>,
+}
+
+impl<T: StreamingTransport + Unpin> Stream for WsRead<T> {
+    type Item = Result<Item, decode::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let frame = match ready!(self.read.poll_next_unpin(cx)) {
+                Some(Ok(Message::Text(text))) => Bytes::from(text),
+                Some(Ok(Message::Binary(bytes))) => bytes,
+                // Pings are answered by tungstenite, pongs answer the keep-alive pings sent by us
+                Some(Ok(Message::Pong(..))) => {
+                    return Poll::Ready(Some(Ok(Item::KeepAliveResponse)));
+                }
+                Some(Ok(Message::Ping(..) | Message::Frame(..))) => continue,
+                Some(Ok(Message::Close(..)) | Err(WsError::ConnectionClosed)) | None => {
+                    return Poll::Ready(None);
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(io::Error::other(e).into()))),
+            };
+
+            match decode::decode_frame(frame) {
+                Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+                Ok(None) => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::tcp::TcpConnector;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn keep_alive_with_ping() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio_tungstenite::accept_hdr_async(stream, negotiate_subprotocol)
+                .await
+                .unwrap();
+
+            let message = stream.next().await.unwrap().unwrap();
+
+            // Keep reading so tungstenite flushes the pong
+            let _ = stream.next().await;
+
+            message
+        });
+
+        let mut client = Endpoint::builder();
+        client.add_transport_factory(Arc::new(WsConnector::new(TcpConnector::new())));
+        let client = client.build();
+
+        let uri: SipUri = format!("sip:127.0.0.1:{port};transport=ws")
+            .parse()
+            .unwrap();
+        let (transport, target) = client.select_transport(&uri).await.unwrap();
+
+        client.send_keep_alive(&transport, target).await.unwrap();
+
+        drop(transport);
+        drop(client);
+
+        assert!(matches!(server.await.unwrap(), Message::Ping(..)));
+    }
+}
@@ -141,6 +141,10 @@ codes! {
     /// 200 OK
     [200 => OK, "OK"];
 
+    /// [[RFC3515, Section 2.4.2](https://datatracker.ietf.org/doc/html/rfc3515#section-2.4.2)]
+    /// 202 Accepted
+    [202 => ACCEPTED, "Accepted"];
+
     // ==== REDIRECTION 3XX ====
 
     /// [[RFC3621, Section 21.3.1](https://tools.ietf.org/html/rfc3261#section-21.3.1)]
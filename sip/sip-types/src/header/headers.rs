@@ -5,7 +5,7 @@ use bytesstr::BytesStr;
 use internal::verbose_error_to_owned;
 use nom::Finish;
 use std::iter::{FromIterator, once};
-use std::mem::take;
+use std::mem::{replace, take};
 use std::{fmt, slice};
 
 /// Headers is simple container for SIP-Message headers.
@@ -60,7 +60,7 @@ impl Headers {
         let ctx = PrintCtx::default();
 
         if let Some(Entry { values, .. }) = self.entry_mut(&name) {
-            header.extend_values(ctx, values);
+            values.prepend(header.create_values(ctx));
         } else {
             self.entries.insert(
                 0,
@@ -84,7 +84,7 @@ impl Headers {
         let value = value.print_ctx(ctx).to_string();
 
         if let Some(Entry { values, .. }) = self.entry_mut(&name) {
-            values.push(value.into());
+            values.prepend(OneOrMore::One(value.into()));
         } else {
             self.entries.insert(
                 0,
@@ -377,6 +377,16 @@ impl OneOrMore {
         }
     }
 
+    /// Insert `values` before all existing values
+    fn prepend(&mut self, values: OneOrMore) {
+        let existing = replace(self, values);
+
+        match existing {
+            OneOrMore::One(value) => self.push(value),
+            OneOrMore::More(existing) => self.extend(existing),
+        }
+    }
+
     fn decode<H: DecodeValues>(&self, name: Name) -> Result<H, HeaderError> {
         match &self {
             OneOrMore::One(v) => H::decode(&mut once(v)).finish(),
@@ -461,6 +471,24 @@ mod test {
         );
     }
 
+    #[test]
+    fn header_insert_front_existing() {
+        let mut headers = Headers::new();
+
+        headers.insert(Name::VIA, BytesStr::from_static("SIP/2.0/UDP b"));
+        headers.insert(Name::CALL_ID, BytesStr::from_static("call-id"));
+        headers.insert_front(Name::VIA, BytesStr::from_static("SIP/2.0/UDP a"));
+
+        assert_eq!(headers.entries.len(), 2);
+        assert_eq!(
+            headers.entries[0].values,
+            OneOrMore::More(vec![
+                BytesStr::from_static("SIP/2.0/UDP a"),
+                BytesStr::from_static("SIP/2.0/UDP b")
+            ])
+        );
+    }
+
     #[test]
     fn header_remove() {
         let mut headers = Headers::new();
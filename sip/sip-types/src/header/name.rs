@@ -181,6 +181,9 @@ header_names! {
     /// [[RFC3621, Section 20.19](https://tools.ietf.org/html/rfc3261#section-20.19)]
     "Expires",              Expires,            ["expires"],                EXPIRES;
 
+    /// [[RFC5626, Section 11](https://datatracker.ietf.org/doc/html/rfc5626#section-11)]
+    "Flow-Timer",           FlowTimer,          ["flow-timer"],             FLOW_TIMER;
+
     /// [[RFC3621, Section 20.20](https://tools.ietf.org/html/rfc3261#section-20.20)]
     "From",                 From,               ["from", "f"],              FROM;
 
@@ -220,6 +223,9 @@ header_names! {
     /// [[RFC7315, Section 4.6](https://datatracker.ietf.org/doc/html/rfc7315#section-4.6)]
     "P-Charging-Vector", PChargingVector, ["p-charging-vector"], P_CHARGING_VECTOR;
 
+    /// [[RFC3327, Section 4](https://datatracker.ietf.org/doc/html/rfc3327#section-4)]
+    "Path",                 Path,               ["path"],                   PATH;
+
     /// [[RFC3621, Section 20.26](https://tools.ietf.org/html/rfc3261#section-20.26)]
     "Priority",             Priority,           ["priority"],               PRIORITY;
 
@@ -235,9 +241,18 @@ header_names! {
      /// [[RFC3262, Section 20.34](https://datatracker.ietf.org/doc/html/rfc3262#section-7.2)]
     "RAck",                 RAck,               ["rack"],                   RACK;
 
+    /// [[RFC3326, Section 2](https://datatracker.ietf.org/doc/html/rfc3326#section-2)]
+    "Reason",               Reason,             ["reason"],                 REASON;
+
     /// [[RFC3621, Section 20.30](https://tools.ietf.org/html/rfc3261#section-20.30)]
     "Record-Route",         RecordRoute,        ["record-route"],           RECORD_ROUTE;
 
+    /// [[RFC3515, Section 2.1](https://datatracker.ietf.org/doc/html/rfc3515#section-2.1)]
+    "Refer-To",             ReferTo,            ["refer-to", "r"],          REFER_TO;
+
+    /// [[RFC3892, Section 3](https://datatracker.ietf.org/doc/html/rfc3892#section-3)]
+    "Referred-By",          ReferredBy,         ["referred-by", "b"],       REFERRED_BY;
+
     /// [[RFC3891, Section 6.1](https://datatracker.ietf.org/doc/html/rfc3891#section-6.1)]
     "Replaces",             Replaces,           ["replaces"],               REPLACES;
 
@@ -60,7 +60,12 @@ impl ExtendValues for Contact {
 impl Print for Contact {
     fn print(&self, f: &mut fmt::Formatter<'_>, mut ctx: PrintCtx<'_>) -> fmt::Result {
         ctx.uri = Some(UriContext::Contact);
-        write!(f, "{}{}", self.uri.print_ctx(ctx), self.params)?;
+        write!(
+            f,
+            "{}{}",
+            self.uri.print_ctx(ctx),
+            self.params.quoted_print()
+        )?;
         Ok(())
     }
 }
@@ -119,6 +124,29 @@ mod test {
         assert_eq!(contact.uri.name, None)
     }
 
+    #[test]
+    fn print_contact_quoted_param() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::CONTACT,
+            r#"<sip:alice@192.0.2.1>;+sip.instance="<urn:uuid:00000000-0000-1000-8000-000A95A0E128>";reg-id=1"#,
+        );
+
+        let contact: Contact = headers.get_named().unwrap();
+        assert_eq!(
+            contact.params.get_val("+sip.instance").unwrap(),
+            "<urn:uuid:00000000-0000-1000-8000-000A95A0E128>"
+        );
+
+        let mut headers = Headers::new();
+        headers.insert_named(&contact);
+
+        assert_eq!(
+            headers.to_string(),
+            "Contact: <sip:alice@192.0.2.1>;+sip.instance=\"<urn:uuid:00000000-0000-1000-8000-000A95A0E128>\";reg-id=1\r\n"
+        );
+    }
+
     #[test]
     fn parse_multiple_vec() {
         let mut headers = Headers::new();
@@ -14,6 +14,13 @@ from_str_header! {
     u32
 }
 
+from_str_header! {
+    /// `Flow-Timer` header, keep-alive interval requested by the registrar in seconds
+    FlowTimer,
+    Name::FLOW_TIMER,
+    u32
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -58,4 +65,13 @@ mod test {
         let min_expires: MinExpires = headers.get_named().unwrap();
         assert_eq!(min_expires, MIN_EXPIRES);
     }
+
+    #[test]
+    fn parse_flow_timer() {
+        let mut headers = Headers::new();
+        headers.insert(Name::FLOW_TIMER, "120");
+
+        let flow_timer: FlowTimer = headers.get_named().unwrap();
+        assert_eq!(flow_timer, FlowTimer(120));
+    }
 }
@@ -14,6 +14,8 @@ mod extensions;
 mod from_to;
 mod max_fwd;
 mod prack;
+mod reason;
+mod refer;
 mod replaces;
 mod retry_after;
 mod routing;
@@ -30,11 +32,13 @@ pub use contact::Contact;
 pub use content::{ContentLength, ContentType};
 pub use cseq::CSeq;
 pub use event::Event;
-pub use expires::{Expires, MinExpires};
+pub use expires::{Expires, FlowTimer, MinExpires};
 pub use extensions::{Require, Supported, Unsupported};
 pub use from_to::FromTo;
 pub use max_fwd::MaxForwards;
 pub use prack::{RAck, RSeq};
+pub use reason::Reason;
+pub use refer::{ReferTo, ReferredBy};
 pub use replaces::Replaces;
 pub use retry_after::RetryAfter;
 pub use routing::Routing;
@@ -0,0 +1,182 @@
+//! [RFC3326](https://datatracker.ietf.org/doc/html/rfc3326)
+
+use crate::StatusCode;
+use crate::header::headers::OneOrMore;
+use crate::header::name::Name;
+use crate::header::{ConstNamed, ExtendValues, HeaderParse};
+use crate::parse::token;
+use crate::print::PrintCtx;
+use crate::uri::params::{CPS, Params};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use internal::{IResult, ws};
+use nom::bytes::complete::take_while1;
+use nom::combinator::map;
+use std::fmt;
+use std::str::FromStr;
+
+/// `Reason` header, the protocol specific cause of a request (e.g. the `BYE` terminating a call)
+#[derive(Debug, Clone)]
+pub struct Reason {
+    /// Protocol of the cause, usually `SIP` or `Q.850`
+    pub protocol: BytesStr,
+    pub cause: Option<u16>,
+    pub text: Option<BytesStr>,
+    pub params: Params<CPS>,
+}
+
+impl Reason {
+    pub fn new<P>(protocol: P, cause: u16) -> Self
+    where
+        P: Into<BytesStr>,
+    {
+        Self {
+            protocol: protocol.into(),
+            cause: Some(cause),
+            text: None,
+            params: Params::new(),
+        }
+    }
+
+    /// Create a `SIP` reason using the status code and its default reason phrase
+    pub fn sip(code: StatusCode) -> Self {
+        let mut reason = Self::new("SIP", code.into_u16());
+        reason.text = code.text().map(BytesStr::from_static);
+        reason
+    }
+
+    /// Create a `Q.850` reason with the given cause value
+    pub fn q850(cause: u16) -> Self {
+        Self::new("Q.850", cause)
+    }
+
+    pub fn with_text<S>(mut self, text: S) -> Self
+    where
+        S: Into<BytesStr>,
+    {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Returns the SIP status code if this is a `SIP` reason
+    pub fn sip_code(&self) -> Option<StatusCode> {
+        if self.protocol.eq_ignore_ascii_case("SIP") {
+            self.cause.map(StatusCode::from)
+        } else {
+            None
+        }
+    }
+}
+
+impl ConstNamed for Reason {
+    const NAME: Name = Name::REASON;
+}
+
+impl HeaderParse for Reason {
+    fn parse<'i>(src: &'i Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        map(
+            ws((take_while1(token), Params::<CPS>::parse(src))),
+            |(protocol, mut params)| Self {
+                protocol: BytesStr::from_parse(src, protocol),
+                cause: params
+                    .take("cause")
+                    .and_then(|cause| u16::from_str(&cause).ok()),
+                text: params.take("text"),
+                params,
+            },
+        )(i)
+    }
+}
+
+impl ExtendValues for Reason {
+    fn extend_values(&self, _: PrintCtx<'_>, values: &mut OneOrMore) {
+        let value = match values {
+            OneOrMore::One(value) => value,
+            OneOrMore::More(values) => values.last_mut().expect("empty OneOrMore::More variant"),
+        };
+
+        *value = format!("{value}, {self}").into();
+    }
+
+    fn create_values(&self, _: PrintCtx<'_>) -> OneOrMore {
+        OneOrMore::One(self.to_string().into())
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.protocol)?;
+
+        if let Some(cause) = self.cause {
+            write!(f, ";cause={cause}")?;
+        }
+
+        if let Some(text) = &self.text {
+            f.write_str(";text=\"")?;
+
+            for c in text.chars() {
+                if matches!(c, '"' | '\\') {
+                    f.write_str("\\")?;
+                }
+
+                write!(f, "{c}")?;
+            }
+
+            f.write_str("\"")?;
+        }
+
+        write!(f, "{}", self.params.quoted_print())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Headers;
+
+    #[test]
+    fn parse_reason() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::REASON,
+            "SIP ;cause=200 ;text=\"Call completed elsewhere\"",
+        );
+
+        let reason: Reason = headers.get_named().unwrap();
+        assert_eq!(reason.protocol, "SIP");
+        assert_eq!(reason.cause, Some(200));
+        assert_eq!(reason.text.unwrap(), "Call completed elsewhere");
+        assert!(reason.params.is_empty());
+    }
+
+    #[test]
+    fn parse_reason_multiple() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::REASON,
+            "SIP;cause=480;text=\"Temporarily Unavailable\", Q.850;cause=18",
+        );
+
+        let reasons: Vec<Reason> = headers.get_named().unwrap();
+        assert_eq!(reasons.len(), 2);
+        assert_eq!(
+            reasons[0].sip_code(),
+            Some(StatusCode::TEMPORARILY_UNAVAILABLE)
+        );
+        assert_eq!(reasons[1].protocol, "Q.850");
+        assert_eq!(reasons[1].cause, Some(18));
+        assert_eq!(reasons[1].sip_code(), None);
+    }
+
+    #[test]
+    fn print_reason() {
+        let mut headers = Headers::new();
+        headers.insert_named(&Reason::sip(StatusCode::REQUEST_TIMEOUT));
+        headers.insert_named(&Reason::q850(16).with_text("Normal call clearing"));
+
+        assert_eq!(
+            headers.to_string(),
+            "Reason: SIP;cause=408;text=\"Request Timeout\", Q.850;cause=16;text=\"Normal call clearing\"\r\n"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! [RFC3515](https://datatracker.ietf.org/doc/html/rfc3515) & [RFC3892](https://datatracker.ietf.org/doc/html/rfc3892)
+
+use crate::Headers;
+use crate::header::headers::OneOrMore;
+use crate::header::name::Name;
+use crate::header::typed::Replaces;
+use crate::header::{ConstNamed, ExtendValues, HeaderError, HeaderParse};
+use crate::print::{AppendCtx, Print, PrintCtx};
+use crate::uri::NameAddr;
+use crate::uri::params::{CPS, Params};
+use bytes::Bytes;
+use internal::IResult;
+use nom::combinator::map;
+use nom::sequence::tuple;
+use std::fmt;
+
+/// `Refer-To` header
+///
+/// Headers embedded in the URI (e.g. `Replaces`) are stored in the URI's `header_params`.
+#[derive(Debug, Clone)]
+pub struct ReferTo {
+    pub uri: NameAddr,
+    pub params: Params<CPS>,
+}
+
+impl ReferTo {
+    #[inline]
+    pub fn new(uri: NameAddr) -> ReferTo {
+        ReferTo {
+            uri,
+            params: Params::new(),
+        }
+    }
+
+    /// Embed a `Replaces` header into the URI, used for attended transfers
+    pub fn with_replaces(mut self, replaces: &Replaces) -> Self {
+        self.uri
+            .uri
+            .header_params
+            .push_or_edit("Replaces", replaces.to_string());
+        self
+    }
+
+    /// Returns the `Replaces` header embedded in the URI, if any
+    pub fn replaces(&self) -> Option<Result<Replaces, HeaderError>> {
+        let value = self
+            .uri
+            .uri
+            .header_params
+            .get("Replaces")
+            .and_then(|p| p.value.as_ref())?;
+
+        let mut headers = Headers::new();
+        headers.insert(Name::REPLACES, value.clone());
+
+        Some(headers.get_named())
+    }
+
+    impl_with_params!(params, with_key_param, with_value_param);
+}
+
+impl ConstNamed for ReferTo {
+    const NAME: Name = Name::REFER_TO;
+}
+
+impl HeaderParse for ReferTo {
+    fn parse<'i>(src: &'i Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        map(
+            tuple((NameAddr::parse_no_params(src), Params::<CPS>::parse(src))),
+            |(uri, params)| ReferTo { uri, params },
+        )(i)
+    }
+}
+
+impl ExtendValues for ReferTo {
+    fn extend_values(&self, ctx: PrintCtx<'_>, values: &mut OneOrMore) {
+        *values = self.create_values(ctx)
+    }
+
+    fn create_values(&self, ctx: PrintCtx<'_>) -> OneOrMore {
+        OneOrMore::One(self.print_ctx(ctx).to_string().into())
+    }
+}
+
+impl Print for ReferTo {
+    fn print(&self, f: &mut fmt::Formatter<'_>, mut ctx: PrintCtx<'_>) -> fmt::Result {
+        // Print the URI without context to keep the embedded headers
+        ctx.uri = None;
+        write!(f, "{}{}", self.uri.print_ctx(ctx), self.params)
+    }
+}
+
+/// `Referred-By` header
+#[derive(Debug, Clone)]
+pub struct ReferredBy {
+    pub uri: NameAddr,
+    pub params: Params<CPS>,
+}
+
+impl ReferredBy {
+    #[inline]
+    pub fn new(uri: NameAddr) -> ReferredBy {
+        ReferredBy {
+            uri,
+            params: Params::new(),
+        }
+    }
+
+    impl_with_params!(params, with_key_param, with_value_param);
+}
+
+impl ConstNamed for ReferredBy {
+    const NAME: Name = Name::REFERRED_BY;
+}
+
+impl HeaderParse for ReferredBy {
+    fn parse<'i>(src: &'i Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        map(
+            tuple((NameAddr::parse_no_params(src), Params::<CPS>::parse(src))),
+            |(uri, params)| ReferredBy { uri, params },
+        )(i)
+    }
+}
+
+impl ExtendValues for ReferredBy {
+    fn extend_values(&self, ctx: PrintCtx<'_>, values: &mut OneOrMore) {
+        *values = self.create_values(ctx)
+    }
+
+    fn create_values(&self, ctx: PrintCtx<'_>) -> OneOrMore {
+        OneOrMore::One(self.print_ctx(ctx).to_string().into())
+    }
+}
+
+impl Print for ReferredBy {
+    fn print(&self, f: &mut fmt::Formatter<'_>, mut ctx: PrintCtx<'_>) -> fmt::Result {
+        ctx.uri = Some(crate::print::UriContext::FromTo);
+        write!(f, "{}{}", self.uri.print_ctx(ctx), self.params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::uri::SipUri;
+    use bytesstr::BytesStr;
+
+    fn test_replaces() -> Replaces {
+        Replaces {
+            call_id: BytesStr::from_static("SomeCallID"),
+            from_tag: BytesStr::from_static("SomeFromTag"),
+            to_tag: BytesStr::from_static("SomeToTag"),
+            early_only: false,
+        }
+    }
+
+    #[test]
+    fn print_refer_to() {
+        let uri: SipUri = "sip:bob@example.org".parse().unwrap();
+
+        let mut headers = Headers::new();
+        headers.insert_named(&ReferTo::new(NameAddr::uri(uri)));
+        let headers = headers.to_string();
+
+        assert_eq!(headers, "Refer-To: <sip:bob@example.org>\r\n");
+    }
+
+    #[test]
+    fn print_refer_to_replaces() {
+        let uri: SipUri = "sip:bob@example.org".parse().unwrap();
+
+        let mut headers = Headers::new();
+        headers.insert_named(&ReferTo::new(NameAddr::uri(uri)).with_replaces(&test_replaces()));
+        let headers = headers.to_string();
+
+        assert_eq!(
+            headers,
+            "Refer-To: <sip:bob@example.org?Replaces=SomeCallID%3Bfrom-tag%3DSomeFromTag%3Bto-tag%3DSomeToTag>\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_refer_to_replaces() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::REFER_TO,
+            "<sip:bob@example.org?Replaces=SomeCallID%3Bfrom-tag%3DSomeFromTag%3Bto-tag%3DSomeToTag>",
+        );
+
+        let refer_to: ReferTo = headers.get_named().unwrap();
+
+        assert!(
+            refer_to
+                .uri
+                .uri
+                .compare(&"sip:bob@example.org".parse().unwrap())
+        );
+        assert_eq!(refer_to.replaces().unwrap().unwrap(), test_replaces());
+    }
+
+    #[test]
+    fn parse_referred_by() {
+        let mut headers = Headers::new();
+        headers.insert(Name::REFERRED_BY, "\"Alice\" <sip:alice@example.org>");
+
+        let referred_by: ReferredBy = headers.get_named().unwrap();
+
+        assert_eq!(
+            referred_by.uri.name.as_ref().map(BytesStr::as_ref),
+            Some("Alice")
+        );
+    }
+}
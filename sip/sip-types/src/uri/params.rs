@@ -10,6 +10,7 @@ use nom::multi::many0;
 use percent_encoding::{AsciiSet, percent_decode, percent_encode};
 use std::borrow::Cow;
 use std::fmt;
+use std::fmt::Write;
 use std::marker::PhantomData;
 use std::str::Utf8Error;
 
@@ -109,6 +110,14 @@ impl<S: ParamsSpec> Params<S> {
         }
     }
 
+    /// Print the params as header parameters
+    ///
+    /// Values which contain characters that would otherwise be percent-encoded are printed as
+    /// quoted string instead (e.g. `+sip.instance="<urn:uuid:...>"`).
+    pub fn quoted_print(&self) -> QuotedPrint<'_, S> {
+        QuotedPrint { params: self }
+    }
+
     pub(crate) fn parse(src: &Bytes) -> impl Fn(&str) -> IResult<&str, Self> + '_ {
         move |i| {
             map(
@@ -186,6 +195,52 @@ where
     }
 }
 
+/// used to print `Params` as header parameters with quoted values
+pub struct QuotedPrint<'p, S>
+where
+    S: ParamsSpec,
+{
+    params: &'p Params<S>,
+}
+
+impl<S> fmt::Display for QuotedPrint<'_, S>
+where
+    S: ParamsSpec,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, param) in self.params.params.iter().enumerate() {
+            if i == 0 {
+                f.write_str(S::FIRST_DELIMITER)?;
+            } else {
+                f.write_str(S::DELIMITER)?;
+            }
+
+            match &param.value {
+                Some(value) if !value.chars().all(S::CHAR_SPEC) => {
+                    write!(
+                        f,
+                        "{}=\"",
+                        percent_encode(param.name.as_bytes(), S::ENCODE_SET())
+                    )?;
+
+                    for c in value.chars() {
+                        if matches!(c, '"' | '\\') {
+                            f.write_char('\\')?;
+                        }
+
+                        f.write_char(c)?;
+                    }
+
+                    f.write_char('"')?;
+                }
+                _ => param.write(f, S::ENCODE_SET())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl<S> Default for Params<S> {
     fn default() -> Self {
         Params {
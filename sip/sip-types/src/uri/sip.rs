@@ -30,6 +30,17 @@ pub enum SipUriUserPart {
     UserPw(Box<SipUriUserPassword>),
 }
 
+impl SipUriUserPart {
+    /// Returns the user, if any
+    pub fn user(&self) -> Option<&BytesStr> {
+        match self {
+            SipUriUserPart::Empty => None,
+            SipUriUserPart::User(user) => Some(user),
+            SipUriUserPart::UserPw(user_pw) => Some(&user_pw.user),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SipUri {
     pub sips: bool,
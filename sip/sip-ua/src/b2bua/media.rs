@@ -0,0 +1,251 @@
+use super::Leg;
+use crate::{DtmfEvent, MediaBackend, MediaStreamRecord};
+#[cfg(feature = "rtc")]
+use crate::{MediaEvent, RtcMediaBackend, RtcMediaBackendError};
+use parking_lot as pl;
+use sdp_types::SessionDescription;
+use sip_types::StatusCode;
+use std::future::pending;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Media backend of a single [`B2bua`](super::B2bua) leg
+#[allow(clippy::large_enum_variant)]
+pub enum LegMedia {
+    /// The media is terminated and relayed to the other leg
+    #[cfg(feature = "rtc")]
+    Anchored(RtcMediaBackend),
+    /// SDP is forwarded to the other leg
+    PassThrough(SdpPassThrough),
+}
+
+/// Error returned by the [`LegMedia`] backend
+#[derive(Debug, thiserror::Error)]
+pub enum LegMediaError {
+    #[cfg(feature = "rtc")]
+    #[error(transparent)]
+    Anchored(#[from] RtcMediaBackendError),
+    #[error(transparent)]
+    PassThrough(#[from] PassThroughError),
+}
+
+/// Event returned by the [`LegMedia`] backend, only anchored media has events
+pub enum LegMediaEvent {
+    #[cfg(feature = "rtc")]
+    Anchored(MediaEvent),
+}
+
+/// Error returned by [`SdpPassThrough`]
+#[derive(Debug, thiserror::Error)]
+pub enum PassThroughError {
+    #[error("SDP offer was rejected by the other leg with {0:?}")]
+    Rejected(StatusCode),
+    #[error("Other leg has not received any SDP yet")]
+    NoRemoteSdp,
+    #[error("B2BUA has been dropped")]
+    Closed,
+}
+
+/// Request of a leg to send the SDP offer it received to the other leg, answered with the other leg's SDP answer
+pub(super) struct ForwardOffer {
+    pub(super) reply: oneshot::Sender<Result<SessionDescription, StatusCode>>,
+}
+
+/// Media backend which forwards SDP offers and answers between the legs of a [`B2bua`](super::B2bua) unchanged
+///
+/// The media flows directly between the peers. Putting a leg on hold has no effect, hold offers of the peers are
+/// forwarded to the other leg as any other offer.
+pub struct SdpPassThrough {
+    leg: Leg,
+
+    /// Latest SDP received by each leg, indexed by [`Leg::index`]
+    remote: Arc<pl::Mutex<[Option<SessionDescription>; 2]>>,
+
+    /// Answer to the next received offer, set if it's known before the offer is received
+    answer: Option<SessionDescription>,
+
+    /// Offers which must be forwarded to the other leg
+    offers: mpsc::Sender<ForwardOffer>,
+
+    /// Offers are only forwarded once the outbound call has been answered, offers received in early dialogs
+    /// are rejected. Shared by both legs.
+    forward: Arc<AtomicBool>,
+}
+
+impl SdpPassThrough {
+    /// Create the backends for both legs, `inbound_offer` is the SDP offer of the inbound call's INVITE
+    pub(super) fn pair(
+        inbound_offer: Option<SessionDescription>,
+    ) -> (
+        (Self, mpsc::Receiver<ForwardOffer>),
+        (Self, mpsc::Receiver<ForwardOffer>),
+    ) {
+        let remote = Arc::new(pl::Mutex::new([inbound_offer, None]));
+        let forward = Arc::new(AtomicBool::new(false));
+
+        let create = |leg| {
+            let (offers, rx) = mpsc::channel(1);
+
+            let this = Self {
+                leg,
+                remote: remote.clone(),
+                answer: None,
+                offers,
+                forward: forward.clone(),
+            };
+
+            (this, rx)
+        };
+
+        (create(Leg::Inbound), create(Leg::Outbound))
+    }
+
+    /// Latest SDP received by this leg
+    pub(super) fn remote_sdp(&self) -> Option<SessionDescription> {
+        self.remote.lock()[self.leg.index()].clone()
+    }
+
+    /// Set the answer to the next SDP offer received by this leg
+    pub(super) fn set_answer(&mut self, answer: SessionDescription) {
+        self.answer = Some(answer);
+    }
+
+    /// Start forwarding offers between both legs
+    pub(super) fn forward_offers(&self) {
+        self.forward.store(true, Ordering::Relaxed);
+    }
+}
+
+impl MediaBackend for SdpPassThrough {
+    type Error = PassThroughError;
+    type Event = LegMediaEvent;
+
+    fn has_media(&self) -> bool {
+        self.remote.lock()[self.leg.other().index()].is_some()
+    }
+
+    async fn create_sdp_offer(&mut self) -> Result<SessionDescription, Self::Error> {
+        // Offers of the other leg are forwarded by first storing them as its latest SDP
+        self.remote.lock()[self.leg.other().index()]
+            .clone()
+            .ok_or(PassThroughError::NoRemoteSdp)
+    }
+
+    async fn receive_sdp_answer(&mut self, sdp: SessionDescription) -> Result<(), Self::Error> {
+        self.remote.lock()[self.leg.index()] = Some(sdp);
+        Ok(())
+    }
+
+    async fn receive_sdp_offer(
+        &mut self,
+        sdp: SessionDescription,
+    ) -> Result<SessionDescription, Self::Error> {
+        if let Some(answer) = self.answer.take() {
+            self.remote.lock()[self.leg.index()] = Some(sdp);
+            return Ok(answer);
+        }
+
+        if !self.forward.load(Ordering::Relaxed) {
+            return Err(PassThroughError::Rejected(StatusCode::NOT_ACCEPTABLE_HERE));
+        }
+
+        self.remote.lock()[self.leg.index()] = Some(sdp);
+
+        let (reply, response) = oneshot::channel();
+
+        self.offers
+            .send(ForwardOffer { reply })
+            .await
+            .map_err(|_| PassThroughError::Closed)?;
+
+        response
+            .await
+            .map_err(|_| PassThroughError::Closed)?
+            .map_err(PassThroughError::Rejected)
+    }
+
+    async fn run(&mut self) -> Result<Self::Event, Self::Error> {
+        pending().await
+    }
+}
+
+impl MediaBackend for LegMedia {
+    type Error = LegMediaError;
+    type Event = LegMediaEvent;
+
+    fn has_media(&self) -> bool {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => media.has_media(),
+            LegMedia::PassThrough(media) => media.has_media(),
+        }
+    }
+
+    async fn create_sdp_offer(&mut self) -> Result<SessionDescription, Self::Error> {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => Ok(media.create_sdp_offer().await?),
+            LegMedia::PassThrough(media) => Ok(media.create_sdp_offer().await?),
+        }
+    }
+
+    async fn receive_sdp_answer(&mut self, sdp: SessionDescription) -> Result<(), Self::Error> {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => Ok(media.receive_sdp_answer(sdp).await?),
+            LegMedia::PassThrough(media) => Ok(media.receive_sdp_answer(sdp).await?),
+        }
+    }
+
+    async fn receive_sdp_offer(
+        &mut self,
+        sdp: SessionDescription,
+    ) -> Result<SessionDescription, Self::Error> {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => Ok(media.receive_sdp_offer(sdp).await?),
+            LegMedia::PassThrough(media) => Ok(media.receive_sdp_offer(sdp).await?),
+        }
+    }
+
+    fn set_hold(&mut self, hold: bool) {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => media.set_hold(hold),
+            LegMedia::PassThrough(media) => media.set_hold(hold),
+        }
+    }
+
+    async fn run(&mut self) -> Result<Self::Event, Self::Error> {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => Ok(LegMediaEvent::Anchored(media.run().await?)),
+            LegMedia::PassThrough(media) => Ok(media.run().await?),
+        }
+    }
+
+    fn send_dtmf(&mut self, digit: char, duration: Duration) -> bool {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => media.send_dtmf(digit, duration),
+            LegMedia::PassThrough(media) => media.send_dtmf(digit, duration),
+        }
+    }
+
+    fn dtmf_event(event: &Self::Event) -> Option<DtmfEvent> {
+        match *event {
+            #[cfg(feature = "rtc")]
+            LegMediaEvent::Anchored(ref event) => RtcMediaBackend::dtmf_event(event),
+        }
+    }
+
+    fn media_stats(&mut self) -> Vec<MediaStreamRecord> {
+        match self {
+            #[cfg(feature = "rtc")]
+            LegMedia::Anchored(media) => media.media_stats(),
+            LegMedia::PassThrough(media) => media.media_stats(),
+        }
+    }
+}
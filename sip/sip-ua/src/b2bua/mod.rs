@@ -0,0 +1,891 @@
+//! Back-to-back user agent bridging an [`InboundCall`] with an [`OutboundCall`]
+
+#[cfg(feature = "rtc")]
+use crate::RtcMediaBackend;
+use crate::{
+    AcceptCallError, Call, CallError, CallEvent, InboundCall, MakeCallCompletionError,
+    MakeCallError, MakeCallOptions, NoMedia, OutboundCall, OutboundCallProgress, TerminationReason,
+};
+use media::ForwardOffer;
+use relay::RtpRelay;
+use sip_auth::ClientAuthenticator;
+use sip_types::StatusCode;
+use sip_types::header::typed::Contact;
+use sip_types::msg::StatusLine;
+use sip_types::uri::{NameAddr, SipUri};
+use std::pin::pin;
+use tokio::select;
+use tokio::sync::mpsc;
+
+mod media;
+mod relay;
+
+pub use media::{LegMedia, LegMediaError, LegMediaEvent, PassThroughError, SdpPassThrough};
+
+/// One of the two calls bridged by a [`B2bua`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leg {
+    /// The call received by the B2BUA
+    Inbound,
+    /// The call created by the B2BUA
+    Outbound,
+}
+
+impl Leg {
+    pub fn other(self) -> Leg {
+        match self {
+            Leg::Inbound => Leg::Outbound,
+            Leg::Outbound => Leg::Inbound,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Leg::Inbound => 0,
+            Leg::Outbound => 1,
+        }
+    }
+}
+
+/// How the media of the bridged calls is handled
+#[allow(clippy::large_enum_variant)]
+pub enum BridgeMedia {
+    /// Terminate the media of both legs in the given media backends and relay RTP between them
+    ///
+    /// The outbound backend must already contain local media, to create the SDP offer of the outbound INVITE.
+    /// RTP is only relayed between streams which negotiated the same codec on both legs.
+    #[cfg(feature = "rtc")]
+    Anchored {
+        inbound: RtcMediaBackend,
+        outbound: RtcMediaBackend,
+    },
+    /// Forward SDP offers and answers between the legs unchanged, the media flows directly between the peers
+    PassThrough,
+}
+
+/// Errors that might be encountered while bridging the calls using [`B2bua::bridge`]
+///
+/// If the outbound call failed, the inbound call has already been declined.
+#[derive(Debug, thiserror::Error)]
+pub enum B2buaError<A> {
+    #[error("Failed to create the outbound call")]
+    MakeCall(#[source] MakeCallError<LegMediaError, A>),
+    #[error("Failed to complete the outbound call")]
+    Completion(#[source] MakeCallCompletionError<LegMediaError>),
+    #[error("Failed to accept the inbound call")]
+    Accept(#[source] AcceptCallError<LegMediaError>),
+    #[error("Inbound call was cancelled by the peer")]
+    Cancelled,
+}
+
+/// Event returned by [`B2bua::run`]
+pub enum B2buaEvent {
+    /// Event of one of the legs which isn't handled by the B2BUA (e.g. [`CallEvent::ReferReceived`])
+    Leg(Leg, CallEvent<LegMedia>),
+    /// Both calls have been terminated, `leg` is the call which has been terminated first.
+    /// Subsequent calls to [`B2bua::run`] keep returning this event.
+    Terminated { leg: Leg, reason: TerminationReason },
+}
+
+/// Back-to-back user agent bridging an inbound and outbound call
+///
+/// Provisional and final responses of the outbound call are relayed to the inbound call. Once both calls are
+/// established, BYE and INFO requests are relayed as well as DTMF, received either as RFC4733 telephone-events or
+/// SIP INFO.
+/// Depending on the [`BridgeMedia`] re-INVITE and UPDATE offers are either answered by each leg (anchored media)
+/// or forwarded to the other leg using the same method (SDP pass-through).
+pub struct B2bua {
+    inbound: Call<LegMedia>,
+    outbound: Call<LegMedia>,
+
+    /// Offers received by the inbound/outbound leg which must be forwarded, only used with SDP pass-through
+    inbound_offers: mpsc::Receiver<ForwardOffer>,
+    outbound_offers: mpsc::Receiver<ForwardOffer>,
+
+    relay: RtpRelay,
+
+    pass_through: bool,
+
+    /// Termination reason of each leg, indexed by [`Leg::index`]
+    terminated: [Option<TerminationReason>; 2],
+    first_terminated: Option<Leg>,
+}
+
+impl B2bua {
+    /// Bridge the inbound call with a new outbound call to `target`, using the given identity and contact
+    ///
+    /// Returns once both calls have been established. [`MakeCallOptions::early_media`] is always enabled, to relay
+    /// early media of the outbound call.
+    pub async fn bridge<A: ClientAuthenticator + Send + 'static>(
+        inbound: InboundCall<NoMedia>,
+        authenticator: A,
+        id: NameAddr,
+        contact: Contact,
+        target: SipUri,
+        mut options: MakeCallOptions,
+        media: BridgeMedia,
+    ) -> Result<Self, B2buaError<A::Error>> {
+        let pass_through = matches!(media, BridgeMedia::PassThrough);
+
+        let ((inbound_media, inbound_offers), (outbound_media, outbound_offers)) = match media {
+            #[cfg(feature = "rtc")]
+            BridgeMedia::Anchored { inbound, outbound } => {
+                // Senders are dropped immediately, as anchored media never forwards offers
+                let (_, inbound_offers) = mpsc::channel(1);
+                let (_, outbound_offers) = mpsc::channel(1);
+
+                (
+                    (LegMedia::Anchored(inbound), inbound_offers),
+                    (LegMedia::Anchored(outbound), outbound_offers),
+                )
+            }
+            BridgeMedia::PassThrough => {
+                let ((inbound, inbound_offers), (outbound, outbound_offers)) =
+                    SdpPassThrough::pair(inbound.sdp_offer().cloned());
+
+                (
+                    (LegMedia::PassThrough(inbound), inbound_offers),
+                    (LegMedia::PassThrough(outbound), outbound_offers),
+                )
+            }
+        };
+
+        let mut inbound = inbound.with_media(inbound_media);
+
+        let mut relay = RtpRelay::default();
+
+        options.early_media = true;
+
+        let mut outbound = {
+            let mut make = pin!(OutboundCall::make_with_options(
+                inbound.endpoint().clone(),
+                authenticator,
+                id,
+                contact,
+                target,
+                options,
+                outbound_media,
+            ));
+
+            let result = select! {
+                result = &mut make => result,
+                _ = inbound.cancelled() => {
+                    // The INVITE can only be cancelled once a provisional response has been received
+                    if let Ok(outbound) = make.await
+                        && let Err(e) = outbound.cancel().await
+                    {
+                        log::warn!("Failed to cancel outbound call, {e}");
+                    }
+
+                    return Err(B2buaError::Cancelled);
+                }
+            };
+
+            match result {
+                Ok(outbound) => outbound,
+                Err(e) => {
+                    let code = match &e {
+                        MakeCallError::Failed(line) => relayed_status(line),
+                        MakeCallError::Core(sip_core::Error::RequestTimedOut) => {
+                            StatusCode::REQUEST_TIMEOUT
+                        }
+                        _ => StatusCode::SERVER_INTERNAL_ERROR,
+                    };
+
+                    decline(inbound, code).await;
+                    return Err(B2buaError::MakeCall(e));
+                }
+            }
+        };
+
+        let early_code = outbound
+            .early_dialogs()
+            .next()
+            .map(|early_dialog| early_dialog.status.code);
+
+        if let Some(code) = early_code
+            && let Err(e) = inbound.respond_provisional(code).await
+        {
+            let _ = outbound.cancel().await;
+            return Err(B2buaError::Accept(e.into()));
+        }
+
+        // Relay the progress of the outbound call until it has been answered
+        let mut inbound_early_media = false;
+
+        let unacknowledged = loop {
+            let progress = select! {
+                progress = outbound.wait_for_progress() => progress,
+                event = inbound.run_media_or_cancelled(inbound_early_media) => {
+                    match event {
+                        None => {
+                            if let Err(e) = outbound.cancel().await {
+                                log::warn!("Failed to cancel outbound call, {e}");
+                            }
+
+                            return Err(B2buaError::Cancelled);
+                        }
+                        Some(Ok(event)) => relay.handle_event(Leg::Inbound, event),
+                        Some(Err(e)) => log::warn!("Inbound media failed during early media, {e}"),
+                    }
+
+                    continue;
+                }
+            };
+
+            let result = match progress {
+                Ok(OutboundCallProgress::Early(early_dialog)) => inbound
+                    .respond_provisional(early_dialog.status.code)
+                    .await
+                    .map_err(AcceptCallError::from),
+                Ok(OutboundCallProgress::EarlyMedia(early_dialog)) => {
+                    if inbound.has_sdp_offer() {
+                        if let (LegMedia::PassThrough(media), Some(sdp)) =
+                            (inbound.media(), early_dialog.sdp)
+                        {
+                            media.set_answer(sdp);
+                        }
+
+                        inbound_early_media = true;
+                        inbound.respond_early_media().await
+                    } else {
+                        inbound
+                            .respond_provisional(early_dialog.status.code)
+                            .await
+                            .map_err(AcceptCallError::from)
+                    }
+                }
+                Ok(OutboundCallProgress::Media(event)) => {
+                    relay.handle_event(Leg::Outbound, event);
+                    Ok(())
+                }
+                Ok(OutboundCallProgress::Redirected(..)) => Ok(()),
+                Ok(OutboundCallProgress::Completed(unacknowledged)) => {
+                    // Offers received in early dialogs have been rejected until now
+                    match inbound.media() {
+                        LegMedia::PassThrough(media) => media.forward_offers(),
+                        #[cfg(feature = "rtc")]
+                        LegMedia::Anchored(..) => {}
+                    }
+
+                    break unacknowledged;
+                }
+                Err(e) => {
+                    let code = match &e {
+                        MakeCallCompletionError::Failed(line) => relayed_status(line),
+                        _ => StatusCode::SERVER_INTERNAL_ERROR,
+                    };
+
+                    decline(inbound, code).await;
+                    return Err(B2buaError::Completion(e));
+                }
+            };
+
+            if let Err(e) = result {
+                if let Err(e) = outbound.cancel().await {
+                    log::warn!("Failed to cancel outbound call, {e}");
+                }
+
+                return Err(B2buaError::Accept(e));
+            }
+        };
+
+        // Complete the outbound call and accept the inbound call. Without an SDP offer in the inbound INVITE the
+        // 2XX response of the outbound call contains the offer, which must be answered by the inbound peer first.
+        let mut inbound = Some(inbound);
+        let mut inbound_call = None;
+        let mut outbound_offers = outbound_offers;
+
+        let outbound_call = {
+            let mut finish = pin!(unacknowledged.finish());
+
+            loop {
+                select! {
+                    result = &mut finish => break result,
+                    Some(forward) = outbound_offers.recv(), if inbound.is_some() => {
+                        let mut result = inbound.take().unwrap().accept().await;
+
+                        let answer = match &mut result {
+                            Ok(call) => remote_sdp(call).ok_or(StatusCode::NOT_ACCEPTABLE_HERE),
+                            Err(_) => Err(StatusCode::SERVER_INTERNAL_ERROR),
+                        };
+
+                        let _ = forward.reply.send(answer);
+                        inbound_call = Some(result);
+                    }
+                }
+            }
+        };
+
+        let mut outbound_call = match outbound_call {
+            Ok(outbound_call) => outbound_call,
+            Err(e) => {
+                match inbound {
+                    Some(inbound) => decline(inbound, StatusCode::SERVER_INTERNAL_ERROR).await,
+                    None => {
+                        if let Some(Ok(mut inbound_call)) = inbound_call {
+                            let _ = inbound_call.terminate().await;
+                        }
+                    }
+                }
+
+                return Err(B2buaError::Completion(e));
+            }
+        };
+
+        let inbound_call = match inbound {
+            Some(mut inbound) => {
+                // The answer sent with early media is reused by the inbound call
+                if !inbound_early_media
+                    && inbound.has_sdp_offer()
+                    && let (LegMedia::PassThrough(media), Some(answer)) =
+                        (inbound.media(), remote_sdp(&mut outbound_call))
+                {
+                    media.set_answer(answer);
+                }
+
+                inbound.accept().await
+            }
+            None => inbound_call
+                .expect("inbound call is accepted when the outbound call forwards its offer"),
+        };
+
+        let inbound_call = match inbound_call {
+            Ok(inbound_call) => inbound_call,
+            Err(e) => {
+                if let Err(e) = outbound_call.terminate().await {
+                    log::warn!("Failed to terminate outbound call, {e}");
+                }
+
+                return Err(B2buaError::Accept(e));
+            }
+        };
+
+        Ok(Self {
+            inbound: inbound_call,
+            outbound: outbound_call,
+            inbound_offers,
+            outbound_offers,
+            relay,
+            pass_through,
+            terminated: [None, None],
+            first_terminated: None,
+        })
+    }
+
+    /// Returns the call of the given leg
+    pub fn leg(&mut self, leg: Leg) -> &mut Call<LegMedia> {
+        match leg {
+            Leg::Inbound => &mut self.inbound,
+            Leg::Outbound => &mut self.outbound,
+        }
+    }
+
+    /// Run both calls, relaying requests and media between them
+    ///
+    /// If one of the calls fails, the other call is terminated before returning the error.
+    /// Unlike [`Call::run`] this function is not cancel safe.
+    pub async fn run(&mut self) -> Result<B2buaEvent, CallError<LegMediaError>> {
+        loop {
+            if let ([Some(..), Some(..)], Some(leg)) = (&self.terminated, self.first_terminated) {
+                return Ok(B2buaEvent::Terminated {
+                    leg,
+                    reason: self.terminated[leg.index()].clone().unwrap(),
+                });
+            }
+
+            let (leg, result) = select! {
+                event = self.inbound.run(), if self.terminated[0].is_none() => (Leg::Inbound, event),
+                event = self.outbound.run(), if self.terminated[1].is_none() => (Leg::Outbound, event),
+            };
+
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    // Don't leave the other leg running when giving up on the bridge
+                    if self.terminated[leg.other().index()].is_none()
+                        && let Err(e) = self.leg(leg.other()).terminate().await
+                    {
+                        log::warn!("Failed to terminate {:?} leg, {e}", leg.other());
+                    }
+
+                    return Err(e);
+                }
+            };
+
+            if let Some(event) = self.handle_event(leg, event).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    async fn handle_event(
+        &mut self,
+        leg: Leg,
+        event: CallEvent<LegMedia>,
+    ) -> Result<Option<B2buaEvent>, CallError<LegMediaError>> {
+        match event {
+            CallEvent::Internal(event) => {
+                let is_update = event.is_update();
+
+                let (this, other, offers) = match leg {
+                    Leg::Inbound => (
+                        &mut self.inbound,
+                        &mut self.outbound,
+                        &mut self.inbound_offers,
+                    ),
+                    Leg::Outbound => (
+                        &mut self.outbound,
+                        &mut self.inbound,
+                        &mut self.outbound_offers,
+                    ),
+                };
+
+                let result = {
+                    let mut handle = pin!(this.handle_internal_event(event));
+
+                    loop {
+                        select! {
+                            result = &mut handle => break result,
+                            Some(forward) = offers.recv() => {
+                                let answer = forward_offer(other, is_update).await;
+                                let _ = forward.reply.send(answer);
+                            }
+                        }
+                    }
+                };
+
+                match result {
+                    // The peer's request has already been rejected
+                    Err(CallError::Media(LegMediaError::PassThrough(e))) => {
+                        log::warn!("Failed to forward SDP offer of {leg:?} leg, {e}");
+                    }
+                    result => result?,
+                }
+            }
+            CallEvent::Media(event) => self.relay.handle_event(leg, event),
+            CallEvent::RemoteHold | CallEvent::RemoteResume if self.pass_through => {
+                // The hold offer has already been forwarded
+            }
+            CallEvent::RemoteHold => self.leg(leg.other()).hold().await?,
+            CallEvent::RemoteResume => self.leg(leg.other()).resume().await?,
+            CallEvent::Dtmf(event) => {
+                let result = self
+                    .leg(leg.other())
+                    .send_dtmf(&event.digit.to_string(), event.duration)
+                    .await;
+
+                if let Err(e) = result {
+                    log::warn!("Failed to relay DTMF digit to {:?} leg, {e}", leg.other());
+                }
+            }
+            CallEvent::InfoReceived(info) => {
+                let result = self
+                    .leg(leg.other())
+                    .send_info(info.content_type().clone(), info.body().clone())
+                    .await;
+
+                // Relay the response of the other leg's peer
+                let code = match result {
+                    Ok(()) => StatusCode::OK,
+                    Err(CallError::InfoRejected(code)) => code,
+                    Err(e) => {
+                        log::warn!("Failed to relay INFO to {:?} leg, {e}", leg.other());
+                        StatusCode::SERVER_INTERNAL_ERROR
+                    }
+                };
+
+                if let Err(e) = info.respond(code).await {
+                    log::warn!("Failed to respond to INFO of {leg:?} leg, {e}");
+                }
+            }
+            CallEvent::Terminated(reason) => {
+                self.terminated[leg.index()] = Some(reason.clone());
+
+                if self.first_terminated.is_some() {
+                    return Ok(None);
+                }
+
+                self.first_terminated = Some(leg);
+
+                let other = self.leg(leg.other());
+
+                let result = match &reason {
+                    TerminationReason::RemoteBye { reasons } if !reasons.is_empty() => {
+                        other.terminate_with_reason(reasons[0].clone()).await
+                    }
+                    _ => other.terminate().await,
+                };
+
+                if let Err(e) = result {
+                    log::warn!("Failed to terminate {:?} leg, {e}", leg.other());
+                }
+            }
+            event => return Ok(Some(B2buaEvent::Leg(leg, event))),
+        }
+
+        Ok(None)
+    }
+}
+
+/// Send the offer received by the other leg to this leg's peer, using the same method
+async fn forward_offer(
+    call: &mut Call<LegMedia>,
+    is_update: bool,
+) -> Result<sdp_types::SessionDescription, StatusCode> {
+    let result = if is_update {
+        call.update().await
+    } else {
+        call.renegotiate().await
+    };
+
+    match result {
+        Ok(()) => remote_sdp(call).ok_or(StatusCode::NOT_ACCEPTABLE_HERE),
+        Err(CallError::ReInviteRejected(code) | CallError::UpdateRejected(code)) => Err(code),
+        Err(e) => {
+            log::warn!("Failed to forward SDP offer, {e}");
+            Err(StatusCode::SERVER_INTERNAL_ERROR)
+        }
+    }
+}
+
+/// Latest SDP received by the call, if it uses SDP pass-through
+fn remote_sdp(call: &mut Call<LegMedia>) -> Option<sdp_types::SessionDescription> {
+    match call.media() {
+        LegMedia::PassThrough(media) => media.remote_sdp(),
+        #[cfg(feature = "rtc")]
+        LegMedia::Anchored(..) => None,
+    }
+}
+
+/// Status code used to decline the inbound call, authentication challenges are meant for the B2BUA only
+fn relayed_status(line: &StatusLine) -> StatusCode {
+    if line.code == StatusCode::UNAUTHORIZED
+        || line.code == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    {
+        StatusCode::FORBIDDEN
+    } else {
+        line.code
+    }
+}
+
+async fn decline(inbound: InboundCall<LegMedia>, code: StatusCode) {
+    if let Err(e) = inbound.decline(code, None).await {
+        log::warn!("Failed to decline inbound call, {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{
+        TakeRequests, TestMedia, authenticator, contact, endpoint, next_event, within,
+    };
+    use bytes::Bytes;
+    use bytesstr::BytesStr;
+    use sip_auth::DigestError;
+    use sip_core::Endpoint;
+    use sip_types::Method;
+    use sip_types::header::typed::{ContentType, Reason};
+    use tokio::task::JoinHandle;
+
+    /// Result of alice's call, the codes of all relayed provisional responses and the final response code on failure
+    type AliceResult = (Vec<StatusCode>, Result<Call<TestMedia>, StatusCode>);
+
+    /// Alice calls bob through a B2BUA using SDP pass-through, bob handles the INVITE using `bob`
+    async fn call_through_b2bua<F, T>(
+        alice_media: TestMedia,
+        bob: impl FnOnce(InboundCall<TestMedia>) -> F + Send + 'static,
+    ) -> (
+        AliceResult,
+        Result<B2bua, B2buaError<DigestError>>,
+        T,
+        [Endpoint; 3],
+    )
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (b2bua_invites, mut b2bua_incoming) = TakeRequests::new(Method::INVITE);
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (b2bua_endpoint, b2bua) =
+            endpoint("b2bua", |builder| builder.add_layer(b2bua_invites)).await;
+        let (bob_endpoint, bob_uri) =
+            endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+
+        let bridge = {
+            let b2bua_endpoint = b2bua_endpoint.clone();
+            let b2bua = b2bua.clone();
+
+            tokio::spawn(async move {
+                let invite = b2bua_incoming.recv().await.unwrap();
+                let inbound =
+                    InboundCall::from_invite(b2bua_endpoint, invite, contact(&b2bua)).unwrap();
+
+                B2bua::bridge(
+                    inbound,
+                    authenticator(),
+                    NameAddr::uri(b2bua.clone()),
+                    contact(&b2bua),
+                    bob_uri,
+                    MakeCallOptions::default(),
+                    BridgeMedia::PassThrough,
+                )
+                .await
+            })
+        };
+
+        let bob = {
+            let bob_endpoint = bob_endpoint.clone();
+
+            tokio::spawn(async move {
+                let invite = bob_incoming.recv().await.unwrap();
+                let bob_contact = contact(&invite.line.uri);
+                let inbound = InboundCall::from_invite(bob_endpoint, invite, bob_contact)
+                    .unwrap()
+                    .with_media(TestMedia::default());
+
+                bob(inbound).await
+            })
+        };
+
+        let alice = within(async {
+            let mut outbound_call = match OutboundCall::make(
+                alice_endpoint.clone(),
+                authenticator(),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                b2bua,
+                alice_media,
+            )
+            .await
+            {
+                Ok(outbound_call) => outbound_call,
+                Err(MakeCallError::Failed(line)) => return (vec![], Err(line.code)),
+                Err(e) => panic!("failed to make call, {e:?}"),
+            };
+
+            let mut progress: Vec<_> = outbound_call
+                .early_dialogs()
+                .map(|early_dialog| early_dialog.status.code)
+                .collect();
+
+            loop {
+                match outbound_call.wait_for_progress().await {
+                    Ok(OutboundCallProgress::Early(early_dialog)) => {
+                        progress.push(early_dialog.status.code)
+                    }
+                    Ok(OutboundCallProgress::Completed(completed)) => {
+                        return (progress, Ok(completed.finish().await.unwrap()));
+                    }
+                    Ok(_) => {}
+                    Err(MakeCallCompletionError::Failed(line)) => {
+                        return (progress, Err(line.code));
+                    }
+                    Err(e) => panic!("failed to complete call, {e:?}"),
+                }
+            }
+        })
+        .await;
+
+        let bridge = within(bridge).await.unwrap();
+        let bob = within(bob).await.unwrap();
+
+        (
+            alice,
+            bridge,
+            bob,
+            [alice_endpoint, b2bua_endpoint, bob_endpoint],
+        )
+    }
+
+    async fn accept(inbound: InboundCall<TestMedia>) -> Call<TestMedia> {
+        inbound.accept().await.unwrap()
+    }
+
+    /// Run the B2BUA until both calls have been terminated
+    fn run(mut b2bua: B2bua) -> JoinHandle<(Leg, TerminationReason)> {
+        tokio::spawn(async move {
+            loop {
+                if let B2buaEvent::Terminated { leg, reason } = b2bua.run().await.unwrap() {
+                    return (leg, reason);
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn relayed_status_hides_challenges() {
+        let line = |code| StatusLine { code, reason: None };
+
+        assert_eq!(
+            relayed_status(&line(StatusCode::UNAUTHORIZED)),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            relayed_status(&line(StatusCode::PROXY_AUTHENTICATION_REQUIRED)),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            relayed_status(&line(StatusCode::BUSY_HERE)),
+            StatusCode::BUSY_HERE
+        );
+    }
+
+    #[tokio::test]
+    async fn relay_provisional_and_success_responses() {
+        let ((progress, alice), b2bua, _bob, _endpoints) =
+            call_through_b2bua(TestMedia::default(), async |mut inbound| {
+                inbound
+                    .respond_provisional(StatusCode::RINGING)
+                    .await
+                    .unwrap();
+
+                accept(inbound).await
+            })
+            .await;
+
+        assert_eq!(progress, [StatusCode::RINGING]);
+        assert!(alice.is_ok());
+        assert!(b2bua.is_ok());
+    }
+
+    #[tokio::test]
+    async fn relay_failure_responses() {
+        for (code, relayed) in [
+            (StatusCode::BUSY_HERE, StatusCode::BUSY_HERE),
+            // Authentication challenges are meant for the B2BUA only
+            (
+                StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+                StatusCode::FORBIDDEN,
+            ),
+        ] {
+            let ((_, alice), b2bua, _, _endpoints) =
+                call_through_b2bua(TestMedia::default(), async move |inbound| {
+                    inbound.decline(code, None).await.unwrap();
+                })
+                .await;
+
+            assert_eq!(alice.err(), Some(relayed));
+            assert!(matches!(b2bua, Err(B2buaError::MakeCall(..))));
+        }
+    }
+
+    #[tokio::test]
+    async fn relay_bye_with_reason() {
+        let ((_, alice), b2bua, mut bob, _endpoints) =
+            call_through_b2bua(TestMedia::default(), accept).await;
+
+        let mut alice = alice.unwrap();
+        let b2bua = run(b2bua.unwrap());
+
+        alice.terminate_with_reason(Reason::q850(16)).await.unwrap();
+
+        let reason = next_event(&mut bob, |event| match event {
+            CallEvent::Terminated(reason) => Some(reason),
+            _ => None,
+        })
+        .await;
+
+        let TerminationReason::RemoteBye { reasons } = reason else {
+            panic!("expected RemoteBye, got {reason:?}");
+        };
+        assert_eq!(reasons[0].cause, Some(16));
+
+        let (leg, reason) = within(b2bua).await.unwrap();
+        assert_eq!(leg, Leg::Inbound);
+        assert!(matches!(reason, TerminationReason::RemoteBye { .. }));
+    }
+
+    #[tokio::test]
+    async fn relay_info() {
+        let ((_, alice), b2bua, mut bob, _endpoints) =
+            call_through_b2bua(TestMedia::default(), accept).await;
+
+        let mut alice = alice.unwrap();
+        let _b2bua = run(b2bua.unwrap());
+
+        let content_type = ContentType(BytesStr::from_static("application/x-test"));
+
+        for code in [StatusCode::OK, StatusCode::NOT_ACCEPTABLE_HERE] {
+            let send = alice.send_info(content_type.clone(), Bytes::from_static(b"test"));
+
+            let received = async {
+                let info = next_event(&mut bob, |event| match event {
+                    CallEvent::InfoReceived(info) => Some(info),
+                    _ => None,
+                })
+                .await;
+
+                assert_eq!(info.content_type(), &content_type);
+                assert_eq!(&info.body()[..], b"test");
+
+                info.respond(code).await.unwrap();
+            };
+
+            let (result, ()) = within(async { tokio::join!(send, received) }).await;
+
+            match result {
+                Ok(()) => assert_eq!(code, StatusCode::OK),
+                Err(CallError::InfoRejected(rejected)) => assert_eq!(rejected, code),
+                Err(e) => panic!("failed to send INFO, {e:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn offerless_invite() {
+        let alice_media = TestMedia {
+            no_offer: true,
+            ..TestMedia::default()
+        };
+
+        let ((_, alice), b2bua, mut bob, _endpoints) =
+            call_through_b2bua(alice_media, accept).await;
+
+        let mut alice = alice.unwrap();
+        assert!(b2bua.is_ok());
+
+        // Bob's offer in the 2XX response is answered by alice in the ACK
+        assert_eq!(alice.media().offers.len(), 1);
+        assert_eq!(bob.media().answers.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn forward_offers_with_same_method() {
+        let ((_, alice), b2bua, mut bob, _endpoints) =
+            call_through_b2bua(TestMedia::default(), accept).await;
+
+        let mut alice = alice.unwrap();
+        let _b2bua = run(b2bua.unwrap());
+
+        for method in [Method::UPDATE, Method::INVITE] {
+            let send = async {
+                if method == Method::UPDATE {
+                    alice.update().await
+                } else {
+                    alice.renegotiate().await
+                }
+            };
+
+            let received = next_event(&mut bob, |event| match event {
+                CallEvent::ReInviteCompleted {
+                    method,
+                    by_peer: true,
+                    code,
+                } => Some((method, code)),
+                _ => None,
+            });
+
+            let (result, received) = within(async { tokio::join!(send, received) }).await;
+            result.unwrap();
+
+            assert_eq!(received, (method, StatusCode::OK));
+        }
+
+        // The INVITE's offer and both forwarded offers
+        assert_eq!(bob.media().offers.len(), 3);
+    }
+}
@@ -0,0 +1,103 @@
+use super::{Leg, media::LegMediaEvent};
+#[cfg(feature = "rtc")]
+use crate::{Codec, MediaEvent, RtpReceiver, RtpSender};
+#[cfg(feature = "rtc")]
+use rtc::rtp_session::SendRtpPacket;
+#[cfg(feature = "rtc")]
+use rtp::RtpTimestamp;
+#[cfg(feature = "rtc")]
+use std::time::{Duration, Instant};
+
+/// Pairs RTP receivers of one leg with RTP senders of the other leg using the same codec
+#[derive(Default)]
+pub(super) struct RtpRelay {
+    #[cfg(feature = "rtc")]
+    receivers: Vec<(Leg, RtpReceiver, Codec)>,
+    #[cfg(feature = "rtc")]
+    senders: Vec<(Leg, RtpSender, Codec)>,
+}
+
+impl RtpRelay {
+    /// Handle a media event of an anchored leg
+    #[cfg_attr(not(feature = "rtc"), allow(unused_variables))]
+    pub(super) fn handle_event(&mut self, leg: Leg, event: LegMediaEvent) {
+        match event {
+            #[cfg(feature = "rtc")]
+            LegMediaEvent::Anchored(MediaEvent::SenderAdded { sender, codec }) => {
+                self.senders.push((leg, sender, codec));
+                self.pair();
+            }
+            #[cfg(feature = "rtc")]
+            LegMediaEvent::Anchored(MediaEvent::ReceiverAdded { receiver, codec }) => {
+                self.receivers.push((leg, receiver, codec));
+                self.pair();
+            }
+            // Telephone-events are reported as CallEvent::Dtmf and relayed by the B2BUA
+            #[cfg(feature = "rtc")]
+            LegMediaEvent::Anchored(MediaEvent::Dtmf(..)) => {}
+        }
+    }
+
+    /// Spawn a relay task for every receiver which has a matching sender on the other leg
+    #[cfg(feature = "rtc")]
+    fn pair(&mut self) {
+        let mut i = 0;
+
+        while i < self.receivers.len() {
+            let (leg, _, codec) = &self.receivers[i];
+
+            let Some(j) = self
+                .senders
+                .iter()
+                .position(|(sender_leg, _, sender_codec)| {
+                    *sender_leg == leg.other() && same_codec(codec, sender_codec)
+                })
+            else {
+                i += 1;
+                continue;
+            };
+
+            let (_, receiver, codec) = self.receivers.remove(i);
+            let (_, sender, sender_codec) = self.senders.remove(j);
+
+            tokio::spawn(relay(receiver, sender, codec.clock_rate, sender_codec.pt));
+        }
+    }
+}
+
+#[cfg(feature = "rtc")]
+fn same_codec(a: &Codec, b: &Codec) -> bool {
+    a.name.eq_ignore_ascii_case(&b.name) && a.clock_rate == b.clock_rate && a.channels == b.channels
+}
+
+/// Forward all RTP packets of the receiver to the sender until either of them is closed
+#[cfg(feature = "rtc")]
+async fn relay(mut receiver: RtpReceiver, mut sender: RtpSender, clock_rate: u32, pt: u8) {
+    // Map the RTP timestamps of the received packets to the media time of the sent packets
+    let mut base: Option<(RtpTimestamp, Instant)> = None;
+
+    while let Some(packet) = receiver.recv().await {
+        let now = Instant::now();
+        let (base_timestamp, base_instant) = *base.get_or_insert((packet.timestamp, now));
+
+        let offset = packet.timestamp.0.wrapping_sub(base_timestamp.0) as i32;
+        let offset_duration =
+            Duration::from_secs_f64(f64::from(offset.unsigned_abs()) / f64::from(clock_rate));
+
+        let media_time = if offset >= 0 {
+            base_instant + offset_duration
+        } else {
+            base_instant
+                .checked_sub(offset_duration)
+                .unwrap_or(base_instant)
+        };
+
+        let packet = SendRtpPacket::new(media_time, pt, packet.payload)
+            .send_at(now)
+            .marker(packet.marker);
+
+        if sender.send(packet).await.is_err() {
+            return;
+        }
+    }
+}
@@ -1,15 +1,26 @@
+use crate::call_record::{CallRecord, SetupTimes};
+use crate::dtmf::{CONTENT_TYPE_DTMF_RELAY, create_dtmf_relay, digit_to_event, parse_dtmf_relay};
 use crate::invite::session::{
-    InviteSession, InviteSessionEvent, ReInviteReceived, SessionRefreshError,
+    InfoReceived, InviteSession, InviteSessionEvent, ReInviteReceived, SessionRefreshError,
+    SessionTermination, UpdateReceived,
 };
-use crate::{MediaBackend, media_backend::CONTENT_TYPE_SDP};
+use crate::transfer::{self, IncomingRefer, TransferProgress};
+use crate::{DtmfEvent, DtmfMode, IncomingInfo, MediaBackend, media_backend::CONTENT_TYPE_SDP};
 use bytes::Bytes;
 use bytesstr::BytesStr;
-use sdp_types::SessionDescription;
-use sip_types::{StatusCode, header::typed::ContentType};
+use sdp_types::{Direction, SessionDescription, TaggedAddress};
+use sip_core::IncomingRequest;
+use sip_core::transaction::TsxResponse;
+use sip_types::header::typed::{ContentType, Reason, ReferTo, ReferredBy, Replaces};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{CodeKind, Method, StatusCode};
 use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::net::Ipv4Addr;
 use std::pin::pin;
+use std::time::Duration;
 use tokio::select;
+use tokio::time::sleep;
 
 /// Error returned by [`Call::run`]
 #[derive(Debug, thiserror::Error)]
@@ -18,6 +29,22 @@ pub enum CallError<M> {
     Core(#[from] sip_core::Error),
     #[error("Failed to refresh the INVITE session")]
     RefreshFailed(#[from] SessionRefreshError),
+    #[error("re-INVITE was rejected with {0:?}")]
+    ReInviteRejected(StatusCode),
+    #[error("UPDATE was rejected with {0:?}")]
+    UpdateRejected(StatusCode),
+    #[error("Response to the re-INVITE or UPDATE did not contain a valid SDP answer")]
+    MissingSdpAnswer,
+    #[error("REFER was rejected with {0:?}")]
+    TransferRejected(StatusCode),
+    #[error("The call has been terminated or its dialog is not established")]
+    NotEstablished,
+    #[error("INFO was rejected with {0:?}")]
+    InfoRejected(StatusCode),
+    #[error("Invalid DTMF digit {0:?}")]
+    InvalidDtmfDigit(char),
+    #[error("No telephone-event payload type has been negotiated")]
+    DtmfNotNegotiated,
     #[error(transparent)]
     Media(M),
 }
@@ -31,7 +58,15 @@ pub struct Call<M: MediaBackend> {
 
     backlog: VecDeque<CallEvent<M>>,
 
-    terminated: bool,
+    /// The call has been put on hold using [`Call::hold`]
+    local_hold: bool,
+    /// Last received SDP offer put the call on hold
+    remote_hold: bool,
+
+    dtmf_mode: DtmfMode,
+
+    setup_times: SetupTimes,
+    termination: Option<TerminationReason>,
 }
 
 /// Event returned by [`Call::run`]
@@ -40,8 +75,50 @@ pub enum CallEvent<M: MediaBackend> {
     Internal(InternalCallEvent),
     /// Media backend specific evet
     Media(M::Event),
-    /// Call has been terminated by the peer
-    Terminated,
+    /// The peer has put the call on hold
+    RemoteHold,
+    /// The peer has resumed the call after putting it on hold
+    RemoteResume,
+    /// The peer asks to transfer the call to another target
+    ReferReceived(Box<IncomingRefer>),
+    /// Progress of a transfer initiated by [`Call::transfer_to`] or [`Call::transfer_attended`]
+    TransferProgress(TransferProgress),
+    /// The peer sent a DTMF digit, either as RFC4733 telephone-event or SIP INFO request
+    Dtmf(DtmfEvent),
+    /// The peer sent an INFO request with a body which isn't handled by the call (RFC6086)
+    InfoReceived(Box<IncomingInfo>),
+    /// A re-INVITE or UPDATE request carrying an SDP offer has been completed with the final response `code`
+    ReInviteCompleted {
+        /// Either `INVITE` or `UPDATE`
+        method: Method,
+        /// The request was sent by the peer
+        by_peer: bool,
+        code: StatusCode,
+    },
+    /// The session has been refreshed (RFC4028), either by a local refresh request or an UPDATE without body
+    /// received from the peer. Refreshes using re-INVITEs of the peer are reported as [`CallEvent::ReInviteCompleted`].
+    SessionRefreshed { by_peer: bool },
+    /// Call detail record including the media statistics, returned once right before [`CallEvent::Terminated`]
+    Record(Box<CallRecord>),
+    /// The call has been terminated, subsequent calls to [`Call::run`] keep returning this event
+    Terminated(TerminationReason),
+}
+
+/// Why a [`Call`] has been terminated, returned by [`CallEvent::Terminated`]
+#[derive(Debug, Clone)]
+pub enum TerminationReason {
+    /// Terminated using [`Call::terminate`] or [`Call::terminate_with_reason`]
+    LocalBye { reason: Option<Reason> },
+    /// The peer sent a BYE request, containing the given `Reason` headers (RFC3326)
+    RemoteBye { reasons: Vec<Reason> },
+    /// The session timer expired or the session couldn't be refreshed (RFC4028)
+    SessionExpired,
+    /// Replaced by another call using the `Replaces` header (RFC3891)
+    Replaced,
+    /// A request to refresh the session failed to reach the peer
+    TransportFailure,
+    /// The peer never acknowledged the response to its re-INVITE
+    AckTimeout,
 }
 
 /// Internal event, must be passed to [`Call::handle_internal_event`]
@@ -49,13 +126,24 @@ pub struct InternalCallEvent {
     event: Box<InviteSessionEvent>,
 }
 
+impl InternalCallEvent {
+    /// Returns if the event is an UPDATE request received from the peer
+    pub(crate) fn is_update(&self) -> bool {
+        matches!(*self.event, InviteSessionEvent::UpdateReceived(..))
+    }
+}
+
 impl<M: MediaBackend> Call<M> {
-    pub(crate) fn new(invite_session: InviteSession, media: M) -> Self {
+    pub(crate) fn new(invite_session: InviteSession, media: M, setup_times: SetupTimes) -> Self {
         Self {
             invite_session: Some(invite_session),
             media,
             backlog: VecDeque::new(),
-            terminated: false,
+            local_hold: false,
+            remote_hold: false,
+            dtmf_mode: DtmfMode::default(),
+            setup_times,
+            termination: None,
         }
     }
 
@@ -73,16 +161,18 @@ impl<M: MediaBackend> Call<M> {
             return Ok(event);
         }
 
-        let Some(invite_session) = &mut self.invite_session else {
-            return Ok(CallEvent::Terminated);
-        };
+        if let Some(reason) = &self.termination {
+            return Ok(CallEvent::Terminated(reason.clone()));
+        }
+
+        let invite_session = self.invite_session.as_mut().unwrap();
 
         let event = select! {
             invite_session_event = invite_session.run() => {
                 CallEvent::Internal(InternalCallEvent { event: Box::new(invite_session_event?) })
             },
             media_event = self.media.run() => {
-                CallEvent::Media(media_event.map_err(CallError::Media)?)
+                media_call_event(media_event.map_err(CallError::Media)?)
             }
         };
 
@@ -100,26 +190,134 @@ impl<M: MediaBackend> Call<M> {
 
         match *event.event {
             InviteSessionEvent::RefreshNeeded => {
-                let refresh = pin!(invite_session.refresh());
+                let result = {
+                    let refresh = pin!(invite_session.refresh());
+                    run_media_and_future(&mut self.backlog, &mut self.media, refresh).await
+                };
 
-                run_media_and_future(&mut self.backlog, &mut self.media, refresh).await?;
+                match result {
+                    Ok(()) => {
+                        self.backlog
+                            .push_back(CallEvent::SessionRefreshed { by_peer: false });
+                    }
+                    Err(CallError::RefreshFailed(SessionRefreshError::UnexpectedStatus(code)))
+                        if code == StatusCode::REQUEST_PENDING =>
+                    {
+                        // The peer's own re-INVITE refreshes the session as well
+                    }
+                    Err(CallError::RefreshFailed(SessionRefreshError::Core(e))) => {
+                        log::warn!("Failed to send session refresh, terminating call: {e}");
+                        self.terminate_internal(TerminationReason::TransportFailure, None)
+                            .await;
+                    }
+                    Err(CallError::RefreshFailed(e)) => {
+                        log::warn!("Failed to refresh session, terminating call: {e}");
+                        self.session_expired().await;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            InviteSessionEvent::SessionExpired => {
+                self.session_expired().await;
             }
             InviteSessionEvent::ReInviteReceived(event) => {
                 self.handle_reinvite(event).await?;
             }
+            InviteSessionEvent::UpdateReceived(event) => {
+                self.handle_update(event).await?;
+            }
             InviteSessionEvent::Bye(event) => {
-                invite_session.handle_bye(event).await?;
+                let reasons = event.reasons();
 
-                self.backlog.push_back(CallEvent::Terminated);
+                // The session is over even if responding to the BYE fails
+                self.set_terminated(TerminationReason::RemoteBye { reasons });
+
+                self.invite_session
+                    .as_mut()
+                    .unwrap()
+                    .handle_bye(event)
+                    .await?;
+            }
+            InviteSessionEvent::ReferReceived(event) => {
+                match IncomingRefer::new(invite_session.dialog.clone(), event) {
+                    Ok(refer) => self
+                        .backlog
+                        .push_back(CallEvent::ReferReceived(Box::new(refer))),
+                    Err(err) => {
+                        let (event, code) = *err;
+                        transfer::respond(&invite_session.dialog, event, code).await?;
+                    }
+                }
             }
-            InviteSessionEvent::Terminated => {
-                self.backlog.push_back(CallEvent::Terminated);
+            InviteSessionEvent::NotifyReceived(event) => {
+                let progress = transfer::handle_notify(&invite_session.dialog, event).await?;
+
+                self.backlog
+                    .push_back(CallEvent::TransferProgress(progress));
+            }
+            InviteSessionEvent::InfoReceived(event) => {
+                self.handle_info(event).await?;
+            }
+            InviteSessionEvent::Replaced => {
+                self.terminate_internal(TerminationReason::Replaced, None)
+                    .await;
+            }
+            InviteSessionEvent::Terminated(termination) => {
+                let reason = match termination {
+                    SessionTermination::Local { reason } => TerminationReason::LocalBye { reason },
+                    SessionTermination::Remote { reasons } => {
+                        TerminationReason::RemoteBye { reasons }
+                    }
+                };
+
+                self.set_terminated(reason);
             }
         }
 
         Ok(())
     }
 
+    fn set_terminated(&mut self, reason: TerminationReason) {
+        if self.termination.is_some() {
+            return;
+        }
+
+        let record = CallRecord::new(
+            self.invite_session.as_ref().unwrap(),
+            self.setup_times,
+            &reason,
+            self.media.media_stats(),
+        );
+
+        self.termination = Some(reason.clone());
+        self.backlog.push_back(CallEvent::Record(Box::new(record)));
+        self.backlog.push_back(CallEvent::Terminated(reason));
+    }
+
+    /// Send a BYE request for a call which can no longer be continued, errors are only logged
+    async fn terminate_internal(&mut self, reason: TerminationReason, header: Option<Reason>) {
+        let invite_session = self.invite_session.as_mut().unwrap();
+
+        let result = match &header {
+            Some(header) => invite_session.terminate_with_reason(header).await,
+            None => invite_session.terminate().await,
+        };
+
+        if let Err(e) = result {
+            log::warn!("Failed to send BYE after {reason:?}, {e:?}");
+        }
+
+        self.set_terminated(reason);
+    }
+
+    async fn session_expired(&mut self) {
+        self.terminate_internal(
+            TerminationReason::SessionExpired,
+            Some(Reason::sip(StatusCode::REQUEST_TIMEOUT).with_text("Session Timer Expired")),
+        )
+        .await;
+    }
+
     async fn handle_reinvite(
         &mut self,
         event: ReInviteReceived,
@@ -137,14 +335,22 @@ impl<M: MediaBackend> Call<M> {
         if invite_contains_sdp {
             let Some(sdp_offer) = parse_sdp_body(invite.body.clone()) else {
                 respond_failure(invite_session, event, StatusCode::BAD_REQUEST).await?;
+                self.reinvite_completed(Method::INVITE, true, StatusCode::BAD_REQUEST);
                 return Ok(());
             };
 
+            let remote_hold = is_hold_offer(&sdp_offer);
+
             let sdp_answer = match self.media.receive_sdp_offer(sdp_offer).await {
                 Ok(sdp_answer) => sdp_answer,
                 Err(e) => {
                     respond_failure(invite_session, event, StatusCode::SERVER_INTERNAL_ERROR)
                         .await?;
+                    self.reinvite_completed(
+                        Method::INVITE,
+                        true,
+                        StatusCode::SERVER_INTERNAL_ERROR,
+                    );
                     return Err(CallError::Media(e));
                 }
             };
@@ -157,15 +363,29 @@ impl<M: MediaBackend> Call<M> {
             response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
             response.msg.body = sdp_answer.to_string().into();
 
-            let respond_success = pin!(invite_session.handle_reinvite_success(event, response));
+            let result = {
+                let respond_success = pin!(invite_session.handle_reinvite_success(event, response));
+
+                run_media_and_future(&mut self.backlog, &mut self.media, respond_success).await
+            };
+
+            if self.ack_received(result).await?.is_none() {
+                return Ok(());
+            }
 
-            run_media_and_future(&mut self.backlog, &mut self.media, respond_success).await?;
+            self.reinvite_completed(Method::INVITE, true, StatusCode::OK);
+            self.set_remote_hold(remote_hold);
         } else {
             let sdp_offer = match self.media.create_sdp_offer().await {
                 Ok(sdp_answer) => sdp_answer,
                 Err(e) => {
                     respond_failure(invite_session, event, StatusCode::SERVER_INTERNAL_ERROR)
                         .await?;
+                    self.reinvite_completed(
+                        Method::INVITE,
+                        true,
+                        StatusCode::SERVER_INTERNAL_ERROR,
+                    );
                     return Err(CallError::Media(e));
                 }
             };
@@ -177,10 +397,17 @@ impl<M: MediaBackend> Call<M> {
             response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
             response.msg.body = sdp_offer.to_string().into();
 
-            let respond_success = pin!(invite_session.handle_reinvite_success(event, response));
+            let result = {
+                let respond_success = pin!(invite_session.handle_reinvite_success(event, response));
 
-            let ack =
-                run_media_and_future(&mut self.backlog, &mut self.media, respond_success).await?;
+                run_media_and_future(&mut self.backlog, &mut self.media, respond_success).await
+            };
+
+            let Some(ack) = self.ack_received(result).await? else {
+                return Ok(());
+            };
+
+            self.reinvite_completed(Method::INVITE, true, StatusCode::OK);
 
             let ack_contains_sdp = ack
                 .headers
@@ -208,22 +435,482 @@ impl<M: MediaBackend> Call<M> {
         Ok(())
     }
 
+    /// Terminate the call if the peer never acknowledged the 2xx response to its re-INVITE (RFC3261 Section 13.3.1.4)
+    async fn ack_received(
+        &mut self,
+        result: Result<IncomingRequest, CallError<M::Error>>,
+    ) -> Result<Option<IncomingRequest>, CallError<M::Error>> {
+        match result {
+            Ok(ack) => Ok(Some(ack)),
+            Err(CallError::Core(sip_core::Error::RequestTimedOut)) => {
+                self.terminate_internal(
+                    TerminationReason::AckTimeout,
+                    Some(Reason::sip(StatusCode::REQUEST_TIMEOUT).with_text("ACK Timeout")),
+                )
+                .await;
+
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn reinvite_completed(&mut self, method: Method, by_peer: bool, code: StatusCode) {
+        self.backlog.push_back(CallEvent::ReInviteCompleted {
+            method,
+            by_peer,
+            code,
+        });
+    }
+
+    async fn handle_update(&mut self, event: UpdateReceived) -> Result<(), CallError<M::Error>> {
+        let invite_session = self.invite_session.as_mut().unwrap();
+
+        let UpdateReceived { update, .. } = &event;
+
+        if update.body.is_empty() {
+            // Session refresh without an offer
+            let response = invite_session
+                .dialog
+                .create_response(update, StatusCode::OK, None)?;
+
+            invite_session.respond_update(event, response).await?;
+
+            self.backlog
+                .push_back(CallEvent::SessionRefreshed { by_peer: true });
+            return Ok(());
+        }
+
+        let is_sdp = update
+            .headers
+            .get_named::<ContentType>()
+            .is_ok_and(|c| c == CONTENT_TYPE_SDP);
+
+        let Some(sdp_offer) = is_sdp
+            .then(|| parse_sdp_body(update.body.clone()))
+            .flatten()
+        else {
+            let response =
+                invite_session
+                    .dialog
+                    .create_response(update, StatusCode::BAD_REQUEST, None)?;
+
+            invite_session.respond_update(event, response).await?;
+            self.reinvite_completed(Method::UPDATE, true, StatusCode::BAD_REQUEST);
+            return Ok(());
+        };
+
+        let remote_hold = is_hold_offer(&sdp_offer);
+
+        let sdp_answer = match self.media.receive_sdp_offer(sdp_offer).await {
+            Ok(sdp_answer) => sdp_answer,
+            Err(e) => {
+                let response = invite_session.dialog.create_response(
+                    update,
+                    StatusCode::SERVER_INTERNAL_ERROR,
+                    None,
+                )?;
+
+                invite_session.respond_update(event, response).await?;
+                self.reinvite_completed(Method::UPDATE, true, StatusCode::SERVER_INTERNAL_ERROR);
+                return Err(CallError::Media(e));
+            }
+        };
+
+        let mut response = invite_session
+            .dialog
+            .create_response(update, StatusCode::OK, None)?;
+        response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+        response.msg.body = sdp_answer.to_string().into();
+
+        invite_session.respond_update(event, response).await?;
+
+        self.reinvite_completed(Method::UPDATE, true, StatusCode::OK);
+        self.set_remote_hold(remote_hold);
+
+        Ok(())
+    }
+
+    async fn handle_info(&mut self, event: InfoReceived) -> Result<(), CallError<M::Error>> {
+        let invite_session = self.invite_session.as_mut().unwrap();
+
+        let InfoReceived { info, transaction } = event;
+
+        let content_type = info.headers.get_named::<ContentType>().ok();
+        let is_dtmf_relay = content_type
+            .as_ref()
+            .is_some_and(|c| *c == CONTENT_TYPE_DTMF_RELAY);
+
+        // All other INFO requests with a body are left to the user, e.g. to relay them
+        if let Some(content_type) = content_type
+            && !is_dtmf_relay
+            && !info.body.is_empty()
+        {
+            let info = IncomingInfo::new(
+                invite_session.dialog.clone(),
+                InfoReceived { info, transaction },
+                content_type,
+            );
+
+            self.backlog
+                .push_back(CallEvent::InfoReceived(Box::new(info)));
+
+            return Ok(());
+        }
+
+        let dtmf = is_dtmf_relay
+            .then(|| str::from_utf8(&info.body).ok().and_then(parse_dtmf_relay))
+            .flatten();
+
+        let code = if dtmf.is_some() || info.body.is_empty() {
+            StatusCode::OK
+        } else if is_dtmf_relay {
+            StatusCode::BAD_REQUEST
+        } else {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        };
+
+        let response = invite_session.dialog.create_response(&info, code, None)?;
+        transaction.respond(response).await?;
+
+        if let Some(dtmf) = dtmf {
+            self.backlog.push_back(CallEvent::Dtmf(dtmf));
+        }
+
+        Ok(())
+    }
+
+    fn set_remote_hold(&mut self, remote_hold: bool) {
+        if remote_hold != self.remote_hold {
+            self.remote_hold = remote_hold;
+
+            self.backlog.push_back(if remote_hold {
+                CallEvent::RemoteHold
+            } else {
+                CallEvent::RemoteResume
+            });
+        }
+    }
+
+    /// Put the call on hold
+    ///
+    /// Sends a re-INVITE with an SDP offer in which all media is marked as `sendonly` or `inactive`.
+    /// If the re-INVITE fails, the media stays in its previous state.
+    pub async fn hold(&mut self) -> Result<(), CallError<M::Error>> {
+        self.set_local_hold(true).await
+    }
+
+    /// Resume the call after it has been put on hold using [`Call::hold`]
+    pub async fn resume(&mut self) -> Result<(), CallError<M::Error>> {
+        self.set_local_hold(false).await
+    }
+
+    /// Returns if the call has been put on hold using [`Call::hold`]
+    pub fn is_local_hold(&self) -> bool {
+        self.local_hold
+    }
+
+    async fn set_local_hold(&mut self, hold: bool) -> Result<(), CallError<M::Error>> {
+        self.media.set_hold(hold);
+
+        match self.renegotiate().await {
+            Ok(()) => {
+                self.local_hold = hold;
+                Ok(())
+            }
+            Err(e) => {
+                self.media.set_hold(self.local_hold);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns if the peer has put the call on hold
+    pub fn is_remote_hold(&self) -> bool {
+        self.remote_hold
+    }
+
+    /// Send a re-INVITE with a new SDP offer created by the media backend
+    ///
+    /// Use this after modifying the media backend's session to apply the changes. When the peer responds with
+    /// `491 Request Pending` the re-INVITE is retried after a randomized back-off (RFC3261 Section 14.1).
+    ///
+    /// The media backend keeps running while waiting for responses, media events are returned by [`Call::run`].
+    ///
+    /// This function is not cancel safe.
+    pub async fn renegotiate(&mut self) -> Result<(), CallError<M::Error>> {
+        self.send_sdp_offer(Method::INVITE).await
+    }
+
+    /// Send an UPDATE request with a new SDP offer created by the media backend (RFC3311)
+    ///
+    /// Like [`Call::renegotiate`], but without the overhead of a re-INVITE transaction. Requires the peer to
+    /// support UPDATE requests, otherwise [`CallError::UpdateRejected`] is returned.
+    ///
+    /// This function is not cancel safe.
+    pub async fn update(&mut self) -> Result<(), CallError<M::Error>> {
+        self.send_sdp_offer(Method::UPDATE).await
+    }
+
+    async fn send_sdp_offer(&mut self, method: Method) -> Result<(), CallError<M::Error>> {
+        let invite_session = self.invite_session.as_mut().unwrap();
+
+        let response = loop {
+            let sdp_offer = self
+                .media
+                .create_sdp_offer()
+                .await
+                .map_err(CallError::Media)?;
+
+            let mut request = invite_session.dialog.create_request(method.clone());
+            request.headers.insert_named(&CONTENT_TYPE_SDP);
+            request.body = sdp_offer.to_string().into();
+
+            let result = {
+                let send = pin!(async {
+                    if method == Method::UPDATE {
+                        invite_session.update(request).await
+                    } else {
+                        invite_session.reinvite(request).await
+                    }
+                });
+
+                run_media_and_future(&mut self.backlog, &mut self.media, send).await
+            };
+
+            match result {
+                Ok(response) => {
+                    self.backlog.push_back(CallEvent::ReInviteCompleted {
+                        method,
+                        by_peer: false,
+                        code: response.line.code,
+                    });
+
+                    break response;
+                }
+                Err(CallError::RefreshFailed(SessionRefreshError::UnexpectedStatus(code)))
+                    if code == StatusCode::REQUEST_PENDING =>
+                {
+                    let backoff = invite_session.request_pending_backoff();
+
+                    log::debug!("{method} rejected with 491, retrying in {backoff:?}");
+
+                    let backoff = pin!(async {
+                        sleep(backoff).await;
+                        Ok::<_, sip_core::Error>(())
+                    });
+
+                    run_media_and_future(&mut self.backlog, &mut self.media, backoff).await?;
+                }
+                Err(CallError::RefreshFailed(SessionRefreshError::UnexpectedStatus(code))) => {
+                    let error = if method == Method::UPDATE {
+                        CallError::UpdateRejected(code)
+                    } else {
+                        CallError::ReInviteRejected(code)
+                    };
+
+                    self.backlog.push_back(CallEvent::ReInviteCompleted {
+                        method,
+                        by_peer: false,
+                        code,
+                    });
+
+                    return Err(error);
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let Some(sdp_answer) = parse_sdp_response(response) else {
+            return Err(CallError::MissingSdpAnswer);
+        };
+
+        self.media
+            .receive_sdp_answer(sdp_answer)
+            .await
+            .map_err(CallError::Media)
+    }
+
+    /// Transfer the peer to the given `target` (blind transfer)
+    ///
+    /// Sends a REFER request and returns once the peer has accepted it.
+    /// The progress of the transfer is reported using [`CallEvent::TransferProgress`].
+    ///
+    /// This function is not cancel safe.
+    pub async fn transfer_to(&mut self, target: SipUri) -> Result<(), CallError<M::Error>> {
+        self.send_refer(ReferTo::new(NameAddr::uri(target))).await
+    }
+
+    /// Transfer the peer to the peer of the `other` call (attended transfer)
+    ///
+    /// The REFER request contains a `Replaces` header embedded in the `Refer-To` URI, which makes the transfer target
+    /// replace the `other` call with the new one. The `other` call will be terminated by the transfer target.
+    ///
+    /// Returns [`CallError::NotEstablished`] if either call has already been terminated.
+    ///
+    /// This function is not cancel safe.
+    pub async fn transfer_attended<O: MediaBackend>(
+        &mut self,
+        other: &Call<O>,
+    ) -> Result<(), CallError<M::Error>> {
+        let other_dialog = match &other.invite_session {
+            Some(invite_session) if other.termination.is_none() => &invite_session.dialog,
+            _ => return Err(CallError::NotEstablished),
+        };
+
+        let Some(from_tag) = other_dialog.local_fromto.tag.clone() else {
+            return Err(CallError::NotEstablished);
+        };
+
+        // Replaces must be seen from the perspective of the transfer target
+        let replaces = Replaces {
+            call_id: other_dialog.call_id.0.clone(),
+            from_tag,
+            to_tag: other_dialog.peer_fromto.tag.clone().unwrap_or_default(),
+            early_only: false,
+        };
+
+        let refer_to = ReferTo::new(NameAddr::uri(other_dialog.peer_contact.uri.uri.clone()))
+            .with_replaces(&replaces);
+
+        self.send_refer(refer_to).await
+    }
+
+    async fn send_refer(&mut self, refer_to: ReferTo) -> Result<(), CallError<M::Error>> {
+        if self.termination.is_some() {
+            return Err(CallError::NotEstablished);
+        }
+
+        let dialog = self.invite_session.as_ref().unwrap().dialog.clone();
+
+        let mut refer = dialog.create_request(Method::REFER);
+        refer.headers.insert_named(&refer_to);
+        refer
+            .headers
+            .insert_named(&ReferredBy::new(dialog.local_fromto.uri.clone()));
+        refer.headers.insert_named(&dialog.local_contact);
+
+        let send_refer = pin!(dialog.send_request(refer));
+        let response = run_media_and_future(&mut self.backlog, &mut self.media, send_refer).await?;
+
+        if response.line.code.kind() == CodeKind::Success {
+            Ok(())
+        } else {
+            Err(CallError::TransferRejected(response.line.code))
+        }
+    }
+
+    /// Set how digits are sent by [`Call::send_dtmf`], defaults to [`DtmfMode::Auto`]
+    pub fn set_dtmf_mode(&mut self, mode: DtmfMode) {
+        self.dtmf_mode = mode;
+    }
+
+    /// Send the DTMF `digits` (`0-9`, `*`, `#` and `A-D`) as tones of the given `duration`
+    ///
+    /// Depending on the [`DtmfMode`] the digits are queued as RFC4733 telephone-events in the media backend, which
+    /// sends them while [`Call::run`] is polled, or sent as SIP INFO requests with an `application/dtmf-relay` body.
+    ///
+    /// This function is not cancel safe.
+    pub async fn send_dtmf(
+        &mut self,
+        digits: &str,
+        duration: Duration,
+    ) -> Result<(), CallError<M::Error>> {
+        if let Some(invalid) = digits.chars().find(|&c| digit_to_event(c).is_none()) {
+            return Err(CallError::InvalidDtmfDigit(invalid));
+        }
+
+        for digit in digits.chars() {
+            if self.dtmf_mode != DtmfMode::Info && self.media.send_dtmf(digit, duration) {
+                continue;
+            }
+
+            if self.dtmf_mode == DtmfMode::Rfc4733 {
+                return Err(CallError::DtmfNotNegotiated);
+            }
+
+            self.send_dtmf_info(digit, duration).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_dtmf_info(
+        &mut self,
+        digit: char,
+        duration: Duration,
+    ) -> Result<(), CallError<M::Error>> {
+        self.send_info(
+            CONTENT_TYPE_DTMF_RELAY,
+            create_dtmf_relay(digit, duration).into(),
+        )
+        .await
+    }
+
+    /// Send an INFO request with the given body to the peer (RFC6086)
+    ///
+    /// Returns [`CallError::InfoRejected`] if the peer didn't respond with a 2XX status code.
+    ///
+    /// This function is not cancel safe.
+    pub async fn send_info(
+        &mut self,
+        content_type: ContentType,
+        body: Bytes,
+    ) -> Result<(), CallError<M::Error>> {
+        let dialog = self.invite_session.as_ref().unwrap().dialog.clone();
+
+        let mut info = dialog.create_request(Method::INFO);
+        info.headers.insert_named(&content_type);
+        info.body = body;
+
+        let send_info = pin!(dialog.send_request(info));
+        let response = run_media_and_future(&mut self.backlog, &mut self.media, send_info).await?;
+
+        if response.line.code.kind() == CodeKind::Success {
+            Ok(())
+        } else {
+            Err(CallError::InfoRejected(response.line.code))
+        }
+    }
+
     /// Returns access to the inner media backend
     pub fn media(&mut self) -> &mut M {
         &mut self.media
     }
 
     /// Terminate the call
-    pub async fn terminate(mut self) -> Result<(), sip_core::Error> {
-        self.invite_session.as_mut().unwrap().terminate().await?;
-        self.terminated = true;
-        Ok(())
+    ///
+    /// Subsequent calls to [`Call::run`] return [`CallEvent::Terminated`] with [`TerminationReason::LocalBye`].
+    pub async fn terminate(&mut self) -> Result<(), sip_core::Error> {
+        self.send_bye(None).await
+    }
+
+    /// Terminate the call with a BYE request containing the given `Reason` header (RFC3326)
+    pub async fn terminate_with_reason(&mut self, reason: Reason) -> Result<(), sip_core::Error> {
+        self.send_bye(Some(reason)).await
+    }
+
+    async fn send_bye(&mut self, reason: Option<Reason>) -> Result<(), sip_core::Error> {
+        if self.termination.is_some() {
+            return Ok(());
+        }
+
+        let invite_session = self.invite_session.as_mut().unwrap();
+
+        let result = match &reason {
+            Some(reason) => invite_session.terminate_with_reason(reason).await,
+            None => invite_session.terminate().await,
+        };
+
+        self.set_terminated(TerminationReason::LocalBye { reason });
+
+        result.map(|_| ())
     }
 }
 
 impl<M: MediaBackend> Drop for Call<M> {
     fn drop(&mut self) {
-        if self.terminated {
+        if self.termination.is_some() {
             return;
         }
 
@@ -255,10 +942,47 @@ async fn respond_failure(
     event.transaction.respond_failure(response).await
 }
 
-fn parse_sdp_body(body: Bytes) -> Option<SessionDescription> {
+pub(crate) fn parse_sdp_body(body: Bytes) -> Option<SessionDescription> {
     SessionDescription::parse(&BytesStr::from_utf8_bytes(body).ok()?).ok()
 }
 
+pub(crate) fn parse_sdp_response(response: TsxResponse) -> Option<SessionDescription> {
+    let contains_sdp = response
+        .headers
+        .get_named::<ContentType>()
+        .map(|c| c == CONTENT_TYPE_SDP)
+        .unwrap_or_default();
+
+    if contains_sdp {
+        parse_sdp_body(response.body)
+    } else {
+        None
+    }
+}
+
+/// Check if the SDP offer puts the call on hold
+///
+/// This is the case if no media is received by the peer. This includes the legacy
+/// way of setting the connection address to `0.0.0.0` (RFC3264 Section 8.4).
+fn is_hold_offer(sdp: &SessionDescription) -> bool {
+    let is_unspecified = |address: &TaggedAddress| matches!(address, TaggedAddress::IP4(ip) if *ip == Ipv4Addr::UNSPECIFIED);
+
+    !sdp.media_descriptions.is_empty()
+        && sdp.media_descriptions.iter().all(|media| {
+            let connection = media.connection.as_ref().or(sdp.connection.as_ref());
+
+            matches!(media.direction, Direction::SendOnly | Direction::Inactive)
+                || connection.is_some_and(|c| is_unspecified(&c.address))
+        })
+}
+
+fn media_call_event<M: MediaBackend>(event: M::Event) -> CallEvent<M> {
+    match M::dtmf_event(&event) {
+        Some(dtmf) => CallEvent::Dtmf(dtmf),
+        None => CallEvent::Media(event),
+    }
+}
+
 // utility to keep running the media backend while resolving some other future
 //
 // primarily used for the SIP session refresh, which can sometimes take some time
@@ -276,8 +1000,432 @@ where
         select! {
             result = &mut future => return Ok(result?),
             media_event = media.run() => {
-                backlog.push_back(CallEvent::Media(media_event.map_err(CallError::Media)?));
+                backlog.push_back(media_call_event(media_event.map_err(CallError::Media)?));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RefreshMethod;
+    use crate::invite::InviteLayer;
+    use crate::test_util::{TestMedia, authenticator, call_pair, next_event, within};
+    use crate::{InboundCall, OutboundCall};
+
+    /// Run the call until it's terminated, the call record must be returned right before
+    async fn terminated(call: &mut Call<TestMedia>) -> TerminationReason {
+        let mut record = false;
+
+        next_event(call, |event| match event {
+            CallEvent::Record(_) => {
+                record = true;
+                None
+            }
+            CallEvent::Terminated(reason) => {
+                assert!(record, "no call record before termination");
+                Some(reason)
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn hold_and_resume() {
+        let mut calls = call_pair().await;
+
+        let (result, ()) = tokio::join!(
+            calls.alice.hold(),
+            next_event(&mut calls.bob, |event| match event {
+                CallEvent::RemoteHold => Some(()),
+                _ => None,
+            })
+        );
+
+        result.unwrap();
+        assert!(calls.alice.is_local_hold());
+        assert!(calls.bob.is_remote_hold());
+
+        let (result, ()) = tokio::join!(
+            calls.alice.resume(),
+            next_event(&mut calls.bob, |event| match event {
+                CallEvent::RemoteResume => Some(()),
+                _ => None,
+            })
+        );
+
+        result.unwrap();
+        assert!(!calls.alice.is_local_hold());
+        assert!(!calls.alice.media().hold);
+    }
+
+    #[tokio::test]
+    async fn failed_hold_restores_media() {
+        let mut calls = call_pair().await;
+
+        calls.bob.media().reject_offers = true;
+
+        let bob = async {
+            let Ok(CallEvent::Internal(event)) = calls.bob.run().await else {
+                panic!("expected re-INVITE");
+            };
+
+            calls.bob.handle_internal_event(event).await
+        };
+
+        let (result, bob_result) = within(async { tokio::join!(calls.alice.hold(), bob) }).await;
+
+        assert!(matches!(bob_result, Err(CallError::Media(_))));
+        assert!(matches!(
+            result,
+            Err(CallError::ReInviteRejected(
+                StatusCode::SERVER_INTERNAL_ERROR
+            ))
+        ));
+        assert!(!calls.alice.is_local_hold());
+        assert!(!calls.alice.media().hold);
+        assert!(!calls.bob.is_remote_hold());
+    }
+
+    #[tokio::test]
+    async fn terminate_with_reason() {
+        let mut calls = call_pair().await;
+
+        let reason = Reason::q850(16).with_text("Normal call clearing");
+
+        let (result, bob_reason) = tokio::join!(
+            calls.alice.terminate_with_reason(reason),
+            terminated(&mut calls.bob)
+        );
+        result.unwrap();
+
+        let TerminationReason::RemoteBye { reasons } = bob_reason else {
+            panic!("expected RemoteBye, got {bob_reason:?}");
+        };
+        assert_eq!(reasons.len(), 1);
+        assert_eq!(reasons[0].protocol, "Q.850");
+        assert_eq!(reasons[0].cause, Some(16));
+
+        let alice_reason = within(terminated(&mut calls.alice)).await;
+        assert!(matches!(
+            alice_reason,
+            TerminationReason::LocalBye { reason: Some(_) }
+        ));
+
+        // Terminating again is a no-op and run keeps returning the same reason
+        calls.alice.terminate().await.unwrap();
+        assert!(matches!(
+            calls.alice.run().await,
+            Ok(CallEvent::Terminated(TerminationReason::LocalBye {
+                reason: Some(_)
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn replaced_call_is_terminated() {
+        let mut calls = call_pair().await;
+
+        let dialog = calls.bob.invite_session.as_ref().unwrap().dialog.clone();
+
+        // Seen from the perspective of a third party sending an INVITE to bob
+        let replaces = Replaces {
+            call_id: dialog.call_id.0.clone(),
+            from_tag: dialog.peer_fromto.tag.clone().unwrap(),
+            to_tag: dialog.local_fromto.tag.clone().unwrap(),
+            early_only: false,
+        };
+
+        within(
+            calls._endpoints[1]
+                .layer::<InviteLayer>()
+                .find_replaced_session(&replaces)
+                .unwrap()
+                .replaced(),
+        )
+        .await;
+
+        let (bob_reason, alice_reason) =
+            tokio::join!(terminated(&mut calls.bob), terminated(&mut calls.alice));
+
+        assert!(matches!(bob_reason, TerminationReason::Replaced));
+        assert!(matches!(
+            alice_reason,
+            TerminationReason::RemoteBye { reasons } if reasons.is_empty()
+        ));
+    }
+
+    #[tokio::test]
+    async fn transfer_of_terminated_call() {
+        let mut calls = call_pair().await;
+        let mut other = call_pair().await;
+
+        let (result, _) = tokio::join!(other.alice.terminate(), terminated(&mut other.bob));
+        result.unwrap();
+
+        assert!(matches!(
+            calls.alice.transfer_attended(&other.alice).await,
+            Err(CallError::NotEstablished)
+        ));
+
+        let target = "sip:carol@example.com".parse().unwrap();
+        assert!(matches!(
+            other.alice.transfer_to(target).await,
+            Err(CallError::NotEstablished)
+        ));
+    }
+
+    async fn incoming_refer(call: &mut Call<TestMedia>) -> Box<IncomingRefer> {
+        next_event(call, |event| match event {
+            CallEvent::ReferReceived(refer) => Some(refer),
+            _ => None,
+        })
+        .await
+    }
+
+    async fn transfer_progress(call: &mut Call<TestMedia>) -> TransferProgress {
+        next_event(call, |event| match event {
+            CallEvent::TransferProgress(progress) => Some(progress),
+            _ => None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn blind_transfer() {
+        let mut calls = call_pair().await;
+
+        let target: SipUri = "sip:carol@example.com".parse().unwrap();
+
+        let (progress, mut notifier) = tokio::join!(
+            async {
+                calls.alice.transfer_to(target.clone()).await.unwrap();
+                transfer_progress(&mut calls.alice).await
+            },
+            async {
+                let refer = incoming_refer(&mut calls.bob).await;
+
+                assert_eq!(format!("{:?}", refer.target()), "sip:carol@example.com");
+                assert!(refer.referred_by().is_some());
+                assert!(refer.invite_headers().get_named::<Replaces>().is_err());
+
+                refer.accept().await.unwrap()
+            }
+        );
+
+        // Accepting the REFER reports 100 Trying
+        assert_eq!(progress.status.unwrap().code, StatusCode::TRYING);
+        assert!(!progress.terminated);
+
+        let (result, progress) = tokio::join!(
+            notifier.notify(StatusCode::RINGING),
+            transfer_progress(&mut calls.alice)
+        );
+        result.unwrap();
+        assert_eq!(progress.status.unwrap().code, StatusCode::RINGING);
+        assert!(!progress.terminated);
+
+        let (result, progress) = tokio::join!(
+            notifier.notify(StatusCode::OK),
+            transfer_progress(&mut calls.alice)
+        );
+        result.unwrap();
+        assert_eq!(progress.status.unwrap().code, StatusCode::OK);
+        assert!(progress.terminated);
+        assert!(notifier.is_terminated());
+    }
+
+    #[tokio::test]
+    async fn rejected_transfer() {
+        let mut calls = call_pair().await;
+
+        let (result, ()) = tokio::join!(
+            calls
+                .alice
+                .transfer_to("sip:carol@example.com".parse().unwrap()),
+            async {
+                let refer = incoming_refer(&mut calls.bob).await;
+                refer.reject(StatusCode::DECLINE).await.unwrap();
+            }
+        );
+
+        assert!(matches!(
+            result,
+            Err(CallError::TransferRejected(StatusCode::DECLINE))
+        ));
+    }
+
+    #[tokio::test]
+    async fn attended_transfer() {
+        // alice is the transferor of both calls, bob of the first call is transferred to bob of the second call
+        let mut transferee = call_pair().await;
+        let mut target = call_pair().await;
+
+        let (progress, mut notifier) = tokio::join!(
+            async {
+                transferee
+                    .alice
+                    .transfer_attended(&target.alice)
+                    .await
+                    .unwrap();
+                transfer_progress(&mut transferee.alice).await
+            },
+            async {
+                let refer = incoming_refer(&mut transferee.bob).await;
+                refer.accept().await.unwrap()
+            }
+        );
+        assert_eq!(progress.status.unwrap().code, StatusCode::TRYING);
+
+        let transferee_dialog = transferee
+            .bob
+            .invite_session
+            .as_ref()
+            .unwrap()
+            .dialog
+            .clone();
+        let replaced_dialog = target.bob.invite_session.as_ref().unwrap().dialog.clone();
+
+        // The transferee calls the target with the Replaces header from the REFER
+        let outbound_call = tokio::spawn(OutboundCall::make_with_headers(
+            transferee._endpoints[1].clone(),
+            authenticator(),
+            transferee_dialog.local_fromto.uri.clone(),
+            transferee_dialog.local_contact.clone(),
+            notifier.target(),
+            notifier.invite_headers(),
+            TestMedia::default(),
+        ));
+
+        let mut invites = target.invites;
+        let target_endpoint = target._endpoints[1].clone();
+
+        let inbound_call = tokio::spawn(async move {
+            let invite = invites.recv().await.unwrap();
+
+            let inbound_call = InboundCall::from_invite(
+                target_endpoint,
+                invite,
+                replaced_dialog.local_contact.clone(),
+            )
+            .unwrap();
+
+            let replaces = inbound_call.replaces().unwrap();
+            assert_eq!(replaces.call_id, replaced_dialog.call_id.0);
+            assert_eq!(
+                Some(&replaces.from_tag),
+                replaced_dialog.peer_fromto.tag.as_ref()
+            );
+            assert_eq!(
+                Some(&replaces.to_tag),
+                replaced_dialog.local_fromto.tag.as_ref()
+            );
+
+            inbound_call
+                .with_media(TestMedia::default())
+                .accept()
+                .await
+                .unwrap()
+        });
+
+        let (_transferee_call, _target_call) = within(async {
+            let mut outbound_call = outbound_call.await.unwrap().unwrap();
+            let call = outbound_call.wait_for_completion().await.unwrap();
+
+            (call.finish().await.unwrap(), inbound_call.await.unwrap())
+        })
+        .await;
+
+        // The replaced call is terminated by the target
+        let (target_reason, transferor_reason) =
+            tokio::join!(terminated(&mut target.bob), terminated(&mut target.alice));
+
+        assert!(matches!(target_reason, TerminationReason::Replaced));
+        assert!(matches!(
+            transferor_reason,
+            TerminationReason::RemoteBye { .. }
+        ));
+
+        let (result, progress) = tokio::join!(
+            notifier.notify(StatusCode::OK),
+            transfer_progress(&mut transferee.alice)
+        );
+        result.unwrap();
+        assert_eq!(progress.status.unwrap().code, StatusCode::OK);
+        assert!(progress.terminated);
+    }
+
+    #[tokio::test]
+    async fn dropped_info_is_rejected() {
+        let mut calls = call_pair().await;
+
+        let (result, ()) = tokio::join!(
+            calls.alice.send_info(
+                ContentType(BytesStr::from_static("application/x-test")),
+                Bytes::from_static(b"test"),
+            ),
+            next_event(&mut calls.bob, |event| match event {
+                CallEvent::InfoReceived(_info) => Some(()),
+                _ => None,
+            })
+        );
+
+        assert!(matches!(
+            result,
+            Err(CallError::InfoRejected(StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        ));
+    }
+
+    /// Let the session timer of the call fire immediately
+    fn fire_session_timer(call: &mut Call<TestMedia>) {
+        let session_timer = &mut call.invite_session.as_mut().unwrap().session_timer;
+
+        session_timer.real_delta_secs = 0;
+        session_timer.reset();
+    }
+
+    #[tokio::test]
+    async fn refresh_session_with_update() {
+        let mut calls = call_pair().await;
+
+        // The initiator refreshes the session by default
+        calls
+            .alice
+            .invite_session
+            .as_mut()
+            .unwrap()
+            .session_timer
+            .refresh_method = RefreshMethod::Update;
+        fire_session_timer(&mut calls.alice);
+
+        tokio::join!(
+            next_event(&mut calls.alice, |event| match event {
+                CallEvent::SessionRefreshed { by_peer: false } => Some(()),
+                _ => None,
+            }),
+            next_event(&mut calls.bob, |event| match event {
+                CallEvent::SessionRefreshed { by_peer: true } => Some(()),
+                _ => None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn terminate_expired_session() {
+        let mut calls = call_pair().await;
+
+        fire_session_timer(&mut calls.bob);
+
+        let (bob_reason, alice_reason) =
+            tokio::join!(terminated(&mut calls.bob), terminated(&mut calls.alice));
+
+        assert!(matches!(bob_reason, TerminationReason::SessionExpired));
+
+        let TerminationReason::RemoteBye { reasons } = alice_reason else {
+            panic!("expected RemoteBye, got {alice_reason:?}");
+        };
+        assert_eq!(reasons[0].cause, Some(408));
+    }
+}
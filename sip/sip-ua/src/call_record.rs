@@ -0,0 +1,442 @@
+//! Call detail records and RFC6035 `vq-rtcpxr` session reports
+
+use crate::TerminationReason;
+use crate::invite::session::{InviteSession, Role};
+use bytesstr::BytesStr;
+use sip_types::StatusCode;
+use sip_types::header::typed::ContentType;
+use sip_types::print::AppendCtx;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) const CONTENT_TYPE_VQ_RTCPXR: ContentType =
+    ContentType(BytesStr::from_static("application/vq-rtcpxr"));
+
+/// Event package used to PUBLISH session reports to a collector (RFC6035)
+pub(crate) const EVENT_VQ_RTCPXR: &str = "vq-rtcpxr";
+
+/// Points in time of the call setup, tracked by [`OutboundCall`](crate::OutboundCall) and
+/// [`InboundCall`](crate::InboundCall) and passed on to the [`Call`](crate::Call)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SetupTimes {
+    invite: SystemTime,
+    progress: Option<SystemTime>,
+    answer: Option<SystemTime>,
+}
+
+impl SetupTimes {
+    /// Start tracking a call whose INVITE is being sent or has just been received
+    pub(crate) fn new() -> Self {
+        Self {
+            invite: SystemTime::now(),
+            progress: None,
+            answer: None,
+        }
+    }
+
+    /// Record the first provisional response other than `100 Trying`
+    pub(crate) fn progress(&mut self, code: StatusCode) {
+        if code.into_u16() > 100 && self.progress.is_none() {
+            self.progress = Some(SystemTime::now());
+        }
+    }
+
+    /// Record the final 2XX response
+    pub(crate) fn answered(&mut self) {
+        self.answer.get_or_insert_with(SystemTime::now);
+    }
+}
+
+/// Why a call has been terminated, a serializable version of [`TerminationReason`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TerminationCause {
+    LocalBye,
+    RemoteBye,
+    SessionExpired,
+    Replaced,
+    TransportFailure,
+    AckTimeout,
+}
+
+impl From<&TerminationReason> for TerminationCause {
+    fn from(reason: &TerminationReason) -> Self {
+        match reason {
+            TerminationReason::LocalBye { .. } => TerminationCause::LocalBye,
+            TerminationReason::RemoteBye { .. } => TerminationCause::RemoteBye,
+            TerminationReason::SessionExpired => TerminationCause::SessionExpired,
+            TerminationReason::Replaced => TerminationCause::Replaced,
+            TerminationReason::TransportFailure => TerminationCause::TransportFailure,
+            TerminationReason::AckTimeout => TerminationCause::AckTimeout,
+        }
+    }
+}
+
+/// Call detail record, returned by [`CallEvent::Record`](crate::CallEvent::Record) when the call has been terminated
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallRecord {
+    pub call_id: String,
+
+    /// Local identity as used in the From or To header
+    pub local: String,
+    pub local_tag: String,
+    /// Identity of the peer as used in the From or To header
+    pub peer: String,
+    pub peer_tag: Option<String>,
+
+    /// The call was initiated locally
+    pub outbound: bool,
+
+    /// When the INVITE was sent or received
+    pub invite_time: SystemTime,
+    /// When the first provisional response (other than `100 Trying`) was sent or received
+    pub progress_time: Option<SystemTime>,
+    /// When the 2XX response to the INVITE was sent or received, `None` if the call was never answered
+    pub answer_time: Option<SystemTime>,
+    /// When the call was terminated
+    pub end_time: SystemTime,
+
+    pub termination: TerminationCause,
+    /// `Reason` headers sent or received with the BYE request
+    pub reasons: Vec<String>,
+
+    /// Statistics of every media stream of the call, in the order of the SDP media descriptions
+    pub streams: Vec<MediaStreamRecord>,
+}
+
+/// Statistics of a single media stream, returned by [`MediaBackend::media_stats`](crate::MediaBackend::media_stats)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MediaStreamRecord {
+    /// Name of the negotiated codec (e.g. `PCMU`)
+    pub codec: String,
+    pub pt: u8,
+    pub clock_rate: u32,
+
+    pub local_address: Option<SocketAddr>,
+    pub remote_address: Option<SocketAddr>,
+    pub local_ssrc: Option<u32>,
+    pub remote_ssrc: Option<u32>,
+
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub packets_lost: u64,
+
+    /// Inbound packet loss ranging from 0 to 1.0
+    pub loss: f32,
+    /// Inbound interarrival jitter
+    pub jitter: Duration,
+    /// Round trip time, calculated from RTCP reports of the peer
+    pub rtt: Option<Duration>,
+
+    /// Packet loss of the outbound stream as reported by the peer, ranging from 0 to 1.0
+    pub remote_loss: Option<f32>,
+    /// Interarrival jitter of the outbound stream as reported by the peer
+    pub remote_jitter: Option<Duration>,
+}
+
+impl CallRecord {
+    pub(crate) fn new(
+        invite_session: &InviteSession,
+        times: SetupTimes,
+        termination: &TerminationReason,
+        streams: Vec<MediaStreamRecord>,
+    ) -> Self {
+        let dialog = &invite_session.dialog;
+        let end_time = SystemTime::now();
+
+        let reasons = match termination {
+            TerminationReason::LocalBye { reason } => {
+                reason.iter().map(ToString::to_string).collect()
+            }
+            TerminationReason::RemoteBye { reasons } => {
+                reasons.iter().map(ToString::to_string).collect()
+            }
+            _ => vec![],
+        };
+
+        Self {
+            call_id: dialog.call_id.0.to_string(),
+            local: dialog.local_fromto.uri.default_print_ctx().to_string(),
+            local_tag: dialog
+                .local_fromto
+                .tag
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            peer: dialog.peer_fromto.uri.default_print_ctx().to_string(),
+            peer_tag: dialog.peer_fromto.tag.as_ref().map(ToString::to_string),
+            outbound: matches!(invite_session.role, Role::Uac),
+            invite_time: times.invite,
+            progress_time: times.progress,
+            answer_time: times.answer,
+            end_time,
+            termination: termination.into(),
+            reasons,
+            streams,
+        }
+    }
+
+    /// Create a RFC6035 `application/vq-rtcpxr` session report of the first media stream
+    ///
+    /// Metrics which haven't been measured (e.g. the round trip time without RTCP reports of the peer) are omitted.
+    /// The remote metrics only contain values reported by the peer using RTCP.
+    pub fn to_vq_rtcpxr(&self) -> String {
+        let mut report = String::new();
+
+        let (orig, from_tag, to_tag) = if self.outbound {
+            (&self.local, Some(&self.local_tag), self.peer_tag.as_ref())
+        } else {
+            (&self.peer, self.peer_tag.as_ref(), Some(&self.local_tag))
+        };
+
+        let _ = write!(
+            report,
+            "VQSessionReport: CallTerm\r\n\
+             CallID: {}\r\n\
+             LocalID: {}\r\n\
+             RemoteID: {}\r\n\
+             OrigID: {}\r\n",
+            self.call_id, self.local, self.peer, orig
+        );
+
+        let stream = self.streams.first();
+
+        if let Some(stream) = stream {
+            write_addr(
+                &mut report,
+                "LocalAddr",
+                stream.local_address,
+                stream.local_ssrc,
+            );
+            write_addr(
+                &mut report,
+                "RemoteAddr",
+                stream.remote_address,
+                stream.remote_ssrc,
+            );
+        }
+
+        let _ = write!(report, "DialogID: {}", self.call_id);
+        if let Some(to_tag) = to_tag {
+            let _ = write!(report, ";to-tag={to_tag}");
+        }
+        if let Some(from_tag) = from_tag {
+            let _ = write!(report, ";from-tag={from_tag}");
+        }
+        report.push_str("\r\n");
+
+        let timestamps = format!(
+            "Timestamps: START={} STOP={}\r\n",
+            format_timestamp(self.answer_time.unwrap_or(self.invite_time)),
+            format_timestamp(self.end_time)
+        );
+
+        report.push_str("LocalMetrics:\r\n");
+        report.push_str(&timestamps);
+
+        let Some(stream) = stream else {
+            return report;
+        };
+
+        let session_desc = format!(
+            "SessionDesc: PT={} PD={} SR={}\r\n",
+            stream.pt, stream.codec, stream.clock_rate
+        );
+
+        report.push_str(&session_desc);
+        let _ = write!(report, "PacketLoss: NLR={:.1}\r\n", stream.loss * 100.0);
+        write_delay(&mut report, stream.rtt, Some(stream.jitter));
+
+        if stream.remote_loss.is_some() || stream.remote_jitter.is_some() {
+            report.push_str("RemoteMetrics:\r\n");
+            report.push_str(&timestamps);
+            report.push_str(&session_desc);
+            if let Some(loss) = stream.remote_loss {
+                let _ = write!(report, "PacketLoss: NLR={:.1}\r\n", loss * 100.0);
+            }
+            // The round trip time is measured locally, the peer's RTCP reports don't contain it
+            write_delay(&mut report, None, stream.remote_jitter);
+        }
+
+        report
+    }
+}
+
+fn write_addr(report: &mut String, name: &str, addr: Option<SocketAddr>, ssrc: Option<u32>) {
+    if addr.is_none() && ssrc.is_none() {
+        return;
+    }
+
+    let _ = write!(report, "{name}:");
+
+    if let Some(addr) = addr {
+        let _ = write!(report, " IP={} PORT={}", addr.ip(), addr.port());
+    }
+
+    if let Some(ssrc) = ssrc {
+        let _ = write!(report, " SSRC=0x{ssrc:08X}");
+    }
+
+    report.push_str("\r\n");
+}
+
+fn write_delay(report: &mut String, rtt: Option<Duration>, jitter: Option<Duration>) {
+    if rtt.is_none() && jitter.is_none() {
+        return;
+    }
+
+    report.push_str("Delay:");
+
+    if let Some(rtt) = rtt {
+        let _ = write!(report, " RTD={}", rtt.as_millis());
+    }
+
+    if let Some(jitter) = jitter {
+        let _ = write!(report, " IAJ={}", jitter.as_millis());
+    }
+
+    report.push_str("\r\n");
+}
+
+/// Format a timestamp as UTC date-time as defined in RFC3339 (e.g. `2004-10-10T18:23:43Z`)
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date (http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1097432623)),
+            "2004-10-10T18:23:43Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    fn record() -> CallRecord {
+        let start = UNIX_EPOCH + Duration::from_secs(1097432623);
+
+        CallRecord {
+            call_id: "6dg37f1890463".into(),
+            local: "Alice <sip:alice@pc33.atlanta.com>".into(),
+            local_tag: "9123dh311".into(),
+            peer: "<sip:bill@net.example.com>".into(),
+            peer_tag: Some("8472761".into()),
+            outbound: true,
+            invite_time: start - Duration::from_secs(5),
+            progress_time: None,
+            answer_time: Some(start),
+            end_time: start + Duration::from_secs(30),
+            termination: TerminationCause::LocalBye,
+            reasons: vec![],
+            streams: vec![MediaStreamRecord {
+                codec: "PCMU".into(),
+                pt: 0,
+                clock_rate: 8000,
+                local_address: Some("10.10.1.100:5000".parse().unwrap()),
+                remote_address: Some("11.1.1.150:1234".parse().unwrap()),
+                local_ssrc: Some(0x2468abcd),
+                remote_ssrc: None,
+                packets_sent: 1500,
+                bytes_sent: 240000,
+                packets_received: 1425,
+                bytes_received: 228000,
+                packets_lost: 75,
+                loss: 0.05,
+                jitter: Duration::from_millis(2),
+                rtt: None,
+                remote_loss: None,
+                remote_jitter: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn vq_rtcpxr_report() {
+        let record = record();
+
+        assert_eq!(
+            record.to_vq_rtcpxr(),
+            "VQSessionReport: CallTerm\r\n\
+             CallID: 6dg37f1890463\r\n\
+             LocalID: Alice <sip:alice@pc33.atlanta.com>\r\n\
+             RemoteID: <sip:bill@net.example.com>\r\n\
+             OrigID: Alice <sip:alice@pc33.atlanta.com>\r\n\
+             LocalAddr: IP=10.10.1.100 PORT=5000 SSRC=0x2468ABCD\r\n\
+             RemoteAddr: IP=11.1.1.150 PORT=1234\r\n\
+             DialogID: 6dg37f1890463;to-tag=8472761;from-tag=9123dh311\r\n\
+             LocalMetrics:\r\n\
+             Timestamps: START=2004-10-10T18:23:43Z STOP=2004-10-10T18:24:13Z\r\n\
+             SessionDesc: PT=0 PD=PCMU SR=8000\r\n\
+             PacketLoss: NLR=5.0\r\n\
+             Delay: IAJ=2\r\n"
+        );
+    }
+
+    #[test]
+    fn vq_rtcpxr_remote_metrics() {
+        let mut record = record();
+        record.streams[0].rtt = Some(Duration::from_millis(40));
+        record.streams[0].remote_loss = Some(0.02);
+        record.streams[0].remote_jitter = Some(Duration::from_millis(5));
+
+        let report = record.to_vq_rtcpxr();
+        let (local, remote) = report.split_once("RemoteMetrics:\r\n").unwrap();
+
+        assert!(local.ends_with("Delay: RTD=40 IAJ=2\r\n"));
+        assert_eq!(
+            remote,
+            "Timestamps: START=2004-10-10T18:23:43Z STOP=2004-10-10T18:24:13Z\r\n\
+             SessionDesc: PT=0 PD=PCMU SR=8000\r\n\
+             PacketLoss: NLR=2.0\r\n\
+             Delay: IAJ=5\r\n"
+        );
+    }
+
+    #[test]
+    fn vq_rtcpxr_unanswered() {
+        let mut record = record();
+        record.answer_time = None;
+
+        assert!(
+            record
+                .to_vq_rtcpxr()
+                .contains("Timestamps: START=2004-10-10T18:23:38Z STOP=2004-10-10T18:24:13Z\r\n")
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use self::layer::DialogEntry;
 use crate::util::{random_sequence_number, random_string};
 use bytesstr::BytesStr;
+use sip_core::transaction::TsxResponse;
 use sip_core::transport::{OutgoingResponse, TargetTransportInfo};
 use sip_core::{Endpoint, IncomingRequest, Request, Result};
 use sip_types::header::HeaderError;
@@ -115,10 +116,15 @@ impl Dialog {
     pub fn create_request(&self, method: Method) -> Request {
         let mut request = Request::new(method.clone(), self.peer_contact.uri.uri.clone());
 
-        let cseq = CSeq::new(
-            self.local_cseq.fetch_add(1, Ordering::Relaxed),
-            method.clone(),
-        );
+        // ACK requests take the CSeq number of the INVITE they acknowledge and must not use up a new one,
+        // otherwise the peer waits for the skipped number before handling the next request
+        let cseq = if method == Method::ACK {
+            self.local_cseq.load(Ordering::Relaxed)
+        } else {
+            self.local_cseq.fetch_add(1, Ordering::Relaxed)
+        };
+
+        let cseq = CSeq::new(cseq, method.clone());
 
         request.headers.insert_type(Name::FROM, &self.local_fromto);
         request.headers.insert_type(Name::TO, &self.peer_fromto);
@@ -137,6 +143,20 @@ impl Dialog {
         request
     }
 
+    /// Send a request created using [`Dialog::create_request`] and wait for the final response
+    pub async fn send_request(&self, request: Request) -> Result<TsxResponse> {
+        let mut target_tp_info = self.target_tp_info.lock().await;
+
+        let mut transaction = self
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+
+        drop(target_tp_info);
+
+        transaction.receive_final().await
+    }
+
     pub fn create_response(
         &self,
         request: &IncomingRequest,
@@ -162,14 +182,16 @@ impl Dialog {
                 response.msg.headers.insert_named(self.endpoint.allowed());
             }
 
-            if let 200..=299 = code {
-                if request.base_headers.to.tag.is_none() {
-                    // Add To-tag to success response to create dialog
-                    response.msg.headers.edit(Name::TO, |to: &mut FromTo| {
-                        to.tag.clone_from(&self.local_fromto.tag);
-                    })?;
-                }
+            if let 101..=299 = code
+                && request.base_headers.to.tag.is_none()
+            {
+                // Add To-tag to provisional and success responses to create the (early) dialog
+                response.msg.headers.edit(Name::TO, |to: &mut FromTo| {
+                    to.tag.clone_from(&self.local_fromto.tag);
+                })?;
+            }
 
+            if let 200..=299 = code {
                 response.msg.headers.insert_named(self.endpoint.supported());
             }
         }
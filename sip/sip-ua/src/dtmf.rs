@@ -0,0 +1,408 @@
+//! DTMF helpers for RFC4733 telephone-events and SIP INFO `application/dtmf-relay` bodies
+
+use bytesstr::BytesStr;
+use sip_types::header::typed::ContentType;
+#[cfg(feature = "rtc")]
+use std::time::Instant;
+use std::{fmt::Write, time::Duration};
+
+pub(crate) const CONTENT_TYPE_DTMF_RELAY: ContentType =
+    ContentType(BytesStr::from_static("application/dtmf-relay"));
+
+/// Interval in which telephone-event updates and retransmissions of the final packet are sent
+#[cfg(feature = "rtc")]
+const TELEPHONE_EVENT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Number of times the final packet of an event is sent (RFC4733 Section 2.5.1.4)
+#[cfg(feature = "rtc")]
+const END_RETRANSMISSIONS: u32 = 3;
+
+/// Volume of generated events as -dBm0, 10 is used by most implementations
+#[cfg(feature = "rtc")]
+const VOLUME: u8 = 10;
+
+/// Time without any packets after which an inbound event whose end packet got lost is considered ended
+#[cfg(feature = "rtc")]
+const END_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A DTMF digit received from the peer, either as RFC4733 telephone-event or SIP INFO request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DtmfEvent {
+    /// One of `0-9`, `*`, `#` or `A-D`
+    pub digit: char,
+    /// Reported length of the tone
+    pub duration: Duration,
+}
+
+/// How [`Call::send_dtmf`](crate::Call::send_dtmf) transmits digits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DtmfMode {
+    /// Send RFC4733 telephone-events if they have been negotiated, otherwise use SIP INFO
+    #[default]
+    Auto,
+    /// Only send RFC4733 telephone-events
+    Rfc4733,
+    /// Only send SIP INFO requests with an `application/dtmf-relay` body
+    Info,
+}
+
+/// Map a DTMF digit to its RFC4733 event code
+pub(crate) fn digit_to_event(digit: char) -> Option<u8> {
+    match digit.to_ascii_uppercase() {
+        c @ '0'..='9' => Some(c as u8 - b'0'),
+        '*' => Some(10),
+        '#' => Some(11),
+        c @ 'A'..='D' => Some(c as u8 - b'A' + 12),
+        _ => None,
+    }
+}
+
+/// Map a RFC4733 event code to a DTMF digit
+pub(crate) fn event_to_digit(event: u8) -> Option<char> {
+    match event {
+        0..=9 => Some((b'0' + event) as char),
+        10 => Some('*'),
+        11 => Some('#'),
+        12..=15 => Some((b'A' + event - 12) as char),
+        _ => None,
+    }
+}
+
+/// Single RTP packet payload of a telephone-event, scheduled relative to the start of the event
+#[cfg(feature = "rtc")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TelephoneEventPacket {
+    pub(crate) offset: Duration,
+    pub(crate) marker: bool,
+    pub(crate) payload: [u8; 4],
+}
+
+/// Create all packets for a single telephone-event (RFC4733 Section 2.5.1)
+///
+/// All packets must share the RTP timestamp of the event's start. The first packet has the marker bit set,
+/// followed by an update every [`TELEPHONE_EVENT_INTERVAL`] and the final packet with the end bit set, which is
+/// sent three times.
+#[cfg(feature = "rtc")]
+pub(crate) fn telephone_event_packets(
+    event: u8,
+    duration: Duration,
+    clock_rate: u32,
+) -> Vec<TelephoneEventPacket> {
+    let units = |d: Duration| {
+        let units = d.as_secs_f64() * f64::from(clock_rate);
+        units.min(f64::from(u16::MAX)) as u16
+    };
+
+    let payload = |end: bool, duration: u16| {
+        let [d0, d1] = duration.to_be_bytes();
+        [event, (u8::from(end) << 7) | VOLUME, d0, d1]
+    };
+
+    let mut packets = vec![TelephoneEventPacket {
+        offset: Duration::ZERO,
+        marker: true,
+        payload: payload(false, 0),
+    }];
+
+    let mut offset = TELEPHONE_EVENT_INTERVAL;
+    while offset < duration {
+        packets.push(TelephoneEventPacket {
+            offset,
+            marker: false,
+            payload: payload(false, units(offset)),
+        });
+
+        offset += TELEPHONE_EVENT_INTERVAL;
+    }
+
+    for i in 0..END_RETRANSMISSIONS {
+        packets.push(TelephoneEventPacket {
+            offset: duration + TELEPHONE_EVENT_INTERVAL * i,
+            marker: false,
+            payload: payload(true, units(duration)),
+        });
+    }
+
+    packets
+}
+
+/// Time from the start of an event until the next event may start
+#[cfg(feature = "rtc")]
+pub(crate) fn telephone_event_length(duration: Duration) -> Duration {
+    duration + TELEPHONE_EVENT_INTERVAL * END_RETRANSMISSIONS
+}
+
+/// Decoded telephone-event payload
+#[cfg(feature = "rtc")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TelephoneEvent {
+    pub(crate) event: u8,
+    pub(crate) end: bool,
+    pub(crate) duration: u16,
+}
+
+#[cfg(feature = "rtc")]
+pub(crate) fn parse_telephone_event(payload: &[u8]) -> Option<TelephoneEvent> {
+    let [event, flags, d0, d1, ..] = *payload else {
+        return None;
+    };
+
+    Some(TelephoneEvent {
+        event,
+        end: flags & 0x80 != 0,
+        duration: u16::from_be_bytes([d0, d1]),
+    })
+}
+
+/// Tracks inbound telephone-events to report every event exactly once
+///
+/// Events are reported when their end packet is received. If all end packets got lost, the event is reported
+/// once the next event starts or no packet has been received for [`END_TIMEOUT`].
+#[cfg(feature = "rtc")]
+#[derive(Debug, Default)]
+pub(crate) struct TelephoneEventReceiver {
+    /// RTP timestamp of the last reported event, used to ignore retransmissions of its end packet
+    last_reported: Option<u32>,
+    /// Event which has not received its end packet yet
+    pending: Option<PendingTelephoneEvent>,
+}
+
+#[cfg(feature = "rtc")]
+#[derive(Debug)]
+struct PendingTelephoneEvent {
+    timestamp: u32,
+    event: u8,
+    duration: u16,
+    last_packet: Instant,
+}
+
+#[cfg(feature = "rtc")]
+impl TelephoneEventReceiver {
+    /// Handle a telephone-event packet with the given RTP timestamp, returns all events that have ended
+    pub(crate) fn receive(
+        &mut self,
+        timestamp: u32,
+        event: TelephoneEvent,
+        now: Instant,
+    ) -> Vec<TelephoneEvent> {
+        let mut ended = Vec::new();
+
+        if self.last_reported == Some(timestamp) {
+            return ended;
+        }
+
+        // A new event started before the end of the previous one has been received
+        if let Some(pending) = self
+            .pending
+            .take_if(|pending| pending.timestamp != timestamp)
+        {
+            ended.push(self.report(pending));
+        }
+
+        if event.end {
+            self.pending = None;
+            self.last_reported = Some(timestamp);
+            ended.push(event);
+        } else {
+            let duration = self.pending.as_ref().map_or(event.duration, |pending| {
+                pending.duration.max(event.duration)
+            });
+
+            self.pending = Some(PendingTelephoneEvent {
+                timestamp,
+                event: event.event,
+                duration,
+                last_packet: now,
+            });
+        }
+
+        ended
+    }
+
+    /// Returns when the pending event times out
+    pub(crate) fn timeout(&self) -> Option<Instant> {
+        self.pending
+            .as_ref()
+            .map(|pending| pending.last_packet + END_TIMEOUT)
+    }
+
+    /// Returns the pending event if it has timed out
+    pub(crate) fn poll_timeout(&mut self, now: Instant) -> Option<TelephoneEvent> {
+        let pending = self
+            .pending
+            .take_if(|pending| now >= pending.last_packet + END_TIMEOUT)?;
+
+        Some(self.report(pending))
+    }
+
+    fn report(&mut self, pending: PendingTelephoneEvent) -> TelephoneEvent {
+        self.last_reported = Some(pending.timestamp);
+
+        TelephoneEvent {
+            event: pending.event,
+            end: true,
+            duration: pending.duration,
+        }
+    }
+}
+
+/// Create an `application/dtmf-relay` body, the duration is given in milliseconds
+pub(crate) fn create_dtmf_relay(digit: char, duration: Duration) -> String {
+    let mut body = String::new();
+    let _ = write!(
+        body,
+        "Signal={}\r\nDuration={}\r\n",
+        digit.to_ascii_uppercase(),
+        duration.as_millis()
+    );
+    body
+}
+
+pub(crate) fn parse_dtmf_relay(body: &str) -> Option<DtmfEvent> {
+    let mut digit = None;
+    let mut duration = Duration::ZERO;
+
+    for line in body.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        if key.trim().eq_ignore_ascii_case("signal") {
+            let mut chars = value.chars();
+            digit = match (chars.next(), chars.next()) {
+                (Some(c), None) => digit_to_event(c).and_then(event_to_digit),
+                // Some implementations send the event code instead of the digit
+                _ => value.parse().ok().and_then(event_to_digit),
+            };
+        } else if key.trim().eq_ignore_ascii_case("duration") {
+            duration = Duration::from_millis(value.parse().ok()?);
+        }
+    }
+
+    Some(DtmfEvent {
+        digit: digit?,
+        duration,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn digit_mapping() {
+        for digit in "0123456789*#ABCD".chars() {
+            assert_eq!(event_to_digit(digit_to_event(digit).unwrap()), Some(digit));
+        }
+
+        assert_eq!(digit_to_event('b'), Some(13));
+        assert_eq!(digit_to_event('E'), None);
+        assert_eq!(event_to_digit(16), None);
+    }
+
+    #[cfg(feature = "rtc")]
+    #[test]
+    fn event_packets() {
+        let packets = telephone_event_packets(5, Duration::from_millis(120), 8000);
+
+        let offsets: Vec<_> = packets.iter().map(|p| p.offset.as_millis()).collect();
+        assert_eq!(offsets, [0, 50, 100, 120, 170, 220]);
+
+        assert!(packets[0].marker);
+        assert!(packets[1..].iter().all(|p| !p.marker));
+
+        assert_eq!(packets[0].payload, [5, 10, 0, 0]);
+        assert_eq!(packets[2].payload, [5, 10, 0x03, 0x20]);
+
+        for packet in &packets[3..] {
+            assert_eq!(packet.payload, [5, 0x80 | 10, 0x03, 0xC0]);
+        }
+
+        assert_eq!(
+            parse_telephone_event(&packets[5].payload),
+            Some(TelephoneEvent {
+                event: 5,
+                end: true,
+                duration: 960
+            })
+        );
+    }
+
+    #[cfg(feature = "rtc")]
+    fn event(event: u8, end: bool, duration: u16) -> TelephoneEvent {
+        TelephoneEvent {
+            event,
+            end,
+            duration,
+        }
+    }
+
+    #[cfg(feature = "rtc")]
+    #[test]
+    fn receive_events() {
+        let mut receiver = TelephoneEventReceiver::default();
+        let now = Instant::now();
+
+        assert_eq!(receiver.receive(100, event(1, false, 0), now), []);
+        assert_eq!(receiver.receive(100, event(1, false, 400), now), []);
+        assert_eq!(
+            receiver.receive(100, event(1, true, 800), now),
+            [event(1, true, 800)]
+        );
+
+        // Retransmissions of the end packet are ignored
+        assert_eq!(receiver.receive(100, event(1, true, 800), now), []);
+        assert_eq!(receiver.timeout(), None);
+    }
+
+    #[cfg(feature = "rtc")]
+    #[test]
+    fn receive_events_without_end() {
+        let mut receiver = TelephoneEventReceiver::default();
+        let now = Instant::now();
+
+        assert_eq!(receiver.receive(100, event(1, false, 400), now), []);
+
+        // The next event starts, the previous one is reported with the last known duration
+        assert_eq!(
+            receiver.receive(2000, event(2, false, 0), now),
+            [event(1, true, 400)]
+        );
+
+        let timeout = receiver.timeout().unwrap();
+        assert_eq!(timeout, now + END_TIMEOUT);
+
+        assert_eq!(receiver.poll_timeout(now), None);
+        assert_eq!(receiver.poll_timeout(timeout), Some(event(2, true, 0)));
+
+        // End packets arriving after the timeout are ignored
+        assert_eq!(receiver.receive(2000, event(2, true, 800), timeout), []);
+        assert_eq!(receiver.timeout(), None);
+    }
+
+    #[test]
+    fn dtmf_relay() {
+        let body = create_dtmf_relay('#', Duration::from_millis(160));
+        assert_eq!(body, "Signal=#\r\nDuration=160\r\n");
+
+        assert_eq!(
+            parse_dtmf_relay(&body),
+            Some(DtmfEvent {
+                digit: '#',
+                duration: Duration::from_millis(160)
+            })
+        );
+
+        assert_eq!(
+            parse_dtmf_relay("signal = 11\nduration = 250"),
+            Some(DtmfEvent {
+                digit: '#',
+                duration: Duration::from_millis(250)
+            })
+        );
+
+        assert_eq!(parse_dtmf_relay("Duration=100"), None);
+    }
+}
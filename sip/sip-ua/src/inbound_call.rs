@@ -1,4 +1,7 @@
-use crate::{Call, MediaBackend, media_backend::CONTENT_TYPE_SDP};
+use crate::call::{parse_sdp_body, parse_sdp_response};
+use crate::invite::session::UpdateReceived;
+use crate::invite::{InviteLayer, SessionTimerConfig};
+use crate::{Call, MediaBackend, call_record::SetupTimes, media_backend::CONTENT_TYPE_SDP};
 use crate::{dialog::Dialog, invite::acceptor::InviteAcceptor};
 use bytesstr::BytesStr;
 use sdp_types::{ParseSessionDescriptionError, SessionDescription};
@@ -8,10 +11,11 @@ use sip_types::{
     StatusCode,
     header::{
         HeaderError,
-        typed::{Contact, ContentType},
+        typed::{Contact, ContentType, Replaces},
     },
 };
 use std::str::Utf8Error;
+use tokio::select;
 
 /// Error returned by [`InboundCall::from_invite`]
 #[derive(Debug, thiserror::Error)]
@@ -29,9 +33,17 @@ pub struct NoMedia;
 
 /// A incoming call that can be accepted or declined
 pub struct InboundCall<M> {
+    endpoint: Endpoint,
     acceptor: InviteAcceptor,
     sdp_offer: Option<SessionDescription>,
+    /// Local SDP sent with early media, repeated when accepting the call
+    sdp_answer: Option<SessionDescription>,
+    /// Early media has been started with a reliable provisional response, further offers can be exchanged using
+    /// UPDATE requests
+    early_negotiated: bool,
+    replaces: Option<Replaces>,
     media: M,
+    setup_times: SetupTimes,
 }
 
 impl InboundCall<NoMedia> {
@@ -71,6 +83,15 @@ impl InboundCall<NoMedia> {
             None
         };
 
+        let replaces = match invite.headers.try_get_named::<Replaces>() {
+            Some(Ok(replaces)) => Some(replaces),
+            Some(Err(e)) => {
+                log::warn!("Ignoring invalid Replaces header in INVITE, {e}");
+                None
+            }
+            None => None,
+        };
+
         let dialog = match Dialog::new_server(endpoint.clone(), &invite, contact) {
             Ok(dialog) => dialog,
             Err(e) => {
@@ -84,23 +105,42 @@ impl InboundCall<NoMedia> {
         let acceptor = InviteAcceptor::new(dialog, invite);
 
         Ok(Self {
+            endpoint,
             acceptor,
             sdp_offer,
+            sdp_answer: None,
+            early_negotiated: false,
+            replaces,
             media: NoMedia,
+            setup_times: SetupTimes::new(),
         })
     }
 
     /// Set the media backend to use for this incoming call
     pub fn with_media<M: MediaBackend>(self, media: M) -> InboundCall<M> {
         InboundCall {
+            endpoint: self.endpoint,
             acceptor: self.acceptor,
             sdp_offer: self.sdp_offer,
+            sdp_answer: self.sdp_answer,
+            early_negotiated: self.early_negotiated,
+            replaces: self.replaces,
             media,
+            setup_times: self.setup_times,
         }
     }
 }
 
 impl<M> InboundCall<M> {
+    /// Set the session timer settings used when accepting the call
+    ///
+    /// When accepting, the call is rejected with `422 Session Interval Too Small` if the session interval requested
+    /// by the peer is smaller than [`SessionTimerConfig::min_se`].
+    pub fn with_session_timer(mut self, config: SessionTimerConfig) -> Self {
+        *self.acceptor.timer_config() = config;
+        self
+    }
+
     /// Send a 100 TRYING response
     pub async fn respond_provisional(
         &mut self,
@@ -114,7 +154,10 @@ impl<M> InboundCall<M> {
 
         let response = self.acceptor.create_response(code, None).await?;
 
-        self.acceptor.respond_provisional(response).await
+        self.acceptor.respond_provisional(response).await?;
+        self.setup_times.progress(code);
+
+        Ok(())
     }
 
     /// Returns if the initial invite contains an SDP offer
@@ -122,6 +165,29 @@ impl<M> InboundCall<M> {
         self.sdp_offer.is_some()
     }
 
+    pub(crate) fn sdp_offer(&self) -> Option<&SessionDescription> {
+        self.sdp_offer.as_ref()
+    }
+
+    pub(crate) fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Returns the `Replaces` header of the INVITE, if any
+    ///
+    /// When accepting a call with a `Replaces` header, the referenced call will be terminated and
+    /// receives [`CallEvent::Terminated`](crate::CallEvent::Terminated) with [`TerminationReason::Replaced`](crate::TerminationReason::Replaced).
+    pub fn replaces(&self) -> Option<&Replaces> {
+        self.replaces.as_ref()
+    }
+
+    /// Returns access to the inner media backend
+    ///
+    /// Can be used to run the media backend during early media, see [`InboundCall::respond_early_media`].
+    pub fn media(&mut self) -> &mut M {
+        &mut self.media
+    }
+
     /// Returns when the call has been cancelled
     pub async fn cancelled(&mut self) {
         self.acceptor.cancelled().await
@@ -154,6 +220,20 @@ pub enum AcceptCallError<M> {
     InvalidSdp(#[source] ParseSessionDescriptionError),
     #[error("Call was canceled by the peer")]
     Cancelled,
+    #[error("Call referenced by the Replaces header cannot be replaced, rejected with {0:?}")]
+    Replaces(StatusCode),
+    #[error("Session interval requested by the peer is too small")]
+    SessionIntervalTooSmall,
+    #[error("INVITE contains no SDP offer to answer with early media")]
+    NoSdpOffer,
+    #[error("Early media has not been started with a reliable provisional response")]
+    NoReliableEarlyAnswer,
+    #[error("Another SDP offer is still pending")]
+    OfferPending,
+    #[error("UPDATE was rejected with {0:?}")]
+    UpdateRejected(StatusCode),
+    #[error("Response to the UPDATE did not contain a valid SDP answer")]
+    InvalidSdpAnswer,
 }
 
 impl<M> From<crate::invite::acceptor::Error> for AcceptCallError<M> {
@@ -161,24 +241,206 @@ impl<M> From<crate::invite::acceptor::Error> for AcceptCallError<M> {
         match e {
             crate::invite::acceptor::Error::Core(e) => AcceptCallError::Core(e),
             crate::invite::acceptor::Error::RequestTerminated => AcceptCallError::Cancelled,
+            crate::invite::acceptor::Error::OfferPending => AcceptCallError::OfferPending,
         }
     }
 }
 
 impl<M: MediaBackend> InboundCall<M> {
+    /// Send a `183 Session Progress` response containing the SDP answer to the INVITE's offer, to start early media
+    ///
+    /// The response is sent reliably (RFC3262) if the peer supports it, returning once the PRACK has been received.
+    /// Accepting the call afterwards uses the same SDP answer. Media events must be handled using
+    /// [`InboundCall::run_media_or_cancelled`], which also answers UPDATE requests of the early dialog, until the call
+    /// is accepted.
+    pub async fn respond_early_media(&mut self) -> Result<(), AcceptCallError<M::Error>> {
+        let sdp_answer = match &self.sdp_answer {
+            Some(sdp_answer) => sdp_answer.clone(),
+            None => {
+                let Some(sdp_offer) = self.sdp_offer.clone() else {
+                    return Err(AcceptCallError::NoSdpOffer);
+                };
+
+                self.media
+                    .receive_sdp_offer(sdp_offer)
+                    .await
+                    .map_err(AcceptCallError::Media)?
+            }
+        };
+
+        let mut response = self
+            .acceptor
+            .create_response(StatusCode::SESSION_PROGRESS, None)
+            .await?;
+
+        response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+        response.msg.body = sdp_answer.to_string().into();
+
+        self.sdp_answer = Some(sdp_answer);
+
+        if self.acceptor.peer_supports_100rel() {
+            self.acceptor.respond_provisional_reliable(response).await?;
+            self.early_negotiated = true;
+        } else {
+            self.acceptor.respond_provisional(response).await?;
+        }
+
+        self.setup_times.progress(StatusCode::SESSION_PROGRESS);
+
+        Ok(())
+    }
+
+    /// Run the media backend if `run_media` is set, until it returns an event. Returns `None` once the call
+    /// has been cancelled.
+    ///
+    /// SDP offers received in UPDATE requests of the early dialog are answered by the media backend meanwhile.
+    pub async fn run_media_or_cancelled(
+        &mut self,
+        run_media: bool,
+    ) -> Option<Result<M::Event, M::Error>> {
+        loop {
+            let update = select! {
+                update = self.acceptor.receive_update() => update?,
+                event = self.media.run(), if run_media => return Some(event),
+            };
+
+            if let Err(e) = self.handle_early_update(update).await {
+                log::warn!("Failed to handle UPDATE in early dialog, {e}");
+            }
+        }
+    }
+
+    /// Answer the SDP offer of an UPDATE request received in the early dialog
+    async fn handle_early_update(
+        &mut self,
+        event: UpdateReceived,
+    ) -> Result<(), AcceptCallError<M::Error>> {
+        let is_sdp = event
+            .update
+            .headers
+            .get_named::<ContentType>()
+            .is_ok_and(|content_type| content_type == CONTENT_TYPE_SDP);
+
+        let Some(sdp_offer) = is_sdp
+            .then(|| parse_sdp_body(event.update.body.clone()))
+            .flatten()
+        else {
+            let response = self
+                .acceptor
+                .create_update_response(&event.update, StatusCode::BAD_REQUEST)
+                .await?;
+
+            return Ok(self.acceptor.respond_update(event, response).await?);
+        };
+
+        let sdp_answer = match self.media.receive_sdp_offer(sdp_offer).await {
+            Ok(sdp_answer) => sdp_answer,
+            Err(e) => {
+                let response = self
+                    .acceptor
+                    .create_update_response(&event.update, StatusCode::SERVER_INTERNAL_ERROR)
+                    .await?;
+
+                self.acceptor.respond_update(event, response).await?;
+                return Err(AcceptCallError::Media(e));
+            }
+        };
+
+        let mut response = self
+            .acceptor
+            .create_update_response(&event.update, StatusCode::OK)
+            .await?;
+        response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+        response.msg.body = sdp_answer.to_string().into();
+
+        self.sdp_answer = Some(sdp_answer);
+
+        Ok(self.acceptor.respond_update(event, response).await?)
+    }
+
+    /// Send a new SDP offer inside the early dialog using an UPDATE request (RFC3311)
+    ///
+    /// Early media must have been started using [`InboundCall::respond_early_media`] with a reliable provisional
+    /// response. The new offer is repeated when accepting the call.
+    ///
+    /// This function is not cancel safe.
+    pub async fn update_early_media(&mut self) -> Result<(), AcceptCallError<M::Error>> {
+        if !self.early_negotiated {
+            return Err(AcceptCallError::NoReliableEarlyAnswer);
+        }
+
+        let sdp_offer = self
+            .media
+            .create_sdp_offer()
+            .await
+            .map_err(AcceptCallError::Media)?;
+
+        let mut update = self.acceptor.create_update().await?;
+        update.headers.insert_named(&CONTENT_TYPE_SDP);
+        update.body = sdp_offer.to_string().into();
+
+        let response = self.acceptor.send_update(update).await?;
+
+        if response.line.code.kind() != CodeKind::Success {
+            return Err(AcceptCallError::UpdateRejected(response.line.code));
+        }
+
+        let sdp_answer = parse_sdp_response(response).ok_or(AcceptCallError::InvalidSdpAnswer)?;
+
+        self.media
+            .receive_sdp_answer(sdp_answer)
+            .await
+            .map_err(AcceptCallError::Media)?;
+
+        self.sdp_answer = Some(sdp_offer);
+
+        Ok(())
+    }
+
     /// Accept the call and negotiate the media session
     pub async fn accept(mut self) -> Result<Call<M>, AcceptCallError<M::Error>> {
+        if let Some(min_se) = self.acceptor.session_interval_too_small().await {
+            let mut response = self
+                .acceptor
+                .create_response(StatusCode::SESSION_INTERVAL_TOO_SMALL, None)
+                .await?;
+            response.msg.headers.insert_named(&min_se);
+            self.acceptor.respond_failure(response).await?;
+            return Err(AcceptCallError::SessionIntervalTooSmall);
+        }
+
+        let replaced_session = match &self.replaces {
+            Some(replaces) => match self
+                .endpoint
+                .layer::<InviteLayer>()
+                .find_replaced_session(replaces)
+            {
+                Ok(replaced_session) => Some(replaced_session),
+                Err(code) => {
+                    let response = self.acceptor.create_response(code, None).await?;
+                    self.acceptor.respond_failure(response).await?;
+                    return Err(AcceptCallError::Replaces(code));
+                }
+            },
+            None => None,
+        };
+
         let mut response = self.acceptor.create_response(StatusCode::OK, None).await?;
+        self.setup_times.answered();
 
         response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
 
         let invite_session = if let Some(sdp_offer) = self.sdp_offer {
-            let sdp_response = match self.media.receive_sdp_offer(sdp_offer).await {
-                Ok(sdp_response) => sdp_response,
-                Err(e) => {
-                    Self::internal_error(self.acceptor).await?;
-                    return Err(AcceptCallError::Media(e));
-                }
+            // Reuse the answer sent with early media
+            let sdp_response = match self.sdp_answer {
+                Some(sdp_answer) => sdp_answer,
+                None => match self.media.receive_sdp_offer(sdp_offer).await {
+                    Ok(sdp_response) => sdp_response,
+                    Err(e) => {
+                        Self::internal_error(self.acceptor).await?;
+                        return Err(AcceptCallError::Media(e));
+                    }
+                },
             };
 
             response.msg.body = sdp_response.to_string().into();
@@ -231,7 +493,11 @@ impl<M: MediaBackend> InboundCall<M> {
             session
         };
 
-        Ok(Call::new(invite_session, self.media))
+        if let Some(replaced_session) = replaced_session {
+            replaced_session.replaced().await;
+        }
+
+        Ok(Call::new(invite_session, self.media, self.setup_times))
     }
 
     async fn internal_error(acceptor: InviteAcceptor) -> Result<(), AcceptCallError<M::Error>> {
@@ -0,0 +1,77 @@
+//! INFO requests inside a call ([RFC6086](https://datatracker.ietf.org/doc/html/rfc6086))
+
+use crate::dialog::Dialog;
+use crate::invite::session::InfoReceived;
+use bytes::Bytes;
+use sip_core::{IncomingRequest, Result};
+use sip_types::StatusCode;
+use sip_types::header::typed::ContentType;
+use std::sync::Arc;
+
+/// INFO request received inside a [`Call`](crate::Call) which isn't handled by the call itself
+///
+/// INFO requests carrying DTMF digits are reported as [`CallEvent::Dtmf`](crate::CallEvent::Dtmf) instead.
+/// Must be responded to, dropping it will respond with `415 Unsupported Media Type`.
+pub struct IncomingInfo {
+    dialog: Arc<Dialog>,
+    event: Option<InfoReceived>,
+    content_type: ContentType,
+}
+
+impl IncomingInfo {
+    pub(crate) fn new(dialog: Arc<Dialog>, event: InfoReceived, content_type: ContentType) -> Self {
+        Self {
+            dialog,
+            event: Some(event),
+            content_type,
+        }
+    }
+
+    /// The received INFO request
+    pub fn request(&self) -> &IncomingRequest {
+        &self.event.as_ref().expect("only taken when consumed").info
+    }
+
+    /// Content type of the INFO request's body
+    pub fn content_type(&self) -> &ContentType {
+        &self.content_type
+    }
+
+    /// Body of the INFO request
+    pub fn body(&self) -> &Bytes {
+        &self.request().body
+    }
+
+    /// Respond to the INFO request with the given status code
+    pub async fn respond(mut self, code: StatusCode) -> Result<()> {
+        let event = self.event.take().expect("only taken when consumed");
+
+        respond(&self.dialog, event, code).await
+    }
+}
+
+impl Drop for IncomingInfo {
+    fn drop(&mut self) {
+        let Some(event) = self.event.take() else {
+            return;
+        };
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let dialog = self.dialog.clone();
+
+        handle.spawn(async move {
+            if let Err(e) = respond(&dialog, event, StatusCode::UNSUPPORTED_MEDIA_TYPE).await {
+                log::warn!("Failed to reject dropped INFO request {e:?}");
+            }
+        });
+    }
+}
+
+async fn respond(dialog: &Dialog, event: InfoReceived, code: StatusCode) -> Result<()> {
+    let response = dialog.create_response(&event.info, code, None)?;
+
+    event.transaction.respond(response).await
+}
@@ -1,18 +1,21 @@
-use super::session::InviteSession;
-use super::timer::{AcceptorTimerConfig, SessionTimer};
-use super::{AwaitedAck, AwaitedPrack, Inner, InviteLayer};
+use super::session::{InviteSession, LocalOffer, UpdateReceived};
+use super::timer::{SessionTimer, SessionTimerConfig};
+use super::{AwaitedAck, AwaitedPrack, Inner, InviteLayer, Negotiation};
 use crate::dialog::{Dialog, UsageGuard, register_usage};
 use crate::invite::session::Role;
 use crate::invite::{InviteSessionState, InviteUsage};
 use crate::util::random_sequence_number;
 use bytesstr::BytesStr;
 use parking_lot as pl;
+use sip_core::transaction::TsxResponse;
 use sip_core::transaction::consts::T1;
 use sip_core::transport::OutgoingResponse;
-use sip_core::{Endpoint, IncomingRequest, Result};
-use sip_types::header::typed::{RSeq, Require, Supported};
-use sip_types::{Method, StatusCode};
+use sip_core::{Endpoint, IncomingRequest, Request, Result};
+use sip_types::header::typed::{MinSe, RSeq, Require, Supported};
+use sip_types::{CodeKind, Method, StatusCode};
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use tokio::select;
 use tokio::sync::{Mutex, Notify, mpsc, oneshot};
 use tokio::time::timeout;
 
@@ -23,6 +26,9 @@ pub enum Error {
 
     #[error("peer cancelled its request")]
     RequestTerminated,
+
+    #[error("another SDP offer is still pending")]
+    OfferPending,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -37,8 +43,11 @@ pub struct InviteAcceptor {
     cancelled: bool,
     usage_guard: Option<UsageGuard>,
 
+    /// UPDATE requests received in the early dialog
+    early_updates: mpsc::Receiver<IncomingRequest>,
+
     /// Configuration for `timer` extension
-    timer_config: AcceptorTimerConfig,
+    timer_config: SessionTimerConfig,
 }
 
 impl Drop for InviteAcceptor {
@@ -85,6 +94,8 @@ impl InviteAcceptor {
         };
         let cancelled_notify = Arc::new(Notify::new());
 
+        let (early_updates_sink, early_updates) = mpsc::channel(4);
+
         // Create Inner shared state
         let tsx = endpoint.create_server_inv_tsx(&mut invite);
         let inner = Arc::new(Inner {
@@ -93,11 +104,15 @@ impl InviteAcceptor {
                 tsx,
                 invite,
                 cancelled_notify: cancelled_notify.clone(),
+                early_negotiated: false,
+                early_updates: early_updates_sink,
             }),
             peer_supports_timer,
             peer_supports_100rel,
             awaited_ack: pl::Mutex::new(None),
             awaited_prack: pl::Mutex::new(None),
+            reinvite_pending: AtomicBool::new(false),
+            negotiation: pl::Mutex::new(Negotiation::Idle),
         });
 
         // Register the usage to the dialog
@@ -125,12 +140,13 @@ impl InviteAcceptor {
             cancellable_key,
             cancelled_notify,
             cancelled: false,
-            timer_config: AcceptorTimerConfig::default(),
+            early_updates,
+            timer_config: SessionTimerConfig::default(),
         }
     }
 
     /// Configure the `timer` extension
-    pub fn timer_config(&mut self) -> &mut AcceptorTimerConfig {
+    pub fn timer_config(&mut self) -> &mut SessionTimerConfig {
         &mut self.timer_config
     }
 
@@ -152,6 +168,22 @@ impl InviteAcceptor {
         self.inner.peer_supports_timer
     }
 
+    /// Returns the `Min-SE` to reject the INVITE with `422 Session Interval Too Small`, if the requested
+    /// session interval is smaller than configured
+    pub async fn session_interval_too_small(&self) -> Option<MinSe> {
+        if !self.peer_supports_timer() {
+            return None;
+        }
+
+        let state = self.inner.state.lock().await;
+
+        if let InviteSessionState::UasProvisional { invite, .. } = &*state {
+            self.timer_config.interval_too_small(invite)
+        } else {
+            None
+        }
+    }
+
     pub async fn create_response(
         &self,
         code: StatusCode,
@@ -200,7 +232,13 @@ impl InviteAcceptor {
 
         let mut state = self.inner.state.lock().await;
 
-        if let InviteSessionState::UasProvisional { tsx, invite, .. } = &mut *state {
+        if let InviteSessionState::UasProvisional {
+            tsx,
+            invite,
+            early_negotiated,
+            ..
+        } = &mut *state
+        {
             let rack = random_sequence_number();
 
             response.msg.headers.insert_named(&Require("100rel".into()));
@@ -235,12 +273,133 @@ impl InviteAcceptor {
                 }
             }
 
-            prack.ok_or(Error::RequestTerminated)
+            let prack = prack.ok_or(Error::RequestTerminated)?;
+
+            // The offer/answer exchange is complete, when the response contained either the answer to the
+            // INVITE's offer or an offer which has been answered by the PRACK
+            *early_negotiated |= !response.msg.body.is_empty()
+                && (!invite.body.is_empty() || !prack.body.is_empty());
+
+            Ok(prack)
+        } else {
+            Err(Error::RequestTerminated)
+        }
+    }
+
+    /// Receive the next UPDATE request containing an SDP offer sent by the peer inside the early dialog, returns
+    /// `None` once the INVITE has been cancelled
+    ///
+    /// Offers are only passed on once the offer/answer exchange of the INVITE has been completed using a reliable
+    /// provisional response, the answer must be sent using [`InviteAcceptor::respond_update`].
+    /// This function is cancel safe.
+    pub async fn receive_update(&mut self) -> Option<UpdateReceived> {
+        if self.cancelled {
+            return None;
+        }
+
+        // The sender is dropped once a final response has been sent, from then on the session handles UPDATEs
+        let mut update = select! {
+            _ = self.cancelled_notify.notified() => {
+                self.cancelled = true;
+                return None;
+            }
+            Some(update) = self.early_updates.recv() => update,
+        };
+
+        let transaction = self.endpoint.create_server_tsx(&mut update);
+
+        Some(UpdateReceived {
+            update,
+            transaction,
+        })
+    }
+
+    /// Create a response to an UPDATE request received using [`InviteAcceptor::receive_update`]
+    pub async fn create_update_response(
+        &self,
+        update: &IncomingRequest,
+        code: StatusCode,
+    ) -> Result<OutgoingResponse, Error> {
+        let state = self.inner.state.lock().await;
+
+        if let InviteSessionState::UasProvisional { dialog, .. } = &*state {
+            let mut response = dialog.create_response(update, code, None)?;
+
+            if code.kind() == CodeKind::Success {
+                response.msg.headers.insert_named(&dialog.local_contact);
+            }
+
+            Ok(response)
         } else {
             Err(Error::RequestTerminated)
         }
     }
 
+    /// Respond to an UPDATE request received using [`InviteAcceptor::receive_update`]
+    ///
+    /// A successful `response` must contain the SDP answer.
+    pub async fn respond_update(
+        &self,
+        event: UpdateReceived,
+        response: OutgoingResponse,
+    ) -> Result<(), Error> {
+        // The offer has been answered or rejected with this response
+        *self.inner.negotiation.lock() = Negotiation::Idle;
+
+        event.transaction.respond(response).await?;
+
+        Ok(())
+    }
+
+    /// Create an UPDATE request inside the early dialog
+    pub async fn create_update(&self) -> Result<Request, Error> {
+        let state = self.inner.state.lock().await;
+
+        if let InviteSessionState::UasProvisional { dialog, .. } = &*state {
+            let mut update = dialog.create_request(Method::UPDATE);
+            update.headers.insert_named(&dialog.local_contact);
+
+            Ok(update)
+        } else {
+            Err(Error::RequestTerminated)
+        }
+    }
+
+    /// Send an UPDATE request containing an SDP offer inside the early dialog and wait for the final response
+    ///
+    /// An offer can only be sent after the offer/answer exchange of the INVITE has been completed using a reliable
+    /// provisional response, and while no other offer is pending (RFC3311 Section 5.1).
+    pub async fn send_update(&mut self, update: Request) -> Result<TsxResponse, Error> {
+        let mut target_tp_info = {
+            let state = self.inner.state.lock().await;
+
+            let InviteSessionState::UasProvisional {
+                dialog,
+                early_negotiated,
+                ..
+            } = &*state
+            else {
+                return Err(Error::RequestTerminated);
+            };
+
+            if !early_negotiated || *self.inner.negotiation.lock() != Negotiation::Idle {
+                return Err(Error::OfferPending);
+            }
+
+            // Don't hold the state while waiting for the response, the peer may still send requests
+            dialog.target_tp_info.lock().await.clone()
+        };
+
+        let _offer = LocalOffer::new(&self.inner);
+
+        let mut transaction = self
+            .endpoint
+            .send_request(update, &mut target_tp_info)
+            .await?;
+
+        Ok(transaction.receive_final().await?)
+    }
+
     pub async fn respond_success(
         mut self,
         mut response: OutgoingResponse,
@@ -1,24 +1,26 @@
 // TODO: remove clippy allow
 #![allow(clippy::large_enum_variant)]
 
-use super::prack::get_rseq;
-use super::session::{InviteSession, Role};
-use super::timer::InitiatorTimerConfig;
-use super::{Inner, InviteSessionState, InviteUsage};
-use crate::dialog::{ClientDialogBuilder, Dialog};
+use super::prack::{create_prack, get_rseq};
+use super::session::{InviteSession, Role, UpdateReceived};
+use super::timer::SessionTimerConfig;
+use super::{Inner, InviteSessionState, InviteUsage, Negotiation};
+use crate::dialog::{ClientDialogBuilder, Dialog, Usage, UsageGuard};
 use bytesstr::BytesStr;
 use parking_lot as pl;
 use sip_core::transaction::{ClientInvTsx, TsxResponse};
-use sip_core::transport::OutgoingRequest;
-use sip_core::{Endpoint, Error, Request};
+use sip_core::transport::{OutgoingRequest, OutgoingResponse};
+use sip_core::{Endpoint, Error, IncomingRequest, MayTake, Request};
 use sip_types::header::HeaderError;
-use sip_types::header::typed::{Contact, RSeq, Refresher, Supported};
+use sip_types::header::typed::{Contact, RSeq, Supported};
 use sip_types::uri::{NameAddr, SipUri};
-use sip_types::{Method, Name, StatusCode};
+use sip_types::{CodeKind, Method, Name, StatusCode};
 use std::collections::HashMap;
 use std::future::poll_fn;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use std::task::{Context, Poll, ready};
+use tokio::sync::mpsc::error::SendError;
 use tokio::sync::{Mutex, mpsc};
 
 #[derive(Debug)]
@@ -51,7 +53,7 @@ pub struct InviteInitiator {
     pub support_timer: bool,
     pub support_100rel: bool,
 
-    pub timer_config: InitiatorTimerConfig,
+    pub timer_config: SessionTimerConfig,
 }
 
 impl InviteInitiator {
@@ -70,15 +72,16 @@ impl InviteInitiator {
             created_sessions: HashMap::new(),
             support_timer: true,
             support_100rel: true,
-            timer_config: InitiatorTimerConfig {
-                expires_secs: None,
-                refresher: Refresher::Unspecified,
-                expires_secs_min: 90,
-            },
+            timer_config: SessionTimerConfig::default(),
         }
     }
 
     pub fn create_invite(&mut self) -> Request {
+        // INVITE requests sent again (e.g. with credentials) must use a new CSeq
+        if self.transaction.is_some() {
+            self.dialog_builder.local_cseq += 1;
+        }
+
         let mut request = self.dialog_builder.create_request(Method::INVITE);
 
         if self.support_100rel {
@@ -106,14 +109,12 @@ impl InviteInitiator {
     }
 
     pub async fn cancel(mut self) -> Result<(), sip_core::Error> {
-        let request = self.dialog_builder.create_request(Method::CANCEL);
+        let Some(transaction) = &self.transaction else {
+            return Ok(());
+        };
 
-        self.dialog_builder
-            .endpoint
-            .send_request(request, &mut self.dialog_builder.target_tp_info)
-            .await?
-            .receive_final()
-            .await?;
+        // The CANCEL must use the same branch as the INVITE, to be matched to its transaction
+        transaction.cancel().await?.receive_final().await?;
 
         loop {
             match self.receive().await? {
@@ -160,6 +161,11 @@ impl InviteInitiator {
                 None => return Ok(Response::Finished),
             };
 
+            // The transaction may have failed over to another server of the target
+            let parts = &transaction.request().parts;
+            self.dialog_builder.target_tp_info.transport =
+                Some((parts.transport.clone(), parts.destination));
+
             let code = response.line.code.into_u16();
 
             if code <= 100 {
@@ -233,13 +239,17 @@ impl InviteInitiator {
 
         let (tx, response_rx) = mpsc::channel(4);
 
+        let usage_guard = dialog.register_usage(EarlyUsage { events: tx.clone() });
+
         self.early_list.push((to_tag, tx));
 
         Ok(Early {
             endpoint: self.dialog_builder.endpoint.clone(),
+            usage_guard: Some(usage_guard),
             dialog: Some(dialog),
             response_rx,
             timer_config: self.timer_config,
+            last_rseq: None,
         })
     }
 
@@ -262,6 +272,8 @@ impl InviteInitiator {
             peer_supports_100rel,
             awaited_ack: pl::Mutex::new(None),
             awaited_prack: pl::Mutex::new(None),
+            reinvite_pending: AtomicBool::new(false),
+            negotiation: pl::Mutex::new(Negotiation::Idle),
         });
 
         let usage_guard = dialog.register_usage(InviteUsage {
@@ -285,27 +297,114 @@ impl InviteInitiator {
 #[derive(Debug)]
 enum EarlyEvent {
     Response(TsxResponse),
+    Update(IncomingRequest),
     Terminate,
 }
 
+/// Passes UPDATE requests received inside an early dialog to its [`Early`]
+struct EarlyUsage {
+    events: mpsc::Sender<EarlyEvent>,
+}
+
+#[async_trait::async_trait]
+impl Usage for EarlyUsage {
+    fn name(&self) -> &'static str {
+        "early-invite-usage"
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::UPDATE {
+            return;
+        }
+
+        let update = request.inner().take().unwrap();
+
+        if let Err(SendError(EarlyEvent::Update(update))) =
+            self.events.send(EarlyEvent::Update(update)).await
+        {
+            *request.inner() = Some(update);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Early {
     endpoint: Endpoint,
+
+    // drop usage before dialog
+    usage_guard: Option<UsageGuard>,
     dialog: Option<Dialog>,
 
     response_rx: mpsc::Receiver<EarlyEvent>,
 
-    timer_config: InitiatorTimerConfig,
+    timer_config: SessionTimerConfig,
+
+    /// RSeq of the last acknowledged reliable provisional response
+    last_rseq: Option<u32>,
 }
 
 #[derive(Debug)]
 pub enum EarlyResponse {
     Provisional(TsxResponse, Option<RSeq>),
     Success(InviteSession, TsxResponse),
+    /// The peer sent an UPDATE request inside the early dialog (RFC3311)
+    Update(UpdateReceived),
     Terminated,
 }
 
 impl Early {
+    /// Returns the early dialog
+    pub fn dialog(&self) -> &Dialog {
+        self.dialog
+            .as_ref()
+            .expect("dialog is only taken when the session is created")
+    }
+
+    /// Acknowledge a reliable provisional response using a PRACK request (RFC3262)
+    ///
+    /// Retransmissions of already acknowledged responses are ignored. The PRACK transaction is completed in the
+    /// background.
+    pub async fn prack(&mut self, response: &TsxResponse, rseq: RSeq) -> Result<(), Error> {
+        if self.last_rseq.is_some_and(|last_rseq| rseq.0 <= last_rseq) {
+            return Ok(());
+        }
+
+        self.last_rseq = Some(rseq.0);
+
+        let dialog = self.dialog();
+        let prack = create_prack(dialog, response, rseq.0);
+
+        let mut target_tp_info = dialog.target_tp_info.lock().await.clone();
+        let mut transaction = self
+            .endpoint
+            .send_request(prack, &mut target_tp_info)
+            .await?;
+
+        tokio::spawn(async move {
+            if let Err(e) = transaction.receive_final().await {
+                log::warn!("PRACK transaction failed, {e}");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Create a response to an UPDATE request received inside the early dialog
+    pub fn create_update_response(
+        &self,
+        update: &IncomingRequest,
+        code: StatusCode,
+    ) -> Result<OutgoingResponse, Error> {
+        let dialog = self.dialog();
+        let mut response = dialog.create_response(update, code, None)?;
+
+        if code.kind() == CodeKind::Success {
+            response.msg.headers.insert_named(&dialog.local_contact);
+        }
+
+        Ok(response)
+    }
+
     pub fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<Result<EarlyResponse, Error>> {
         let dialog = self.dialog.as_mut().unwrap();
 
@@ -333,8 +432,13 @@ impl Early {
                         peer_supports_100rel,
                         awaited_ack: pl::Mutex::new(None),
                         awaited_prack: pl::Mutex::new(None),
+                        reinvite_pending: AtomicBool::new(false),
+                        negotiation: pl::Mutex::new(Negotiation::Idle),
                     });
 
+                    // The session handles all requests from now on
+                    self.usage_guard = None;
+
                     let usage_guard = dialog.register_usage(InviteUsage {
                         inner: inner.clone(),
                     });
@@ -355,6 +459,14 @@ impl Early {
                 }
                 _ => unreachable!("initiator only forwards messages with 101..=299 status"),
             },
+            EarlyEvent::Update(mut update) => {
+                let transaction = self.endpoint.create_server_tsx(&mut update);
+
+                Poll::Ready(Ok(EarlyResponse::Update(UpdateReceived {
+                    update,
+                    transaction,
+                })))
+            }
             EarlyEvent::Terminate => Poll::Ready(Ok(EarlyResponse::Terminated)),
         }
     }
@@ -386,6 +498,7 @@ impl Early {
                         return Ok(());
                     }
                 }
+                Some(EarlyEvent::Update(_)) => {}
                 Some(EarlyEvent::Terminate) => return Ok(()),
                 None => return Ok(()),
             }
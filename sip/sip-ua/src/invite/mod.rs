@@ -1,17 +1,20 @@
-use crate::dialog::{Dialog, Usage};
+use crate::dialog::{Dialog, DialogKey, Usage};
+use crate::transfer::is_refer_event;
 use acceptor::CancellableKey;
 use parking_lot as pl;
 use prack::AwaitedPrack;
+use rand::Rng;
 use session::UsageEvent;
 use sip_core::transaction::consts::{T1, T2};
 use sip_core::transaction::{Accepted, ServerInvTsx, TsxKey};
 use sip_core::transport::OutgoingRequest;
 use sip_core::{Endpoint, EndpointBuilder, Error, IncomingRequest, Layer, MayTake, Result};
-use sip_types::header::typed::CSeq;
+use sip_types::header::typed::{CSeq, Event, Replaces, RetryAfter};
 use sip_types::{Method, StatusCode};
 use std::collections::HashMap;
 use std::mem::replace;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::{Mutex, Notify, mpsc, oneshot};
 use tokio::time::timeout;
@@ -22,6 +25,8 @@ pub mod prack;
 pub mod session;
 mod timer;
 
+pub use timer::{RefreshMethod, SessionTimerConfig};
+
 #[derive(Debug)]
 struct AwaitedAck {
     cseq: u32,
@@ -39,6 +44,24 @@ struct Inner {
 
     awaited_ack: pl::Mutex<Option<AwaitedAck>>,
     awaited_prack: pl::Mutex<Option<AwaitedPrack>>,
+
+    /// Set while a locally initiated re-INVITE is in progress.
+    /// Incoming re-INVITEs are rejected with 491 during that time (RFC3261 Section 14.2).
+    reinvite_pending: AtomicBool,
+
+    /// State of SDP offers exchanged using UPDATE requests
+    negotiation: pl::Mutex<Negotiation>,
+}
+
+/// State of the SDP offer/answer exchange inside an INVITE session (RFC3311 Section 5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Negotiation {
+    /// No offer is outstanding
+    Idle,
+    /// An offer has been sent and is waiting for its answer
+    LocalOffer,
+    /// An offer has been received and must still be answered
+    RemoteOffer,
 }
 
 #[derive(Debug)]
@@ -50,6 +73,11 @@ enum InviteSessionState {
         tsx: ServerInvTsx,
         invite: IncomingRequest,
         cancelled_notify: Arc<Notify>,
+        /// The offer/answer exchange of the INVITE has been completed by a reliable provisional response,
+        /// further offers can be exchanged using UPDATE requests (RFC3311 Section 5.1)
+        early_negotiated: bool,
+        /// Passes UPDATE requests received in the early dialog to the acceptor
+        early_updates: mpsc::Sender<IncomingRequest>,
     },
 
     /// Cancelled: A CANCEL Request for the invite has been received
@@ -77,6 +105,7 @@ impl InviteSessionState {
                 tsx,
                 invite,
                 cancelled_notify,
+                ..
             } = replace(self, InviteSessionState::Cancelled)
             {
                 cancelled_notify.notify_one();
@@ -100,7 +129,7 @@ impl InviteSessionState {
                 dialog,
                 tsx,
                 invite,
-                cancelled_notify: _,
+                ..
             } = replace(self, InviteSessionState::Established { evt_sink })
             {
                 Some((dialog, tsx, invite))
@@ -121,6 +150,9 @@ impl InviteSessionState {
 #[derive(Default)]
 pub struct InviteLayer {
     cancellables: pl::Mutex<HashMap<CancellableKey, Arc<Inner>>>,
+
+    /// All established INVITE sessions, used to find dialogs referenced by a `Replaces` header
+    sessions: pl::Mutex<HashMap<DialogKey, Weak<Inner>>>,
 }
 
 #[async_trait::async_trait]
@@ -136,9 +168,13 @@ impl Layer for InviteLayer {
         endpoint.add_allow(Method::ACK);
         endpoint.add_allow(Method::CANCEL);
         endpoint.add_allow(Method::PRACK);
+        endpoint.add_allow(Method::REFER);
+        endpoint.add_allow(Method::NOTIFY);
+        endpoint.add_allow(Method::INFO);
 
         endpoint.add_supported("100rel");
         endpoint.add_supported("timer");
+        endpoint.add_supported("replaces");
     }
 
     async fn receive(&self, endpoint: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
@@ -153,6 +189,38 @@ impl Layer for InviteLayer {
 }
 
 impl InviteLayer {
+    /// Find the established INVITE session referenced by the `Replaces` header of an incoming INVITE
+    ///
+    /// On failure the status code to reject the INVITE with is returned (RFC3891 Section 3).
+    pub(crate) fn find_replaced_session(
+        &self,
+        replaces: &Replaces,
+    ) -> Result<ReplacedSession, StatusCode> {
+        // Replaces header is seen from the perspective of the peer
+        let key = DialogKey {
+            call_id: replaces.call_id.clone(),
+            peer_tag: Some(replaces.from_tag.clone()),
+            local_tag: replaces.to_tag.clone(),
+        };
+
+        let inner = {
+            let mut sessions = self.sessions.lock();
+            sessions.retain(|_, inner| inner.strong_count() > 0);
+            sessions.get(&key).and_then(Weak::upgrade)
+        };
+
+        let Some(inner) = inner else {
+            return Err(StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+        };
+
+        // Only established sessions are tracked, which cannot be replaced if `early-only` is set
+        if replaces.early_only {
+            return Err(StatusCode::BUSY_HERE);
+        }
+
+        Ok(ReplacedSession { inner })
+    }
+
     async fn handle_cancel(
         &self,
         endpoint: &Endpoint,
@@ -202,6 +270,24 @@ impl InviteLayer {
     }
 }
 
+/// Established INVITE session which is about to be replaced by a new one
+pub(crate) struct ReplacedSession {
+    inner: Arc<Inner>,
+}
+
+impl ReplacedSession {
+    /// Notify the session that it has been replaced, which will then be terminated
+    pub(crate) async fn replaced(self) {
+        let state = self.inner.state.lock().await;
+
+        if let InviteSessionState::Established { evt_sink } = &*state
+            && evt_sink.send(UsageEvent::Replaced).await.is_err()
+        {
+            log::debug!("Replaced session has already been terminated");
+        }
+    }
+}
+
 struct InviteUsage {
     inner: Arc<Inner>,
 }
@@ -215,6 +301,16 @@ impl Usage for InviteUsage {
     async fn receive(&self, endpoint: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
         match request.line.method {
             Method::INVITE => {
+                if self.inner.reinvite_pending.load(Ordering::Relaxed)
+                    || *self.inner.negotiation.lock() == Negotiation::LocalOffer
+                {
+                    if let Err(e) = self.reject_glaring_reinvite(endpoint, request.take()).await {
+                        log::warn!("Failed to reject re-INVITE with 491: {e:?}");
+                    }
+
+                    return;
+                }
+
                 let state = self.inner.state.lock().await;
 
                 if let InviteSessionState::Established { evt_sink } = &*state {
@@ -227,6 +323,14 @@ impl Usage for InviteUsage {
                     }
                 }
             }
+            Method::UPDATE => {
+                if let Err(e) = self
+                    .handle_update(endpoint, MayTake::new(request.inner()))
+                    .await
+                {
+                    log::warn!("Failed to handle UPDATE request {e:?}");
+                }
+            }
             Method::ACK => {
                 let mut awaited_ack_opt = self.inner.awaited_ack.lock();
 
@@ -253,6 +357,7 @@ impl Usage for InviteUsage {
                         tsx,
                         invite,
                         cancelled_notify,
+                        ..
                     } => {
                         cancelled_notify.notify_one();
                         if let Err(e) = self
@@ -282,6 +387,55 @@ impl Usage for InviteUsage {
                     }
                 }
             }
+            Method::REFER => {
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let refer = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Refer(refer))) =
+                        evt_sink.send(UsageEvent::Refer(refer)).await
+                    {
+                        *request.inner() = Some(refer);
+                    }
+                }
+            }
+            Method::NOTIFY => {
+                // Only handle NOTIFYs of the implicit subscription created by REFER
+                let is_refer_event = request
+                    .headers
+                    .get_named::<Event>()
+                    .is_ok_and(|event| is_refer_event(&event));
+
+                if !is_refer_event {
+                    return;
+                }
+
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let notify = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Notify(notify))) =
+                        evt_sink.send(UsageEvent::Notify(notify)).await
+                    {
+                        *request.inner() = Some(notify);
+                    }
+                }
+            }
+            Method::INFO => {
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let info = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Info(info))) =
+                        evt_sink.send(UsageEvent::Info(info)).await
+                    {
+                        *request.inner() = Some(info);
+                    }
+                }
+            }
             Method::PRACK if self.inner.peer_supports_100rel => {
                 if let Err(e) = self
                     .handle_prack(endpoint, MayTake::new(request.inner()))
@@ -296,6 +450,129 @@ impl Usage for InviteUsage {
 }
 
 impl InviteUsage {
+    async fn reject_glaring_reinvite(
+        &self,
+        endpoint: &Endpoint,
+        mut invite: IncomingRequest,
+    ) -> Result<()> {
+        let tsx = endpoint.create_server_inv_tsx(&mut invite);
+        let response = endpoint.create_response(&invite, StatusCode::REQUEST_PENDING, None);
+
+        tsx.respond_failure(response).await
+    }
+
+    async fn handle_update(
+        &self,
+        endpoint: &Endpoint,
+        mut request: MayTake<'_, IncomingRequest>,
+    ) -> Result<()> {
+        let has_offer = !request.body.is_empty();
+
+        let state = self.inner.state.lock().await;
+
+        match &*state {
+            InviteSessionState::UasProvisional {
+                dialog,
+                early_negotiated,
+                early_updates,
+                ..
+            } => {
+                if has_offer {
+                    // The offer of the INVITE must be answered reliably, before any other offer is accepted
+                    let rejection = if *early_negotiated {
+                        self.accept_remote_offer()
+                    } else {
+                        Some(StatusCode::SERVER_INTERNAL_ERROR)
+                    };
+
+                    if let Some(code) = rejection {
+                        drop(state);
+                        return self
+                            .reject_update_offer(endpoint, request.take(), code)
+                            .await;
+                    }
+
+                    let early_updates = early_updates.clone();
+                    drop(state);
+
+                    let update = request.inner().take().unwrap();
+
+                    if let Err(SendError(update)) = early_updates.send(update).await {
+                        *self.inner.negotiation.lock() = Negotiation::Idle;
+                        *request.inner() = Some(update);
+                    }
+
+                    return Ok(());
+                }
+
+                let mut update = request.take();
+                let tsx = endpoint.create_server_tsx(&mut update);
+
+                let mut response = dialog.create_response(&update, StatusCode::OK, None)?;
+                response.msg.headers.insert_named(&dialog.local_contact);
+
+                drop(state);
+
+                tsx.respond(response).await
+            }
+            InviteSessionState::Established { evt_sink } => {
+                if has_offer && let Some(code) = self.accept_remote_offer() {
+                    drop(state);
+                    return self
+                        .reject_update_offer(endpoint, request.take(), code)
+                        .await;
+                }
+
+                let update = request.inner().take().unwrap();
+
+                if let Err(SendError(UsageEvent::Update(update))) =
+                    evt_sink.send(UsageEvent::Update(update)).await
+                {
+                    *self.inner.negotiation.lock() = Negotiation::Idle;
+                    *request.inner() = Some(update);
+                }
+
+                Ok(())
+            }
+            InviteSessionState::Cancelled | InviteSessionState::Terminated => Ok(()),
+        }
+    }
+
+    /// Mark an offer received in an UPDATE request as pending, returns the status code to reject it with
+    /// if another offer is still outstanding
+    fn accept_remote_offer(&self) -> Option<StatusCode> {
+        let mut negotiation = self.inner.negotiation.lock();
+
+        let glare = self.inner.reinvite_pending.load(Ordering::Relaxed)
+            || *negotiation == Negotiation::LocalOffer;
+
+        if glare {
+            Some(StatusCode::REQUEST_PENDING)
+        } else if *negotiation == Negotiation::RemoteOffer {
+            Some(StatusCode::SERVER_INTERNAL_ERROR)
+        } else {
+            *negotiation = Negotiation::RemoteOffer;
+            None
+        }
+    }
+
+    /// Reject an UPDATE request containing an offer while another offer is outstanding (RFC3311 Section 5.2)
+    async fn reject_update_offer(
+        &self,
+        endpoint: &Endpoint,
+        mut update: IncomingRequest,
+        code: StatusCode,
+    ) -> Result<()> {
+        let tsx = endpoint.create_server_tsx(&mut update);
+        let mut response = endpoint.create_response(&update, code, None);
+
+        if code == StatusCode::SERVER_INTERNAL_ERROR {
+            response.msg.headers.insert_named(&offer_retry_after());
+        }
+
+        tsx.respond(response).await
+    }
+
     async fn handle_bye_in_provisional_state(
         &self,
         endpoint: &Endpoint,
@@ -320,6 +597,11 @@ impl InviteUsage {
     }
 }
 
+/// Random `Retry-After` between 0 and 10 seconds for offers received while another offer must still be answered
+fn offer_retry_after() -> RetryAfter {
+    RetryAfter::new(rand::rng().random_range(0..=10))
+}
+
 pub async fn create_ack(dialog: &Dialog, cseq_num: u32) -> Result<OutgoingRequest> {
     let mut ack = dialog.create_request(Method::ACK);
 
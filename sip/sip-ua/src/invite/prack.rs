@@ -62,7 +62,7 @@ pub fn get_rseq(response: &TsxResponse) -> Option<RSeq> {
     None
 }
 
-pub fn create_prack(dialog: &Dialog, response: &mut TsxResponse, rack: u32) -> Request {
+pub fn create_prack(dialog: &Dialog, response: &TsxResponse, rack: u32) -> Request {
     let mut request = dialog.create_request(Method::PRACK);
 
     request.headers.insert_named(&RAck {
@@ -1,13 +1,16 @@
-use super::Inner;
-use super::timer::SessionTimer;
+use super::timer::{RefreshMethod, SessionTimer};
+use super::{Inner, InviteLayer, Negotiation};
 use crate::dialog::{Dialog, UsageGuard};
 use crate::invite::AwaitedAck;
+use rand::Rng;
 use sip_core::transaction::{ServerInvTsx, ServerTsx, TsxResponse};
 use sip_core::transport::OutgoingResponse;
-use sip_core::{Endpoint, IncomingRequest, Result};
-use sip_types::header::typed::Refresher;
+use sip_core::{Endpoint, IncomingRequest, Request, Result};
+use sip_types::header::typed::Reason;
 use sip_types::{CodeKind, Method, StatusCode};
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 use tokio::select;
 use tokio::sync::mpsc::{self, Receiver};
 use tokio::sync::oneshot;
@@ -30,6 +33,9 @@ pub struct InviteSession {
 
     pub session_timer: SessionTimer,
 
+    /// Recorded once a BYE request has been sent or received
+    termination: Option<SessionTermination>,
+
     // drop usage before dialog
     _usage_guard: UsageGuard,
     pub dialog: Arc<Dialog>,
@@ -46,9 +52,26 @@ pub enum SessionRefreshError {
 #[allow(clippy::large_enum_variant)] // TODO address this
 pub enum InviteSessionEvent {
     RefreshNeeded,
+    /// The peer didn't refresh the session in time, it must be terminated
+    SessionExpired,
     ReInviteReceived(ReInviteReceived),
+    UpdateReceived(UpdateReceived),
     Bye(ByeEvent),
-    Terminated,
+    ReferReceived(ReferReceived),
+    NotifyReceived(NotifyReceived),
+    InfoReceived(InfoReceived),
+    /// The session has been replaced by another one using the `Replaces` header
+    Replaced,
+    Terminated(SessionTermination),
+}
+
+/// Which side terminated the session using a BYE request
+#[derive(Debug, Clone)]
+pub enum SessionTermination {
+    /// A BYE request has been sent, optionally containing a `Reason` header
+    Local { reason: Option<Reason> },
+    /// A BYE request has been received containing the given `Reason` headers
+    Remote { reasons: Vec<Reason> },
 }
 
 pub struct ReInviteReceived {
@@ -56,11 +79,39 @@ pub struct ReInviteReceived {
     pub transaction: ServerInvTsx,
 }
 
+#[derive(Debug)]
+pub struct UpdateReceived {
+    pub update: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+pub struct ReferReceived {
+    pub refer: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+pub struct NotifyReceived {
+    pub notify: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+pub struct InfoReceived {
+    pub info: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
 pub struct ByeEvent {
     bye: IncomingRequest,
     transaction: ServerTsx,
 }
 
+impl ByeEvent {
+    /// Returns the `Reason` headers of the BYE request (RFC3326)
+    pub fn reasons(&self) -> Vec<Reason> {
+        self.bye.headers.get_named().unwrap_or_default()
+    }
+}
+
 impl InviteSession {
     pub(super) fn new(
         endpoint: Endpoint,
@@ -71,12 +122,19 @@ impl InviteSession {
         usage_guard: UsageGuard,
         dialog: Dialog,
     ) -> Self {
+        endpoint
+            .layer::<InviteLayer>()
+            .sessions
+            .lock()
+            .insert(dialog.key(), Arc::downgrade(&inner));
+
         Self {
             endpoint,
             inner,
             role,
             usage_events,
             session_timer,
+            termination: None,
             _usage_guard: usage_guard,
             dialog: Arc::new(dialog),
         }
@@ -85,7 +143,7 @@ impl InviteSession {
     pub async fn run(&mut self) -> Result<InviteSessionEvent> {
         select! {
             _ = self.session_timer.wait() => {
-               self.handle_session_timer().await
+                Ok(self.handle_session_timer())
             }
             event = self.usage_events.recv() => {
                 self.handle_usage_event(event)
@@ -94,10 +152,31 @@ impl InviteSession {
     }
 
     pub async fn terminate(&mut self) -> Result<TsxResponse, sip_core::Error> {
+        self.send_bye(None).await
+    }
+
+    /// Terminate the session with a BYE request containing the given `Reason` header (RFC3326)
+    pub async fn terminate_with_reason(
+        &mut self,
+        reason: &Reason,
+    ) -> Result<TsxResponse, sip_core::Error> {
+        self.send_bye(Some(reason)).await
+    }
+
+    async fn send_bye(&mut self, reason: Option<&Reason>) -> Result<TsxResponse, sip_core::Error> {
         let mut state = self.inner.state.lock().await;
         state.set_terminated();
 
-        let request = self.dialog.create_request(Method::BYE);
+        self.termination
+            .get_or_insert_with(|| SessionTermination::Local {
+                reason: reason.cloned(),
+            });
+
+        let mut request = self.dialog.create_request(Method::BYE);
+
+        if let Some(reason) = reason {
+            request.headers.insert_named(reason);
+        }
 
         let mut target_tp_info = self.dialog.target_tp_info.lock().await;
 
@@ -115,13 +194,23 @@ impl InviteSession {
         let evt = if let Some(evt) = evt {
             evt
         } else {
-            // Usage events channel has been dropped,
-            // because the state was set to Terminated.
-            return Ok(InviteSessionEvent::Terminated);
+            // Usage events channel has been dropped, because the state was set to Terminated.
+            // That only happens when sending a BYE or after a received BYE has been passed on.
+            let termination = self
+                .termination
+                .clone()
+                .unwrap_or(SessionTermination::Remote { reasons: vec![] });
+
+            return Ok(InviteSessionEvent::Terminated(termination));
         };
 
         match evt {
             UsageEvent::Bye(mut request) => {
+                self.termination
+                    .get_or_insert_with(|| SessionTermination::Remote {
+                        reasons: request.headers.get_named().unwrap_or_default(),
+                    });
+
                 let transaction = self.endpoint.create_server_tsx(&mut request);
 
                 Ok(InviteSessionEvent::Bye(ByeEvent {
@@ -129,8 +218,34 @@ impl InviteSession {
                     transaction,
                 }))
             }
+            UsageEvent::Refer(mut refer) => {
+                let transaction = self.endpoint.create_server_tsx(&mut refer);
+
+                Ok(InviteSessionEvent::ReferReceived(ReferReceived {
+                    refer,
+                    transaction,
+                }))
+            }
+            UsageEvent::Notify(mut notify) => {
+                let transaction = self.endpoint.create_server_tsx(&mut notify);
+
+                Ok(InviteSessionEvent::NotifyReceived(NotifyReceived {
+                    notify,
+                    transaction,
+                }))
+            }
+            UsageEvent::Info(mut info) => {
+                let transaction = self.endpoint.create_server_tsx(&mut info);
+
+                Ok(InviteSessionEvent::InfoReceived(InfoReceived {
+                    info,
+                    transaction,
+                }))
+            }
+            UsageEvent::Replaced => Ok(InviteSessionEvent::Replaced),
             UsageEvent::ReInvite(mut invite) => {
-                self.session_timer.reset();
+                self.session_timer
+                    .on_refresh_request(self.role, &invite.headers);
 
                 let transaction = self.endpoint.create_server_inv_tsx(&mut invite);
 
@@ -139,33 +254,118 @@ impl InviteSession {
                     transaction,
                 }))
             }
+            UsageEvent::Update(mut update) => {
+                let transaction = self.endpoint.create_server_tsx(&mut update);
+
+                Ok(InviteSessionEvent::UpdateReceived(UpdateReceived {
+                    update,
+                    transaction,
+                }))
+            }
         }
     }
 
-    async fn handle_session_timer(&mut self) -> Result<InviteSessionEvent> {
-        match (self.role, self.session_timer.refresher) {
-            (_, Refresher::Unspecified) => unreachable!(),
-            (Role::Uac, Refresher::Uac) | (Role::Uas, Refresher::Uas) => {
-                // We are responsible for the refresh
-                // Timer expired meaning we are responsible for refresh now
-                self.session_timer.reset();
+    fn handle_session_timer(&mut self) -> InviteSessionEvent {
+        if self.session_timer.is_local_refresher(self.role) {
+            // We are responsible for the refresh
+            // Timer expired meaning we are responsible for refresh now
+            self.session_timer.reset();
+
+            InviteSessionEvent::RefreshNeeded
+        } else {
+            // Peer is responsible for refresh
+            // Timer expired meaning we didn't get a refresh request
+            InviteSessionEvent::SessionExpired
+        }
+    }
 
-                Ok(InviteSessionEvent::RefreshNeeded)
+    /// Refresh the session using the negotiated [`RefreshMethod`]
+    ///
+    /// Only a `408 Request Timeout`, `481 Call/Transaction Does Not Exist` or `491 Request Pending` response is
+    /// returned as error, the session is kept alive after any other rejection (RFC4028 Section 10).
+    pub async fn refresh(&mut self) -> Result<(), SessionRefreshError> {
+        match self.send_refresh().await {
+            Err(SessionRefreshError::UnexpectedStatus(code))
+                if code != StatusCode::REQUEST_TIMEOUT
+                    && code != StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST
+                    && code != StatusCode::REQUEST_PENDING =>
+            {
+                log::debug!("Session refresh rejected with {code:?}, keeping the session");
+                Ok(())
             }
-            (Role::Uac, Refresher::Uas) | (Role::Uas, Refresher::Uac) => {
-                // Peer is responsible for refresh
-                // Timer expired meaning we didn't get a RE-INVITE
-                self.terminate().await?;
-                Ok(InviteSessionEvent::Terminated)
+            result => result,
+        }
+    }
+
+    async fn send_refresh(&mut self) -> Result<(), SessionRefreshError> {
+        if self.session_timer.refresh_method == RefreshMethod::Update {
+            let update = self.dialog.create_request(Method::UPDATE);
+
+            match self.update(update).await {
+                Err(SessionRefreshError::UnexpectedStatus(code))
+                    if code == StatusCode::METHOD_NOT_ALLOWED
+                        || code == StatusCode::NOT_IMPLMENTED =>
+                {
+                    // Peer doesn't handle UPDATE after all, use re-INVITEs from now on
+                    self.session_timer.refresh_method = RefreshMethod::ReInvite;
+                }
+                result => return result.map(|_| ()),
             }
         }
+
+        let invite = self.dialog.create_request(Method::INVITE);
+
+        self.reinvite(invite).await?;
+
+        Ok(())
     }
 
-    pub async fn refresh(&mut self) -> Result<(), SessionRefreshError> {
+    /// Send an UPDATE request inside the session and wait for the final response (RFC3311)
+    ///
+    /// The `update` must be created using the session's dialog. If it contains a body, it is treated as SDP offer
+    /// and incoming offers are rejected until the response has been received. Sending an UPDATE also refreshes
+    /// the session.
+    ///
+    /// Returns the successful final response. Like with [`InviteSession::reinvite`] a `491 Request Pending` response
+    /// is returned as [`SessionRefreshError::UnexpectedStatus`].
+    pub async fn update(
+        &mut self,
+        mut update: Request,
+    ) -> Result<TsxResponse, SessionRefreshError> {
+        let _offer = (!update.body.is_empty()).then(|| LocalOffer::new(&self.inner));
+
+        update.headers.insert_named(&self.dialog.local_contact);
+
         self.session_timer.reset();
+        self.session_timer.populate_refresh(self.role, &mut update);
+
+        let response = self.dialog.send_request(update).await?;
 
-        let mut invite = self.dialog.create_request(Method::INVITE);
-        self.session_timer.populate_refresh(&mut invite);
+        if response.line.code.kind() == CodeKind::Success {
+            self.session_timer
+                .on_refresh_response(self.role, &response.headers);
+
+            Ok(response)
+        } else {
+            Err(SessionRefreshError::UnexpectedStatus(response.line.code))
+        }
+    }
+
+    /// Send a re-INVITE inside the session and wait for the final response
+    ///
+    /// The `invite` must be created using the session's dialog. Sending a re-INVITE also refreshes the session.
+    ///
+    /// Returns the successful final response after it has been acknowledged. A `491 Request Pending` response is
+    /// returned as [`SessionRefreshError::UnexpectedStatus`], it's up to the caller to retry after a back-off
+    /// (see [`InviteSession::request_pending_backoff`]).
+    pub async fn reinvite(
+        &mut self,
+        mut invite: Request,
+    ) -> Result<TsxResponse, SessionRefreshError> {
+        let _pending = ReInvitePending::new(&self.inner);
+
+        self.session_timer.reset();
+        self.session_timer.populate_refresh(self.role, &mut invite);
 
         let mut target_tp_info = self.dialog.target_tp_info.lock().await;
 
@@ -176,32 +376,51 @@ impl InviteSession {
 
         drop(target_tp_info);
 
-        let mut ack = None;
-
         while let Some(response) = transaction.receive().await? {
             match response.line.code.kind() {
                 CodeKind::Provisional => { /* ignore */ }
                 CodeKind::Success => {
-                    let ack = if let Some(ack) = &mut ack {
-                        ack
-                    } else {
-                        let ack_req =
-                            super::create_ack(&self.dialog, response.base_headers.cseq.cseq)
-                                .await?;
+                    self.session_timer
+                        .on_refresh_response(self.role, &response.headers);
 
-                        ack.insert(ack_req)
-                    };
+                    let mut ack =
+                        super::create_ack(&self.dialog, response.base_headers.cseq.cseq).await?;
 
                     self.endpoint
-                        .send_outgoing_request(ack)
+                        .send_outgoing_request(&mut ack)
                         .await
                         .map_err(sip_core::Error::from)?;
+
+                    // Keep acknowledging retransmissions of the 2xx response until the transaction terminates
+                    let endpoint = self.endpoint.clone();
+                    tokio::spawn(async move {
+                        while let Ok(Some(_)) = transaction.receive().await {
+                            if let Err(e) = endpoint.send_outgoing_request(&mut ack).await {
+                                log::warn!("Failed to retransmit ACK for re-INVITE {e:?}");
+                            }
+                        }
+                    });
+
+                    return Ok(response);
                 }
                 _ => return Err(SessionRefreshError::UnexpectedStatus(response.line.code)),
             }
         }
 
-        Ok(())
+        Err(SessionRefreshError::Core(sip_core::Error::RequestTimedOut))
+    }
+
+    /// Returns the time to wait before retrying a re-INVITE that was rejected with `491 Request Pending`
+    ///
+    /// As described in RFC3261 Section 14.1, the owner of the Call-ID waits 2.1 to 4 seconds, the other side waits
+    /// 0 to 2 seconds, both chosen randomly in units of 10ms.
+    pub fn request_pending_backoff(&self) -> Duration {
+        let range = match self.role {
+            Role::Uac => 210..=400,
+            Role::Uas => 0..=200,
+        };
+
+        Duration::from_millis(rand::rng().random_range(range) * 10)
     }
 
     pub async fn handle_bye(&mut self, event: ByeEvent) -> Result<()> {
@@ -214,11 +433,40 @@ impl InviteSession {
         Ok(())
     }
 
+    /// Respond to an UPDATE request received inside the session
+    ///
+    /// A successful `response` must contain the SDP answer if the UPDATE contained an offer.
+    pub async fn respond_update(
+        &mut self,
+        event: UpdateReceived,
+        mut response: OutgoingResponse,
+    ) -> Result<()> {
+        if response.msg.line.code.kind() == CodeKind::Success {
+            self.session_timer
+                .on_refresh_request(self.role, &event.update.headers);
+            self.session_timer
+                .populate_refresh_response(self.role, &mut response);
+
+            response
+                .msg
+                .headers
+                .insert_named(&self.dialog.local_contact);
+        }
+
+        // The offer has been answered or rejected with this response
+        *self.inner.negotiation.lock() = Negotiation::Idle;
+
+        event.transaction.respond(response).await
+    }
+
     pub async fn handle_reinvite_success(
         &mut self,
         event: ReInviteReceived,
-        response: OutgoingResponse,
+        mut response: OutgoingResponse,
     ) -> Result<IncomingRequest> {
+        self.session_timer
+            .populate_refresh_response(self.role, &mut response);
+
         let (ack_sender, ack_recv) = oneshot::channel();
 
         *self.inner.awaited_ack.lock() = Some(AwaitedAck {
@@ -232,7 +480,54 @@ impl InviteSession {
     }
 }
 
+impl Drop for InviteSession {
+    fn drop(&mut self) {
+        self.endpoint
+            .layer::<InviteLayer>()
+            .sessions
+            .lock()
+            .remove(&self.dialog.key());
+    }
+}
+
+/// Marks an offer sent using an UPDATE request as pending while alive
+pub(super) struct LocalOffer<'i>(&'i Inner);
+
+impl<'i> LocalOffer<'i> {
+    pub(super) fn new(inner: &'i Inner) -> Self {
+        *inner.negotiation.lock() = Negotiation::LocalOffer;
+        Self(inner)
+    }
+}
+
+impl Drop for LocalOffer<'_> {
+    fn drop(&mut self) {
+        *self.0.negotiation.lock() = Negotiation::Idle;
+    }
+}
+
+/// Marks a locally initiated re-INVITE as pending while alive
+struct ReInvitePending<'i>(&'i Inner);
+
+impl<'i> ReInvitePending<'i> {
+    fn new(inner: &'i Inner) -> Self {
+        inner.reinvite_pending.store(true, Ordering::Relaxed);
+        Self(inner)
+    }
+}
+
+impl Drop for ReInvitePending<'_> {
+    fn drop(&mut self) {
+        self.0.reinvite_pending.store(false, Ordering::Relaxed);
+    }
+}
+
 pub(super) enum UsageEvent {
     ReInvite(IncomingRequest),
+    Update(IncomingRequest),
     Bye(IncomingRequest),
+    Refer(IncomingRequest),
+    Notify(IncomingRequest),
+    Info(IncomingRequest),
+    Replaced,
 }
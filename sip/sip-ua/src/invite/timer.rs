@@ -1,96 +1,110 @@
+use super::session::Role;
 use sip_core::transaction::TsxResponse;
 use sip_core::transport::OutgoingResponse;
 use sip_core::{IncomingRequest, Request};
-use sip_types::Name;
 use sip_types::header::HeaderError;
-use sip_types::header::typed::{MinSe, Refresher, Require, SessionExpires};
+use sip_types::header::typed::{Allow, MinSe, Refresher, Require, SessionExpires};
+use sip_types::{Headers, Method, Name};
 use std::future::pending;
 use std::pin::Pin;
 use std::time::Duration;
 use tokio::time::{Sleep, sleep};
 
-/// Config of the `timer` extension used by the acceptor
-pub struct AcceptorTimerConfig {
-    pub refresher: Refresher,
-    pub interval_secs: u32,
-}
+/// Smallest session interval allowed by RFC4028
+const MIN_SESSION_EXPIRES: u32 = 90;
 
-impl Default for AcceptorTimerConfig {
-    fn default() -> Self {
-        Self {
-            refresher: Refresher::Uac,
-            interval_secs: 1800,
-        }
-    }
+/// Session interval used by the acceptor if neither side requested one
+const DEFAULT_SESSION_EXPIRES: u32 = 1800;
+
+/// Request method used to refresh a session
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshMethod {
+    /// Refresh the session using a re-INVITE
+    #[default]
+    ReInvite,
+    /// Refresh the session using an UPDATE request (RFC3311)
+    ///
+    /// Falls back to re-INVITE if the peer doesn't allow UPDATE requests.
+    Update,
 }
 
-impl AcceptorTimerConfig {
-    /// Takes the final successful response and the invite the response belongs to.
-    /// Populates the given response with an `Session-Expires` header and returns a
-    /// proper `SessionTimer` object to be used inside a session.
-    pub fn on_responding_success(
-        &mut self,
-        response: &mut OutgoingResponse,
-        invite: &IncomingRequest,
-    ) -> SessionTimer {
-        let delta_secs = if let Ok(min_se) = invite.headers.get_named::<MinSe>() {
-            min_se.0.max(self.interval_secs)
-        } else {
-            self.interval_secs
-        };
+/// Configuration of the `timer` extension (RFC4028)
+#[derive(Debug, Clone, Copy)]
+pub struct SessionTimerConfig {
+    /// Session interval in seconds to request using the `Session-Expires` header
+    ///
+    /// Outgoing INVITE requests only contain a `Session-Expires` header if this is set, letting the peer choose the
+    /// interval otherwise. When accepting an INVITE this is used as upper limit of the interval requested by the peer,
+    /// or as interval if the peer didn't request one (1800 seconds if not set).
+    pub session_expires: Option<u32>,
 
-        let real_delta_secs;
+    /// Smallest session interval in seconds accepted, sent using the `Min-SE` header (at least 90)
+    ///
+    /// Incoming INVITE requests with a smaller interval are rejected with `422 Session Interval Too Small`.
+    pub min_se: u32,
 
-        // Map unspecified -> Uac as usually if none is specified
-        // the UAC side is responsible for refreshes
-        self.refresher = match self.refresher {
-            Refresher::Uas => {
-                real_delta_secs = delta_secs / 2;
-                Refresher::Uas
-            }
-            Refresher::Unspecified | Refresher::Uac => {
-                real_delta_secs = delta_secs;
-                Refresher::Uac
-            }
-        };
-
-        response.msg.headers.insert_named(&Require("timer".into()));
-        response.msg.headers.insert_named(&SessionExpires {
-            delta_secs,
-            refresher: self.refresher,
-        });
+    /// Preferred side responsible for refreshing the session
+    ///
+    /// A refresher chosen by the initiator of the session always takes precedence. If neither side has a
+    /// preference the initiator (UAC) refreshes the session.
+    pub refresher: Refresher,
 
-        let sleep = sleep(Duration::from_secs(real_delta_secs as u64));
+    /// Request method used to refresh the session, when responsible for refreshes
+    pub refresh_method: RefreshMethod,
+}
 
-        SessionTimer {
-            refresher: self.refresher,
-            real_delta_secs,
-            interval: RefreshInterval::Sleeping(Box::pin(sleep)),
+impl Default for SessionTimerConfig {
+    fn default() -> Self {
+        Self {
+            session_expires: None,
+            min_se: MIN_SESSION_EXPIRES,
+            refresher: Refresher::Unspecified,
+            refresh_method: RefreshMethod::ReInvite,
         }
     }
 }
 
-/// Config of the `timer` extension used by the initiator
-#[derive(Debug, Clone, Copy)]
-pub struct InitiatorTimerConfig {
-    pub expires_secs: Option<u32>,
-    pub refresher: Refresher,
-    pub expires_secs_min: u32,
-}
+impl SessionTimerConfig {
+    fn min_se(&self) -> u32 {
+        self.min_se.max(MIN_SESSION_EXPIRES)
+    }
 
-impl InitiatorTimerConfig {
+    /// Populate the headers of an initial INVITE request
     pub fn populate_request(&self, request: &mut Request) {
-        if let Some(expires_secs) = self.expires_secs {
+        if let Some(expires_secs) = self.session_expires {
             request.headers.insert_named(&SessionExpires {
-                delta_secs: expires_secs,
+                delta_secs: expires_secs.max(self.min_se()),
                 refresher: self.refresher,
             });
         }
 
         request.headers.insert(Name::SUPPORTED, "timer");
-        request.headers.insert_named(&MinSe(self.expires_secs_min));
+        request.headers.insert_named(&MinSe(self.min_se()));
+    }
+
+    /// Apply the `Min-SE` of a `422 Session Interval Too Small` response to the config
+    ///
+    /// Returns if the INVITE should be sent again using the raised session interval.
+    pub fn on_interval_too_small(&mut self, headers: &Headers) -> bool {
+        let Ok(MinSe(min_se)) = headers.get_named::<MinSe>() else {
+            return false;
+        };
+
+        // Only retry if the interval actually changes, to avoid looping forever
+        if self
+            .session_expires
+            .is_some_and(|expires_secs| expires_secs >= min_se)
+        {
+            return false;
+        }
+
+        self.min_se = self.min_se.max(min_se);
+        self.session_expires = Some(min_se);
+
+        true
     }
 
+    /// Create the session timer from the final successful response to the initial INVITE
     pub fn create_timer_from_response(
         &self,
         response: &TsxResponse,
@@ -100,52 +114,160 @@ impl InitiatorTimerConfig {
             .try_get_named::<SessionExpires>()
             .transpose()?
         {
-            let real_delta_secs;
-
+            // The UAS must choose a refresher, but be lenient and take over if it didn't
             let refresher = match se.refresher {
-                Refresher::Uas => {
-                    real_delta_secs = se.delta_secs;
-                    Refresher::Uas
-                }
-                Refresher::Unspecified | Refresher::Uac => {
-                    real_delta_secs = se.delta_secs / 2;
-                    Refresher::Uac
-                }
+                Refresher::Uas => Refresher::Uas,
+                Refresher::Unspecified | Refresher::Uac => Refresher::Uac,
             };
 
-            let sleep = sleep(Duration::from_secs(real_delta_secs as u64));
-
-            Ok(SessionTimer {
+            Ok(SessionTimer::new(
+                Role::Uac,
                 refresher,
-                real_delta_secs,
-                interval: RefreshInterval::Sleeping(Box::pin(sleep)),
-            })
+                se.delta_secs,
+                self.refresh_method(&response.headers),
+            ))
         } else {
             Ok(SessionTimer::new_unsupported())
         }
     }
+
+    /// Returns the `Min-SE` to reject the INVITE with, if its requested session interval is too small
+    pub fn interval_too_small(&self, invite: &IncomingRequest) -> Option<MinSe> {
+        invite
+            .headers
+            .get_named::<SessionExpires>()
+            .is_ok_and(|se| se.delta_secs < self.min_se())
+            .then(|| MinSe(self.min_se()))
+    }
+
+    /// Takes the final successful response and the invite the response belongs to.
+    /// Populates the given response with an `Session-Expires` header and returns a
+    /// proper `SessionTimer` object to be used inside a session.
+    pub fn on_responding_success(
+        &self,
+        response: &mut OutgoingResponse,
+        invite: &IncomingRequest,
+    ) -> SessionTimer {
+        let requested = invite.headers.get_named::<SessionExpires>().ok();
+        let peer_min_se = invite
+            .headers
+            .get_named::<MinSe>()
+            .ok()
+            .map(|min_se| min_se.0);
+
+        let session_expires = self.negotiate(requested, peer_min_se);
+
+        response.msg.headers.insert_named(&Require("timer".into()));
+        response.msg.headers.insert_named(&session_expires);
+
+        SessionTimer::new(
+            Role::Uas,
+            session_expires.refresher,
+            session_expires.delta_secs,
+            self.refresh_method(&invite.headers),
+        )
+    }
+
+    /// Choose the session interval and refresher as the acceptor of an INVITE
+    fn negotiate(
+        &self,
+        requested: Option<SessionExpires>,
+        peer_min_se: Option<u32>,
+    ) -> SessionExpires {
+        let delta_secs = match (requested, self.session_expires) {
+            (Some(requested), Some(session_expires)) => requested.delta_secs.min(session_expires),
+            (Some(requested), None) => requested.delta_secs,
+            (None, session_expires) => session_expires.unwrap_or(DEFAULT_SESSION_EXPIRES),
+        };
+
+        let delta_secs = delta_secs
+            .max(peer_min_se.unwrap_or(MIN_SESSION_EXPIRES))
+            .max(self.min_se());
+
+        // The refresher requested by the UAC must not be changed, map unspecified -> Uac as usually
+        // if none is specified the UAC side is responsible for refreshes
+        let refresher = match requested.map(|se| se.refresher) {
+            Some(refresher @ (Refresher::Uac | Refresher::Uas)) => refresher,
+            _ => match self.refresher {
+                Refresher::Uas => Refresher::Uas,
+                Refresher::Unspecified | Refresher::Uac => Refresher::Uac,
+            },
+        };
+
+        SessionExpires {
+            delta_secs,
+            refresher,
+        }
+    }
+
+    /// Only refresh using UPDATE if the peer allows it
+    fn refresh_method(&self, peer_headers: &Headers) -> RefreshMethod {
+        let peer_allows_update = peer_headers
+            .get_named::<Vec<Allow>>()
+            .unwrap_or_default()
+            .iter()
+            .any(|allow| allow.0 == Method::UPDATE);
+
+        match self.refresh_method {
+            RefreshMethod::Update if peer_allows_update => RefreshMethod::Update,
+            _ => RefreshMethod::ReInvite,
+        }
+    }
 }
 
 /// Timer which is used to track whenever a session is expired
 /// and when it needs to be refreshed depending on the refresher
 #[derive(Debug)]
 pub struct SessionTimer {
+    /// Side of the dialog responsible for refreshes
     pub refresher: Refresher,
+    /// Negotiated session interval
+    pub delta_secs: u32,
+    /// Time to wait for the refresh, or the session to expire
     pub real_delta_secs: u32,
+    pub refresh_method: RefreshMethod,
     pub interval: RefreshInterval,
 }
 
 impl SessionTimer {
+    fn new(
+        role: Role,
+        refresher: Refresher,
+        delta_secs: u32,
+        refresh_method: RefreshMethod,
+    ) -> Self {
+        let mut timer = Self {
+            refresher,
+            delta_secs,
+            real_delta_secs: 0,
+            refresh_method,
+            interval: RefreshInterval::Sleeping(Box::pin(sleep(Duration::ZERO))),
+        };
+
+        timer.set_interval(role, refresher, delta_secs);
+        timer
+    }
+
     /// Create a new session timer that will never expire.
     /// Useful for sessions with peers that do not support the `timer` extension.
     pub fn new_unsupported() -> Self {
         Self {
             refresher: Refresher::Unspecified,
+            delta_secs: 0,
             real_delta_secs: 0,
+            refresh_method: RefreshMethod::ReInvite,
             interval: RefreshInterval::Unsupported,
         }
     }
 
+    /// Returns if the local side of the session is responsible for refreshes
+    pub fn is_local_refresher(&self, role: Role) -> bool {
+        matches!(
+            (role, self.refresher),
+            (Role::Uac, Refresher::Uac) | (Role::Uas, Refresher::Uas)
+        )
+    }
+
     /// Wait for the session to expire. Will never return if no session expiry is set
     pub async fn wait(&mut self) {
         match &mut self.interval {
@@ -164,14 +286,100 @@ impl SessionTimer {
         }
     }
 
-    /// Populate headers of an INVITE refresh request
-    pub fn populate_refresh(&mut self, request: &mut Request) {
-        if let RefreshInterval::Unsupported = &mut self.interval {
+    /// Populate headers of a refresh request (re-INVITE or UPDATE)
+    pub fn populate_refresh(&self, role: Role, request: &mut Request) {
+        if let RefreshInterval::Unsupported = &self.interval {
             return;
         }
 
+        // The refresher parameter is relative to the refresh transaction
+        let refresher = if self.is_local_refresher(role) {
+            Refresher::Uac
+        } else {
+            Refresher::Uas
+        };
+
         request.headers.insert(Name::SUPPORTED, "timer");
         request.headers.insert(Name::REQUIRE, "timer");
+        request.headers.insert_named(&SessionExpires {
+            delta_secs: self.delta_secs,
+            refresher,
+        });
+    }
+
+    /// Apply the `Session-Expires` header of the successful response to a refresh request sent by us
+    pub fn on_refresh_response(&mut self, role: Role, headers: &Headers) {
+        if let RefreshInterval::Unsupported = &self.interval {
+            return;
+        }
+
+        if let Ok(se) = headers.get_named::<SessionExpires>() {
+            let local_refresher = se.refresher != Refresher::Uas;
+
+            self.set_interval(role, dialog_refresher(role, local_refresher), se.delta_secs);
+        }
+
+        self.reset();
+    }
+
+    /// Apply the `Session-Expires` header of a refresh request received from the peer
+    pub fn on_refresh_request(&mut self, role: Role, headers: &Headers) {
+        if let RefreshInterval::Unsupported = &self.interval {
+            return;
+        }
+
+        if let Ok(se) = headers.get_named::<SessionExpires>() {
+            let local_refresher = match se.refresher {
+                Refresher::Uac => false,
+                Refresher::Uas => true,
+                Refresher::Unspecified => self.is_local_refresher(role),
+            };
+
+            self.set_interval(role, dialog_refresher(role, local_refresher), se.delta_secs);
+        }
+
+        self.reset();
+    }
+
+    /// Populate headers of the successful response to a refresh request received from the peer
+    pub fn populate_refresh_response(&self, role: Role, response: &mut OutgoingResponse) {
+        if let RefreshInterval::Unsupported = &self.interval {
+            return;
+        }
+
+        let refresher = if self.is_local_refresher(role) {
+            Refresher::Uas
+        } else {
+            Refresher::Uac
+        };
+
+        response.msg.headers.insert_named(&Require("timer".into()));
+        response.msg.headers.insert_named(&SessionExpires {
+            delta_secs: self.delta_secs,
+            refresher,
+        });
+    }
+
+    fn set_interval(&mut self, role: Role, refresher: Refresher, delta_secs: u32) {
+        self.refresher = refresher;
+        self.delta_secs = delta_secs;
+        self.real_delta_secs = if self.is_local_refresher(role) {
+            // Refresh at half the session interval (RFC4028 Section 10)
+            delta_secs / 2
+        } else {
+            // Give the refresher some time before considering the session expired (RFC4028 Section 10)
+            delta_secs - (delta_secs / 3).min(32)
+        };
+
+        self.reset();
+    }
+}
+
+/// Map the refresher from the local perspective to the perspective of the dialog
+fn dialog_refresher(role: Role, local_refresher: bool) -> Refresher {
+    match (role, local_refresher) {
+        (Role::Uac, true) | (Role::Uas, false) => Refresher::Uac,
+        (Role::Uas, true) | (Role::Uac, false) => Refresher::Uas,
     }
 }
 
@@ -180,3 +388,57 @@ pub enum RefreshInterval {
     Unsupported,
     Sleeping(Pin<Box<Sleep>>),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_with_raised_interval() {
+        let mut config = SessionTimerConfig {
+            session_expires: Some(300),
+            ..SessionTimerConfig::default()
+        };
+
+        let mut headers = Headers::new();
+        headers.insert_named(&MinSe(600));
+
+        assert!(config.on_interval_too_small(&headers));
+        assert_eq!(config.session_expires, Some(600));
+        assert_eq!(config.min_se, 600);
+
+        // Same Min-SE again must not cause another retry
+        assert!(!config.on_interval_too_small(&headers));
+
+        // Missing Min-SE cannot be retried
+        assert!(!config.on_interval_too_small(&Headers::new()));
+    }
+
+    #[test]
+    fn acceptor_negotiation() {
+        let config = SessionTimerConfig {
+            session_expires: Some(900),
+            min_se: 120,
+            ..SessionTimerConfig::default()
+        };
+
+        // Interval is limited by the local config, the refresher of the UAC is kept
+        let se = config.negotiate(
+            Some(SessionExpires {
+                delta_secs: 1800,
+                refresher: Refresher::Uas,
+            }),
+            None,
+        );
+        assert_eq!(se.delta_secs, 900);
+        assert_eq!(se.refresher, Refresher::Uas);
+
+        // Interval never goes below the Min-SE of the peer
+        let se = config.negotiate(None, Some(1000));
+        assert_eq!(se.delta_secs, 1000);
+        assert_eq!(se.refresher, Refresher::Uac);
+
+        let se = SessionTimerConfig::default().negotiate(None, None);
+        assert_eq!(se.delta_secs, DEFAULT_SESSION_EXPIRES);
+    }
+}
@@ -5,33 +5,72 @@
 //! Notable types are
 //!
 //! - [`Registration`] A binding to a SIP registrar with an associated identity
+//! - [`UserAgent`] Multiple accounts on one endpoint, which receive the incoming requests addressed to them
 //! - [`Call`] an established and running INVITE session with negotiated SDP
 //! - [`OutboundCall`] An attempt to create a `Call`
 //! - [`InboundCall`] An incoming INVITE session which can be accepted or declined
+//! - [`Subscription`](subscription::Subscription) & [`Notifier`](subscription::Notifier) The subscriber and
+//!   notifier side of a SIP event subscription
+//! - [`RegistrarLayer`] A registrar storing the bindings of REGISTER requests in a [`LocationStore`]
+//! - [`ProxyLayer`](proxy::ProxyLayer) A stateful proxy forwarding requests to the targets of a
+//!   [`TargetLookup`](proxy::TargetLookup)
+//! - [`B2bua`](b2bua::B2bua) A back-to-back user agent bridging an `InboundCall` with an `OutboundCall`
 //!
 //! The modules [`dialog`], [`invite`], [`register`] and [`util`] contain implementation details used inside the top
 //! level abstractions and can be used for more specialized use cases.
 //!
 
+pub mod b2bua;
 pub mod dialog;
 pub mod invite;
+pub mod proxy;
 pub mod register;
+pub mod subscription;
 pub mod util;
 
 mod call;
+mod call_record;
+mod dtmf;
 mod inbound_call;
+mod info;
 mod media_backend;
 #[cfg(feature = "rtc")]
 mod media_rtc;
+mod message;
+mod mwi;
+mod options;
 mod outbound_call;
+mod registrar;
 mod registration;
+#[cfg(test)]
+mod test_util;
+mod transfer;
+mod user_agent;
 
-pub use call::{Call, CallError, CallEvent};
+pub use call::{Call, CallError, CallEvent, TerminationReason};
+pub use call_record::{CallRecord, MediaStreamRecord, TerminationCause};
+pub use dtmf::{DtmfEvent, DtmfMode};
 pub use inbound_call::{AcceptCallError, InboundCall, InboundCallFromInviteError, NoMedia};
+pub use info::IncomingInfo;
+pub use invite::{RefreshMethod, SessionTimerConfig};
 pub use media_backend::MediaBackend;
 #[cfg(feature = "rtc")]
 pub use media_rtc::{
     Codec, MediaEvent, RtcMediaBackend, RtcMediaBackendError, RtpReceiver, RtpSender,
 };
-pub use outbound_call::{MakeCallCompletionError, MakeCallError, OutboundCall};
-pub use registration::{RegisterError, RegistrarConfig, Registration};
+pub use message::{IncomingMessage, IncomingMessages, MessageLayer, SendMessageError};
+pub use mwi::{
+    MessageClass, MessageCounts, MessageSummary, MessageSummaryError, MessageSummaryPackage,
+    MwiSubscription,
+};
+pub use options::{OptionsLayer, OptionsPinger, PeerStatus};
+pub use outbound_call::{
+    EarlyDialog, MakeCallCompletionError, MakeCallError, MakeCallOptions, OutboundCall,
+    OutboundCallProgress, UnacknowledgedCall,
+};
+pub use registrar::{
+    Binding, InMemoryLocationStore, LocationStore, RegistrarLayer, address_of_record,
+};
+pub use registration::{BindingState, RegisterError, RegistrarConfig, Registration};
+pub use transfer::{IncomingRefer, ReferNotifier, TransferProgress};
+pub use user_agent::{Account, AccountEvent, IncomingNotify, UserAgent, UserAgentLayer};
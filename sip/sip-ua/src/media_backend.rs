@@ -1,7 +1,8 @@
+use crate::{DtmfEvent, MediaStreamRecord};
 use bytesstr::BytesStr;
 use sdp_types::SessionDescription;
 use sip_types::header::typed::ContentType;
-use std::{error::Error, fmt::Debug, future::Future};
+use std::{error::Error, fmt::Debug, future::Future, time::Duration};
 
 pub(crate) const CONTENT_TYPE_SDP: ContentType =
     ContentType(BytesStr::from_static("application/sdp"));
@@ -27,6 +28,39 @@ pub trait MediaBackend {
         sdp: SessionDescription,
     ) -> impl Future<Output = Result<SessionDescription, Self::Error>> + Send;
 
+    /// Put all media on hold or resume it
+    ///
+    /// The change must be applied with the next SDP offer created using [`MediaBackend::create_sdp_offer`].
+    /// Media put on hold must no longer be received, by marking it as `sendonly` or `inactive` (RFC3264 Section 8.4).
+    /// Resuming must restore the direction used before the media was put on hold.
+    ///
+    /// Does nothing by default.
+    fn set_hold(&mut self, hold: bool) {
+        let _ = hold;
+    }
+
     /// Run until a media event is received
     fn run(&mut self) -> impl Future<Output = Result<Self::Event, Self::Error>> + Send;
+
+    /// Send a DTMF digit as RFC4733 telephone-event with the given tone `duration`
+    ///
+    /// Digits must be queued after any previously sent digit. Returns `false` if no telephone-event
+    /// payload type has been negotiated, in which case [`Call`](crate::Call) may fall back to SIP INFO.
+    fn send_dtmf(&mut self, digit: char, duration: Duration) -> bool {
+        let _ = (digit, duration);
+        false
+    }
+
+    /// Returns the DTMF digit if the event reports a received RFC4733 telephone-event
+    ///
+    /// These events are returned as [`CallEvent::Dtmf`](crate::CallEvent::Dtmf) instead of [`CallEvent::Media`](crate::CallEvent::Media).
+    fn dtmf_event(event: &Self::Event) -> Option<DtmfEvent> {
+        let _ = event;
+        None
+    }
+
+    /// Returns the statistics of all media streams, used to create the [`CallRecord`](crate::CallRecord) of a call
+    fn media_stats(&mut self) -> Vec<MediaStreamRecord> {
+        Vec::new()
+    }
 }
@@ -1,9 +1,16 @@
-use crate::MediaBackend;
+use crate::{
+    DtmfEvent, MediaBackend, MediaStreamRecord,
+    dtmf::{
+        TelephoneEvent, TelephoneEventReceiver, digit_to_event, event_to_digit,
+        parse_telephone_event, telephone_event_length, telephone_event_packets,
+    },
+};
+use bytes::Bytes;
 use rtc::{
     rtp_session::SendRtpPacket,
     rtp_transport::TransportConnectionState,
     sdp::{
-        Direction, MediaId, NegotiatedCodec, SdpError, SdpSession, SdpSessionEvent,
+        Direction, MediaId, MediaStats, NegotiatedCodec, SdpError, SdpSession, SdpSessionEvent,
         SessionDescription, TransportId,
     },
     tokio::TokioIoState,
@@ -22,10 +29,14 @@ use std::{
         atomic::{AtomicBool, Ordering},
     },
     task::{Context, Poll, ready},
+    time::{Duration, Instant},
 };
-use tokio::sync::{
-    mpsc::{self},
-    watch,
+use tokio::{
+    sync::{
+        mpsc::{self},
+        watch,
+    },
+    time::sleep_until,
 };
 use tokio_util::sync::PollSender;
 
@@ -57,6 +68,9 @@ pub struct RtcMediaBackend {
 
     /// Queue of events for the user
     events: VecDeque<MediaEvent>,
+
+    /// Earliest time the next outbound telephone-event may start
+    dtmf_next_start: Instant,
 }
 
 /// The negotiated codec for the sender/receiver
@@ -76,9 +90,17 @@ struct MediaState {
     transport_id: TransportId,
     codec: NegotiatedCodec,
 
+    /// Currently negotiated local direction
+    direction: Direction,
+    /// Direction to restore when resuming from hold
+    direction_before_hold: Option<Direction>,
+
     /// Track if the sender is still valid
     sender: Option<Arc<AtomicBool>>,
     receiver: Option<mpsc::Sender<RtpPacket>>,
+
+    /// Inbound telephone-events
+    dtmf: TelephoneEventReceiver,
 }
 
 impl RtcMediaBackend {
@@ -93,6 +115,7 @@ impl RtcMediaBackend {
             transports: HashMap::new(),
             media: HashMap::new(),
             events: VecDeque::new(),
+            dtmf_next_start: Instant::now(),
         }
     }
 
@@ -141,13 +164,52 @@ impl MediaBackend for RtcMediaBackend {
         Ok(self.sdp_session.create_sdp_answer(answer_state))
     }
 
+    fn set_hold(&mut self, hold: bool) {
+        for (media_id, media_state) in &mut self.media {
+            let new_direction = if hold {
+                media_state
+                    .direction_before_hold
+                    .get_or_insert(media_state.direction);
+
+                match media_state.direction {
+                    Direction::SendRecv | Direction::SendOnly => Direction::SendOnly,
+                    Direction::RecvOnly | Direction::Inactive => Direction::Inactive,
+                }
+            } else if let Some(direction) = media_state.direction_before_hold.take() {
+                direction
+            } else {
+                continue;
+            };
+
+            self.sdp_session.update_media(*media_id, new_direction);
+        }
+    }
+
     async fn run(&mut self) -> Result<Self::Event, Self::Error> {
         loop {
             if let Some(event) = self.events.pop_front() {
                 return Ok(event);
             }
 
+            let dtmf_timeout = self
+                .media
+                .values()
+                .filter_map(|media_state| media_state.dtmf.timeout())
+                .min();
+            let dtmf_sleep = sleep_until(dtmf_timeout.unwrap_or_else(Instant::now).into());
+
             let event = tokio::select! {
+                _ = dtmf_sleep, if dtmf_timeout.is_some() => {
+                    let now = Instant::now();
+
+                    for media_state in self.media.values_mut() {
+                        if let Some(event) = media_state.dtmf_timeout(now) {
+                            self.events.push_back(MediaEvent::Dtmf(event));
+                        }
+                    }
+
+                    continue;
+                }
                 Some((media_id, packet)) = self.rx.recv() => {
                     if let Some(mut writer) = self.sdp_session.writer(media_id) {
                         writer.send_rtp(packet);
@@ -171,8 +233,11 @@ impl MediaBackend for RtcMediaBackend {
                     let mut media_state = MediaState {
                         transport_id: event.transport_id,
                         codec: event.codec,
+                        direction: event.direction,
+                        direction_before_hold: None,
                         sender: None,
                         receiver: None,
+                        dtmf: TelephoneEventReceiver::default(),
                     };
 
                     if send {
@@ -198,6 +263,8 @@ impl MediaBackend for RtcMediaBackend {
                     let media_state = self.media.get_mut(&event.id).unwrap();
                     let transport_state = &self.transports[&media_state.transport_id];
 
+                    media_state.direction = event.new_direction;
+
                     if old_send
                         && !new_send
                         && let Some(valid) = media_state.sender.take()
@@ -240,9 +307,23 @@ impl MediaBackend for RtcMediaBackend {
                     media_id,
                     rtp_packet,
                 } => {
-                    if let Some(receiver) =
-                        self.media.get(&media_id).and_then(|m| m.receiver.as_ref())
+                    let Some(media_state) = self.media.get_mut(&media_id) else {
+                        continue;
+                    };
+
+                    if media_state
+                        .codec
+                        .dtmf
+                        .as_ref()
+                        .is_some_and(|dtmf| dtmf.pt == rtp_packet.pt)
                     {
+                        self.events.extend(
+                            media_state
+                                .receive_dtmf(&rtp_packet)
+                                .into_iter()
+                                .map(MediaEvent::Dtmf),
+                        );
+                    } else if let Some(receiver) = &media_state.receiver {
                         let _ = receiver.send(rtp_packet).await;
                     }
                 }
@@ -259,6 +340,117 @@ impl MediaBackend for RtcMediaBackend {
             }
         }
     }
+
+    fn send_dtmf(&mut self, digit: char, duration: Duration) -> bool {
+        let Some(event) = digit_to_event(digit) else {
+            return false;
+        };
+
+        let Some((media_id, dtmf_pt, clock_rate)) = self
+            .media
+            .iter()
+            .filter(|(_, media_state)| media_state.sender.is_some())
+            .find_map(|(media_id, media_state)| {
+                let dtmf = media_state.codec.dtmf.as_ref()?;
+                Some((*media_id, dtmf.pt, media_state.codec.clock_rate))
+            })
+        else {
+            return false;
+        };
+
+        let Some(mut writer) = self.sdp_session.writer(media_id) else {
+            return false;
+        };
+
+        // All packets of an event carry the timestamp of its start
+        let start = self.dtmf_next_start.max(Instant::now());
+
+        for packet in telephone_event_packets(event, duration, clock_rate) {
+            writer.send_rtp(
+                SendRtpPacket::new(start, dtmf_pt, Bytes::copy_from_slice(&packet.payload))
+                    .send_at(start + packet.offset)
+                    .marker(packet.marker),
+            );
+        }
+
+        self.dtmf_next_start = start + telephone_event_length(duration);
+
+        true
+    }
+
+    fn dtmf_event(event: &Self::Event) -> Option<DtmfEvent> {
+        match event {
+            MediaEvent::Dtmf(event) => Some(*event),
+            _ => None,
+        }
+    }
+
+    fn media_stats(&mut self) -> Vec<MediaStreamRecord> {
+        let mut media_ids: Vec<MediaId> = self.media.keys().copied().collect();
+        media_ids.sort();
+
+        media_ids
+            .into_iter()
+            .filter_map(|media_id| self.sdp_session.media_stats(media_id))
+            .map(stream_record)
+            .collect()
+    }
+}
+
+fn stream_record(stats: MediaStats) -> MediaStreamRecord {
+    let outbound = stats.outbound.map(|(_, stats)| stats);
+    let inbound = stats.inbound.map(|(_, stats)| stats);
+    let remote = outbound.and_then(|stats| stats.remote);
+
+    MediaStreamRecord {
+        codec: stats.codec.into_owned(),
+        pt: stats.pt,
+        clock_rate: stats.clock_rate,
+        local_address: stats.local_address,
+        remote_address: stats.remote_address,
+        local_ssrc: stats.outbound.map(|(ssrc, _)| ssrc.0),
+        remote_ssrc: stats.inbound.map(|(ssrc, _)| ssrc.0),
+        packets_sent: outbound.map_or(0, |stats| stats.packets_sent),
+        bytes_sent: outbound.map_or(0, |stats| stats.bytes_sent),
+        packets_received: inbound.map_or(0, |stats| stats.packets_received),
+        bytes_received: inbound.map_or(0, |stats| stats.bytes_received),
+        packets_lost: inbound.map_or(0, |stats| stats.packets_lost),
+        loss: inbound.map_or(0.0, |stats| stats.loss),
+        jitter: inbound.map(|stats| stats.jitter).unwrap_or_default(),
+        rtt: remote.and_then(|remote| remote.rtt),
+        remote_loss: remote.map(|remote| remote.loss),
+        remote_jitter: remote.map(|remote| remote.jitter),
+    }
+}
+
+impl MediaState {
+    /// Returns all telephone-events which have ended with the given packet
+    fn receive_dtmf(&mut self, rtp_packet: &RtpPacket) -> Vec<DtmfEvent> {
+        let Some(event) = parse_telephone_event(&rtp_packet.payload) else {
+            return vec![];
+        };
+
+        self.dtmf
+            .receive(rtp_packet.timestamp.0, event, Instant::now())
+            .into_iter()
+            .filter_map(|event| self.dtmf_event(event))
+            .collect()
+    }
+
+    /// Report a telephone-event whose end packets got lost
+    fn dtmf_timeout(&mut self, now: Instant) -> Option<DtmfEvent> {
+        let event = self.dtmf.poll_timeout(now)?;
+        self.dtmf_event(event)
+    }
+
+    fn dtmf_event(&self, event: TelephoneEvent) -> Option<DtmfEvent> {
+        Some(DtmfEvent {
+            digit: event_to_digit(event.event)?,
+            duration: Duration::from_secs_f64(
+                f64::from(event.duration) / f64::from(self.codec.clock_rate),
+            ),
+        })
+    }
 }
 
 fn add_sender(
@@ -319,8 +511,16 @@ fn direction_bools(direction: Direction) -> (bool, bool) {
 
 /// Event returned by [`RtcMediaBackend::run`]
 pub enum MediaEvent {
-    SenderAdded { sender: RtpSender, codec: Codec },
-    ReceiverAdded { receiver: RtpReceiver, codec: Codec },
+    SenderAdded {
+        sender: RtpSender,
+        codec: Codec,
+    },
+    ReceiverAdded {
+        receiver: RtpReceiver,
+        codec: Codec,
+    },
+    /// A RFC4733 telephone-event has been received, these packets are not forwarded to the [`RtpReceiver`]
+    Dtmf(DtmfEvent),
 }
 
 /// RTP sender. Name says it all. Used to send RTP packets to an active media session.
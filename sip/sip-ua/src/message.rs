@@ -0,0 +1,352 @@
+//! Pager-mode instant messaging using MESSAGE requests ([RFC3428](https://datatracker.ietf.org/doc/html/rfc3428))
+
+use crate::util::{random_sequence_number, random_string};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use sip_auth::{ClientAuthenticator, RequestParts, ResponseParts};
+use sip_core::transport::TargetTransportInfo;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake, Request, Result};
+use sip_types::header::typed::{Accept, CSeq, CallID, ContentType, Event, FromTo, MaxForwards};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Headers, Method, Name, StatusCode};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Any errors that might be encountered while sending a MESSAGE or PUBLISH request
+#[derive(Debug, thiserror::Error)]
+pub enum SendMessageError<A> {
+    #[error(transparent)]
+    Core(#[from] sip_core::Error),
+    #[error("Authentication of request failed")]
+    Auth(#[source] A),
+    #[error("Got response with unexpected status code {0:?}")]
+    Failed(StatusCode),
+}
+
+/// Send a MESSAGE request outside of any dialog and wait for it to be accepted
+pub(crate) async fn send_message<A: ClientAuthenticator>(
+    endpoint: &Endpoint,
+    authenticator: A,
+    id: NameAddr,
+    target: SipUri,
+    content_type: ContentType,
+    body: Bytes,
+) -> Result<(), SendMessageError<A::Error>> {
+    send_request(
+        endpoint,
+        authenticator,
+        Method::MESSAGE,
+        None,
+        id,
+        target,
+        content_type,
+        body,
+    )
+    .await
+}
+
+/// Send a request with a body outside of any dialog and wait for it to be accepted
+///
+/// The `Event` header is required for PUBLISH requests (RFC3903).
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn send_request<A: ClientAuthenticator>(
+    endpoint: &Endpoint,
+    mut authenticator: A,
+    method: Method,
+    event: Option<Event>,
+    id: NameAddr,
+    target: SipUri,
+    content_type: ContentType,
+    body: Bytes,
+) -> Result<(), SendMessageError<A::Error>> {
+    let from = FromTo::new(id, Some(random_string()));
+    let to = FromTo::new(NameAddr::uri(target.clone()), None);
+    let call_id = CallID::new(random_string());
+    let mut cseq = random_sequence_number();
+
+    let mut target_transport_info = TargetTransportInfo::default();
+
+    loop {
+        let mut request = Request::new(method.clone(), target.clone());
+        request.headers.insert_named(&MaxForwards(70));
+        request.headers.insert_type(Name::FROM, &from);
+        request.headers.insert_type(Name::TO, &to);
+        request.headers.insert_named(&call_id);
+        request
+            .headers
+            .insert_named(&CSeq::new(cseq, method.clone()));
+        if let Some(event) = &event {
+            request.headers.insert_named(event);
+        }
+        request.headers.insert_named(&content_type);
+        request.body = body.clone();
+
+        cseq += 1;
+
+        authenticator.authorize_request(&mut request.headers);
+
+        let mut transaction = endpoint
+            .send_request(request, &mut target_transport_info)
+            .await?;
+
+        let response = transaction.receive_final().await?;
+
+        match response.line.code.into_u16() {
+            200..=299 => return Ok(()),
+            401 | 407 => {
+                let request = transaction.request();
+
+                authenticator
+                    .handle_rejection(
+                        RequestParts {
+                            line: &request.msg.line,
+                            headers: &request.msg.headers,
+                            body: &request.msg.body,
+                        },
+                        ResponseParts {
+                            line: &response.line,
+                            headers: &response.headers,
+                            body: &response.body,
+                        },
+                    )
+                    .map_err(SendMessageError::Auth)?;
+            }
+            _ => return Err(SendMessageError::Failed(response.line.code)),
+        }
+    }
+}
+
+/// MESSAGE request received by the [`MessageLayer`]
+#[derive(Debug)]
+pub struct IncomingMessage {
+    /// `From` header of the sender
+    pub from: FromTo,
+
+    /// `To` header of the MESSAGE request
+    pub to: FromTo,
+
+    /// Request-URI of the MESSAGE request
+    pub uri: SipUri,
+
+    /// Content type of the message body
+    pub content_type: ContentType,
+
+    /// The message body
+    pub body: Bytes,
+
+    /// All headers of the MESSAGE request
+    pub headers: Headers,
+}
+
+impl IncomingMessage {
+    /// Returns the message body as text, if its content type is `text/plain` and it's valid UTF-8
+    pub fn text(&self) -> Option<&str> {
+        if !content_type_matches(&self.content_type, "text/plain") {
+            return None;
+        }
+
+        std::str::from_utf8(&self.body).ok()
+    }
+}
+
+/// Receiving end of the [`MessageLayer`]
+pub struct IncomingMessages {
+    messages: mpsc::Receiver<IncomingMessage>,
+}
+
+impl IncomingMessages {
+    /// Wait for the next received MESSAGE request
+    pub async fn recv(&mut self) -> Option<IncomingMessage> {
+        self.messages.recv().await
+    }
+}
+
+/// Layer which receives MESSAGE requests outside of any dialog
+///
+/// Requests with an accepted content type are answered with `200 OK` and delivered to [`IncomingMessages`],
+/// all others are rejected with `415 Unsupported Media Type`. If [`IncomingMessages`] isn't keeping up,
+/// requests are rejected with `486 Busy Here`.
+pub struct MessageLayer {
+    accepted: Vec<BytesStr>,
+    sink: mpsc::Sender<IncomingMessage>,
+}
+
+impl MessageLayer {
+    /// Create a new layer accepting messages with the given content types (e.g. `text/plain`)
+    ///
+    /// The content type `*/*` accepts all messages.
+    pub fn new<I, C>(accepted: I) -> (Self, IncomingMessages)
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<BytesStr>,
+    {
+        let (sink, messages) = mpsc::channel(16);
+
+        let layer = Self {
+            accepted: accepted.into_iter().map(Into::into).collect(),
+            sink,
+        };
+
+        (layer, IncomingMessages { messages })
+    }
+
+    fn is_accepted(&self, content_type: &ContentType) -> bool {
+        self.accepted
+            .iter()
+            .any(|accepted| accepted == "*/*" || content_type_matches(content_type, accepted))
+    }
+
+    async fn respond(
+        &self,
+        endpoint: &Endpoint,
+        mut request: IncomingRequest,
+        code: StatusCode,
+    ) -> Result<()> {
+        let mut response = endpoint.create_response(&request, code, None);
+
+        if code == StatusCode::UNSUPPORTED_MEDIA_TYPE {
+            for accepted in &self.accepted {
+                response.msg.headers.insert_named(&Accept(accepted.clone()));
+            }
+        }
+
+        endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for MessageLayer {
+    fn name(&self) -> &'static str {
+        "message"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::MESSAGE);
+
+        for accepted in &self.accepted {
+            endpoint.add_accept(Accept(accepted.clone()));
+        }
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        // Only handle MESSAGE requests outside of a dialog
+        if request.line.method != Method::MESSAGE || request.base_headers.to.tag.is_some() {
+            return;
+        }
+
+        if self.sink.is_closed() {
+            return;
+        }
+
+        let request = request.take();
+
+        let content_type = match request.headers.get_named::<ContentType>() {
+            Ok(content_type) if self.is_accepted(&content_type) => content_type,
+            _ => {
+                if let Err(e) = self
+                    .respond(endpoint, request, StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                    .await
+                {
+                    log::warn!("Failed to reject MESSAGE request, {e:?}");
+                }
+
+                return;
+            }
+        };
+
+        let message = IncomingMessage {
+            from: request.base_headers.from.clone(),
+            to: request.base_headers.to.clone(),
+            uri: request.line.uri.clone(),
+            content_type,
+            body: request.body.clone(),
+            headers: request.headers.clone(),
+        };
+
+        // Never wait for the application here, as it would block the endpoint's receive path
+        let code = match self.sink.try_send(message) {
+            Ok(()) => StatusCode::OK,
+            Err(TrySendError::Full(_)) => StatusCode::BUSY_HERE,
+            Err(TrySendError::Closed(_)) => StatusCode::TEMPORARILY_UNAVAILABLE,
+        };
+
+        if let Err(e) = self.respond(endpoint, request, code).await {
+            log::warn!("Failed to respond to MESSAGE request, {e:?}");
+        }
+    }
+}
+
+/// Returns if the content type (ignoring its parameters) equals `expected`
+fn content_type_matches(content_type: &ContentType, expected: &str) -> bool {
+    content_type
+        .0
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .eq_ignore_ascii_case(expected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{endpoint, request, send, within};
+
+    fn message(from: &SipUri, to: &SipUri, content_type: &'static str) -> Request {
+        let mut message = request(Method::MESSAGE, from, to);
+        message
+            .headers
+            .insert_named(&ContentType(BytesStr::from_static(content_type)));
+        message.body = "hello".into();
+        message
+    }
+
+    #[tokio::test]
+    async fn receive_message() {
+        let (layer, mut messages) = MessageLayer::new(["text/plain"]);
+
+        let (_alice_endpoint, alice) = endpoint("alice", |builder| builder.add_layer(layer)).await;
+        let (bob_endpoint, bob) = endpoint("bob", |_| {}).await;
+
+        let response = send(&bob_endpoint, message(&bob, &alice, "text/plain")).await;
+        assert_eq!(response.line.code, StatusCode::OK);
+
+        let message = within(messages.recv()).await.unwrap();
+        assert_eq!(message.body, "hello");
+        assert_eq!(message.from.uri.uri.user_part.user().unwrap(), "bob");
+    }
+
+    #[tokio::test]
+    async fn reject_unsupported_content_type() {
+        let (layer, _messages) = MessageLayer::new(["text/plain"]);
+
+        let (_alice_endpoint, alice) = endpoint("alice", |builder| builder.add_layer(layer)).await;
+        let (bob_endpoint, bob) = endpoint("bob", |_| {}).await;
+
+        let response = send(&bob_endpoint, message(&bob, &alice, "text/html")).await;
+        assert_eq!(response.line.code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            response.headers.get_named::<Accept>().unwrap().0,
+            "text/plain"
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_when_not_receiving() {
+        let (layer, _messages) = MessageLayer::new(["*/*"]);
+
+        let (_alice_endpoint, alice) = endpoint("alice", |builder| builder.add_layer(layer)).await;
+        let (bob_endpoint, bob) = endpoint("bob", |_| {}).await;
+
+        for _ in 0..16 {
+            let response = send(&bob_endpoint, message(&bob, &alice, "text/plain")).await;
+            assert_eq!(response.line.code, StatusCode::OK);
+        }
+
+        let response = send(&bob_endpoint, message(&bob, &alice, "text/plain")).await;
+        assert_eq!(response.line.code, StatusCode::BUSY_HERE);
+    }
+}
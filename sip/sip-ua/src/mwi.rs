@@ -0,0 +1,368 @@
+//! Message waiting indication using the message-summary event package
+//! ([RFC3842](https://datatracker.ietf.org/doc/html/rfc3842))
+
+use crate::subscription::{EventPackage, Notification, Subscription, UnsolicitedNotifications};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use std::fmt;
+use std::str::FromStr;
+use tokio::select;
+
+/// The `message-summary` event package
+pub struct MessageSummaryPackage;
+
+impl EventPackage for MessageSummaryPackage {
+    const EVENT: &'static str = "message-summary";
+    const CONTENT_TYPE: &'static str = "application/simple-message-summary";
+
+    type State = MessageSummary;
+    type Error = MessageSummaryError;
+
+    fn parse_state(body: &Bytes) -> Result<Self::State, Self::Error> {
+        std::str::from_utf8(body)
+            .map_err(|_| MessageSummaryError::InvalidUtf8)?
+            .parse()
+    }
+
+    fn print_state(state: &Self::State) -> Bytes {
+        state.to_string().into()
+    }
+}
+
+/// Errors that may occur when parsing a `application/simple-message-summary` body
+#[derive(Debug, thiserror::Error)]
+pub enum MessageSummaryError {
+    #[error("message summary is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("message summary is missing the Messages-Waiting line")]
+    MissingMessagesWaiting,
+    #[error("invalid message summary line {0:?}")]
+    InvalidLine(String),
+}
+
+/// Message context class defined in [RFC3458](https://datatracker.ietf.org/doc/html/rfc3458)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageClass {
+    Voice,
+    Fax,
+    Pager,
+    Multimedia,
+    Text,
+    None,
+    Other(BytesStr),
+}
+
+impl MessageClass {
+    fn from_name(name: &str) -> Self {
+        const CLASSES: [(&str, MessageClass); 6] = [
+            ("voice-message", MessageClass::Voice),
+            ("fax-message", MessageClass::Fax),
+            ("pager-message", MessageClass::Pager),
+            ("multimedia-message", MessageClass::Multimedia),
+            ("text-message", MessageClass::Text),
+            ("none", MessageClass::None),
+        ];
+
+        CLASSES
+            .into_iter()
+            .find(|(class_name, _)| class_name.eq_ignore_ascii_case(name))
+            .map(|(_, class)| class)
+            .unwrap_or_else(|| MessageClass::Other(name.into()))
+    }
+}
+
+impl fmt::Display for MessageClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageClass::Voice => f.write_str("Voice-Message"),
+            MessageClass::Fax => f.write_str("Fax-Message"),
+            MessageClass::Pager => f.write_str("Pager-Message"),
+            MessageClass::Multimedia => f.write_str("Multimedia-Message"),
+            MessageClass::Text => f.write_str("Text-Message"),
+            MessageClass::None => f.write_str("None"),
+            MessageClass::Other(other) => f.write_str(other),
+        }
+    }
+}
+
+/// Message counts of a single [`MessageClass`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub class: MessageClass,
+    pub new: u32,
+    pub old: u32,
+    pub new_urgent: u32,
+    pub old_urgent: u32,
+}
+
+impl MessageCounts {
+    fn parse(class: MessageClass, value: &str) -> Option<Self> {
+        let (counts, urgent) = match value.split_once('(') {
+            Some((counts, urgent)) => (counts, Some(urgent.trim().strip_suffix(')')?)),
+            None => (value, None),
+        };
+
+        let (new, old) = parse_count_pair(counts)?;
+        let (new_urgent, old_urgent) = match urgent {
+            Some(urgent) => parse_count_pair(urgent)?,
+            None => (0, 0),
+        };
+
+        Some(Self {
+            class,
+            new,
+            old,
+            new_urgent,
+            old_urgent,
+        })
+    }
+}
+
+impl fmt::Display for MessageCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}/{}", self.class, self.new, self.old)?;
+
+        if self.new_urgent != 0 || self.old_urgent != 0 {
+            write!(f, " ({}/{})", self.new_urgent, self.old_urgent)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_count_pair(i: &str) -> Option<(u32, u32)> {
+    let (a, b) = i.split_once('/')?;
+
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+/// `application/simple-message-summary` body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSummary {
+    /// Are there any new messages waiting
+    pub messages_waiting: bool,
+
+    /// URI of the account the summary refers to
+    pub message_account: Option<BytesStr>,
+
+    /// Message counts per message class
+    pub counts: Vec<MessageCounts>,
+}
+
+impl MessageSummary {
+    /// Returns the counts of the given class, if present
+    pub fn counts_of(&self, class: &MessageClass) -> Option<&MessageCounts> {
+        self.counts.iter().find(|counts| &counts.class == class)
+    }
+}
+
+impl FromStr for MessageSummary {
+    type Err = MessageSummaryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut messages_waiting = None;
+        let mut message_account = None;
+        let mut counts = Vec::new();
+
+        // Optional message headers after the first empty line are ignored
+        for line in s.lines().map(str::trim).take_while(|line| !line.is_empty()) {
+            let invalid_line = || MessageSummaryError::InvalidLine(line.into());
+
+            let (name, value) = line.split_once(':').ok_or_else(invalid_line)?;
+            let (name, value) = (name.trim(), value.trim());
+
+            if name.eq_ignore_ascii_case("messages-waiting") {
+                messages_waiting = Some(match value {
+                    v if v.eq_ignore_ascii_case("yes") => true,
+                    v if v.eq_ignore_ascii_case("no") => false,
+                    _ => return Err(invalid_line()),
+                });
+            } else if name.eq_ignore_ascii_case("message-account") {
+                message_account = Some(value.into());
+            } else {
+                let class = MessageClass::from_name(name);
+
+                counts.push(MessageCounts::parse(class, value).ok_or_else(invalid_line)?);
+            }
+        }
+
+        Ok(Self {
+            messages_waiting: messages_waiting
+                .ok_or(MessageSummaryError::MissingMessagesWaiting)?,
+            message_account,
+            counts,
+        })
+    }
+}
+
+impl fmt::Display for MessageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages_waiting = if self.messages_waiting { "yes" } else { "no" };
+        write!(f, "Messages-Waiting: {messages_waiting}\r\n")?;
+
+        if let Some(message_account) = &self.message_account {
+            write!(f, "Message-Account: {message_account}\r\n")?;
+        }
+
+        for counts in &self.counts {
+            write!(f, "{counts}\r\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Message waiting indications received by a [`Registration`](crate::Registration),
+/// see [`Registration::subscribe_mwi`](crate::Registration::subscribe_mwi)
+pub struct MwiSubscription {
+    pub(crate) subscription: Option<Subscription<MessageSummaryPackage>>,
+    pub(crate) unsolicited: UnsolicitedNotifications<MessageSummaryPackage>,
+}
+
+impl MwiSubscription {
+    /// Returns if the SUBSCRIBE request was accepted and the subscription is still active
+    ///
+    /// If not, only unsolicited NOTIFY requests are received.
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.is_some()
+    }
+
+    /// Wait for the next message summary, either from the subscription or an unsolicited NOTIFY
+    ///
+    /// This function is cancel safe.
+    pub async fn receive(&mut self) -> Option<MessageSummary> {
+        loop {
+            let notification = if let Some(subscription) = &mut self.subscription {
+                let (notification, terminated) = select! {
+                    notification = subscription.receive() => {
+                        let terminated = notification.is_none();
+                        (notification, terminated)
+                    }
+                    notification = self.unsolicited.receive() => (notification, false),
+                };
+
+                if terminated {
+                    log::debug!("message-summary subscription terminated");
+                    self.subscription = None;
+                }
+
+                notification
+            } else {
+                Some(self.unsolicited.receive().await?)
+            };
+
+            if let Some(Notification {
+                state: Some(summary),
+                ..
+            }) = notification
+            {
+                return Some(summary);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::subscription::SubscriptionLayer;
+    use crate::test_util::{endpoint, request, send, within};
+    use sip_types::header::typed::{ContentType, Event};
+    use sip_types::{Method, StatusCode};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const SUMMARY: &str = "Messages-Waiting: yes\r\n\
+        Message-Account: sip:alice@vmail.example.com\r\n\
+        Voice-Message: 4/8 (1/2)\r\n\
+        Fax-Message: 0/1\r\n";
+
+    #[test]
+    fn parse_message_summary() {
+        let summary: MessageSummary = SUMMARY.parse().unwrap();
+
+        assert!(summary.messages_waiting);
+        assert_eq!(
+            summary.message_account.as_deref(),
+            Some("sip:alice@vmail.example.com")
+        );
+        assert_eq!(
+            summary.counts_of(&MessageClass::Voice),
+            Some(&MessageCounts {
+                class: MessageClass::Voice,
+                new: 4,
+                old: 8,
+                new_urgent: 1,
+                old_urgent: 2,
+            })
+        );
+        assert_eq!(
+            summary.counts_of(&MessageClass::Fax),
+            Some(&MessageCounts {
+                class: MessageClass::Fax,
+                new: 0,
+                old: 1,
+                new_urgent: 0,
+                old_urgent: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_message_summary_ignores_message_headers() {
+        let summary: MessageSummary = "messages-waiting: no\r\n\r\nTo: <sip:alice@example.com>\r\n"
+            .parse()
+            .unwrap();
+
+        assert!(!summary.messages_waiting);
+        assert!(summary.counts.is_empty());
+    }
+
+    #[test]
+    fn parse_message_summary_missing_messages_waiting() {
+        assert!("Voice-Message: 1/0\r\n".parse::<MessageSummary>().is_err());
+    }
+
+    #[test]
+    fn print_message_summary() {
+        let summary: MessageSummary = SUMMARY.parse().unwrap();
+
+        assert_eq!(summary.to_string(), SUMMARY);
+    }
+
+    #[tokio::test]
+    async fn unsolicited_notify_while_not_receiving() {
+        let (alice_endpoint, alice) = endpoint("alice", |builder| {
+            builder.add_layer(SubscriptionLayer::default());
+        })
+        .await;
+        let (bob_endpoint, bob) = endpoint("bob", |_| {}).await;
+
+        let mut mwi = MwiSubscription {
+            subscription: None,
+            unsolicited: UnsolicitedNotifications::new(alice_endpoint, vec!["alice".into()]),
+        };
+
+        // A cancelled receive must not lose any NOTIFY
+        assert!(
+            timeout(Duration::from_millis(10), mwi.receive())
+                .await
+                .is_err()
+        );
+
+        let mut notify = request(Method::NOTIFY, &bob, &alice);
+        notify
+            .headers
+            .insert_named(&Event::new(MessageSummaryPackage::EVENT));
+        notify
+            .headers
+            .insert_named(&ContentType(MessageSummaryPackage::CONTENT_TYPE.into()));
+        notify.body = SUMMARY.into();
+
+        let response = send(&bob_endpoint, notify).await;
+        assert_eq!(response.line.code, StatusCode::OK);
+
+        let summary = within(mwi.receive()).await.unwrap();
+        assert_eq!(summary.to_string(), SUMMARY);
+    }
+}
@@ -0,0 +1,256 @@
+//! OPTIONS requests used to query capabilities and qualify the reachability of peers
+//! ([RFC3261 Section 11](https://datatracker.ietf.org/doc/html/rfc3261#section-11))
+
+use crate::util::{random_sequence_number, random_string};
+use sip_core::transport::TargetTransportInfo;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake, Request, Result};
+use sip_types::header::typed::{CSeq, CallID, FromTo, MaxForwards};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Method, Name, StatusCode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::watch;
+use tokio::time::{Instant, MissedTickBehavior, interval};
+
+/// Layer which answers OPTIONS requests outside of any dialog
+///
+/// The response contains the endpoint's `Allow`, `Supported` and `Accept` headers.
+#[derive(Default)]
+pub struct OptionsLayer {}
+
+#[async_trait::async_trait]
+impl Layer for OptionsLayer {
+    fn name(&self) -> &'static str {
+        "options"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::OPTIONS);
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::OPTIONS || request.base_headers.to.tag.is_some() {
+            return;
+        }
+
+        let mut request = request.take();
+
+        let mut response = endpoint.create_response(&request, StatusCode::OK, None);
+        response.msg.headers.insert_named(endpoint.allowed());
+        response.msg.headers.insert_named(endpoint.supported());
+
+        if !endpoint.accepted().is_empty() {
+            response.msg.headers.insert_named(endpoint.accepted());
+        }
+
+        if let Err(e) = endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+        {
+            log::warn!("Failed to respond to OPTIONS request, {e:?}");
+        }
+    }
+}
+
+/// Reachability of a target qualified by the [`OptionsPinger`]
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+    pub target: SipUri,
+
+    /// The target responded to the last OPTIONS request
+    ///
+    /// `503 Service Unavailable` responses are not considered reachable.
+    pub reachable: bool,
+
+    /// Status code of the last response, `None` if no response was received
+    pub status: Option<StatusCode>,
+
+    /// Round-trip time of the last OPTIONS request, `None` if no response was received
+    pub rtt: Option<Duration>,
+}
+
+impl PeerStatus {
+    /// Apply the result of an OPTIONS request, returns if anything has changed
+    fn update(&mut self, status: Option<StatusCode>, rtt: Option<Duration>) -> bool {
+        let reachable = status.is_some_and(|code| code != StatusCode::SERVICE_UNAVAILABLE);
+
+        let changed = self.reachable != reachable || self.status != status || self.rtt != rtt;
+
+        self.reachable = reachable;
+        self.status = status;
+        self.rtt = rtt;
+
+        changed
+    }
+}
+
+/// Periodically sends OPTIONS requests to a set of targets to qualify their reachability
+///
+/// Sending OPTIONS requests stops once this type and all receivers returned by [`OptionsPinger::watch`] are dropped.
+pub struct OptionsPinger {
+    status: watch::Receiver<Vec<PeerStatus>>,
+}
+
+impl OptionsPinger {
+    /// Start sending OPTIONS requests with the identity `id` to all `targets` every `interval`
+    pub fn new(endpoint: Endpoint, id: NameAddr, targets: Vec<SipUri>, interval: Duration) -> Self {
+        let initial = targets
+            .iter()
+            .map(|target| PeerStatus {
+                target: target.clone(),
+                reachable: false,
+                status: None,
+                rtt: None,
+            })
+            .collect();
+
+        let (sender, status) = watch::channel(initial);
+        let sender = Arc::new(sender);
+
+        for (index, target) in targets.into_iter().enumerate() {
+            tokio::spawn(ping_task(
+                endpoint.clone(),
+                id.clone(),
+                target,
+                interval,
+                index,
+                sender.clone(),
+            ));
+        }
+
+        Self { status }
+    }
+
+    /// Returns the current status of all targets
+    pub fn status(&self) -> Vec<PeerStatus> {
+        self.status.borrow().clone()
+    }
+
+    /// Returns a receiver which is notified whenever the status or round-trip time of a target changes
+    pub fn watch(&self) -> watch::Receiver<Vec<PeerStatus>> {
+        self.status.clone()
+    }
+}
+
+async fn ping_task(
+    endpoint: Endpoint,
+    id: NameAddr,
+    target: SipUri,
+    period: Duration,
+    index: usize,
+    sender: Arc<watch::Sender<Vec<PeerStatus>>>,
+) {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut target_transport_info = TargetTransportInfo::default();
+
+    loop {
+        select! {
+            _ = sender.closed() => return,
+            _ = interval.tick() => {}
+        }
+
+        let start = Instant::now();
+
+        let (status, rtt) =
+            match send_options(&endpoint, &id, &target, &mut target_transport_info).await {
+                Ok(code) => (Some(code), Some(start.elapsed())),
+                Err(e) => {
+                    log::debug!("OPTIONS request to {target:?} failed, {e}");
+
+                    // Select a new transport next time
+                    target_transport_info = TargetTransportInfo::default();
+
+                    (None, None)
+                }
+            };
+
+        sender.send_if_modified(|peers| peers[index].update(status, rtt));
+    }
+}
+
+/// Send a single OPTIONS request and return the final response's status code
+async fn send_options(
+    endpoint: &Endpoint,
+    id: &NameAddr,
+    target: &SipUri,
+    target_transport_info: &mut TargetTransportInfo,
+) -> Result<StatusCode> {
+    let mut request = Request::new(Method::OPTIONS, target.clone());
+    request.headers.insert_named(&MaxForwards(70));
+    request
+        .headers
+        .insert_type(Name::FROM, &FromTo::new(id.clone(), Some(random_string())));
+    request
+        .headers
+        .insert_type(Name::TO, &FromTo::new(NameAddr::uri(target.clone()), None));
+    request.headers.insert_named(&CallID::new(random_string()));
+    request
+        .headers
+        .insert_named(&CSeq::new(random_sequence_number(), Method::OPTIONS));
+
+    let mut transaction = endpoint
+        .send_request(request, target_transport_info)
+        .await?;
+
+    let response = transaction.receive_final().await?;
+
+    Ok(response.line.code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{endpoint, within};
+
+    fn peer_status() -> PeerStatus {
+        PeerStatus {
+            target: "sip:example.com".parse().unwrap(),
+            reachable: false,
+            status: None,
+            rtt: None,
+        }
+    }
+
+    #[test]
+    fn update_peer_status() {
+        let mut peer = peer_status();
+
+        assert!(peer.update(Some(StatusCode::OK), Some(Duration::from_millis(20))));
+        assert!(peer.reachable);
+
+        // Only the round-trip time changed
+        assert!(peer.update(Some(StatusCode::OK), Some(Duration::from_millis(30))));
+        assert!(!peer.update(Some(StatusCode::OK), Some(Duration::from_millis(30))));
+
+        assert!(peer.update(Some(StatusCode::SERVICE_UNAVAILABLE), None));
+        assert!(!peer.reachable);
+    }
+
+    #[tokio::test]
+    async fn ping_options_layer() {
+        let (_alice_endpoint, alice) = endpoint("alice", |builder| {
+            builder.add_layer(OptionsLayer::default());
+        })
+        .await;
+        let (bob_endpoint, bob) = endpoint("bob", |_| {}).await;
+
+        let pinger = OptionsPinger::new(
+            bob_endpoint,
+            NameAddr::uri(bob),
+            vec![alice],
+            Duration::from_secs(60),
+        );
+
+        let mut watch = pinger.watch();
+        within(watch.changed()).await.unwrap();
+
+        let status = pinger.status();
+        assert!(status[0].reachable);
+        assert_eq!(status[0].status, Some(StatusCode::OK));
+        assert!(status[0].rtt.is_some());
+    }
+}
@@ -1,20 +1,23 @@
+use crate::call::{Call, CallError, parse_sdp_body};
 use crate::invite::{
-    create_ack,
+    SessionTimerConfig, create_ack,
     initiator::{Early, EarlyResponse, InviteInitiator, Response},
-    session::InviteSession,
+    prack::get_rseq,
+    session::{InviteSession, UpdateReceived},
 };
-use crate::{MediaBackend, call::Call, media_backend::CONTENT_TYPE_SDP};
+use crate::{MediaBackend, call_record::SetupTimes, media_backend::CONTENT_TYPE_SDP};
 use bytesstr::BytesStr;
 use sdp_types::SessionDescription;
-use sip_auth::ClientAuthenticator;
-use sip_core::{Endpoint, Request, transaction::TsxResponse};
+use sip_auth::{ClientAuthenticator, RequestParts, ResponseParts};
+use sip_core::{Endpoint, Request, transaction::TsxResponse, transport::OutgoingRequest};
 use sip_types::{
-    StatusCode,
+    CodeKind, Headers, Method, StatusCode,
     header::typed::{Contact, ContentType},
     msg::StatusLine,
     uri::{NameAddr, SipUri},
 };
 use std::{future::poll_fn, mem::take, task::Poll};
+use tokio::select;
 
 /// Any errors that might be encountered while making the initial call's INVITE request
 #[derive(Debug, thiserror::Error)]
@@ -40,6 +43,96 @@ pub enum MakeCallCompletionError<M> {
     Media(M),
     #[error("Missing SDP in response")]
     MissingSdpInResponse,
+    #[error("Early dialog has not received a reliable SDP answer")]
+    NoReliableEarlyAnswer,
+    #[error("Failed to negotiate a new media session with the fork that accepted the call")]
+    ForkRenegotiation(#[source] CallError<M>),
+}
+
+/// Options for [`OutboundCall::make_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct MakeCallOptions {
+    /// Additional headers added to the INVITE request
+    pub headers: Headers,
+
+    /// Maximum number of 3XX redirects to follow, redirects are treated as failure if `0` (default)
+    ///
+    /// The contacts of all received redirects are tried in the order of their q-value.
+    pub max_redirects: usize,
+
+    /// Session timer settings of the call
+    ///
+    /// A `422 Session Interval Too Small` response is answered by sending the INVITE again using the `Min-SE`
+    /// of the response.
+    pub session_timer: SessionTimerConfig,
+
+    /// Pass the SDP answer of the first early dialog containing one to the media backend (default `false`)
+    ///
+    /// The start of early media is reported using [`OutboundCallProgress::EarlyMedia`], after which
+    /// [`OutboundCall::wait_for_progress`] also runs the media backend and returns its events.
+    pub early_media: bool,
+}
+
+/// Early dialog created by a provisional response to the INVITE request
+///
+/// A forking proxy may create multiple early dialogs, one for every reached UAS.
+#[derive(Debug, Clone)]
+pub struct EarlyDialog {
+    /// To-tag of the peer, which identifies the early dialog
+    pub peer_tag: BytesStr,
+
+    /// Status of the last provisional response received in the early dialog
+    pub status: StatusLine,
+
+    /// SDP received in a provisional response of the early dialog
+    pub sdp: Option<SessionDescription>,
+
+    /// The SDP was received in a reliable provisional response
+    sdp_reliable: bool,
+}
+
+impl EarlyDialog {
+    fn new(tsx_response: &TsxResponse) -> Self {
+        let sdp = extract_sdp(tsx_response);
+
+        Self {
+            peer_tag: tsx_response.base_headers.to.tag.clone().unwrap_or_default(),
+            status: tsx_response.line.clone(),
+            sdp_reliable: sdp.is_some() && get_rseq(tsx_response).is_some(),
+            sdp,
+        }
+    }
+
+    fn update(&mut self, tsx_response: &TsxResponse) {
+        self.status = tsx_response.line.clone();
+
+        if let Some(sdp) = extract_sdp(tsx_response) {
+            self.sdp = Some(sdp);
+            self.sdp_reliable |= get_rseq(tsx_response).is_some();
+        }
+    }
+}
+
+/// Progress of an [`OutboundCall`], returned by [`OutboundCall::wait_for_progress`]
+#[allow(clippy::large_enum_variant)]
+pub enum OutboundCallProgress<M: MediaBackend> {
+    /// An early dialog was created or received another provisional response
+    Early(EarlyDialog),
+
+    /// The SDP answer of the early dialog has been passed to the media backend, early media has started
+    ///
+    /// Only returned if [`MakeCallOptions::early_media`] is enabled.
+    EarlyMedia(EarlyDialog),
+
+    /// Media backend specific event, received during early media
+    Media(M::Event),
+
+    /// The call got redirected and a new INVITE request was sent to the given target.
+    /// All previous early dialogs have been terminated.
+    Redirected(SipUri),
+
+    /// A final success response has been received
+    Completed(UnacknowledgedCall<M>),
 }
 
 /// In-progress outbound call which can still be canceled.
@@ -57,23 +150,328 @@ struct OutboundCallState<M> {
     media: M,
 
     initiator: InviteInitiator,
-    earlies: Vec<(Early, Option<SessionDescription>)>,
+    earlies: Vec<(Early, EarlyDialog)>,
+
+    invite: InviteTemplate,
+    redirects: RedirectTargets,
+    authenticator: Box<dyn InviteAuthenticator>,
+
+    /// The initiator must (re-)send its INVITE request before receiving responses
+    send_invite: bool,
+
+    /// Target of a redirect whose INVITE request has not been sent yet
+    redirect_target: Option<SipUri>,
+
+    /// Peer tag of the early dialog whose SDP answer has been passed to the media backend
+    early_answer: Option<BytesStr>,
+    early_media: bool,
+
+    setup_times: SetupTimes,
+}
+
+impl<M: MediaBackend> OutboundCallState<M> {
+    /// Pass a `401` or `407` response of a redirected INVITE to the authenticator, returns if the INVITE should be sent again
+    fn handle_auth_rejection(&mut self, tsx_response: &TsxResponse) -> bool {
+        let code = tsx_response.line.code;
+
+        if code != StatusCode::UNAUTHORIZED && code != StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            return false;
+        }
+
+        let transaction = self
+            .initiator
+            .transaction()
+            .expect("initiator has sent an INVITE");
+        let request = transaction.request();
+
+        self.authenticator.handle_rejection(
+            RequestParts {
+                line: &request.msg.line,
+                headers: &request.msg.headers,
+                body: &request.msg.body,
+            },
+            ResponseParts {
+                line: &tsx_response.line,
+                headers: &tsx_response.headers,
+                body: &tsx_response.body,
+            },
+        )
+    }
+
+    /// Pass the first SDP answer received in an early dialog to the media backend, if early media is enabled
+    async fn start_early_media(
+        &mut self,
+    ) -> Result<Option<EarlyDialog>, MakeCallCompletionError<M::Error>> {
+        if !self.early_media || !self.sent_sdp_offer || self.early_answer.is_some() {
+            return Ok(None);
+        }
+
+        let Some((_, early_dialog)) = self
+            .earlies
+            .iter()
+            .find(|(_, early_dialog)| early_dialog.sdp.is_some())
+        else {
+            return Ok(None);
+        };
+
+        let sdp_answer = early_dialog.sdp.clone().expect("checked above");
+
+        self.media
+            .receive_sdp_answer(sdp_answer)
+            .await
+            .map_err(MakeCallCompletionError::Media)?;
+
+        self.early_answer = Some(early_dialog.peer_tag.clone());
+
+        Ok(Some(early_dialog.clone()))
+    }
+
+    /// Respond to an UPDATE request received in an early dialog
+    ///
+    /// SDP offers are only answered inside the early dialog whose SDP answer has been passed to the media backend,
+    /// offers in any other early dialog are rejected with `488 Not Acceptable Here`.
+    async fn handle_early_update(
+        &mut self,
+        i: usize,
+        event: UpdateReceived,
+    ) -> Result<(), MakeCallCompletionError<M::Error>> {
+        let (early, early_dialog) = &self.earlies[i];
+        let update = &event.update;
+
+        let sdp_answer = if update.body.is_empty() {
+            // Session refresh without an offer
+            Ok(None)
+        } else if self.early_answer.as_ref() != Some(&early_dialog.peer_tag) {
+            Err(StatusCode::NOT_ACCEPTABLE_HERE)
+        } else {
+            let is_sdp = update
+                .headers
+                .get_named::<ContentType>()
+                .is_ok_and(|content_type| content_type == CONTENT_TYPE_SDP);
+
+            match is_sdp
+                .then(|| parse_sdp_body(update.body.clone()))
+                .flatten()
+            {
+                Some(sdp_offer) => match self.media.receive_sdp_offer(sdp_offer).await {
+                    Ok(sdp_answer) => Ok(Some(sdp_answer)),
+                    Err(e) => {
+                        let response = early
+                            .create_update_response(update, StatusCode::SERVER_INTERNAL_ERROR)?;
+                        event.transaction.respond(response).await?;
+
+                        return Err(MakeCallCompletionError::Media(e));
+                    }
+                },
+                None => Err(StatusCode::BAD_REQUEST),
+            }
+        };
+
+        let response = match sdp_answer {
+            Ok(sdp_answer) => {
+                let mut response = early.create_update_response(update, StatusCode::OK)?;
+
+                if let Some(sdp_answer) = sdp_answer {
+                    response.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+                    response.msg.body = sdp_answer.to_string().into();
+                }
+
+                response
+            }
+            Err(code) => early.create_update_response(update, code)?,
+        };
+
+        event.transaction.respond(response).await?;
+
+        Ok(())
+    }
+}
+
+/// Object safe [`ClientAuthenticator`] used to authorize INVITE requests sent after the call has been created
+trait InviteAuthenticator: Send {
+    fn authorize_request(&mut self, headers: &mut Headers);
+
+    /// Returns if the request should be sent again
+    fn handle_rejection(&mut self, request: RequestParts<'_>, response: ResponseParts<'_>) -> bool;
+}
+
+impl<A: ClientAuthenticator + Send> InviteAuthenticator for A {
+    fn authorize_request(&mut self, headers: &mut Headers) {
+        ClientAuthenticator::authorize_request(self, headers);
+    }
+
+    fn handle_rejection(&mut self, request: RequestParts<'_>, response: ResponseParts<'_>) -> bool {
+        match ClientAuthenticator::handle_rejection(self, request, response) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Failed to authorize INVITE request, {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Everything required to send the INVITE request to another target
+struct InviteTemplate {
+    endpoint: Endpoint,
+    id: NameAddr,
+    contact: Contact,
+    headers: Headers,
+    sdp_offer: Option<SessionDescription>,
+    session_timer: SessionTimerConfig,
+}
+
+impl InviteTemplate {
+    fn initiator(&self, target: SipUri) -> InviteInitiator {
+        let mut initiator = InviteInitiator::new(
+            self.endpoint.clone(),
+            self.id.clone(),
+            self.contact.clone(),
+            target,
+        );
+
+        initiator.timer_config = self.session_timer;
+        initiator
+    }
+
+    fn create_invite(&self, initiator: &mut InviteInitiator) -> Request {
+        let mut invite = initiator.create_invite();
+
+        invite.headers.extend(
+            self.headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+
+        if let Some(offer_sdp) = &self.sdp_offer {
+            attach_sdp(&mut invite, offer_sdp);
+        }
+
+        invite
+    }
+}
+
+/// Targets collected from the contacts of 3XX responses
+struct RedirectTargets {
+    /// Sorted by q-value, highest first
+    targets: Vec<(SipUri, f32)>,
+    tried: Vec<SipUri>,
+    remaining: usize,
+}
+
+impl RedirectTargets {
+    fn new(target: SipUri, max_redirects: usize) -> Self {
+        Self {
+            targets: vec![],
+            tried: vec![target],
+            remaining: max_redirects,
+        }
+    }
+
+    /// Add the contacts of a failure response and return the next target to try, if the response is a redirect
+    fn next_target(&mut self, code: StatusCode, headers: &Headers) -> Option<SipUri> {
+        // 305 (Use Proxy) must not be followed for security reasons and 380 (Alternative Service) describes
+        // alternatives that are not meant to be called automatically
+        if code.kind() != CodeKind::Redirection
+            || code == StatusCode::USE_PROXY
+            || code == StatusCode::ALTERNATIVE_SERVICE
+            || self.remaining == 0
+        {
+            return None;
+        }
+
+        for contact in headers.get_named::<Vec<Contact>>().unwrap_or_default() {
+            let uri = contact.uri.uri;
+
+            let is_known = self
+                .tried
+                .iter()
+                .chain(self.targets.iter().map(|(target, _)| target))
+                .any(|target| target.compare(&uri));
+
+            if is_known {
+                continue;
+            }
+
+            let q = contact
+                .params
+                .get_val("q")
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+
+            self.targets.push((uri, q));
+        }
+
+        self.targets.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        if self.targets.is_empty() {
+            return None;
+        }
+
+        let (target, _) = self.targets.remove(0);
+
+        self.remaining -= 1;
+        self.tried.push(target.clone());
+
+        Some(target)
+    }
 }
 
 impl<M: MediaBackend> OutboundCall<M> {
     /// Create an [`OutboundCall`], sending an INVITE request to the target uri
     ///
     /// Waits for any (non 100 Trying) response before returning
-    pub async fn make<A: ClientAuthenticator>(
+    pub async fn make<A: ClientAuthenticator + Send + 'static>(
+        endpoint: Endpoint,
+        authenticator: A,
+        id: NameAddr,
+        contact: Contact,
+        target: SipUri,
+        media: M,
+    ) -> Result<Self, MakeCallError<M::Error, A::Error>> {
+        Self::make_with_headers(
+            endpoint,
+            authenticator,
+            id,
+            contact,
+            target,
+            Headers::new(),
+            media,
+        )
+        .await
+    }
+
+    /// Create an [`OutboundCall`] like [`OutboundCall::make`], adding the given `headers` to the INVITE request
+    ///
+    /// Used e.g. to call the target of a transfer with the headers returned by
+    /// [`ReferNotifier::invite_headers`](crate::ReferNotifier::invite_headers).
+    pub async fn make_with_headers<A: ClientAuthenticator + Send + 'static>(
+        endpoint: Endpoint,
+        authenticator: A,
+        id: NameAddr,
+        contact: Contact,
+        target: SipUri,
+        headers: Headers,
+        media: M,
+    ) -> Result<Self, MakeCallError<M::Error, A::Error>> {
+        let options = MakeCallOptions {
+            headers,
+            ..MakeCallOptions::default()
+        };
+
+        Self::make_with_options(endpoint, authenticator, id, contact, target, options, media).await
+    }
+
+    /// Create an [`OutboundCall`] like [`OutboundCall::make`], using the given [`MakeCallOptions`]
+    pub async fn make_with_options<A: ClientAuthenticator + Send + 'static>(
         endpoint: Endpoint,
         mut authenticator: A,
         id: NameAddr,
         contact: Contact,
         target: SipUri,
+        options: MakeCallOptions,
         mut media: M,
     ) -> Result<Self, MakeCallError<M::Error, A::Error>> {
-        let mut initiator = InviteInitiator::new(endpoint.clone(), id, contact, target);
-
         // Only create a SDP offer if the sdp-session has media set by the user
         let sdp_offer = if media.has_media() {
             Some(
@@ -86,23 +484,50 @@ impl<M: MediaBackend> OutboundCall<M> {
             None
         };
 
-        'authorize: loop {
-            let mut invite = initiator.create_invite();
+        let mut invite = InviteTemplate {
+            endpoint,
+            id,
+            contact,
+            headers: options.headers,
+            sdp_offer,
+            session_timer: options.session_timer,
+        };
 
-            if let Some(offer_sdp) = &sdp_offer {
-                attach_sdp(&mut invite, offer_sdp);
-            }
+        let mut redirects = RedirectTargets::new(target.clone(), options.max_redirects);
+        let mut initiator = invite.initiator(target);
+        let mut setup_times = SetupTimes::new();
 
-            authenticator.authorize_request(&mut invite.headers);
+        'authorize: loop {
+            let mut request = invite.create_invite(&mut initiator);
+
+            authenticator.authorize_request(&mut request.headers);
 
-            initiator.send_invite(invite).await?;
+            initiator.send_invite(request).await?;
 
             loop {
                 match initiator.receive().await? {
-                    Response::Provisional(..) => {
+                    Response::Provisional(tsx_response) => {
                         // TODO: return OutboundCall here already so the call can be cancelled?
+                        setup_times.progress(tsx_response.line.code);
                     }
                     Response::Failure(tsx_response) => {
+                        if let Some(target) =
+                            redirects.next_target(tsx_response.line.code, &tsx_response.headers)
+                        {
+                            initiator = invite.initiator(target);
+                            continue 'authorize;
+                        }
+
+                        // Retry with a larger session interval if requested
+                        if tsx_response.line.code == StatusCode::SESSION_INTERVAL_TOO_SMALL
+                            && invite
+                                .session_timer
+                                .on_interval_too_small(&tsx_response.headers)
+                        {
+                            initiator.timer_config = invite.session_timer;
+                            continue 'authorize;
+                        }
+
                         // Authorize requests if possible
                         if tsx_response.line.code != StatusCode::UNAUTHORIZED {
                             return Err(MakeCallError::Failed(tsx_response.line));
@@ -111,14 +536,14 @@ impl<M: MediaBackend> OutboundCall<M> {
                         let transaction = initiator
                             .transaction()
                             .expect("initiator isn't finished yet");
-                        let invite = transaction.request();
+                        let request = transaction.request();
 
                         authenticator
                             .handle_rejection(
                                 sip_auth::RequestParts {
-                                    line: &invite.msg.line,
-                                    headers: &invite.msg.headers,
-                                    body: &invite.msg.body,
+                                    line: &request.msg.line,
+                                    headers: &request.msg.headers,
+                                    body: &request.msg.body,
                                 },
                                 sip_auth::ResponseParts {
                                     line: &tsx_response.line,
@@ -130,30 +555,49 @@ impl<M: MediaBackend> OutboundCall<M> {
 
                         continue 'authorize;
                     }
-                    Response::Early(early, tsx_response, ..) => {
+                    Response::Early(mut early, tsx_response, rseq) => {
+                        if let Some(rseq) = rseq {
+                            early.prack(&tsx_response, rseq).await?;
+                        }
+
+                        setup_times.progress(tsx_response.line.code);
+
                         // Got an early dialog - probably ringing, return Outbound call
                         return Ok(OutboundCall {
                             state: Some(OutboundCallState {
-                                sent_sdp_offer: sdp_offer.is_some(),
+                                sent_sdp_offer: invite.sdp_offer.is_some(),
                                 media,
                                 initiator,
-                                earlies: vec![(early, extract_sdp(&tsx_response))],
+                                earlies: vec![(early, EarlyDialog::new(&tsx_response))],
+                                invite,
+                                redirects,
+                                authenticator: Box::new(authenticator),
+                                send_invite: false,
+                                redirect_target: None,
+                                early_answer: None,
+                                early_media: options.early_media,
+                                setup_times,
                             }),
                             unacknowledged: None,
                         });
                     }
                     Response::Session(session, tsx_response) => {
+                        setup_times.answered();
+
                         // First response created a session - great, return it
                         return Ok(OutboundCall {
                             state: None,
                             unacknowledged: Some(UnacknowledgedCall {
-                                sent_sdp_offer: sdp_offer.is_some(),
+                                sent_sdp_offer: invite.sdp_offer.is_some(),
                                 media,
                                 initiator,
                                 earlies: vec![],
                                 session,
                                 final_response: tsx_response,
                                 early_sdp: None,
+                                early_negotiated: false,
+                                renegotiate: false,
+                                setup_times,
                             }),
                         });
                     }
@@ -169,6 +613,89 @@ impl<M: MediaBackend> OutboundCall<M> {
         }
     }
 
+    /// Returns all early dialogs of the call
+    pub fn early_dialogs(&self) -> impl Iterator<Item = &EarlyDialog> {
+        self.state
+            .iter()
+            .flat_map(|state| state.earlies.iter().map(|(_, early_dialog)| early_dialog))
+    }
+
+    /// Send a new SDP offer inside an early dialog using an UPDATE request (RFC3311)
+    ///
+    /// The early dialog must have received the answer to the INVITE's SDP offer in a reliable provisional response.
+    /// This answer is passed to the media backend before creating the new offer. Only one early dialog can be
+    /// updated, since there's only a single media backend.
+    ///
+    /// This function is not cancel safe.
+    pub async fn update_early_dialog(
+        &mut self,
+        peer_tag: &BytesStr,
+    ) -> Result<(), MakeCallCompletionError<M::Error>> {
+        let this = self
+            .state
+            .as_mut()
+            .expect("OutboundCall::update_early_dialog must not be called after completion");
+
+        let Some((early, early_dialog)) = this
+            .earlies
+            .iter_mut()
+            .find(|(_, early_dialog)| &early_dialog.peer_tag == peer_tag)
+        else {
+            return Err(MakeCallCompletionError::NoReliableEarlyAnswer);
+        };
+
+        let answered_other = this
+            .early_answer
+            .as_ref()
+            .is_some_and(|tag| tag != peer_tag);
+
+        if !this.sent_sdp_offer || !early_dialog.sdp_reliable || answered_other {
+            return Err(MakeCallCompletionError::NoReliableEarlyAnswer);
+        }
+
+        if this.early_answer.is_none() {
+            let sdp_answer = early_dialog
+                .sdp
+                .clone()
+                .ok_or(MakeCallCompletionError::NoReliableEarlyAnswer)?;
+
+            this.media
+                .receive_sdp_answer(sdp_answer)
+                .await
+                .map_err(MakeCallCompletionError::Media)?;
+
+            this.early_answer = Some(peer_tag.clone());
+        }
+
+        let sdp_offer = this
+            .media
+            .create_sdp_offer()
+            .await
+            .map_err(MakeCallCompletionError::Media)?;
+
+        let dialog = early.dialog();
+
+        let mut update = dialog.create_request(Method::UPDATE);
+        update.headers.insert_named(&dialog.local_contact);
+        attach_sdp(&mut update, &sdp_offer);
+
+        let response = dialog.send_request(update).await?;
+
+        if response.line.code.kind() != CodeKind::Success {
+            return Err(MakeCallCompletionError::Failed(response.line));
+        }
+
+        let sdp_answer =
+            extract_sdp(&response).ok_or(MakeCallCompletionError::MissingSdpInResponse)?;
+
+        early_dialog.sdp = Some(sdp_answer.clone());
+
+        this.media
+            .receive_sdp_answer(sdp_answer)
+            .await
+            .map_err(MakeCallCompletionError::Media)
+    }
+
     /// Cancel the call gracefully.
     ///
     /// If the call is already set up, but has not received a provisional response,
@@ -179,7 +706,10 @@ impl<M: MediaBackend> OutboundCall<M> {
             return Ok(());
         }
 
-        if let Some(inner) = self.state.take() {
+        // Nothing to cancel if the INVITE to the next target hasn't been sent yet
+        if let Some(inner) = self.state.take()
+            && !inner.send_invite
+        {
             inner.initiator.cancel().await?;
         };
 
@@ -189,67 +719,147 @@ impl<M: MediaBackend> OutboundCall<M> {
     /// Wait for the final response from the peer
     ///
     /// The returned future is cancel-safe, and the call can be canceled as long as this function has not returned.
+    ///
+    /// Media events received during early media are discarded, use [`OutboundCall::wait_for_progress`] to handle them.
     pub async fn wait_for_completion(
         &mut self,
     ) -> Result<UnacknowledgedCall<M>, MakeCallCompletionError<M::Error>> {
+        loop {
+            if let OutboundCallProgress::Completed(completed) = self.wait_for_progress().await? {
+                return Ok(completed);
+            }
+        }
+    }
+
+    /// Wait for the next progress of the call, like the creation of an early dialog or the final response
+    ///
+    /// The returned future is cancel-safe, and the call can be canceled as long as this function has not returned
+    /// [`OutboundCallProgress::Completed`].
+    pub async fn wait_for_progress(
+        &mut self,
+    ) -> Result<OutboundCallProgress<M>, MakeCallCompletionError<M::Error>> {
         if let Some(completed) = self.unacknowledged.take() {
-            return Ok(completed);
+            return Ok(OutboundCallProgress::Completed(completed));
         }
 
         let this = self
             .state
             .as_mut()
-            .expect("OutboundCall::wait_for_completion must not be called again after returning");
+            .expect("OutboundCall::wait_for_progress must not be called again after completion");
 
         loop {
-            match this.initiator.receive().await? {
-                Response::Provisional(..) => {
-                    // ignore provisional responses outside the dialog
-                }
-                Response::Failure(tsx_response) => {
-                    return Err(MakeCallCompletionError::Failed(tsx_response.line));
-                }
-                Response::Early(early, tsx_response, ..) => {
-                    // got an early dialog, store it and poll it concurrently with the invite transaction
-                    this.earlies.push((early, extract_sdp(&tsx_response)))
-                }
-                Response::Session(session, tsx_response) => {
-                    let early_sdp = extract_sdp(&tsx_response);
+            // Sending the INVITE is restartable, so this function stays cancel safe
+            if this.send_invite {
+                let mut request = this.invite.create_invite(&mut this.initiator);
+                this.authenticator.authorize_request(&mut request.headers);
 
-                    let this = take(&mut self.state).unwrap();
+                this.initiator.send_invite(request).await?;
+                this.send_invite = false;
 
-                    return Ok(UnacknowledgedCall {
-                        sent_sdp_offer: this.sent_sdp_offer,
-                        media: this.media,
-                        initiator: this.initiator,
-                        earlies: this.earlies,
-                        session,
-                        final_response: tsx_response,
-                        early_sdp,
-                    });
+                if let Some(target) = this.redirect_target.take() {
+                    return Ok(OutboundCallProgress::Redirected(target));
                 }
-                Response::EarlyEvent => {
-                    // We received an internal message that was forwarded to an early handle
+            }
+
+            if let Some(early_dialog) = this.start_early_media().await? {
+                return Ok(OutboundCallProgress::EarlyMedia(early_dialog));
+            }
+
+            let mut early_event = None;
+
+            let response = select! {
+                response = this.initiator.receive() => Some(response?),
+                (i, response) = receive_early(&mut this.earlies) => {
+                    early_event = Some((i, response?));
+                    None
                 }
-                Response::Finished => {
-                    unreachable!("function returns on all call termination events")
+                event = this.media.run(), if this.early_answer.is_some() => {
+                    let event = event.map_err(MakeCallCompletionError::Media)?;
+
+                    return Ok(OutboundCallProgress::Media(event));
                 }
-            }
+            };
+
+            if let Some(response) = response {
+                match response {
+                    Response::Provisional(tsx_response) => {
+                        // ignore provisional responses outside the dialog
+                        this.setup_times.progress(tsx_response.line.code);
+                    }
+                    Response::Failure(tsx_response) => {
+                        if let Some(target) = this
+                            .redirects
+                            .next_target(tsx_response.line.code, &tsx_response.headers)
+                        {
+                            this.initiator = this.invite.initiator(target.clone());
+                            this.redirect_target = Some(target);
+                        } else if !this.handle_auth_rejection(&tsx_response) {
+                            return Err(MakeCallCompletionError::Failed(tsx_response.line));
+                        }
+
+                        // All early dialogs have been terminated by the failure response
+                        this.earlies.clear();
+                        this.early_answer = None;
+                        this.send_invite = true;
+                    }
+                    Response::Early(mut early, tsx_response, rseq) => {
+                        if let Some(rseq) = rseq {
+                            early.prack(&tsx_response, rseq).await?;
+                        }
+
+                        this.setup_times.progress(tsx_response.line.code);
+
+                        // got an early dialog, store it and poll it concurrently with the invite transaction
+                        let early_dialog = EarlyDialog::new(&tsx_response);
+                        this.earlies.push((early, early_dialog.clone()));
+
+                        return Ok(OutboundCallProgress::Early(early_dialog));
+                    }
+                    Response::Session(session, tsx_response) => {
+                        let early_sdp = extract_sdp(&tsx_response);
 
-            // Poll all early calls,
-            let early_event = poll_fn(|cx| -> Poll<Result<_, MakeCallCompletionError<M::Error>>> {
-                for (i, (early, _)) in this.earlies.iter_mut().enumerate() {
-                    let response = match early.poll_receive(cx) {
-                        Poll::Ready(response) => response?,
-                        Poll::Pending => continue,
-                    };
+                        let mut this = take(&mut self.state).unwrap();
+                        this.setup_times.answered();
 
-                    return Poll::Ready(Ok(Some((i, response))));
+                        // Early media has been started with another fork
+                        let renegotiate = this.early_answer.is_some();
+
+                        return Ok(OutboundCallProgress::Completed(UnacknowledgedCall {
+                            sent_sdp_offer: this.sent_sdp_offer,
+                            media: this.media,
+                            initiator: this.initiator,
+                            earlies: this.earlies,
+                            session,
+                            final_response: tsx_response,
+                            early_sdp,
+                            early_negotiated: false,
+                            renegotiate,
+                            setup_times: this.setup_times,
+                        }));
+                    }
+                    Response::EarlyEvent => {
+                        // We received an internal message that was forwarded to an early handle
+                    }
+                    Response::Finished => {
+                        unreachable!("function returns on all call termination events")
+                    }
                 }
 
-                Poll::Ready(Ok(None))
-            })
-            .await?;
+                // Poll all early calls,
+                early_event = poll_fn(|cx| -> Poll<Result<_, MakeCallCompletionError<M::Error>>> {
+                    for (i, (early, _)) in this.earlies.iter_mut().enumerate() {
+                        let response = match early.poll_receive(cx) {
+                            Poll::Ready(response) => response?,
+                            Poll::Pending => continue,
+                        };
+
+                        return Poll::Ready(Ok(Some((i, response))));
+                    }
+
+                    Poll::Ready(Ok(None))
+                })
+                .await?;
+            }
 
             // We got an early event
             let Some((i, response)) = early_event else {
@@ -257,24 +867,44 @@ impl<M: MediaBackend> OutboundCall<M> {
             };
 
             match response {
-                EarlyResponse::Provisional(..) => {
-                    // ignore additional provisional responses for early dialogs
+                EarlyResponse::Provisional(tsx_response, rseq) => {
+                    let (early, early_dialog) = &mut this.earlies[i];
+
+                    if let Some(rseq) = rseq {
+                        early.prack(&tsx_response, rseq).await?;
+                    }
+
+                    early_dialog.update(&tsx_response);
+                    this.setup_times.progress(tsx_response.line.code);
+
+                    return Ok(OutboundCallProgress::Early(early_dialog.clone()));
                 }
                 EarlyResponse::Success(session, tsx_response) => {
                     // got a success response for an early dialog, establish the call with it and cancel everything else
                     let mut this = take(&mut self.state).unwrap();
+                    this.setup_times.answered();
 
-                    let (_, early_sdp) = this.earlies.remove(i);
+                    let (_, early_dialog) = this.earlies.remove(i);
 
-                    return Ok(UnacknowledgedCall {
+                    let early_negotiated =
+                        this.early_answer.as_ref() == Some(&early_dialog.peer_tag);
+                    let renegotiate = this.early_answer.is_some() && !early_negotiated;
+
+                    return Ok(OutboundCallProgress::Completed(UnacknowledgedCall {
                         sent_sdp_offer: this.sent_sdp_offer,
                         media: this.media,
                         initiator: this.initiator,
                         earlies: this.earlies,
                         session,
                         final_response: tsx_response,
-                        early_sdp,
-                    });
+                        early_sdp: early_dialog.sdp,
+                        early_negotiated,
+                        renegotiate,
+                        setup_times: this.setup_times,
+                    }));
+                }
+                EarlyResponse::Update(event) => {
+                    this.handle_early_update(i, event).await?;
                 }
                 EarlyResponse::Terminated => {
                     unreachable!("function returns on all call termination events");
@@ -294,10 +924,19 @@ pub struct UnacknowledgedCall<M> {
     sent_sdp_offer: bool,
     media: M,
     initiator: InviteInitiator,
-    earlies: Vec<(Early, Option<SessionDescription>)>,
+    earlies: Vec<(Early, EarlyDialog)>,
     session: InviteSession,
     final_response: TsxResponse,
     early_sdp: Option<SessionDescription>,
+
+    /// SDP has already been negotiated inside the early dialog using UPDATE requests
+    early_negotiated: bool,
+
+    /// The media backend received the SDP answer of another fork's early dialog, so the answer of this dialog
+    /// cannot be applied. A new offer is sent using a re-INVITE instead.
+    renegotiate: bool,
+
+    setup_times: SetupTimes,
 }
 
 impl<M: MediaBackend> UnacknowledgedCall<M> {
@@ -307,15 +946,51 @@ impl<M: MediaBackend> UnacknowledgedCall<M> {
         Ok(())
     }
 
+    /// Returns the To-tag of the peer, which identifies the dialog that won the call setup
+    pub fn peer_tag(&self) -> Option<&BytesStr> {
+        self.session.dialog.peer_fromto.tag.as_ref()
+    }
+
     /// Complete the call setup & SDP handshake
     ///
-    /// Cancels all pending early dialogs
+    /// Sessions established by other forks of the INVITE are acknowledged and terminated immediately.
+    ///
+    /// If early media has been started with another fork than the one accepting the call, the media session is
+    /// negotiated again using a re-INVITE. The call is terminated if that fails.
     pub async fn finish(mut self) -> Result<Call<M>, MakeCallCompletionError<M::Error>> {
-        for (early, _) in self.earlies {
-            tokio::spawn(early.cancel());
+        // The SDP answer has already been handled when the early dialog was updated
+        if self.early_negotiated {
+            let pending_ack = create_ack(
+                &self.session.dialog,
+                self.final_response.base_headers.cseq.cseq,
+            )
+            .await?;
+
+            return self.acknowledge(pending_ack).await;
+        }
+
+        // The answer cannot be passed to the media backend a second time
+        if self.renegotiate {
+            let pending_ack = create_ack(
+                &self.session.dialog,
+                self.final_response.base_headers.cseq.cseq,
+            )
+            .await?;
+
+            let mut call = self.acknowledge(pending_ack).await?;
+
+            if let Err(e) = call.renegotiate().await {
+                call.terminate().await?;
+                return Err(MakeCallCompletionError::ForkRenegotiation(e));
+            }
+
+            return Ok(call);
         }
 
-        let remote_sdp = self.early_sdp.or_else(|| extract_sdp(&self.final_response));
+        let remote_sdp = self
+            .early_sdp
+            .take()
+            .or_else(|| extract_sdp(&self.final_response));
 
         let remote_sdp = if let Some(remote_sdp) = remote_sdp {
             remote_sdp
@@ -345,6 +1020,13 @@ impl<M: MediaBackend> UnacknowledgedCall<M> {
             attach_sdp(&mut pending_ack.msg, &sdp_answer);
         }
 
+        self.acknowledge(pending_ack).await
+    }
+
+    async fn acknowledge(
+        mut self,
+        mut pending_ack: OutgoingRequest,
+    ) -> Result<Call<M>, MakeCallCompletionError<M::Error>> {
         self.session
             .endpoint
             .send_outgoing_request(&mut pending_ack)
@@ -353,27 +1035,124 @@ impl<M: MediaBackend> UnacknowledgedCall<M> {
 
         self.initiator.set_acknowledge(&self.session, pending_ack);
 
-        tokio::spawn(async move {
-            while let Ok(event) = self.initiator.receive().await {
-                match event {
-                    Response::Provisional(..) => {}
-                    Response::Failure(..) => break,
-                    Response::Early(early, ..) => {
-                        tokio::spawn(early.cancel());
+        let earlies = self.earlies.into_iter().map(|(early, _)| early).collect();
+
+        tokio::spawn(terminate_forks(self.initiator, earlies));
+
+        Ok(Call::new(self.session, self.media, self.setup_times))
+    }
+}
+
+/// Wait for the next event of any early dialog, never returns if there are none
+async fn receive_early(
+    earlies: &mut [(Early, EarlyDialog)],
+) -> (usize, Result<EarlyResponse, sip_core::Error>) {
+    poll_fn(|cx| {
+        for (i, (early, _)) in earlies.iter_mut().enumerate() {
+            if let Poll::Ready(response) = early.poll_receive(cx) {
+                return Poll::Ready((i, response));
+            }
+        }
+
+        Poll::Pending
+    })
+    .await
+}
+
+/// Receive the remaining responses of a forked INVITE after a session has been established
+///
+/// Every additional session is acknowledged and terminated with a BYE request.
+async fn terminate_forks(mut initiator: InviteInitiator, mut earlies: Vec<Early>) {
+    loop {
+        let early_response = poll_fn(|cx| {
+            for (i, early) in earlies.iter_mut().enumerate() {
+                if let Poll::Ready(response) = early.poll_receive(cx) {
+                    return Poll::Ready((i, response));
+                }
+            }
+
+            Poll::Pending
+        });
+
+        let (session, tsx_response) = tokio::select! {
+            response = initiator.receive() => match response {
+                Ok(Response::Session(session, tsx_response)) => (session, tsx_response),
+                Ok(Response::Early(mut early, tsx_response, rseq)) => {
+                    // Reliable provisional responses must still be acknowledged, or the fork gives up
+                    if let Some(rseq) = rseq
+                        && let Err(e) = early.prack(&tsx_response, rseq).await
+                    {
+                        log::warn!("Failed to send PRACK to forked early dialog, {e}");
                     }
-                    Response::Session(mut session, ..) => {
-                        let _ = session.terminate().await;
+
+                    earlies.push(early);
+                    continue;
+                }
+                Ok(Response::Provisional(..) | Response::EarlyEvent) => continue,
+                Ok(Response::Failure(..) | Response::Finished) | Err(_) => return,
+            },
+            (i, response) = early_response => match response {
+                Ok(EarlyResponse::Success(session, tsx_response)) => {
+                    earlies.remove(i);
+                    (session, tsx_response)
+                }
+                Ok(EarlyResponse::Provisional(tsx_response, rseq)) => {
+                    if let Some(rseq) = rseq
+                        && let Err(e) = earlies[i].prack(&tsx_response, rseq).await
+                    {
+                        log::warn!("Failed to send PRACK to forked early dialog, {e}");
                     }
-                    Response::EarlyEvent => {}
-                    Response::Finished => break,
+
+                    continue;
+                }
+                Ok(EarlyResponse::Update(event)) => {
+                    if let Err(e) = reject_update(&earlies[i], event).await {
+                        log::warn!("Failed to reject UPDATE of forked early dialog, {e}");
+                    }
+
+                    continue;
+                }
+                Ok(EarlyResponse::Terminated) | Err(_) => {
+                    earlies.remove(i);
+                    continue;
                 }
             }
-        });
+        };
 
-        Ok(Call::new(self.session, self.media))
+        if let Err(e) = terminate_fork(&mut initiator, session, tsx_response).await {
+            log::warn!("Failed to terminate forked session, {e}");
+        }
     }
 }
 
+/// Reject an UPDATE request of an early dialog which is going to be terminated
+async fn reject_update(early: &Early, event: UpdateReceived) -> Result<(), sip_core::Error> {
+    let response = early.create_update_response(&event.update, StatusCode::NOT_ACCEPTABLE_HERE)?;
+
+    event.transaction.respond(response).await
+}
+
+async fn terminate_fork(
+    initiator: &mut InviteInitiator,
+    mut session: InviteSession,
+    tsx_response: TsxResponse,
+) -> Result<(), sip_core::Error> {
+    let mut ack = create_ack(&session.dialog, tsx_response.base_headers.cseq.cseq).await?;
+
+    session.endpoint.send_outgoing_request(&mut ack).await?;
+
+    // Retransmissions of the 2XX response are acknowledged by the initiator
+    initiator.set_acknowledge(&session, ack);
+
+    tokio::spawn(async move {
+        if let Err(e) = session.terminate().await {
+            log::warn!("Failed to send BYE to forked session, {e}");
+        }
+    });
+
+    Ok(())
+}
+
 fn attach_sdp(request: &mut Request, sdp: &SessionDescription) {
     request.headers.insert_named(&CONTENT_TYPE_SDP);
     request.body = sdp.to_string().into();
@@ -391,3 +1170,506 @@ fn extract_sdp(tsx_response: &TsxResponse) -> Option<SessionDescription> {
 
     None
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{TakeRequests, TestMedia, contact, endpoint, within};
+    use crate::{AcceptCallError, InboundCall};
+    use sip_auth::{DigestAuthenticator, DigestCredentials, DigestUser, ServerAuthenticator};
+    use sip_types::Name;
+
+    fn contacts(contacts: &[&'static str]) -> Headers {
+        let mut headers = Headers::new();
+
+        for contact in contacts {
+            headers.insert(sip_types::Name::CONTACT, *contact);
+        }
+
+        headers
+    }
+
+    #[test]
+    fn redirect_in_q_order() {
+        let mut redirects = RedirectTargets::new("sip:alice@example.com".parse().unwrap(), 3);
+
+        let headers = contacts(&[
+            "<sip:alice@192.0.2.1>;q=0.5",
+            "<sip:alice@192.0.2.2>;q=0.9",
+            "<sip:alice@example.com>",
+        ]);
+
+        let target = redirects.next_target(StatusCode::MOVED_TEMPORARILY, &headers);
+        assert_eq!(format!("{target:?}"), "Some(sip:alice@192.0.2.2)");
+
+        // Already tried targets are ignored
+        let headers = contacts(&["<sip:alice@192.0.2.2>", "<sip:alice@192.0.2.3>;q=0.1"]);
+
+        let target = redirects.next_target(StatusCode::MOVED_TEMPORARILY, &headers);
+        assert_eq!(format!("{target:?}"), "Some(sip:alice@192.0.2.1)");
+
+        let target = redirects.next_target(StatusCode::MOVED_TEMPORARILY, &Headers::new());
+        assert_eq!(format!("{target:?}"), "Some(sip:alice@192.0.2.3)");
+    }
+
+    #[test]
+    fn redirect_limit() {
+        let mut redirects = RedirectTargets::new("sip:alice@example.com".parse().unwrap(), 1);
+
+        let headers = contacts(&["<sip:alice@192.0.2.1>", "<sip:alice@192.0.2.2>"]);
+
+        assert!(
+            redirects
+                .next_target(StatusCode::MOVED_TEMPORARILY, &headers)
+                .is_some()
+        );
+        assert!(
+            redirects
+                .next_target(StatusCode::MOVED_TEMPORARILY, &headers)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn ignore_non_redirects() {
+        let mut redirects = RedirectTargets::new("sip:alice@example.com".parse().unwrap(), 1);
+
+        let headers = contacts(&["<sip:alice@192.0.2.1>"]);
+
+        assert!(
+            redirects
+                .next_target(StatusCode::USE_PROXY, &headers)
+                .is_none()
+        );
+        assert!(
+            redirects
+                .next_target(StatusCode::NOT_FOUND, &headers)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn authorize_redirected_invite() {
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+        let (carol_invites, mut carol_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+        let (carol_endpoint, carol) =
+            endpoint("carol", |builder| builder.add_layer(carol_invites)).await;
+
+        // Bob creates an early dialog and then redirects the call to carol
+        let redirect = {
+            let bob_contact = contact(&bob);
+            let carol = carol.clone();
+
+            tokio::spawn(async move {
+                let mut invite = bob_incoming.recv().await.unwrap();
+                let mut tsx = bob_endpoint.create_server_inv_tsx(&mut invite);
+
+                let mut to = invite.base_headers.to.clone();
+                to.tag = Some("bob-tag".into());
+
+                let mut ringing = bob_endpoint.create_response(&invite, StatusCode::RINGING, None);
+                ringing.msg.headers.remove(&Name::TO);
+                ringing.msg.headers.insert_type(Name::TO, &to);
+                ringing.msg.headers.insert_named(&bob_contact);
+                tsx.respond_provisional(&mut ringing).await.unwrap();
+
+                let mut redirect =
+                    bob_endpoint.create_response(&invite, StatusCode::MOVED_TEMPORARILY, None);
+                redirect.msg.headers.remove(&Name::TO);
+                redirect.msg.headers.insert_type(Name::TO, &to);
+                redirect.msg.headers.insert_named(&contact(&carol));
+                tsx.respond_failure(redirect).await.unwrap();
+            })
+        };
+
+        // Carol requires authorization of the INVITE
+        let carol_contact = contact(&carol);
+        let accept = tokio::spawn(async move {
+            let mut server = ServerAuthenticator::new("example.com", |_: &str, _: &str| {
+                Some(b"secret".to_vec())
+            });
+            server.proxy = true;
+
+            let mut invite = carol_incoming.recv().await.unwrap();
+            let mut response =
+                carol_endpoint.create_response(&invite, server.challenge_code(), None);
+            server.challenge(&mut response.msg.headers, false);
+            carol_endpoint
+                .create_server_inv_tsx(&mut invite)
+                .respond_failure(response)
+                .await
+                .unwrap();
+
+            let invite = carol_incoming.recv().await.unwrap();
+            let user = server
+                .verify(sip_auth::RequestParts {
+                    line: &invite.line,
+                    headers: &invite.headers,
+                    body: &invite.body,
+                })
+                .unwrap();
+            assert_eq!(user, "alice");
+
+            InboundCall::from_invite(carol_endpoint.clone(), invite, carol_contact)
+                .unwrap()
+                .with_media(TestMedia::default())
+                .accept()
+                .await
+                .unwrap();
+
+            carol_endpoint
+        });
+
+        let mut credentials = DigestCredentials::new();
+        credentials.set_default(DigestUser::new("alice", "secret"));
+
+        let options = MakeCallOptions {
+            max_redirects: 1,
+            ..MakeCallOptions::default()
+        };
+
+        within(async {
+            let mut outbound_call = OutboundCall::make_with_options(
+                alice_endpoint.clone(),
+                DigestAuthenticator::new(credentials),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                bob,
+                options,
+                TestMedia::default(),
+            )
+            .await
+            .unwrap();
+
+            match outbound_call.wait_for_progress().await.unwrap() {
+                OutboundCallProgress::Redirected(target) => {
+                    assert_eq!(format!("{target:?}"), format!("{carol:?}"))
+                }
+                _ => panic!("expected redirect"),
+            }
+
+            let call = outbound_call.wait_for_completion().await.unwrap();
+            call.finish().await.unwrap();
+        })
+        .await;
+
+        within(redirect).await.unwrap();
+        within(accept).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_early_dialog_in_both_directions() {
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+
+        let (updated, mut wait_updated) = tokio::sync::oneshot::channel();
+
+        let bob_contact = contact(&bob);
+        let accept = tokio::spawn(async move {
+            let invite = bob_incoming.recv().await.unwrap();
+            let mut inbound_call =
+                InboundCall::from_invite(bob_endpoint.clone(), invite, bob_contact)
+                    .unwrap()
+                    .with_media(TestMedia::default());
+
+            inbound_call.respond_early_media().await.unwrap();
+
+            // Answer alice's UPDATE
+            tokio::select! {
+                _ = inbound_call.run_media_or_cancelled(false) => panic!("call got cancelled"),
+                _ = &mut wait_updated => {}
+            }
+
+            inbound_call.update_early_media().await.unwrap();
+
+            let mut call = inbound_call.accept().await.unwrap();
+
+            // The INVITE's offer and alice's UPDATE offer
+            assert_eq!(call.media().offers.len(), 2);
+            assert_eq!(call.media().answers.len(), 1);
+
+            (bob_endpoint, call)
+        });
+
+        within(async {
+            let mut outbound_call = OutboundCall::make(
+                alice_endpoint.clone(),
+                DigestAuthenticator::new(DigestCredentials::new()),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                bob,
+                TestMedia::default(),
+            )
+            .await
+            .unwrap();
+
+            let peer_tag = outbound_call
+                .early_dialogs()
+                .next()
+                .unwrap()
+                .peer_tag
+                .clone();
+
+            outbound_call.update_early_dialog(&peer_tag).await.unwrap();
+            updated.send(()).unwrap();
+
+            // Bob's UPDATE is answered while waiting for the final response
+            let mut call = outbound_call
+                .wait_for_completion()
+                .await
+                .unwrap()
+                .finish()
+                .await
+                .unwrap();
+
+            assert_eq!(call.media().offers.len(), 1);
+            // The early answer and the answer to alice's UPDATE
+            assert_eq!(call.media().answers.len(), 2);
+        })
+        .await;
+
+        within(accept).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_early_update_without_reliable_answer() {
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+
+        let bob_contact = contact(&bob);
+        let ringing = tokio::spawn(async move {
+            let invite = bob_incoming.recv().await.unwrap();
+            let mut inbound_call =
+                InboundCall::from_invite(bob_endpoint.clone(), invite, bob_contact)
+                    .unwrap()
+                    .with_media(TestMedia::default());
+
+            inbound_call
+                .respond_provisional(StatusCode::RINGING)
+                .await
+                .unwrap();
+
+            assert!(matches!(
+                inbound_call.update_early_media().await,
+                Err(AcceptCallError::NoReliableEarlyAnswer)
+            ));
+
+            (bob_endpoint, inbound_call)
+        });
+
+        within(async {
+            let mut outbound_call = OutboundCall::make(
+                alice_endpoint.clone(),
+                DigestAuthenticator::new(DigestCredentials::new()),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                bob,
+                TestMedia::default(),
+            )
+            .await
+            .unwrap();
+
+            let peer_tag = outbound_call
+                .early_dialogs()
+                .next()
+                .unwrap()
+                .peer_tag
+                .clone();
+
+            assert!(matches!(
+                outbound_call.update_early_dialog(&peer_tag).await,
+                Err(MakeCallCompletionError::NoReliableEarlyAnswer)
+            ));
+
+            // Bob must reject an offer as long as the early dialog has no reliable answer
+            let state = outbound_call.state.as_mut().unwrap();
+            let dialog = state.earlies[0].0.dialog();
+
+            let mut update = dialog.create_request(Method::UPDATE);
+            update.headers.insert_named(&dialog.local_contact);
+            attach_sdp(
+                &mut update,
+                &TestMedia::default().create_sdp_offer().await.unwrap(),
+            );
+
+            let response = dialog.send_request(update).await.unwrap();
+            assert_eq!(response.line.code, StatusCode::SERVER_INTERNAL_ERROR);
+            assert!(response.headers.contains(&Name::RETRY_AFTER));
+        })
+        .await;
+
+        within(ringing).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn accept_after_early_media() {
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+
+        let (early_media, wait_early_media) = tokio::sync::oneshot::channel();
+
+        let bob_contact = contact(&bob);
+        let accept = tokio::spawn(async move {
+            let invite = bob_incoming.recv().await.unwrap();
+            let mut inbound_call =
+                InboundCall::from_invite(bob_endpoint.clone(), invite, bob_contact)
+                    .unwrap()
+                    .with_media(TestMedia::default());
+
+            inbound_call.respond_early_media().await.unwrap();
+            wait_early_media.await.unwrap();
+
+            let mut call = inbound_call.accept().await.unwrap();
+
+            // The INVITE's offer is only passed to the media backend once
+            assert_eq!(call.media().offers.len(), 1);
+
+            (bob_endpoint, call)
+        });
+
+        let options = MakeCallOptions {
+            early_media: true,
+            ..MakeCallOptions::default()
+        };
+
+        within(async {
+            let mut outbound_call = OutboundCall::make_with_options(
+                alice_endpoint.clone(),
+                DigestAuthenticator::new(DigestCredentials::new()),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                bob,
+                options,
+                TestMedia::default(),
+            )
+            .await
+            .unwrap();
+
+            match outbound_call.wait_for_progress().await.unwrap() {
+                OutboundCallProgress::EarlyMedia(early_dialog) => {
+                    assert!(early_dialog.sdp.is_some());
+                    assert!(early_dialog.sdp_reliable);
+                }
+                _ => panic!("expected early media"),
+            }
+
+            early_media.send(()).unwrap();
+
+            let mut call = outbound_call
+                .wait_for_completion()
+                .await
+                .unwrap()
+                .finish()
+                .await
+                .unwrap();
+
+            // The answer of the early dialog is not passed again
+            assert_eq!(call.media().answers.len(), 1);
+        })
+        .await;
+
+        within(accept).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn renegotiate_when_other_fork_accepts() {
+        let (bob_invites, mut bob_incoming) = TakeRequests::new(Method::INVITE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(bob_invites)).await;
+
+        // Bob's endpoint acts like a forking proxy, early media is sent by fork a, the call accepted by fork b
+        let bob_contact = contact(&bob);
+        let forks = tokio::spawn(async move {
+            let sdp = TestMedia::default().create_sdp_offer().await.unwrap();
+
+            let mut invite = bob_incoming.recv().await.unwrap();
+            let mut tsx = bob_endpoint.create_server_inv_tsx(&mut invite);
+
+            let mut to = invite.base_headers.to.clone();
+            to.tag = Some("fork-a".into());
+
+            let mut progress =
+                bob_endpoint.create_response(&invite, StatusCode::SESSION_PROGRESS, None);
+            progress.msg.headers.remove(&Name::TO);
+            progress.msg.headers.insert_type(Name::TO, &to);
+            progress.msg.headers.insert_named(&bob_contact);
+            progress.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+            progress.msg.body = sdp.to_string().into();
+            tsx.respond_provisional(&mut progress).await.unwrap();
+
+            to.tag = Some("fork-b".into());
+
+            let mut ok = bob_endpoint.create_response(&invite, StatusCode::OK, None);
+            ok.msg.headers.remove(&Name::TO);
+            ok.msg.headers.insert_type(Name::TO, &to);
+            ok.msg.headers.insert_named(&bob_contact);
+            ok.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+            ok.msg.body = sdp.to_string().into();
+            let _accepted = tsx.respond_success(ok).await.unwrap();
+
+            // Alice sends a new offer to fork b
+            let mut reinvite = bob_incoming.recv().await.unwrap();
+            assert_eq!(reinvite.base_headers.to.tag.as_deref(), Some("fork-b"));
+            assert!(!reinvite.body.is_empty());
+
+            let mut ok = bob_endpoint.create_response(&reinvite, StatusCode::OK, None);
+            ok.msg.headers.insert_named(&bob_contact);
+            ok.msg.headers.insert_named(&CONTENT_TYPE_SDP);
+            ok.msg.body = sdp.to_string().into();
+            let _accepted = bob_endpoint
+                .create_server_inv_tsx(&mut reinvite)
+                .respond_success(ok)
+                .await
+                .unwrap();
+
+            bob_endpoint
+        });
+
+        let options = MakeCallOptions {
+            early_media: true,
+            ..MakeCallOptions::default()
+        };
+
+        within(async {
+            let mut outbound_call = OutboundCall::make_with_options(
+                alice_endpoint.clone(),
+                DigestAuthenticator::new(DigestCredentials::new()),
+                NameAddr::uri(alice.clone()),
+                contact(&alice),
+                bob,
+                options,
+                TestMedia::default(),
+            )
+            .await
+            .unwrap();
+
+            match outbound_call.wait_for_progress().await.unwrap() {
+                OutboundCallProgress::EarlyMedia(early_dialog) => {
+                    assert_eq!(early_dialog.peer_tag, "fork-a")
+                }
+                _ => panic!("expected early media"),
+            }
+
+            let completed = outbound_call.wait_for_completion().await.unwrap();
+            assert_eq!(completed.peer_tag().unwrap(), "fork-b");
+
+            let mut call = completed.finish().await.unwrap();
+
+            // The early answer of fork a and the answer to the re-INVITE
+            assert_eq!(call.media().answers.len(), 2);
+        })
+        .await;
+
+        within(forks).await.unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use bytesstr::BytesStr;
+use sip_core::Response;
+use sip_types::msg::StatusLine;
+use sip_types::{Name, StatusCode};
+
+/// Collects the final responses of all branches of a forwarded request and selects the best one
+/// ([RFC3261 Section 16.7](https://datatracker.ietf.org/doc/html/rfc3261#section-16.7))
+///
+/// 2XX responses are forwarded immediately and never added to the context.
+#[derive(Default)]
+pub(super) struct ResponseContext {
+    best: Option<Response>,
+
+    /// `WWW-Authenticate` and `Proxy-Authenticate` headers of all 401 and 407 responses
+    challenges: Vec<(Name, BytesStr)>,
+}
+
+impl ResponseContext {
+    pub(super) fn add(&mut self, response: Response) {
+        let code = response.line.code;
+
+        if code == StatusCode::UNAUTHORIZED || code == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            self.challenges.extend(
+                response
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| is_challenge(name))
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+
+        let is_better = match &self.best {
+            Some(best) => rank(code) < rank(best.line.code),
+            None => true,
+        };
+
+        if is_better {
+            self.best = Some(response);
+        }
+    }
+
+    /// Returns the response to forward upstream, `None` if no final response was received
+    pub(super) fn into_best(self) -> Option<Response> {
+        let mut best = self.best?;
+        let code = best.line.code;
+
+        if code == StatusCode::UNAUTHORIZED || code == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            // Aggregate all challenges, so the client can authenticate with every branch (RFC3261 Section 16.7 Step 7)
+            best.headers.remove(&Name::WWW_AUTHENTICATE);
+            best.headers.remove(&Name::PROXY_AUTHENTICATE);
+
+            for (name, value) in self.challenges {
+                best.headers.insert(name, value);
+            }
+        } else if code == StatusCode::SERVICE_UNAVAILABLE {
+            // 503 must not be forwarded, it would indicate that this proxy is unavailable (RFC3261 Section 16.7 Step 6)
+            best.line = StatusLine {
+                code: StatusCode::SERVER_INTERNAL_ERROR,
+                reason: StatusCode::SERVER_INTERNAL_ERROR
+                    .text()
+                    .map(BytesStr::from_static),
+            };
+        }
+
+        Some(best)
+    }
+}
+
+fn is_challenge(name: &Name) -> bool {
+    *name == Name::WWW_AUTHENTICATE || *name == Name::PROXY_AUTHENTICATE
+}
+
+/// Ranking of a final response, lower is better
+///
+/// Prefers the lowest response class, 6XX before all others. 4XX responses that the client might be able to recover
+/// from are preferred over other 4XX responses.
+fn rank(code: StatusCode) -> (u8, bool) {
+    let class = match code.into_u16() / 100 {
+        6 => 0,
+        3 => 1,
+        4 => 2,
+        _ => 3,
+    };
+
+    let recoverable = matches!(code.into_u16(), 401 | 407 | 415 | 420 | 484);
+
+    (class, !recoverable)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+    use sip_types::Headers;
+
+    fn response(code: u16, headers: &[(Name, &'static str)]) -> Response {
+        let code = StatusCode::from(code);
+
+        let mut response = Response {
+            line: StatusLine { code, reason: None },
+            headers: Headers::new(),
+            body: Bytes::new(),
+        };
+
+        for (name, value) in headers {
+            response
+                .headers
+                .insert(name.clone(), BytesStr::from_static(value));
+        }
+
+        response
+    }
+
+    #[test]
+    fn select_lowest_class() {
+        let mut context = ResponseContext::default();
+        context.add(response(486, &[]));
+        context.add(response(302, &[]));
+        context.add(response(500, &[]));
+
+        assert_eq!(context.into_best().unwrap().line.code.into_u16(), 302);
+    }
+
+    #[test]
+    fn prefer_global_failure() {
+        let mut context = ResponseContext::default();
+        context.add(response(
+            401,
+            &[(Name::WWW_AUTHENTICATE, "Digest realm=\"a\"")],
+        ));
+        context.add(response(603, &[]));
+        context.add(response(500, &[]));
+
+        let best = context.into_best().unwrap();
+
+        assert_eq!(best.line.code.into_u16(), 603);
+        assert!(!best.headers.contains(&Name::WWW_AUTHENTICATE));
+    }
+
+    #[test]
+    fn prefer_recoverable_4xx() {
+        let mut context = ResponseContext::default();
+        context.add(response(404, &[]));
+        context.add(response(484, &[]));
+        context.add(response(403, &[]));
+
+        assert_eq!(context.into_best().unwrap().line.code.into_u16(), 484);
+    }
+
+    #[test]
+    fn replace_service_unavailable() {
+        let mut context = ResponseContext::default();
+        context.add(response(503, &[]));
+
+        assert_eq!(context.into_best().unwrap().line.code.into_u16(), 500);
+    }
+
+    #[test]
+    fn aggregate_challenges() {
+        let mut context = ResponseContext::default();
+        context.add(response(
+            401,
+            &[(Name::WWW_AUTHENTICATE, "Digest realm=\"a\"")],
+        ));
+        context.add(response(
+            407,
+            &[(Name::PROXY_AUTHENTICATE, "Digest realm=\"b\"")],
+        ));
+
+        let best = context.into_best().unwrap();
+
+        assert_eq!(best.line.code.into_u16(), 401);
+        assert!(best.headers.contains(&Name::WWW_AUTHENTICATE));
+        assert!(best.headers.contains(&Name::PROXY_AUTHENTICATE));
+    }
+
+    #[test]
+    fn empty_context() {
+        assert!(ResponseContext::default().into_best().is_none());
+    }
+}
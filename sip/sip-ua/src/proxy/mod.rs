@@ -0,0 +1,1512 @@
+//! Stateful proxy ([RFC3261 Section 16](https://datatracker.ietf.org/doc/html/rfc3261#section-16))
+//!
+//! The [`ProxyLayer`] forwards all requests which reach it to the targets returned by its [`TargetLookup`].
+//! It must be added as the last layer of the endpoint, so that layers which handle requests addressed to this
+//! endpoint (e.g. the [`RegistrarLayer`](crate::RegistrarLayer)) see them first.
+
+use bytesstr::BytesStr;
+use context::ResponseContext;
+use parking_lot as pl;
+use sip_core::transaction::consts::T1;
+use sip_core::transaction::{ClientInvTsx, ServerInvTsx, ServerTsx, TsxKey, TsxResponse};
+use sip_core::transport::{
+    OutgoingParts, OutgoingRequest, OutgoingResponse, TargetTransportInfo, TpHandle,
+};
+use sip_core::{
+    Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake, Request, Response, Result,
+};
+use sip_types::header::typed::{MaxForwards, Routing, Via};
+use sip_types::host::HostPort;
+use sip_types::print::{AppendCtx, PrintCtx};
+use sip_types::uri::params::{Param, Params};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{CodeKind, Headers, Method, Name, StatusCode};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, mpsc, watch};
+use tokio::time::{Instant, sleep, sleep_until};
+
+mod context;
+mod target;
+
+/// Time an INVITE branch may take to send a final response, restarted by every provisional response
+/// ([RFC3261 Section 16.6 Step 11](https://datatracker.ietf.org/doc/html/rfc3261#section-16.6))
+const TIMER_C: Duration = Duration::from_secs(185);
+
+pub use target::{Target, TargetLookup};
+
+/// Defines how a request is forwarded when the lookup returns multiple targets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForkMode {
+    /// Forward the request to all targets at once
+    Parallel,
+
+    /// Forward the request to groups of targets with the same q-value, starting with the highest
+    ///
+    /// Targets of the same group are tried in parallel. The next group is tried once all branches of the current group
+    /// failed, or the current group didn't respond with a final response within `timeout`.
+    Sequential { timeout: Duration },
+}
+
+/// Layer implementing a stateful proxy
+///
+/// Supports parallel and sequential forking, `Record-Route` insertion, loop detection and the propagation of
+/// CANCEL requests to all pending branches. Only loose routing ([RFC3261 Section 16.12](https://datatracker.ietf.org/doc/html/rfc3261#section-16.12))
+/// is supported.
+pub struct ProxyLayer {
+    lookup: Box<dyn TargetLookup>,
+    fork_mode: ForkMode,
+    record_route: bool,
+
+    /// Additional host names which address this proxy in `Route` headers
+    aliases: Vec<HostPort>,
+
+    /// Addresses of all transports requests have been received on or sent with
+    local_addresses: pl::Mutex<HashSet<SocketAddr>>,
+
+    /// Forwarded INVITE requests which can still be cancelled
+    pending: pl::Mutex<HashMap<PendingKey, Arc<Notify>>>,
+
+    /// Branches of all forwarded requests, mapped to the hash used for loop detection
+    branches: Arc<pl::Mutex<HashMap<BytesStr, u64>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PendingKey {
+    branch: BytesStr,
+    cseq: u32,
+}
+
+impl ProxyLayer {
+    pub fn new<L: TargetLookup>(lookup: L) -> Self {
+        Self {
+            lookup: Box::new(lookup),
+            fork_mode: ForkMode::Parallel,
+            record_route: true,
+            aliases: vec![],
+            local_addresses: pl::Mutex::new(HashSet::new()),
+            pending: pl::Mutex::new(HashMap::new()),
+            branches: Arc::new(pl::Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set how requests are forked to multiple targets. Is [`ForkMode::Parallel`] by default
+    pub fn with_fork_mode(self, fork_mode: ForkMode) -> Self {
+        Self { fork_mode, ..self }
+    }
+
+    /// Insert a `Record-Route` header into dialog creating requests, to stay in the path of subsequent requests
+    /// of the dialog. Enabled by default
+    pub fn with_record_route(self, record_route: bool) -> Self {
+        Self {
+            record_route,
+            ..self
+        }
+    }
+
+    /// Add a host (e.g. a domain name) which addresses this proxy when used in a `Route` header
+    ///
+    /// Routes containing the IP address of one of the endpoint's transports are always recognized.
+    /// An alias without port matches all ports.
+    pub fn with_alias(mut self, alias: HostPort) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    /// Returns if the URI addresses this proxy
+    fn is_local(&self, uri: &SipUri) -> bool {
+        let port = uri
+            .host_port
+            .port
+            .unwrap_or(if uri.sips { 5061 } else { 5060 });
+
+        let is_alias = self
+            .aliases
+            .iter()
+            .any(|alias| alias.host == uri.host_port.host && alias.port.is_none_or(|p| p == port));
+
+        if is_alias {
+            return true;
+        }
+
+        match uri.host_port.ip() {
+            Some(ip) => self
+                .local_addresses
+                .lock()
+                .contains(&SocketAddr::new(ip, port)),
+            None => false,
+        }
+    }
+
+    fn add_local_addresses(&self, transport: &TpHandle) {
+        let mut local_addresses = self.local_addresses.lock();
+        local_addresses.insert(transport.sent_by());
+        local_addresses.insert(transport.bound());
+    }
+
+    /// Validate the request and apply the Max-Forwards and Route processing (RFC3261 Section 16.3 & 16.4)
+    ///
+    /// Returns the request to forward and the remaining route set, or the status code to reject the request with.
+    fn process_request(
+        &self,
+        request: &IncomingRequest,
+    ) -> Result<(Request, Vec<Routing>), StatusCode> {
+        let max_forwards = match request.headers.try_get_named::<MaxForwards>() {
+            Some(Ok(MaxForwards(0))) => return Err(StatusCode::TOO_MANY_HOPS),
+            Some(Ok(MaxForwards(max_forwards))) => max_forwards - 1,
+            Some(Err(_)) => return Err(StatusCode::BAD_REQUEST),
+            None => 70,
+        };
+
+        if request.line.method != Method::ACK {
+            let hash = loop_hash(request);
+            let branches = self.branches.lock();
+
+            let is_loop = request
+                .base_headers
+                .via
+                .iter()
+                .filter_map(|via| via.params.get_val("branch"))
+                .any(|branch| branches.get(branch) == Some(&hash));
+
+            // A request with a known branch but a different hash is spiralling, which is fine
+            if is_loop {
+                return Err(StatusCode::LOOP_DETECTED);
+            }
+        }
+
+        let mut routes = match request.headers.try_get::<Vec<Routing>>(Name::ROUTE) {
+            Some(Ok(routes)) => routes,
+            Some(Err(_)) => return Err(StatusCode::BAD_REQUEST),
+            None => vec![],
+        };
+
+        // Remove all routes which address this proxy, there might be two if the request was double record-routed
+        while routes
+            .first()
+            .is_some_and(|route| self.is_local(&route.uri.uri))
+        {
+            routes.remove(0);
+        }
+
+        let mut forward = request.clone_request();
+        forward.headers.remove(&Name::MAX_FORWARDS);
+        forward.headers.remove(&Name::ROUTE);
+        forward.headers.remove(&Name::CONTENT_LENGTH);
+        forward.headers.insert_named(&MaxForwards(max_forwards));
+
+        // Use the parsed Via headers, as the topmost one contains the received & rport parameters
+        forward.headers.remove(&Name::VIA);
+        forward
+            .headers
+            .insert_named_front(&request.base_headers.via);
+
+        Ok((forward, routes))
+    }
+
+    /// Returns the targets of a request, or the status code to reject the request with
+    async fn targets(
+        &self,
+        request: &IncomingRequest,
+        routes: &[Routing],
+    ) -> Result<Vec<Target>, StatusCode> {
+        // Requests inside a dialog or with a preloaded route are forwarded to the request-URI
+        if !routes.is_empty() || request.base_headers.to.tag.is_some() {
+            return Ok(vec![Target::new(request.line.uri.clone())]);
+        }
+
+        let targets = self.lookup.lookup(request).await?;
+
+        if targets.is_empty() {
+            return Err(StatusCode::TEMPORARILY_UNAVAILABLE);
+        }
+
+        Ok(targets)
+    }
+
+    fn group_targets(&self, mut targets: Vec<Target>) -> VecDeque<Vec<Target>> {
+        match self.fork_mode {
+            ForkMode::Parallel => VecDeque::from([targets]),
+            ForkMode::Sequential { .. } => {
+                let q = |target: &Target| target.q.unwrap_or(1.0);
+
+                targets.sort_by(|a, b| q(b).total_cmp(&q(a)));
+
+                let mut groups: VecDeque<Vec<Target>> = VecDeque::new();
+
+                for target in targets {
+                    match groups.back_mut() {
+                        Some(group) if q(&group[0]) == q(&target) => group.push(target),
+                        _ => groups.push_back(vec![target]),
+                    }
+                }
+
+                groups
+            }
+        }
+    }
+
+    /// Create the request for a single target (RFC3261 Section 16.6)
+    async fn create_branch_request(
+        &self,
+        endpoint: &Endpoint,
+        request: &IncomingRequest,
+        forward: &Request,
+        routes: &[Routing],
+        target: &Target,
+    ) -> Result<(Request, TargetTransportInfo)> {
+        let mut branch = forward.clone();
+        branch.line.uri = target.uri.clone();
+
+        let next_hop = target
+            .route
+            .iter()
+            .chain(routes)
+            .next()
+            .map(|route| &route.uri.uri)
+            .unwrap_or(&target.uri);
+
+        let (transport, destination) = endpoint.select_transport(next_hop).await?;
+
+        let mut route_set = target.route.clone();
+        route_set.extend_from_slice(routes);
+
+        if !route_set.is_empty() {
+            branch.headers.insert_type(Name::ROUTE, &route_set);
+        }
+
+        let is_dialog_creating =
+            request.base_headers.to.tag.is_none() && request.line.method != Method::REGISTER;
+
+        if self.record_route && is_dialog_creating {
+            self.add_local_addresses(&transport);
+
+            let incoming = &request.tp_info.transport;
+
+            let mut record_route = vec![record_route_entry(&transport)];
+
+            // Insert two entries when the request changes the transport (RFC5658)
+            if incoming.name() != transport.name() || incoming.sent_by() != transport.sent_by() {
+                record_route.push(record_route_entry(incoming));
+            }
+
+            branch
+                .headers
+                .insert_type_front(Name::RECORD_ROUTE, &record_route);
+        }
+
+        let target = TargetTransportInfo {
+            via_host_port: None,
+            transport: Some((transport, destination)),
+        };
+
+        Ok((branch, target))
+    }
+
+    async fn handle_request(
+        &self,
+        endpoint: &Endpoint,
+        mut request: IncomingRequest,
+    ) -> Result<()> {
+        let (forward, routes) = match self.process_request(&request) {
+            Ok(processed) => processed,
+            Err(code) => return reject(endpoint, request, code).await,
+        };
+
+        let targets = match self.targets(&request, &routes).await {
+            Ok(targets) => targets,
+            Err(code) => return reject(endpoint, request, code).await,
+        };
+
+        // Used as template for all forwarded responses
+        let mut trying = endpoint.create_response(&request, StatusCode::TRYING, None);
+
+        let server = if request.line.method == Method::INVITE {
+            let mut tsx = endpoint.create_server_inv_tsx(&mut request);
+
+            // Respond immediately, INVITE branches might take a while to respond (RFC3261 Section 16.2)
+            tsx.respond_provisional(&mut trying).await?;
+
+            ServerSide::Invite(tsx)
+        } else {
+            ServerSide::NonInvite(endpoint.create_server_tsx(&mut request))
+        };
+
+        let pending_key = PendingKey {
+            branch: request.tsx_key.branch().clone(),
+            cseq: request.base_headers.cseq.cseq,
+        };
+
+        let cancelled = Arc::new(Notify::new());
+
+        if request.line.method == Method::INVITE {
+            self.pending
+                .lock()
+                .insert(pending_key.clone(), cancelled.clone());
+        }
+
+        let mut groups = self.group_targets(targets);
+        let loop_hash = loop_hash(&request);
+
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let mut cancel_groups: Vec<watch::Sender<bool>> = vec![];
+        let mut outstanding = 0;
+        let mut deadline = None;
+        let mut terminated = false;
+        let mut server = Some(server);
+        let mut context = ResponseContext::default();
+
+        loop {
+            let timed_out = deadline.is_some_and(|deadline| deadline <= Instant::now());
+
+            // Start the next group once the current one is done or timed out
+            if (outstanding == 0 || timed_out) && !terminated {
+                if timed_out && let Some(cancel) = cancel_groups.last() {
+                    let _ = cancel.send(true);
+                }
+
+                deadline = None;
+
+                if let Some(group) = groups.pop_front() {
+                    let (cancel_tx, cancel_rx) = watch::channel(false);
+                    cancel_groups.push(cancel_tx);
+
+                    for target in group {
+                        let (branch, target) = match self
+                            .create_branch_request(endpoint, &request, &forward, &routes, &target)
+                            .await
+                        {
+                            Ok(branch) => branch,
+                            Err(e) => {
+                                log::debug!("Failed to create request for {:?}, {e}", target.uri);
+                                continue;
+                            }
+                        };
+
+                        tokio::spawn(run_branch(
+                            endpoint.clone(),
+                            branch,
+                            target,
+                            loop_hash,
+                            self.branches.clone(),
+                            cancel_rx.clone(),
+                            events_tx.clone(),
+                        ));
+
+                        outstanding += 1;
+                    }
+
+                    if let ForkMode::Sequential { timeout } = self.fork_mode
+                        && !groups.is_empty()
+                    {
+                        deadline = Some(Instant::now() + timeout);
+                    }
+
+                    continue;
+                }
+            }
+
+            if outstanding == 0 {
+                break;
+            }
+
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => continue,
+                _ = cancelled.notified(), if !terminated => {
+                    terminated = true;
+                    deadline = None;
+                    cancel_all(&cancel_groups);
+                    continue;
+                }
+            };
+
+            let Some(event) = event else {
+                break;
+            };
+
+            match event {
+                BranchEvent::Provisional(response) => {
+                    if response.line.code == StatusCode::TRYING {
+                        continue;
+                    }
+
+                    if let Some(server) = &mut server {
+                        let mut response = forward_response(&trying.parts, response);
+
+                        if let Err(e) = server.respond_provisional(&mut response).await {
+                            log::debug!("Failed to forward provisional response, {e}");
+                        }
+                    }
+                }
+                BranchEvent::Final(response) => {
+                    outstanding -= 1;
+
+                    match response.line.code.kind() {
+                        CodeKind::Success => {
+                            // No new branches must be started, cancel all pending ones (RFC3261 Section 16.7 Step 10)
+                            terminated = true;
+                            deadline = None;
+                            cancel_all(&cancel_groups);
+
+                            let mut response = forward_response(&trying.parts, response);
+
+                            let result = match server.take() {
+                                Some(server) => server.respond(response).await,
+                                // 2XX responses of other branches are forwarded statelessly
+                                None => endpoint
+                                    .send_outgoing_response(&mut response)
+                                    .await
+                                    .map_err(Into::into),
+                            };
+
+                            if let Err(e) = result {
+                                log::debug!("Failed to forward final response, {e}");
+                            }
+                        }
+                        CodeKind::GlobalFailure => {
+                            // 6XX responses are not forwarded immediately, but all pending branches are cancelled.
+                            // The best response is chosen once they completed (RFC3261 Section 16.7 Step 5)
+                            terminated = true;
+                            deadline = None;
+                            cancel_all(&cancel_groups);
+
+                            context.add(strip_via(response));
+                        }
+                        _ => context.add(strip_via(response)),
+                    }
+                }
+                BranchEvent::Retransmission(response) => {
+                    let mut response = forward_response(&trying.parts, response);
+
+                    if let Err(e) = endpoint.send_outgoing_response(&mut response).await {
+                        log::debug!("Failed to forward 2XX retransmission, {e}");
+                    }
+                }
+                BranchEvent::Failed(e) => {
+                    outstanding -= 1;
+
+                    log::debug!("Branch of {} failed, {e}", request.line.method);
+                }
+            }
+        }
+
+        self.pending.lock().remove(&pending_key);
+
+        if let Some(server) = server {
+            let response = match context.into_best() {
+                Some(best) => outgoing_response(&trying.parts, best),
+                None => endpoint.create_response(&request, StatusCode::REQUEST_TIMEOUT, None),
+            };
+
+            server.respond(response).await?;
+        }
+
+        // Keep forwarding retransmissions of 2XX responses until all branches are terminated
+        drop(events_tx);
+
+        while let Some(event) = events.recv().await {
+            if let BranchEvent::Final(response) | BranchEvent::Retransmission(response) = event
+                && response.line.code.kind() == CodeKind::Success
+            {
+                let mut response = forward_response(&trying.parts, response);
+                endpoint.send_outgoing_response(&mut response).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// ACK requests for 2XX responses are end-to-end and forwarded statelessly
+    async fn handle_ack(&self, endpoint: &Endpoint, request: IncomingRequest) -> Result<()> {
+        let Ok((mut forward, routes)) = self.process_request(&request) else {
+            return Ok(());
+        };
+
+        let next_hop = routes
+            .first()
+            .map(|route| &route.uri.uri)
+            .unwrap_or(&request.line.uri);
+
+        let (transport, destination) = endpoint.select_transport(next_hop).await?;
+
+        if !routes.is_empty() {
+            forward.headers.insert_type(Name::ROUTE, &routes);
+        }
+
+        let via = endpoint.create_via(&transport, &TsxKey::client(&Method::ACK), None);
+        forward.headers.insert_named_front(&via);
+
+        let mut forward = OutgoingRequest {
+            msg: forward,
+            parts: OutgoingParts {
+                transport,
+                destination,
+                buffer: Default::default(),
+            },
+        };
+
+        endpoint.send_outgoing_request(&mut forward).await?;
+
+        Ok(())
+    }
+
+    /// Propagate the CANCEL to all pending branches of the INVITE (RFC3261 Section 16.10)
+    async fn handle_cancel(&self, endpoint: &Endpoint, mut request: IncomingRequest) -> Result<()> {
+        let cancelled = self
+            .pending
+            .lock()
+            .get(&PendingKey {
+                branch: request.tsx_key.branch().clone(),
+                cseq: request.base_headers.cseq.cseq,
+            })
+            .cloned();
+
+        let code = match cancelled {
+            Some(cancelled) => {
+                cancelled.notify_one();
+                StatusCode::OK
+            }
+            None => StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
+        };
+
+        let response = endpoint.create_response(&request, code, None);
+
+        endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for ProxyLayer {
+    fn name(&self) -> &'static str {
+        "proxy"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::CANCEL);
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        let request = request.take();
+
+        self.add_local_addresses(&request.tp_info.transport);
+
+        let method = request.line.method.clone();
+
+        let result = if method == Method::ACK {
+            self.handle_ack(endpoint, request).await
+        } else if method == Method::CANCEL {
+            self.handle_cancel(endpoint, request).await
+        } else {
+            self.handle_request(endpoint, request).await
+        };
+
+        if let Err(e) = result {
+            log::warn!("Failed to proxy {method} request, {e:?}");
+        }
+    }
+}
+
+/// Server transaction of the request that is forwarded
+enum ServerSide {
+    Invite(ServerInvTsx),
+    NonInvite(ServerTsx),
+}
+
+impl ServerSide {
+    async fn respond_provisional(&mut self, response: &mut OutgoingResponse) -> Result<()> {
+        match self {
+            ServerSide::Invite(tsx) => tsx.respond_provisional(response).await,
+            ServerSide::NonInvite(tsx) => tsx.respond_provisional(response).await,
+        }
+    }
+
+    async fn respond(self, response: OutgoingResponse) -> Result<()> {
+        match self {
+            ServerSide::Invite(tsx) if response.msg.line.code.kind() == CodeKind::Success => {
+                let accepted = tsx.respond_success(response).await?;
+
+                // Absorb retransmissions of the INVITE. Retransmissions of the 2XX response are the responsibility
+                // of the UAS, so `accepted` is only kept alive (RFC6026 Section 8.5)
+                tokio::spawn(async move {
+                    sleep(T1 * 64).await;
+                    drop(accepted);
+                });
+
+                Ok(())
+            }
+            ServerSide::Invite(tsx) => {
+                // Don't block the proxy while waiting for the ACK
+                tokio::spawn(async move {
+                    if let Err(e) = tsx.respond_failure(response).await {
+                        log::debug!("Failed to forward final response to INVITE, {e}");
+                    }
+                });
+
+                Ok(())
+            }
+            ServerSide::NonInvite(tsx) => tsx.respond(response).await,
+        }
+    }
+}
+
+enum BranchEvent {
+    Provisional(TsxResponse),
+
+    /// The first final response of a branch
+    Final(TsxResponse),
+
+    /// Subsequent 2XX responses of an INVITE branch
+    Retransmission(TsxResponse),
+
+    /// The branch failed without receiving a final response
+    Failed(sip_core::Error),
+}
+
+/// Send the request of a single branch and report all responses to `events`
+async fn run_branch(
+    endpoint: Endpoint,
+    request: Request,
+    mut target: TargetTransportInfo,
+    loop_hash: u64,
+    branches: Arc<pl::Mutex<HashMap<BytesStr, u64>>>,
+    mut cancel: watch::Receiver<bool>,
+    events: mpsc::UnboundedSender<BranchEvent>,
+) {
+    if request.line.method != Method::INVITE {
+        let mut tsx = match endpoint.send_request(request, &mut target).await {
+            Ok(tsx) => tsx,
+            Err(e) => {
+                let _ = events.send(BranchEvent::Failed(e));
+                return;
+            }
+        };
+
+        let branch = top_via_branch(&tsx.request().msg.headers);
+
+        if let Some(branch) = &branch {
+            branches.lock().insert(branch.clone(), loop_hash);
+        }
+
+        loop {
+            match tsx.receive().await {
+                Ok(response) if response.line.code.kind() == CodeKind::Provisional => {
+                    let _ = events.send(BranchEvent::Provisional(response));
+                }
+                Ok(response) => {
+                    let _ = events.send(BranchEvent::Final(response));
+                    break;
+                }
+                Err(e) => {
+                    let _ = events.send(BranchEvent::Failed(e));
+                    break;
+                }
+            }
+        }
+
+        if let Some(branch) = &branch {
+            branches.lock().remove(branch);
+        }
+
+        return;
+    }
+
+    let mut tsx = match endpoint.send_invite(request, &mut target).await {
+        Ok(tsx) => tsx,
+        Err(e) => {
+            let _ = events.send(BranchEvent::Failed(e));
+            return;
+        }
+    };
+
+    // Timer C replaces the transaction's own timeout, which is only kept as a limit for the CANCEL to complete
+    tsx.set_proceeding_timeout(TIMER_C + T1 * 64);
+
+    let branch = top_via_branch(&tsx.request().msg.headers);
+
+    if let Some(branch) = &branch {
+        branches.lock().insert(branch.clone(), loop_hash);
+    }
+
+    let mut received_provisional = false;
+    let mut received_final = false;
+    let mut cancel_requested = false;
+    let mut cancel_sent = false;
+
+    // Set when Timer C fired, the branch is then handled as if it received a 408 response
+    let mut timed_out = false;
+    let mut timer_c = Instant::now() + TIMER_C;
+
+    loop {
+        tokio::select! {
+            result = tsx.receive() => {
+                let response = match result {
+                    Ok(Some(response)) => response,
+                    Ok(None) => break,
+                    Err(e) => {
+                        if !received_final && !timed_out {
+                            let _ = events.send(BranchEvent::Failed(e));
+                        }
+
+                        break;
+                    }
+                };
+
+                let event = match response.line.code.kind() {
+                    CodeKind::Provisional => {
+                        received_provisional = true;
+
+                        if !timed_out {
+                            timer_c = Instant::now() + TIMER_C;
+                        }
+
+                        BranchEvent::Provisional(response)
+                    }
+                    // Only 2XX responses are still forwarded after Timer C fired
+                    CodeKind::Success if timed_out => BranchEvent::Retransmission(response),
+                    _ if timed_out => continue,
+                    _ if received_final => BranchEvent::Retransmission(response),
+                    _ => {
+                        received_final = true;
+                        BranchEvent::Final(response)
+                    }
+                };
+
+                let _ = events.send(event);
+            }
+            result = cancel.wait_for(|cancel| *cancel), if !cancel_requested => {
+                cancel_requested = true;
+
+                if result.is_err() {
+                    // The proxy is no longer interested in this branch
+                    continue;
+                }
+            }
+            _ = sleep_until(timer_c), if !received_final && !timed_out => {
+                // RFC3261 Section 16.8: cancel the branch if it's proceeding and behave as if it received a 408
+                timed_out = true;
+                cancel_requested = true;
+
+                let _ = events.send(BranchEvent::Failed(sip_core::Error::RequestTimedOut));
+            }
+        }
+
+        // CANCEL must only be sent after a provisional response has been received (RFC3261 Section 9.1)
+        if cancel_requested && received_provisional && !received_final && !cancel_sent {
+            cancel_sent = true;
+            send_cancel(&mut tsx).await;
+        }
+    }
+
+    if let Some(branch) = &branch {
+        branches.lock().remove(branch);
+    }
+}
+
+async fn send_cancel(tsx: &mut ClientInvTsx) {
+    match tsx.cancel().await {
+        Ok(mut cancel_tsx) => {
+            tokio::spawn(async move {
+                if let Err(e) = cancel_tsx.receive_final().await {
+                    log::debug!("CANCEL request failed, {e}");
+                }
+            });
+        }
+        Err(e) => log::debug!("Failed to send CANCEL request, {e}"),
+    }
+}
+
+fn cancel_all(cancel_groups: &[watch::Sender<bool>]) {
+    for cancel in cancel_groups {
+        let _ = cancel.send(true);
+    }
+}
+
+fn top_via_branch(headers: &Headers) -> Option<BytesStr> {
+    headers
+        .get_named::<Vec<Via>>()
+        .ok()?
+        .into_iter()
+        .next()?
+        .params
+        .get_val("branch")
+        .cloned()
+}
+
+/// Remove the topmost Via header, which was added by this proxy
+fn strip_via(response: TsxResponse) -> Response {
+    let TsxResponse {
+        line,
+        base_headers,
+        mut headers,
+        body,
+        ..
+    } = response;
+
+    headers.remove(&Name::VIA);
+    headers.remove(&Name::CONTENT_LENGTH);
+
+    if base_headers.via.len() > 1 {
+        headers.insert_named_front(&base_headers.via[1..].to_vec());
+    }
+
+    Response {
+        line,
+        headers,
+        body,
+    }
+}
+
+fn forward_response(parts: &OutgoingParts, response: TsxResponse) -> OutgoingResponse {
+    outgoing_response(parts, strip_via(response))
+}
+
+/// Create a response which is sent using the same transport and destination as `parts`
+fn outgoing_response(parts: &OutgoingParts, msg: Response) -> OutgoingResponse {
+    OutgoingResponse {
+        msg,
+        parts: OutgoingParts {
+            transport: parts.transport.clone(),
+            destination: parts.destination,
+            buffer: Default::default(),
+        },
+    }
+}
+
+async fn reject(endpoint: &Endpoint, mut request: IncomingRequest, code: StatusCode) -> Result<()> {
+    let response = endpoint.create_response(&request, code, None);
+
+    if request.line.method == Method::INVITE {
+        endpoint
+            .create_server_inv_tsx(&mut request)
+            .respond_failure(response)
+            .await
+    } else {
+        endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+    }
+}
+
+fn record_route_entry(transport: &TpHandle) -> Routing {
+    let mut uri = SipUri::new(transport.sent_by().into());
+
+    if transport.name().eq_ignore_ascii_case("TLS") {
+        uri.sips = true;
+    } else if !transport.name().eq_ignore_ascii_case("UDP") {
+        uri.uri_params.push(Param::value(
+            "transport",
+            transport.name().to_ascii_lowercase(),
+        ));
+    }
+
+    uri.uri_params.push(Param::name("lr"));
+
+    Routing {
+        uri: NameAddr::uri(uri),
+        params: Params::new(),
+    }
+}
+
+/// Hash over all fields of a request that are relevant for loop detection (RFC3261 Section 16.6 Step 8)
+///
+/// A request which arrives again with the same hash is looping, otherwise it's spiralling.
+fn loop_hash(request: &IncomingRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    request
+        .line
+        .uri
+        .print_ctx(PrintCtx::default())
+        .to_string()
+        .hash(&mut hasher);
+    request.base_headers.from.tag.hash(&mut hasher);
+    request.base_headers.to.tag.hash(&mut hasher);
+    request.base_headers.call_id.0.hash(&mut hasher);
+    request.base_headers.cseq.cseq.hash(&mut hasher);
+
+    for (name, value) in request.headers.iter() {
+        if *name == Name::PROXY_REQUIRE || *name == Name::PROXY_AUTHORIZATION {
+            value.hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::within;
+    use sip_core::transport::udp::Udp;
+    use tokio::net::UdpSocket;
+    use tokio::time::timeout;
+
+    fn target(uri: &str, q: Option<f32>) -> Target {
+        Target {
+            uri: uri.parse().unwrap(),
+            route: vec![],
+            q,
+        }
+    }
+
+    struct NoTargets;
+
+    #[async_trait::async_trait]
+    impl TargetLookup for NoTargets {
+        async fn lookup(&self, _: &IncomingRequest) -> Result<Vec<Target>, StatusCode> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn sequential_groups_by_q() {
+        let layer = ProxyLayer::new(NoTargets).with_fork_mode(ForkMode::Sequential {
+            timeout: Duration::from_secs(10),
+        });
+
+        let groups = layer.group_targets(vec![
+            target("sip:a@example.com", Some(0.5)),
+            target("sip:b@example.com", None),
+            target("sip:c@example.com", Some(0.5)),
+            target("sip:d@example.com", Some(1.0)),
+        ]);
+
+        let groups: Vec<Vec<String>> = groups
+            .into_iter()
+            .map(|group| group.iter().map(|t| format!("{:?}", t.uri)).collect())
+            .collect();
+
+        assert_eq!(
+            groups,
+            [
+                vec!["sip:b@example.com", "sip:d@example.com"],
+                vec!["sip:a@example.com", "sip:c@example.com"],
+            ]
+        );
+    }
+
+    #[test]
+    fn local_alias() {
+        let layer = ProxyLayer::new(NoTargets).with_alias(HostPort::host_name("proxy.example.com"));
+
+        assert!(layer.is_local(&"sip:proxy.example.com;lr".parse().unwrap()));
+        assert!(layer.is_local(&"sip:proxy.example.com:5080;lr".parse().unwrap()));
+        assert!(!layer.is_local(&"sip:example.com;lr".parse().unwrap()));
+    }
+
+    #[test]
+    fn local_address() {
+        let layer = ProxyLayer::new(NoTargets);
+        layer
+            .local_addresses
+            .lock()
+            .insert("192.0.2.1:5060".parse().unwrap());
+
+        assert!(layer.is_local(&"sip:192.0.2.1;lr".parse().unwrap()));
+        assert!(!layer.is_local(&"sip:192.0.2.1:5080;lr".parse().unwrap()));
+    }
+
+    /// UDP socket acting as UAC or UAS, exchanging raw messages with the proxy
+    struct Peer {
+        socket: UdpSocket,
+        addr: SocketAddr,
+
+        /// All messages received so far, used to ignore retransmissions
+        received: Vec<String>,
+    }
+
+    impl Peer {
+        async fn bind() -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = socket.local_addr().unwrap();
+
+            Self {
+                socket,
+                addr,
+                received: vec![],
+            }
+        }
+
+        fn uri(&self, user: &str) -> String {
+            format!("sip:{user}@{}", self.addr)
+        }
+
+        async fn send(&self, message: &str, destination: SocketAddr) {
+            self.socket
+                .send_to(message.as_bytes(), destination)
+                .await
+                .unwrap();
+        }
+
+        /// Receive the next message which isn't a retransmission
+        async fn receive(&mut self) -> String {
+            let mut buffer = vec![0; 65535];
+
+            loop {
+                let (len, _) = within(self.socket.recv_from(&mut buffer)).await.unwrap();
+                let message = String::from_utf8_lossy(&buffer[..len]).into_owned();
+
+                if !self.received.contains(&message) {
+                    self.received.push(message.clone());
+                    return message;
+                }
+            }
+        }
+
+        /// Receive the next message and assert that it's a request with the given method
+        async fn receive_request(&mut self, method: &str) -> String {
+            let request = self.receive().await;
+            assert!(
+                request.starts_with(&format!("{method} ")),
+                "expected {method} request, got {request}"
+            );
+            request
+        }
+
+        /// Receive the next message and assert that it's a response with the given status code
+        async fn receive_response(&mut self, code: u16) -> String {
+            let response = self.receive().await;
+            assert!(
+                response.starts_with(&format!("SIP/2.0 {code} ")),
+                "expected {code} response, got {response}"
+            );
+            response
+        }
+    }
+
+    /// Proxy endpoint listening on a random UDP port
+    async fn proxy(layer: ProxyLayer) -> (Endpoint, SocketAddr) {
+        let mut builder = Endpoint::builder();
+        builder.add_layer(layer);
+
+        let transport = Udp::spawn(&mut builder, "127.0.0.1:0").await.unwrap();
+
+        (builder.build(), transport.bound())
+    }
+
+    /// Lookup returning the same targets for every request
+    struct Targets(Vec<Target>);
+
+    #[async_trait::async_trait]
+    impl TargetLookup for Targets {
+        async fn lookup(&self, _: &IncomingRequest) -> Result<Vec<Target>, StatusCode> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn request(method: &str, uri: &str, from: &Peer, branch: &str, headers: &str) -> String {
+        format!(
+            "{method} {uri} SIP/2.0\r\n\
+             Via: SIP/2.0/UDP {addr};branch=z9hG4bK{branch}\r\n\
+             Max-Forwards: 70\r\n\
+             From: <sip:alice@example.com>;tag=alice\r\n\
+             To: <{uri}>\r\n\
+             Call-ID: {branch}@example.com\r\n\
+             CSeq: 1 {method}\r\n\
+             {headers}\
+             Content-Length: 0\r\n\r\n",
+            addr = from.addr,
+        )
+    }
+
+    /// ACK request for a non-2XX response to an INVITE, which is sent within the INVITE's transaction
+    fn ack(invite: &str, response: &str) -> String {
+        let uri = invite.split(' ').nth(1).unwrap();
+
+        format!(
+            "ACK {uri} SIP/2.0\r\n\
+             Via: {via}\r\n\
+             Max-Forwards: 70\r\n\
+             From: {from}\r\n\
+             To: {to}\r\n\
+             Call-ID: {call_id}\r\n\
+             CSeq: 1 ACK\r\n\
+             Content-Length: 0\r\n\r\n",
+            via = header(invite, "Via")[0],
+            from = header(invite, "From")[0],
+            to = header(response, "To")[0],
+            call_id = header(invite, "Call-ID")[0],
+        )
+    }
+
+    /// Response to a request as created by a UAS, copying the headers required by RFC3261 Section 8.2.6.2
+    fn response(request: &str, code: u16) -> String {
+        let mut response = format!("SIP/2.0 {code} Test\r\n");
+
+        for line in request.lines() {
+            let Some((name, _)) = line.split_once(':') else {
+                continue;
+            };
+
+            if name.eq_ignore_ascii_case("To") && !line.contains(";tag=") {
+                response += &format!("{line};tag=uas\r\n");
+            } else if ["Via", "Record-Route", "From", "To", "Call-ID", "CSeq"]
+                .iter()
+                .any(|n| name.eq_ignore_ascii_case(n))
+            {
+                response += &format!("{line}\r\n");
+            }
+        }
+
+        response + "Content-Length: 0\r\n\r\n"
+    }
+
+    /// All values of a header, comma separated values are split
+    fn header<'m>(message: &'m str, name: &str) -> Vec<&'m str> {
+        message
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::trim)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn forward_invite() {
+        let mut uac = Peer::bind().await;
+        let mut uas = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![target(
+            &uas.uri("bob"),
+            None,
+        )])))
+        .await;
+
+        let invite = request("INVITE", "sip:bob@example.com", &uac, "forward", "");
+        uac.send(&invite, proxy).await;
+        uac.receive_response(100).await;
+
+        let forwarded = uas.receive_request("INVITE").await;
+        assert!(forwarded.starts_with(&format!("INVITE {} SIP/2.0", uas.uri("bob"))));
+        assert_eq!(header(&forwarded, "Max-Forwards"), ["69"]);
+        assert_eq!(
+            header(&forwarded, "Record-Route"),
+            [format!("<sip:{proxy};lr>")]
+        );
+
+        let vias = header(&forwarded, "Via");
+        assert_eq!(vias.len(), 2);
+        assert!(vias[0].starts_with(&format!("SIP/2.0/UDP {proxy};branch=z9hG4bK")));
+        assert!(vias[1].starts_with(&format!("SIP/2.0/UDP {};branch=z9hG4bKforward", uac.addr)));
+
+        uas.send(&response(&forwarded, 180), proxy).await;
+        let ringing = uac.receive_response(180).await;
+        assert_eq!(header(&ringing, "Via"), header(&invite, "Via"));
+
+        uas.send(&response(&forwarded, 200), proxy).await;
+        let ok = uac.receive_response(200).await;
+        assert_eq!(header(&ok, "Via"), header(&invite, "Via"));
+        assert_eq!(header(&ok, "Record-Route"), [format!("<sip:{proxy};lr>")]);
+        assert!(header(&ok, "To")[0].ends_with(";tag=uas"));
+
+        // The ACK for the 2XX response follows the recorded route
+        let ack = request(
+            "ACK",
+            &uas.uri("bob"),
+            &uac,
+            "forward-ack",
+            &format!("Route: <sip:{proxy};lr>\r\n"),
+        )
+        .replace(
+            &format!("To: <{}>", uas.uri("bob")),
+            &format!("To: {}", header(&ok, "To")[0]),
+        );
+        uac.send(&ack, proxy).await;
+
+        let ack = uas.receive_request("ACK").await;
+        assert!(header(&ack, "Route").is_empty());
+        assert_eq!(header(&ack, "Max-Forwards"), ["69"]);
+        assert_eq!(header(&ack, "Via").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn route_processing() {
+        let mut uac = Peer::bind().await;
+        let mut uas = Peer::bind().await;
+        let mut next_hop = Peer::bind().await;
+
+        // In-dialog requests are routed without consulting the lookup
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(NoTargets)).await;
+
+        let bye = request(
+            "BYE",
+            &uas.uri("bob"),
+            &uac,
+            "route",
+            &format!("Route: <sip:{proxy};lr>, <sip:{};lr>\r\n", next_hop.addr),
+        )
+        .replace(
+            &format!("To: <{}>", uas.uri("bob")),
+            &format!("To: <{}>;tag=uas", uas.uri("bob")),
+        );
+        uac.send(&bye, proxy).await;
+
+        let forwarded = next_hop.receive_request("BYE").await;
+        assert!(forwarded.starts_with(&format!("BYE {} SIP/2.0", uas.uri("bob"))));
+        assert_eq!(
+            header(&forwarded, "Route"),
+            [format!("<sip:{};lr>", next_hop.addr)]
+        );
+        assert!(header(&forwarded, "Record-Route").is_empty());
+
+        next_hop.send(&response(&forwarded, 200), proxy).await;
+
+        let ok = uac.receive_response(200).await;
+        assert_eq!(header(&ok, "Via"), header(&bye, "Via"));
+
+        // Nothing was sent to the request-URI
+        assert!(
+            timeout(Duration::from_millis(100), uas.receive())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn too_many_hops() {
+        let mut uac = Peer::bind().await;
+        let mut uas = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![target(
+            &uas.uri("bob"),
+            None,
+        )])))
+        .await;
+
+        let options = request("OPTIONS", "sip:bob@example.com", &uac, "hops", "")
+            .replace("Max-Forwards: 70", "Max-Forwards: 0");
+        uac.send(&options, proxy).await;
+
+        uac.receive_response(483).await;
+        assert!(
+            timeout(Duration::from_millis(100), uas.receive())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn loop_detected() {
+        let mut uac = Peer::bind().await;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        drop(socket);
+
+        // The target addresses the proxy itself, so the request arrives again unchanged
+        let uri = format!("sip:bob@{addr}");
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(ProxyLayer::new(Targets(vec![target(&uri, None)])));
+        Udp::spawn(&mut builder, addr).await.unwrap();
+        let _endpoint = builder.build();
+
+        uac.send(&request("OPTIONS", &uri, &uac, "loop", ""), addr)
+            .await;
+
+        uac.receive_response(482).await;
+    }
+
+    #[tokio::test]
+    async fn parallel_forking() {
+        let mut uac = Peer::bind().await;
+        let mut uas1 = Peer::bind().await;
+        let mut uas2 = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![
+            target(&uas1.uri("bob"), None),
+            target(&uas2.uri("bob"), None),
+        ])))
+        .await;
+
+        uac.send(
+            &request("INVITE", "sip:bob@example.com", &uac, "parallel", ""),
+            proxy,
+        )
+        .await;
+        uac.receive_response(100).await;
+
+        // Both targets receive the request before any of them responded
+        let invite1 = uas1.receive_request("INVITE").await;
+        let invite2 = uas2.receive_request("INVITE").await;
+
+        uas1.send(&response(&invite1, 486), proxy).await;
+        uas1.receive_request("ACK").await;
+
+        uas2.send(&response(&invite2, 180), proxy).await;
+        uac.receive_response(180).await;
+
+        // The 486 is dropped in favor of the 2XX response
+        uas2.send(&response(&invite2, 200), proxy).await;
+        uac.receive_response(200).await;
+    }
+
+    #[tokio::test]
+    async fn sequential_forking() {
+        let mut uac = Peer::bind().await;
+        let mut uas1 = Peer::bind().await;
+        let mut uas2 = Peer::bind().await;
+
+        let layer = ProxyLayer::new(Targets(vec![
+            target(&uas2.uri("bob"), Some(0.5)),
+            target(&uas1.uri("bob"), Some(1.0)),
+        ]))
+        .with_fork_mode(ForkMode::Sequential {
+            timeout: Duration::from_secs(10),
+        });
+        let (_endpoint, proxy) = proxy(layer).await;
+
+        uac.send(
+            &request("INVITE", "sip:bob@example.com", &uac, "sequential", ""),
+            proxy,
+        )
+        .await;
+        uac.receive_response(100).await;
+
+        let invite1 = uas1.receive_request("INVITE").await;
+
+        // The second target is only tried after the first one failed
+        assert!(
+            timeout(Duration::from_millis(100), uas2.receive())
+                .await
+                .is_err()
+        );
+
+        uas1.send(&response(&invite1, 480), proxy).await;
+        uas1.receive_request("ACK").await;
+
+        let invite2 = uas2.receive_request("INVITE").await;
+        uas2.send(&response(&invite2, 200), proxy).await;
+
+        uac.receive_response(200).await;
+    }
+
+    #[tokio::test]
+    async fn cancel_propagation() {
+        let mut uac = Peer::bind().await;
+        let mut uas = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![target(
+            &uas.uri("bob"),
+            None,
+        )])))
+        .await;
+
+        let invite = request("INVITE", "sip:bob@example.com", &uac, "cancel", "");
+        uac.send(&invite, proxy).await;
+        uac.receive_response(100).await;
+
+        let forwarded = uas.receive_request("INVITE").await;
+        uas.send(&response(&forwarded, 180), proxy).await;
+        uac.receive_response(180).await;
+
+        uac.send(
+            &request("CANCEL", "sip:bob@example.com", &uac, "cancel", ""),
+            proxy,
+        )
+        .await;
+        let ok = uac.receive_response(200).await;
+        assert_eq!(header(&ok, "CSeq"), ["1 CANCEL"]);
+
+        // The CANCEL is sent within the transaction of the forwarded INVITE
+        let cancel = uas.receive_request("CANCEL").await;
+        assert_eq!(header(&cancel, "Via"), header(&forwarded, "Via")[..1]);
+
+        uas.send(&response(&cancel, 200), proxy).await;
+        uas.send(&response(&forwarded, 487), proxy).await;
+        uas.receive_request("ACK").await;
+
+        let terminated = uac.receive_response(487).await;
+        assert_eq!(header(&terminated, "CSeq"), ["1 INVITE"]);
+        uac.send(&ack(&invite, &terminated), proxy).await;
+    }
+
+    #[tokio::test]
+    async fn global_failure_cancels_branches() {
+        let mut uac = Peer::bind().await;
+        let mut uas1 = Peer::bind().await;
+        let mut uas2 = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![
+            target(&uas1.uri("bob"), None),
+            target(&uas2.uri("bob"), None),
+        ])))
+        .await;
+
+        let invite = request("INVITE", "sip:bob@example.com", &uac, "global", "");
+        uac.send(&invite, proxy).await;
+        uac.receive_response(100).await;
+
+        let invite1 = uas1.receive_request("INVITE").await;
+        let invite2 = uas2.receive_request("INVITE").await;
+
+        uas2.send(&response(&invite2, 180), proxy).await;
+        uac.receive_response(180).await;
+
+        uas1.send(&response(&invite1, 603), proxy).await;
+        uas1.receive_request("ACK").await;
+
+        // The 6XX response cancels the other branch, but isn't forwarded before it completed
+        let cancel = uas2.receive_request("CANCEL").await;
+        assert!(
+            timeout(Duration::from_millis(100), uac.receive())
+                .await
+                .is_err()
+        );
+
+        uas2.send(&response(&cancel, 200), proxy).await;
+        uas2.send(&response(&invite2, 487), proxy).await;
+        uas2.receive_request("ACK").await;
+
+        let decline = uac.receive_response(603).await;
+        uac.send(&ack(&invite, &decline), proxy).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timer_c_cancels_branch() {
+        let mut uac = Peer::bind().await;
+        let mut uas = Peer::bind().await;
+
+        let (_endpoint, proxy) = proxy(ProxyLayer::new(Targets(vec![target(
+            &uas.uri("bob"),
+            None,
+        )])))
+        .await;
+
+        let invite = request("INVITE", "sip:bob@example.com", &uac, "timer-c", "");
+        uac.send(&invite, proxy).await;
+        uac.receive_response(100).await;
+
+        let forwarded = uas.receive_request("INVITE").await;
+        uas.send(&response(&forwarded, 180), proxy).await;
+        uac.receive_response(180).await;
+
+        // The branch never sends a final response
+        sleep(TIMER_C).await;
+
+        let cancel = uas.receive_request("CANCEL").await;
+        let request_timeout = uac.receive_response(408).await;
+        uac.send(&ack(&invite, &request_timeout), proxy).await;
+
+        // Final responses of the branch are no longer forwarded
+        uas.send(&response(&cancel, 200), proxy).await;
+        uas.send(&response(&forwarded, 487), proxy).await;
+        uas.receive_request("ACK").await;
+
+        assert!(
+            timeout(Duration::from_millis(100), uac.receive())
+                .await
+                .is_err()
+        );
+    }
+}
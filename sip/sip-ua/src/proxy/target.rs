@@ -0,0 +1,61 @@
+use crate::registrar::{LocationStore, address_of_record};
+use sip_core::IncomingRequest;
+use sip_types::StatusCode;
+use sip_types::header::typed::Routing;
+use sip_types::uri::SipUri;
+use std::sync::Arc;
+
+/// Destination a request is forwarded to by the [`ProxyLayer`](super::ProxyLayer)
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// The new request-URI
+    pub uri: SipUri,
+
+    /// Route set which is prepended to the `Route` headers of the request (e.g. the `Path` of a binding)
+    pub route: Vec<Routing>,
+
+    /// Preference of this target in the range of 0.0 to 1.0, used for sequential forking
+    pub q: Option<f32>,
+}
+
+impl Target {
+    pub fn new(uri: SipUri) -> Self {
+        Self {
+            uri,
+            route: vec![],
+            q: None,
+        }
+    }
+}
+
+/// Resolves the request-URI of a request to the set of targets it is forwarded to
+/// ([RFC3261 Section 16.5](https://datatracker.ietf.org/doc/html/rfc3261#section-16.5))
+///
+/// Only used for requests outside of a dialog which don't contain a `Route` header after route processing.
+#[async_trait::async_trait]
+pub trait TargetLookup: Send + Sync + 'static {
+    /// Returns all targets for the request, or the status code to reject the request with
+    ///
+    /// An empty target set is rejected with `480 Temporarily Unavailable`.
+    async fn lookup(&self, request: &IncomingRequest) -> Result<Vec<Target>, StatusCode>;
+}
+
+/// Use the active bindings of the request-URI's address-of-record as targets
+#[async_trait::async_trait]
+impl TargetLookup for Arc<dyn LocationStore> {
+    async fn lookup(&self, request: &IncomingRequest) -> Result<Vec<Target>, StatusCode> {
+        let aor = address_of_record(&request.line.uri);
+
+        let targets = LocationStore::lookup(&**self, &aor)
+            .await
+            .into_iter()
+            .map(|binding| Target {
+                uri: binding.contact.uri.uri,
+                route: binding.path,
+                q: binding.q,
+            })
+            .collect();
+
+        Ok(targets)
+    }
+}
@@ -1,7 +1,10 @@
 use crate::util::{random_sequence_number, random_string};
+use bytesstr::BytesStr;
 use sip_core::Request;
 use sip_core::transaction::TsxResponse;
-use sip_types::header::typed::{CSeq, CallID, Contact, Expires, FromTo, MinExpires};
+use sip_types::header::typed::{
+    CSeq, CallID, Contact, Expires, FlowTimer, FromTo, MinExpires, Require, Supported,
+};
 use sip_types::uri::{NameAddr, SipUri};
 use sip_types::{CodeKind, Method, Name};
 use std::time::Duration;
@@ -22,6 +25,15 @@ pub struct Registration {
 
     /// Re-registration interval, is set to `expires - 10`
     register_interval: Interval,
+
+    /// Request SIP Outbound (RFC5626) support from the registrar
+    outbound: bool,
+
+    /// The registrar confirmed SIP Outbound support with `Require: outbound`
+    outbound_confirmed: bool,
+
+    /// Keep-alive interval requested by the registrar using the `Flow-Timer` header
+    flow_timer: Option<Duration>,
 }
 
 impl Registration {
@@ -36,9 +48,42 @@ impl Registration {
 
             expires: expiry,
             register_interval: create_reg_interval(expiry),
+            outbound: false,
+            outbound_confirmed: false,
+            flow_timer: None,
+        }
+    }
+
+    /// Request SIP Outbound support from the registrar ([RFC5626](https://datatracker.ietf.org/doc/html/rfc5626))
+    ///
+    /// The contact must contain the `+sip.instance` and `reg-id` parameters.
+    pub fn with_outbound(self) -> Self {
+        Self {
+            outbound: true,
+            ..self
         }
     }
 
+    /// Returns the contact which is bound to the address-of-record
+    pub fn contact(&self) -> &Contact {
+        &self.contact
+    }
+
+    /// Replace the contact used in the following REGISTER requests, e.g. when the transport to the registrar changed
+    pub fn set_contact(&mut self, contact: Contact) {
+        self.contact = contact;
+    }
+
+    /// Returns if the registrar confirmed SIP Outbound support in its last success response
+    pub fn outbound_confirmed(&self) -> bool {
+        self.outbound_confirmed
+    }
+
+    /// Returns the keep-alive interval requested by the registrar using the `Flow-Timer` header
+    pub fn flow_timer(&self) -> Option<Duration> {
+        self.flow_timer
+    }
+
     /// Create a new REGISTER request.
     ///
     /// `remove_binding` must be `false` to create a new binding on the registrar.
@@ -47,6 +92,7 @@ impl Registration {
         let mut request = Request::new(Method::REGISTER, self.registrar.clone());
 
         request.headers.insert_type(Name::FROM, &self.from);
+        request.headers.insert_type(Name::TO, &self.to);
         request.headers.insert_named(&self.call_id);
 
         self.cseq += 1;
@@ -63,6 +109,15 @@ impl Registration {
         request.headers.insert_named(&expires);
         request.headers.insert_named(&self.contact);
 
+        if self.outbound {
+            request
+                .headers
+                .insert_named(&Supported(BytesStr::from_static("outbound")));
+            request
+                .headers
+                .insert_named(&Supported(BytesStr::from_static("path")));
+        }
+
         request
     }
 
@@ -82,6 +137,19 @@ impl Registration {
             }
         }
 
+        if self.outbound {
+            self.outbound_confirmed = response
+                .headers
+                .get_named::<Vec<Require>>()
+                .is_ok_and(|require| require.iter().any(|ext| ext.0 == "outbound"));
+
+            self.flow_timer = response
+                .headers
+                .get_named::<FlowTimer>()
+                .ok()
+                .map(|flow_timer| Duration::from_secs(flow_timer.0.into()));
+        }
+
         if self.to.tag.is_none() {
             self.to.tag = response.base_headers.to.tag;
         }
@@ -121,3 +189,86 @@ fn create_reg_interval(period: Duration) -> Interval {
     register_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
     register_interval
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{TakeRequests, contact, endpoint, send, within};
+    use sip_types::StatusCode;
+
+    /// Send a REGISTER request to a registrar which responds with the given headers
+    async fn register(registration: &mut Registration, headers: &[(Name, &'static str)]) {
+        let (registers, mut incoming) = TakeRequests::new(Method::REGISTER);
+
+        let (registrar_endpoint, registrar) =
+            endpoint("registrar", |builder| builder.add_layer(registers)).await;
+        let (client, _) = endpoint("alice", |_| {}).await;
+
+        registration.registrar = registrar;
+
+        let headers = headers.to_vec();
+        tokio::spawn(async move {
+            let mut request = incoming.recv().await.unwrap();
+
+            let mut response = registrar_endpoint.create_response(&request, StatusCode::OK, None);
+
+            for (name, value) in headers {
+                response.msg.headers.insert(name, value);
+            }
+
+            registrar_endpoint
+                .create_server_tsx(&mut request)
+                .respond(response)
+                .await
+                .unwrap();
+        });
+
+        let response = send(&client, registration.create_register(false)).await;
+        registration.receive_success_response(response);
+    }
+
+    fn registration() -> Registration {
+        let uri: SipUri = "sip:alice@127.0.0.1".parse().unwrap();
+
+        Registration::new(
+            NameAddr::uri(uri.clone()),
+            contact(&uri),
+            uri,
+            Duration::from_secs(300),
+        )
+    }
+
+    #[tokio::test]
+    async fn outbound_confirmed_by_registrar() {
+        let mut registration = registration().with_outbound();
+
+        within(register(
+            &mut registration,
+            &[(Name::REQUIRE, "outbound"), (Name::FLOW_TIMER, "30")],
+        ))
+        .await;
+
+        assert!(registration.outbound_confirmed());
+        assert_eq!(registration.flow_timer(), Some(Duration::from_secs(30)));
+
+        // The registrar stopped supporting outbound
+        within(register(&mut registration, &[])).await;
+
+        assert!(!registration.outbound_confirmed());
+        assert_eq!(registration.flow_timer(), None);
+    }
+
+    #[tokio::test]
+    async fn ignore_outbound_if_not_requested() {
+        let mut registration = registration();
+
+        within(register(
+            &mut registration,
+            &[(Name::REQUIRE, "outbound"), (Name::FLOW_TIMER, "30")],
+        ))
+        .await;
+
+        assert!(!registration.outbound_confirmed());
+        assert_eq!(registration.flow_timer(), None);
+    }
+}
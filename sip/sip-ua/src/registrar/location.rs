@@ -0,0 +1,192 @@
+use bytesstr::BytesStr;
+use parking_lot as pl;
+use sip_types::header::typed::{Contact, Routing};
+use sip_types::uri::SipUri;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+/// A contact bound to an address-of-record by a REGISTER request
+#[derive(Debug, Clone)]
+pub struct Binding {
+    /// Contact as received in the REGISTER request, without the `expires` parameter
+    pub contact: Contact,
+
+    /// Time at which the binding expires, unless it is refreshed
+    pub expires_at: SystemTime,
+
+    /// Preference of this binding (`q` parameter) in the range of 0.0 to 1.0
+    pub q: Option<f32>,
+
+    /// Call-ID of the REGISTER request which last updated the binding
+    pub call_id: BytesStr,
+
+    /// CSeq number of the REGISTER request which last updated the binding
+    pub cseq: u32,
+
+    /// `+sip.instance` parameter of the contact
+    ///
+    /// Bindings with an instance-id are matched using it instead of the contact URI, if both bindings have one.
+    pub instance_id: Option<BytesStr>,
+
+    /// `reg-id` parameter of the contact ([RFC5626](https://datatracker.ietf.org/doc/html/rfc5626))
+    pub reg_id: Option<u32>,
+
+    /// `Path` of the REGISTER request, which must be used as route set when sending requests to the contact
+    pub path: Vec<Routing>,
+
+    /// Address the REGISTER request was received from
+    pub source: SocketAddr,
+}
+
+impl Binding {
+    /// Remaining duration until the binding expires
+    pub fn expires_in(&self, now: SystemTime) -> Duration {
+        self.expires_at.duration_since(now).unwrap_or_default()
+    }
+
+    /// Returns if the binding has expired
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at <= now
+    }
+
+    /// Returns if both bindings refer to the same contact
+    pub fn matches(&self, other: &Binding) -> bool {
+        match (&self.instance_id, &other.instance_id) {
+            (Some(a), Some(b)) => a == b && self.reg_id == other.reg_id,
+            _ => self.contact.uri.uri.compare(&other.contact.uri.uri),
+        }
+    }
+}
+
+/// Storage of all bindings of a registrar
+///
+/// Calls to [`LocationStore::set_bindings`] of the same address-of-record are serialized by the
+/// [`RegistrarLayer`](super::RegistrarLayer), so implementations don't need to deal with concurrent updates of a single binding.
+#[async_trait::async_trait]
+pub trait LocationStore: Send + Sync + 'static {
+    /// Returns all bindings of the address-of-record, expired bindings may be included
+    async fn bindings(&self, aor: &str) -> Vec<Binding>;
+
+    /// Replace all bindings of the address-of-record
+    async fn set_bindings(&self, aor: &str, bindings: Vec<Binding>);
+
+    /// Returns all active bindings of the address-of-record, ordered by their q-value (highest first)
+    async fn lookup(&self, aor: &str) -> Vec<Binding> {
+        let now = SystemTime::now();
+
+        let mut bindings = self.bindings(aor).await;
+        bindings.retain(|binding| !binding.is_expired(now));
+        bindings.sort_by(|a, b| {
+            let (a, b) = (a.q.unwrap_or(1.0), b.q.unwrap_or(1.0));
+            b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+        });
+
+        bindings
+    }
+}
+
+/// [`LocationStore`] that keeps all bindings in memory
+#[derive(Default)]
+pub struct InMemoryLocationStore {
+    bindings: pl::Mutex<HashMap<String, Vec<Binding>>>,
+}
+
+impl InMemoryLocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all address-of-records with at least one active binding
+    pub fn address_of_records(&self) -> Vec<BytesStr> {
+        let now = SystemTime::now();
+
+        self.bindings
+            .lock()
+            .iter()
+            .filter(|(_, bindings)| bindings.iter().any(|binding| !binding.is_expired(now)))
+            .map(|(aor, _)| aor.as_str().into())
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl LocationStore for InMemoryLocationStore {
+    async fn bindings(&self, aor: &str) -> Vec<Binding> {
+        self.bindings.lock().get(aor).cloned().unwrap_or_default()
+    }
+
+    async fn set_bindings(&self, aor: &str, mut bindings: Vec<Binding>) {
+        let now = SystemTime::now();
+        bindings.retain(|binding| !binding.is_expired(now));
+
+        let mut map = self.bindings.lock();
+
+        if bindings.is_empty() {
+            map.remove(aor);
+        } else {
+            map.insert(aor.into(), bindings);
+        }
+    }
+}
+
+/// Returns the canonical address-of-record of the URI (`sip:user@host`), which is used as key in the [`LocationStore`]
+pub fn address_of_record(uri: &SipUri) -> BytesStr {
+    let scheme = if uri.sips { "sips" } else { "sip" };
+    let host = uri.host_port.host.to_string().to_ascii_lowercase();
+
+    match uri.user_part.user() {
+        Some(user) => format!("{scheme}:{user}@{host}").into(),
+        None => format!("{scheme}:{host}").into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sip_types::uri::NameAddr;
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    fn binding(contact: &str, instance_id: Option<&str>) -> Binding {
+        Binding {
+            contact: Contact::new(NameAddr::uri(contact.parse::<SipUri>().unwrap())),
+            expires_at: SystemTime::now() + Duration::from_secs(60),
+            q: None,
+            call_id: "call-id".into(),
+            cseq: 1,
+            instance_id: instance_id.map(BytesStr::from),
+            reg_id: None,
+            path: vec![],
+            source: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5060).into(),
+        }
+    }
+
+    #[test]
+    fn canonical_address_of_record() {
+        let uri: SipUri = "sip:alice@Example.COM:5061;transport=tcp".parse().unwrap();
+
+        assert_eq!(address_of_record(&uri), "sip:alice@example.com");
+    }
+
+    #[test]
+    fn match_bindings_by_uri() {
+        let a = binding("sip:alice@192.0.2.1:5060", None);
+
+        assert!(a.matches(&binding("sip:alice@192.0.2.1:5060", None)));
+        assert!(!a.matches(&binding("sip:alice@192.0.2.2:5060", None)));
+    }
+
+    #[test]
+    fn match_bindings_by_instance_id() {
+        let a = binding("sip:alice@192.0.2.1:5060", Some("<urn:uuid:1>"));
+
+        assert!(a.matches(&binding("sip:alice@192.0.2.2:5060", Some("<urn:uuid:1>"))));
+        assert!(!a.matches(&binding("sip:alice@192.0.2.1:5060", Some("<urn:uuid:2>"))));
+
+        // Without an instance-id on both sides the contact URIs are compared
+        assert!(a.matches(&binding("sip:alice@192.0.2.1:5060", None)));
+        assert!(!a.matches(&binding("sip:alice@192.0.2.2:5060", None)));
+        assert!(binding("sip:alice@192.0.2.1:5060", None).matches(&a));
+    }
+}
@@ -0,0 +1,397 @@
+//! Registrar accepting REGISTER requests ([RFC3261 Section 10.3](https://datatracker.ietf.org/doc/html/rfc3261#section-10.3))
+
+use sip_core::transport::OutgoingResponse;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Contact, Expires, MinExpires, Routing, Supported};
+use sip_types::{Headers, Method, Name, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod location;
+
+pub use location::{Binding, InMemoryLocationStore, LocationStore, address_of_record};
+
+/// Layer which processes REGISTER requests and stores the resulting bindings in a [`LocationStore`]
+///
+/// Requests with a `Path` header are accepted and the path is stored in the [`Binding`].
+/// Contacts with a `+sip.instance` parameter are matched using the instance-id instead of their URI.
+pub struct RegistrarLayer {
+    store: Arc<dyn LocationStore>,
+
+    /// Serializes updates of the location store
+    update_lock: tokio::sync::Mutex<()>,
+
+    min_expires: Duration,
+    max_expires: Duration,
+    default_expires: Duration,
+}
+
+/// Contacts of a REGISTER request
+enum RegisterContacts {
+    /// No Contact header, only query the current bindings
+    None,
+
+    /// `Contact: *` removes all bindings
+    Wildcard,
+
+    List(Vec<Contact>),
+}
+
+impl RegistrarLayer {
+    pub fn new(store: Arc<dyn LocationStore>) -> Self {
+        Self {
+            store,
+            update_lock: tokio::sync::Mutex::new(()),
+            min_expires: Duration::from_secs(60),
+            max_expires: Duration::from_secs(7200),
+            default_expires: Duration::from_secs(3600),
+        }
+    }
+
+    /// Reject bindings with a shorter expiry with `423 Interval Too Brief`. Is 60 seconds by default
+    pub fn with_min_expires(self, min_expires: Duration) -> Self {
+        Self {
+            min_expires,
+            ..self
+        }
+    }
+
+    /// Limit the expiry of bindings to the given duration. Is 2 hours by default
+    pub fn with_max_expires(self, max_expires: Duration) -> Self {
+        Self {
+            max_expires,
+            ..self
+        }
+    }
+
+    /// Expiry of bindings when the REGISTER request doesn't specify one. Is 1 hour by default
+    pub fn with_default_expires(self, default_expires: Duration) -> Self {
+        Self {
+            default_expires,
+            ..self
+        }
+    }
+
+    /// Returns the location store containing all bindings
+    pub fn location_store(&self) -> &Arc<dyn LocationStore> {
+        &self.store
+    }
+
+    async fn handle_register(
+        &self,
+        endpoint: &Endpoint,
+        request: &IncomingRequest,
+    ) -> OutgoingResponse {
+        let reject = |code| endpoint.create_response(request, code, None);
+
+        let Ok(contacts) = parse_contacts(&request.headers) else {
+            return reject(StatusCode::BAD_REQUEST);
+        };
+
+        let path = match request.headers.try_get::<Vec<Routing>>(Name::PATH) {
+            Some(Ok(path)) => path,
+            Some(Err(_)) => return reject(StatusCode::BAD_REQUEST),
+            None => vec![],
+        };
+
+        let expires = match request.headers.try_get_named::<Expires>() {
+            Some(Ok(expires)) => Some(Duration::from_secs(expires.0.into())),
+            Some(Err(_)) => return reject(StatusCode::BAD_REQUEST),
+            None => None,
+        };
+
+        let aor = address_of_record(&request.base_headers.to.uri.uri);
+        let call_id = &request.base_headers.call_id.0;
+        let cseq = request.base_headers.cseq.cseq;
+
+        let _update_guard = self.update_lock.lock().await;
+
+        let now = SystemTime::now();
+        let mut bindings = self.store.bindings(&aor).await;
+        bindings.retain(|binding| !binding.is_expired(now));
+
+        // Requests of the same Call-ID must arrive in order (RFC3261 Section 10.3 Step 7)
+        let is_out_of_order =
+            |binding: &Binding| binding.call_id == *call_id && cseq <= binding.cseq;
+
+        match contacts {
+            RegisterContacts::None => {}
+            RegisterContacts::Wildcard => {
+                if expires != Some(Duration::ZERO) {
+                    return reject(StatusCode::BAD_REQUEST);
+                }
+
+                if bindings.iter().any(is_out_of_order) {
+                    return reject(StatusCode::SERVER_INTERNAL_ERROR);
+                }
+
+                bindings.clear();
+                self.store.set_bindings(&aor, vec![]).await;
+            }
+            RegisterContacts::List(contacts) => {
+                for mut contact in contacts {
+                    let contact_expires = match contact.params.take("expires") {
+                        Some(expires) => match expires.parse() {
+                            Ok(expires) => Some(Duration::from_secs(expires)),
+                            Err(_) => return reject(StatusCode::BAD_REQUEST),
+                        },
+                        None => None,
+                    };
+
+                    let expires = contact_expires.or(expires).unwrap_or(self.default_expires);
+
+                    if !expires.is_zero() && expires < self.min_expires {
+                        let mut response = reject(StatusCode::INTERVAL_TOO_BRIEF);
+                        response
+                            .msg
+                            .headers
+                            .insert_named(&MinExpires(self.min_expires.as_secs() as u32));
+
+                        return response;
+                    }
+
+                    let expires = expires.min(self.max_expires);
+
+                    let binding = Binding {
+                        q: contact.params.get_val("q").and_then(|q| q.parse().ok()),
+                        instance_id: contact.params.get_val("+sip.instance").cloned(),
+                        reg_id: contact
+                            .params
+                            .get_val("reg-id")
+                            .and_then(|reg_id| reg_id.parse().ok()),
+                        contact,
+                        expires_at: now + expires,
+                        call_id: call_id.clone(),
+                        cseq,
+                        path: path.clone(),
+                        source: request.tp_info.source,
+                    };
+
+                    match bindings
+                        .iter()
+                        .position(|existing| existing.matches(&binding))
+                    {
+                        Some(i) => {
+                            if is_out_of_order(&bindings[i]) {
+                                return reject(StatusCode::SERVER_INTERNAL_ERROR);
+                            }
+
+                            if expires.is_zero() {
+                                bindings.remove(i);
+                            } else {
+                                bindings[i] = binding;
+                            }
+                        }
+                        None if !expires.is_zero() => bindings.push(binding),
+                        None => {}
+                    }
+                }
+
+                self.store.set_bindings(&aor, bindings.clone()).await;
+            }
+        }
+
+        let mut response = endpoint.create_response(request, StatusCode::OK, None);
+
+        for binding in bindings {
+            let expires = binding.expires_in(now).as_secs();
+
+            let mut contact = binding.contact;
+            contact.params.push_or_edit("expires", expires.to_string());
+
+            response.msg.headers.insert_named(&contact);
+        }
+
+        // Echo the Path if the UA supports it (RFC3327 Section 5.3)
+        let supports_path = request
+            .headers
+            .get_named::<Vec<Supported>>()
+            .is_ok_and(|supported| supported.iter().any(|s| s.0.eq_ignore_ascii_case("path")));
+
+        if supports_path && !path.is_empty() {
+            response.msg.headers.insert_type(Name::PATH, &path);
+        }
+
+        response
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for RegistrarLayer {
+    fn name(&self) -> &'static str {
+        "registrar"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::REGISTER);
+        endpoint.add_supported("path");
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::REGISTER {
+            return;
+        }
+
+        let mut request = request.take();
+
+        let response = self.handle_register(endpoint, &request).await;
+
+        if let Err(e) = endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+        {
+            log::warn!("Failed to respond to REGISTER request, {e:?}");
+        }
+    }
+}
+
+fn parse_contacts(headers: &Headers) -> Result<RegisterContacts, ()> {
+    let contacts: Vec<&str> = headers
+        .iter()
+        .filter(|(name, _)| **name == Name::CONTACT)
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    if contacts.contains(&"*") {
+        // The wildcard must be the only contact
+        return if contacts.len() == 1 {
+            Ok(RegisterContacts::Wildcard)
+        } else {
+            Err(())
+        };
+    }
+
+    match headers.try_get_named::<Vec<Contact>>() {
+        Some(Ok(contacts)) => Ok(RegisterContacts::List(contacts)),
+        Some(Err(_)) => Err(()),
+        None => Ok(RegisterContacts::None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{contact, endpoint, request, send};
+    use sip_types::header::typed::{CSeq, CallID};
+    use sip_types::uri::SipUri;
+
+    struct Registrar {
+        store: Arc<InMemoryLocationStore>,
+        client: Endpoint,
+        alice: SipUri,
+        registrar: SipUri,
+        _endpoint: Endpoint,
+    }
+
+    async fn registrar() -> Registrar {
+        let store = Arc::new(InMemoryLocationStore::new());
+
+        let (registrar_endpoint, registrar) = endpoint("alice", |builder| {
+            builder.add_layer(RegistrarLayer::new(store.clone()));
+        })
+        .await;
+        let (client, alice) = endpoint("alice", |_| {}).await;
+
+        Registrar {
+            store,
+            client,
+            alice,
+            registrar,
+            _endpoint: registrar_endpoint,
+        }
+    }
+
+    impl Registrar {
+        async fn register(
+            &self,
+            call_id: &str,
+            cseq: u32,
+            expires: Option<u32>,
+            contacts: &[&str],
+        ) -> StatusCode {
+            let mut request = request(Method::REGISTER, &self.alice, &self.registrar);
+            request.headers.remove(&Name::CALL_ID);
+            request.headers.remove(&Name::CSEQ);
+            request.headers.insert_named(&CallID::new(call_id));
+            request
+                .headers
+                .insert_named(&CSeq::new(cseq, Method::REGISTER));
+
+            if let Some(expires) = expires {
+                request.headers.insert_named(&Expires(expires));
+            }
+
+            for contact in contacts {
+                request.headers.insert(Name::CONTACT, contact);
+            }
+
+            send(&self.client, request).await.line.code
+        }
+
+        async fn bindings(&self) -> Vec<Binding> {
+            self.store.lookup(&address_of_record(&self.registrar)).await
+        }
+    }
+
+    #[tokio::test]
+    async fn wildcard_removes_all_bindings() {
+        let registrar = registrar().await;
+        let contact = format!("<{:?}>", registrar.alice);
+
+        let code = registrar.register("a", 1, None, &[&contact]).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(registrar.bindings().await.len(), 1);
+
+        // The wildcard is only valid with Expires: 0 and as the only contact
+        let code = registrar.register("a", 2, None, &["*"]).await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+
+        let code = registrar
+            .register("a", 3, Some(0), &[&format!("*, {contact}")])
+            .await;
+        assert_eq!(code, StatusCode::BAD_REQUEST);
+        assert_eq!(registrar.bindings().await.len(), 1);
+
+        let code = registrar.register("a", 4, Some(0), &["*"]).await;
+        assert_eq!(code, StatusCode::OK);
+        assert!(registrar.bindings().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_too_brief_expires() {
+        let registrar = registrar().await;
+
+        let mut request = request(Method::REGISTER, &registrar.alice, &registrar.registrar);
+        request.headers.insert_named(&Expires(30));
+        request.headers.insert_named(&contact(&registrar.alice));
+
+        let response = send(&registrar.client, request).await;
+
+        assert_eq!(response.line.code, StatusCode::INTERVAL_TOO_BRIEF);
+        assert_eq!(response.headers.get_named::<MinExpires>().unwrap().0, 60);
+        assert!(registrar.bindings().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_out_of_order_requests() {
+        let registrar = registrar().await;
+        let contact = format!("<{:?}>", registrar.alice);
+
+        let code = registrar.register("a", 2, None, &[&contact]).await;
+        assert_eq!(code, StatusCode::OK);
+
+        // Same Call-ID with a lower or equal CSeq
+        let code = registrar.register("a", 2, Some(0), &[&contact]).await;
+        assert_eq!(code, StatusCode::SERVER_INTERNAL_ERROR);
+        let code = registrar.register("a", 1, Some(0), &["*"]).await;
+        assert_eq!(code, StatusCode::SERVER_INTERNAL_ERROR);
+        assert_eq!(registrar.bindings().await.len(), 1);
+
+        // Another Call-ID is not ordered against the existing binding
+        let code = registrar.register("b", 1, Some(0), &[&contact]).await;
+        assert_eq!(code, StatusCode::OK);
+        assert!(registrar.bindings().await.is_empty());
+    }
+}
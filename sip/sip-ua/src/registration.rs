@@ -1,16 +1,26 @@
+use crate::call_record::{CONTENT_TYPE_VQ_RTCPXR, EVENT_VQ_RTCPXR};
+use crate::message::{SendMessageError, send_message, send_request};
+use crate::mwi::MwiSubscription;
 use crate::register::Registration as RegistrationProto;
+use crate::subscription::{SubscribeError, Subscription, UnsolicitedNotifications};
 use crate::{
-    MediaBackend,
+    CallRecord, MediaBackend,
     outbound_call::{MakeCallError, OutboundCall},
 };
+use bytes::Bytes;
+use parking_lot as pl;
 use sip_auth::{ClientAuthenticator, RequestParts, ResponseParts};
-use sip_core::{Endpoint, transport::TargetTransportInfo};
+use sip_core::transport::{ServerEntry, TargetTransportInfo, TpHandle};
+use sip_core::{Endpoint, StunError};
 use sip_types::{
-    StatusCode,
-    header::typed::Contact,
-    uri::{NameAddr, SipUri},
+    CodeKind, Method, StatusCode,
+    header::typed::{Contact, ContentType, Event, RetryAfter},
+    uri::{NameAddr, SipUri, params::Param},
 };
-use std::{sync::Arc, time::Duration};
+use std::future::pending;
+use std::net::SocketAddr;
+use std::{io, sync::Arc, time::Duration};
+use tokio::time::{Instant, Interval, interval_at, sleep};
 use tokio::{select, sync::watch};
 
 /// Any errors that might be encountered while registering with a SIP registrar.
@@ -22,6 +32,27 @@ pub enum RegisterError<A> {
     Auth(#[source] A),
     #[error("Got response to REGISTER with unexpected status code {0:?}")]
     Failed(StatusCode),
+    #[error("Registrar is unavailable, retry after {retry_after:?}")]
+    ServiceUnavailable { retry_after: Option<Duration> },
+}
+
+/// Current state of the binding of a [`Registration`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingState {
+    /// The binding is active on the registrar server at the given address
+    Registered {
+        server: SocketAddr,
+        /// The server is the most preferred one of the registrar
+        primary: bool,
+    },
+    /// No binding is active
+    Unregistered,
+}
+
+impl BindingState {
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Self::Registered { .. })
+    }
 }
 
 /// Configuration used to bind an account to a SIP registrar
@@ -42,6 +73,22 @@ pub struct RegistrarConfig {
 
     /// Override the default expiry duration
     pub expiry: Option<Duration>,
+
+    /// Instance ID of this user agent (e.g. `urn:uuid:...`), sent in the `+sip.instance` Contact parameter
+    pub instance_id: Option<String>,
+
+    /// Registration ID of the flow to the registrar, enables SIP Outbound ([RFC5626](https://datatracker.ietf.org/doc/html/rfc5626))
+    /// when set together with [`instance_id`](Self::instance_id)
+    ///
+    /// The flow is kept alive using double-CRLF pings on connection oriented transports and STUN binding requests on UDP.
+    /// When the flow fails, a new one is created and the binding is registered again.
+    pub reg_id: Option<u32>,
+
+    /// Interval in which registering with the primary server is attempted while bound to a fallback server
+    ///
+    /// Servers of the registrar are resolved using NAPTR/SRV records. When a server fails, the binding is moved to
+    /// the next one. Defaults to 5 minutes.
+    pub failback_interval: Option<Duration>,
 }
 
 impl RegistrarConfig {
@@ -53,6 +100,9 @@ impl RegistrarConfig {
             override_id: None,
             override_contact: None,
             expiry: None,
+            instance_id: None,
+            reg_id: None,
+            failback_interval: None,
         }
     }
 
@@ -87,6 +137,31 @@ impl RegistrarConfig {
             ..self
         }
     }
+
+    /// Set the instance ID of this user agent, see [`RegistrarConfig::instance_id`]
+    pub fn with_instance_id(self, instance_id: String) -> Self {
+        Self {
+            instance_id: Some(instance_id),
+            ..self
+        }
+    }
+
+    /// Enable SIP Outbound using the given instance ID and registration ID, see [`RegistrarConfig::reg_id`]
+    pub fn with_outbound(self, instance_id: String, reg_id: u32) -> Self {
+        Self {
+            instance_id: Some(instance_id),
+            reg_id: Some(reg_id),
+            ..self
+        }
+    }
+
+    /// Override the default failback interval, see [`RegistrarConfig::failback_interval`]
+    pub fn with_failback_interval(self, failback_interval: Duration) -> Self {
+        Self {
+            failback_interval: Some(failback_interval),
+            ..self
+        }
+    }
 }
 
 /// An active registration with a SIP registrar.
@@ -94,18 +169,49 @@ impl RegistrarConfig {
 /// Dropping this type will remove the registration from the SIP registrar.
 pub struct Registration {
     endpoint: Endpoint,
-    is_registered: watch::Receiver<bool>,
+    state: watch::Receiver<BindingState>,
     inner: Arc<RegistrationInner>,
 }
 
 pub(crate) struct RegistrationInner {
     id: NameAddr,
-    contact: Contact,
+    /// Contact of the binding, its host is updated when the transport to the registrar changes
+    contact: pl::Mutex<Contact>,
+    /// The host of the contact is the address of the transport to the registrar
+    contact_from_transport: bool,
+    /// Contact used in dialog-forming requests, without `reg-id` and with the `ob` URI parameter when using SIP Outbound
+    ///
+    /// Updated after every successful REGISTER, since the registrar may stop confirming outbound support.
+    dialog_contact: pl::Mutex<Contact>,
+    outbound: bool,
     registrar: SipUri,
     // the expiry we request, not the one that actually was returned by the server
     request_expiry: Duration,
+    failback_interval: Duration,
+
+    state: watch::Sender<BindingState>,
+}
+
+impl RegistrationInner {
+    /// Publish the new binding state after a successful REGISTER
+    fn registered(&self, registration: &RegistrationProto, targets: &RegistrarTargets) {
+        *self.contact.lock() = registration.contact().clone();
+        *self.dialog_contact.lock() = create_dialog_contact(registration);
+        self.state.send_replace(targets.binding_state());
+    }
+}
+
+/// Create the Contact used in dialog-forming requests from the Contact of the binding
+fn create_dialog_contact(registration: &RegistrationProto) -> Contact {
+    // The reg-id must only be used in REGISTER requests (RFC5626 Section 4.2.1)
+    let mut dialog_contact = registration.contact().clone();
+    dialog_contact.params.take("reg-id");
 
-    is_registered: watch::Sender<bool>,
+    if registration.outbound_confirmed() {
+        dialog_contact.uri.uri.uri_params.push(Param::name("ob"));
+    }
+
+    dialog_contact
 }
 
 impl Registration {
@@ -127,13 +233,33 @@ impl Registration {
             }
         });
 
-        let (transport, remote_addr) = endpoint.select_transport(&config.registrar).await?;
-        let contact = config.override_contact.clone().unwrap_or_else(|| {
+        let contact_from_transport = config.override_contact.is_none();
+
+        let mut targets =
+            RegistrarTargets::resolve(&endpoint, config.registrar.clone(), contact_from_transport)
+                .await?;
+        targets.connect(&endpoint).await?;
+
+        let mut contact = config.override_contact.clone().unwrap_or_else(|| {
+            let (transport, _) = targets.transport_info.transport.as_ref().unwrap();
+
             Contact::new(NameAddr::uri(
                 SipUri::new(transport.sent_by().into()).user(config.username.clone().into()),
             ))
         });
 
+        let outbound = config.instance_id.is_some() && config.reg_id.is_some();
+
+        if let Some(instance_id) = &config.instance_id {
+            contact
+                .params
+                .push_or_edit("+sip.instance", format!("<{instance_id}>"));
+
+            if let Some(reg_id) = config.reg_id {
+                contact.params.push_or_edit("reg-id", reg_id.to_string());
+            }
+        }
+
         let mut registration = RegistrationProto::new(
             id.clone(),
             contact.clone(),
@@ -141,47 +267,53 @@ impl Registration {
             Duration::from_secs(300),
         );
 
-        let mut target_transport_info = TargetTransportInfo {
-            via_host_port: Some(transport.sent_by().into()),
-            transport: Some((transport, remote_addr)),
-        };
+        if outbound {
+            registration = registration.with_outbound();
+        }
 
-        register(
+        register_with_failover(
             &endpoint,
-            &mut target_transport_info,
+            &mut targets,
             &mut registration,
             &mut authenticator,
-            false,
         )
         .await?;
 
+        let dialog_contact = create_dialog_contact(&registration);
+
         // keep alive
-        let (tx, rx) = watch::channel(true);
+        let (tx, rx) = watch::channel(targets.binding_state());
         let inner = Arc::new(RegistrationInner {
             id,
-            contact,
+            contact: pl::Mutex::new(registration.contact().clone()),
+            contact_from_transport,
+            dialog_contact: pl::Mutex::new(dialog_contact),
+            outbound,
             registrar: config.registrar,
             request_expiry: config.expiry.unwrap_or(Duration::from_secs(300)),
-            is_registered: tx,
+            failback_interval: config
+                .failback_interval
+                .unwrap_or(DEFAULT_FAILBACK_INTERVAL),
+            state: tx,
         });
 
         tokio::spawn(keep_alive_task(
             endpoint.clone(),
             registration,
-            target_transport_info,
+            targets,
             authenticator,
             inner.clone(),
         ));
 
         Ok(Self {
             endpoint,
-            is_registered: rx,
+            state: rx,
             inner,
         })
     }
 
     /// Make a call to the user on the registrar this `Registration` is bound to
-    pub async fn make_call<A: ClientAuthenticator, M: MediaBackend>(
+    pub async fn make_call<A: ClientAuthenticator + Send + 'static, M: MediaBackend>(
         &self,
         target: String,
         authenticator: A,
@@ -197,7 +329,7 @@ impl Registration {
     }
 
     /// Make a call to the specified target uri using this registrations local user identity
-    pub async fn make_call_to_uri<A: ClientAuthenticator, M: MediaBackend>(
+    pub async fn make_call_to_uri<A: ClientAuthenticator + Send + 'static, M: MediaBackend>(
         &self,
         target: SipUri,
         authenticator: A,
@@ -207,26 +339,146 @@ impl Registration {
             self.endpoint.clone(),
             authenticator,
             self.inner.id.clone(),
-            self.inner.contact.clone(),
+            self.dialog_contact(),
             target,
             media,
         )
         .await
     }
 
+    /// Send a MESSAGE request to the user on the registrar this `Registration` is bound to
+    pub async fn send_message<A: ClientAuthenticator>(
+        &self,
+        target: String,
+        content_type: ContentType,
+        body: impl Into<Bytes>,
+        authenticator: A,
+    ) -> Result<(), SendMessageError<A::Error>> {
+        let target = if let Ok(target) = target.parse() {
+            target
+        } else {
+            self.inner.registrar.clone().user(target.into())
+        };
+
+        self.send_message_to_uri(target, content_type, body, authenticator)
+            .await
+    }
+
+    /// Send a MESSAGE request to the specified target uri using this registrations local user identity
+    pub async fn send_message_to_uri<A: ClientAuthenticator>(
+        &self,
+        target: SipUri,
+        content_type: ContentType,
+        body: impl Into<Bytes>,
+        authenticator: A,
+    ) -> Result<(), SendMessageError<A::Error>> {
+        send_message(
+            &self.endpoint,
+            authenticator,
+            self.inner.id.clone(),
+            target,
+            content_type,
+            body.into(),
+        )
+        .await
+    }
+
+    /// Send the [`CallRecord`] as RFC6035 `vq-rtcpxr` session report to the given collector using a PUBLISH request
+    pub async fn publish_call_record<A: ClientAuthenticator>(
+        &self,
+        collector: SipUri,
+        record: &CallRecord,
+        authenticator: A,
+    ) -> Result<(), SendMessageError<A::Error>> {
+        send_request(
+            &self.endpoint,
+            authenticator,
+            Method::PUBLISH,
+            Some(Event::new(EVENT_VQ_RTCPXR)),
+            self.inner.id.clone(),
+            collector,
+            CONTENT_TYPE_VQ_RTCPXR,
+            record.to_vq_rtcpxr().into(),
+        )
+        .await
+    }
+
+    /// Subscribe to the message-summary event package of the user to receive message waiting indications
+    ///
+    /// Unsolicited message-summary NOTIFY requests sent to this user are received as well. If the SUBSCRIBE
+    /// request is rejected because the server doesn't support it, only these are received.
+    ///
+    /// Requires the [`SubscriptionLayer`](crate::subscription::SubscriptionLayer) to be added to the endpoint.
+    pub async fn subscribe_mwi<A: ClientAuthenticator + Send + 'static>(
+        &self,
+        authenticator: A,
+    ) -> Result<MwiSubscription, SubscribeError<A::Error>> {
+        let dialog_contact = self.dialog_contact();
+
+        let users = [&self.inner.id.uri, &dialog_contact.uri.uri]
+            .into_iter()
+            .filter_map(|uri| uri.user_part.user().cloned())
+            .collect();
+
+        // Listen for unsolicited NOTIFYs before subscribing, so none are missed
+        let unsolicited = UnsolicitedNotifications::new(self.endpoint.clone(), users);
+
+        let subscription = Subscription::subscribe(
+            self.endpoint.clone(),
+            authenticator,
+            self.inner.id.clone(),
+            dialog_contact,
+            self.inner.id.uri.clone(),
+            None,
+        )
+        .await;
+
+        let subscription = match subscription {
+            Ok(subscription) => Some(subscription),
+            Err(SubscribeError::Failed(code))
+                if code == StatusCode::BAD_EVENT
+                    || code == StatusCode::METHOD_NOT_ALLOWED
+                    || code == StatusCode::NOT_IMPLMENTED =>
+            {
+                log::debug!(
+                    "message-summary SUBSCRIBE rejected with {code:?}, only receiving unsolicited NOTIFYs"
+                );
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(MwiSubscription {
+            subscription,
+            unsolicited,
+        })
+    }
+
+    /// The identity (address-of-record) of this registration
+    pub fn id(&self) -> &NameAddr {
+        &self.inner.id
+    }
+
+    /// The Contact used in dialog-forming requests sent using this registration
+    pub fn dialog_contact(&self) -> Contact {
+        self.inner.dialog_contact.lock().clone()
+    }
+
     /// Returns if the binding is still active
     pub fn is_registered(&mut self) -> bool {
-        *self.is_registered.borrow_and_update()
+        self.state.borrow_and_update().is_registered()
+    }
+
+    /// Returns a receiver of the current [`BindingState`], which is updated when the binding changes or fails
+    pub fn binding_state(&self) -> watch::Receiver<BindingState> {
+        self.state.clone()
     }
 
     /// Returns once the registration has failed.
     ///
     /// The failure state is permanent and the registration can be retried using [`Registration::retry_register`]
     pub async fn wait_for_registration_failure(&mut self) {
-        let _ = self
-            .is_registered
-            .wait_for(|is_registered| !(*is_registered))
-            .await;
+        let _ = self.state.wait_for(|state| !state.is_registered()).await;
     }
 
     /// Retry registering with the registrar.
@@ -243,27 +495,38 @@ impl Registration {
 
         let mut registration = RegistrationProto::new(
             self.inner.id.clone(),
-            self.inner.contact.clone(),
+            self.inner.contact.lock().clone(),
             self.inner.registrar.clone(),
             self.inner.request_expiry,
         );
 
-        let mut target_transport_info = TargetTransportInfo::default();
+        if self.inner.outbound {
+            registration = registration.with_outbound();
+        }
+
+        let mut targets = RegistrarTargets::resolve(
+            &self.endpoint,
+            self.inner.registrar.clone(),
+            self.inner.contact_from_transport,
+        )
+        .await?;
+        targets.connect(&self.endpoint).await?;
 
-        register(
+        register_with_failover(
             &self.endpoint,
-            &mut target_transport_info,
+            &mut targets,
             &mut registration,
             &mut authenticator,
-            false,
         )
         .await?;
 
+        self.inner.registered(&registration, &targets);
+
         // keep alive
         tokio::spawn(keep_alive_task(
             self.endpoint.clone(),
             registration,
-            target_transport_info,
+            targets,
             authenticator,
             self.inner.clone(),
         ));
@@ -272,43 +535,124 @@ impl Registration {
     }
 }
 
+enum KeepAliveEvent {
+    Refresh,
+    Failback,
+    FlowFailed,
+}
+
 async fn keep_alive_task<A: ClientAuthenticator>(
     endpoint: Endpoint,
     mut registration: RegistrationProto,
-    mut target_transport_info: TargetTransportInfo,
+    mut targets: RegistrarTargets,
     mut authenticator: A,
     inner: Arc<RegistrationInner>,
 ) {
+    let mut flow = if inner.outbound {
+        Flow::new(&registration, &targets.transport_info)
+    } else {
+        None
+    };
+
+    let mut failback = interval_at(
+        Instant::now() + inner.failback_interval,
+        inner.failback_interval,
+    );
+    failback.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
     loop {
-        select! {
-            _ = inner.is_registered.closed() => {
+        let event = select! {
+            _ = inner.state.closed() => {
                 // Registration dropped, exit loop
                 break;
             }
-            _ = registration.wait_for_expiry() => {}
+            _ = registration.wait_for_expiry() => KeepAliveEvent::Refresh,
+            _ = failback.tick(), if !targets.is_primary() => KeepAliveEvent::Failback,
+            e = wait_for_flow_failure(&endpoint, flow.as_mut()) => {
+                log::warn!("Flow to registrar failed, {e}");
+                KeepAliveEvent::FlowFailed
+            }
+        };
+
+        match event {
+            KeepAliveEvent::Refresh => {
+                match register_with_failover(
+                    &endpoint,
+                    &mut targets,
+                    &mut registration,
+                    &mut authenticator,
+                )
+                .await
+                {
+                    Ok(()) => {
+                        inner.registered(&registration, &targets);
+
+                        if inner.outbound {
+                            match &mut flow {
+                                Some(flow) if flow.uses(&targets.transport_info) => {
+                                    flow.set_flow_timer(registration.flow_timer());
+                                }
+                                _ => flow = Flow::new(&registration, &targets.transport_info),
+                            }
+                        }
+
+                        continue;
+                    }
+                    Err(e) => {
+                        log::warn!("REGISTER request to refresh binding failed: {e}");
+
+                        if flow.is_none() {
+                            inner.state.send_replace(BindingState::Unregistered);
+                            continue;
+                        }
+                    }
+                }
+            }
+            KeepAliveEvent::Failback => {
+                if try_failback(
+                    &endpoint,
+                    &mut targets,
+                    &mut registration,
+                    &mut authenticator,
+                )
+                .await
+                {
+                    inner.registered(&registration, &targets);
+
+                    if inner.outbound {
+                        flow = Flow::new(&registration, &targets.transport_info);
+                    }
+                }
+
+                continue;
+            }
+            KeepAliveEvent::FlowFailed => {}
         }
 
-        // Refresh binding
-        if let Err(e) = register(
+        // The flow is no longer usable, replace it with a new one
+        inner.state.send_replace(BindingState::Unregistered);
+
+        flow = recover_flow(
             &endpoint,
-            &mut target_transport_info,
+            &mut targets,
             &mut registration,
             &mut authenticator,
-            false,
+            &inner,
         )
-        .await
-        {
-            inner.is_registered.send_replace(false);
-            log::warn!("REGISTER request to refresh binding failed: {e}");
-        } else {
-            inner.is_registered.send_replace(true);
+        .await;
+
+        if flow.is_none() {
+            // Registration dropped while recovering
+            break;
         }
+
+        inner.registered(&registration, &targets);
     }
 
     // Remove binding
     if let Err(e) = register(
         &endpoint,
-        &mut target_transport_info,
+        &mut targets.transport_info,
         &mut registration,
         &mut authenticator,
         true,
@@ -318,7 +662,434 @@ async fn keep_alive_task<A: ClientAuthenticator>(
         log::warn!("REGISTER request to remove binding failed: {e}");
     }
 
-    inner.is_registered.send_replace(false);
+    inner.state.send_replace(BindingState::Unregistered);
+}
+
+/// Create a new flow to the registrar and register the binding using it
+/// ([RFC5626 Section 4.5](https://datatracker.ietf.org/doc/html/rfc5626#section-4.5))
+///
+/// Retries with an exponential backoff until it succeeds. Returns `None` if the registration was dropped.
+async fn recover_flow<A: ClientAuthenticator>(
+    endpoint: &Endpoint,
+    targets: &mut RegistrarTargets,
+    registration: &mut RegistrationProto,
+    authenticator: &mut A,
+    inner: &RegistrationInner,
+) -> Option<Flow> {
+    let mut consecutive_failures = 0;
+
+    loop {
+        // Select a new transport to create a new flow
+        match targets.connect(endpoint).await {
+            Ok(()) => {
+                match register_with_failover(endpoint, targets, registration, authenticator).await {
+                    Ok(()) => {
+                        if let Some(flow) = Flow::new(registration, &targets.transport_info) {
+                            return Some(flow);
+                        }
+                    }
+                    Err(e) => log::warn!("Failed to register binding using a new flow: {e}"),
+                }
+            }
+            Err(e) => log::warn!("Failed to create a new flow: {e}"),
+        }
+
+        let delay = flow_recovery_delay(consecutive_failures);
+        consecutive_failures += 1;
+
+        log::debug!("Retrying to create a new flow in {delay:?}");
+
+        select! {
+            _ = inner.state.closed() => return None,
+            _ = sleep(delay) => {}
+        }
+    }
+}
+
+const DEFAULT_FAILBACK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Servers of the registrar resolved using NAPTR/SRV records, ordered by preference
+struct RegistrarTargets {
+    registrar: SipUri,
+    servers: Vec<ServerEntry>,
+
+    /// Index of the server currently used
+    current: usize,
+    /// Transport to the current server
+    transport_info: TargetTransportInfo,
+
+    /// Use the address of the transport as host of the binding's contact
+    contact_from_transport: bool,
+}
+
+impl RegistrarTargets {
+    async fn resolve(
+        endpoint: &Endpoint,
+        registrar: SipUri,
+        contact_from_transport: bool,
+    ) -> Result<Self, sip_core::Error> {
+        let servers = endpoint.resolve_servers(&registrar).await?;
+
+        Ok(Self {
+            registrar,
+            servers,
+            current: 0,
+            transport_info: TargetTransportInfo::default(),
+            contact_from_transport,
+        })
+    }
+
+    /// Make the contact of the binding reachable using the transport, unless the contact was configured by the user
+    fn update_contact(
+        &self,
+        registration: &mut RegistrationProto,
+        transport_info: &TargetTransportInfo,
+    ) {
+        if !self.contact_from_transport {
+            return;
+        }
+
+        if let Some((transport, _)) = &transport_info.transport {
+            let mut contact = registration.contact().clone();
+            contact.uri.uri.host_port = transport.sent_by().into();
+            registration.set_contact(contact);
+        }
+    }
+
+    /// Select a transport to the most preferred server which isn't blacklisted, only considering the first `count` servers
+    ///
+    /// Servers which failed are blacklisted by the endpoint, which also blacklists servers it fails to connect to.
+    async fn select(
+        &self,
+        endpoint: &Endpoint,
+        count: usize,
+    ) -> Option<(usize, TargetTransportInfo)> {
+        for (index, server) in self.servers.iter().enumerate().take(count) {
+            if endpoint.is_server_blacklisted(server) {
+                continue;
+            }
+
+            match endpoint
+                .select_transport_for_server(&self.registrar, server)
+                .await
+            {
+                Ok(transport) => {
+                    let transport_info = TargetTransportInfo {
+                        via_host_port: None,
+                        transport: Some((transport, server.address)),
+                    };
+
+                    return Some((index, transport_info));
+                }
+                Err(e) => {
+                    log::debug!(
+                        "Failed to connect to registrar server {}, {e}",
+                        server.address
+                    );
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Use the most preferred server which isn't blacklisted
+    async fn connect(&mut self, endpoint: &Endpoint) -> Result<(), sip_core::Error> {
+        let (index, transport_info) = self
+            .select(endpoint, self.servers.len())
+            .await
+            .ok_or_else(|| io::Error::other("no registrar server available"))?;
+
+        self.current = index;
+        self.transport_info = transport_info;
+
+        Ok(())
+    }
+
+    /// Blacklist the server at `index` after the REGISTER request sent to it failed
+    fn blacklist(&self, endpoint: &Endpoint, index: usize, failure: &ServerFailure) {
+        endpoint.blacklist_server(&self.servers[index], failure.retry_after());
+    }
+
+    fn is_primary(&self) -> bool {
+        self.current == 0
+    }
+
+    fn binding_state(&self) -> BindingState {
+        BindingState::Registered {
+            server: self.servers[self.current].address,
+            primary: self.is_primary(),
+        }
+    }
+}
+
+/// Register the binding, failing over to the next server of the registrar when the current one fails
+async fn register_with_failover<A: ClientAuthenticator>(
+    endpoint: &Endpoint,
+    targets: &mut RegistrarTargets,
+    registration: &mut RegistrationProto,
+    authenticator: &mut A,
+) -> Result<(), RegisterError<A::Error>> {
+    loop {
+        targets.update_contact(registration, &targets.transport_info);
+
+        let e = match register(
+            endpoint,
+            &mut targets.transport_info,
+            registration,
+            authenticator,
+            false,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let failure = ServerFailure::try_from(e)?;
+
+        log::warn!(
+            "Registrar server {} failed, trying next server. {failure:?}",
+            targets.servers[targets.current].address
+        );
+
+        targets.blacklist(endpoint, targets.current, &failure);
+
+        if targets.connect(endpoint).await.is_err() {
+            return Err(failure.into());
+        }
+    }
+}
+
+/// Try to move the binding to a more preferred server than the current one
+///
+/// The binding on the previous server is not removed and expires on its own, since servers of the same registrar
+/// may share their bindings.
+async fn try_failback<A: ClientAuthenticator>(
+    endpoint: &Endpoint,
+    targets: &mut RegistrarTargets,
+    registration: &mut RegistrationProto,
+    authenticator: &mut A,
+) -> bool {
+    let Some((index, mut transport_info)) = targets.select(endpoint, targets.current).await else {
+        return false;
+    };
+
+    targets.update_contact(registration, &transport_info);
+
+    match register(
+        endpoint,
+        &mut transport_info,
+        registration,
+        authenticator,
+        false,
+    )
+    .await
+    {
+        Ok(()) => {
+            log::info!(
+                "Moved binding back to registrar server {}",
+                targets.servers[index].address
+            );
+
+            targets.current = index;
+            targets.transport_info = transport_info;
+
+            true
+        }
+        Err(e) => {
+            log::debug!("Failed to move binding back to a preferred registrar server, {e}");
+
+            targets.update_contact(registration, &targets.transport_info);
+
+            if let Ok(failure) = ServerFailure::try_from(e) {
+                targets.blacklist(endpoint, index, &failure);
+            }
+
+            false
+        }
+    }
+}
+
+/// REGISTER failure caused by a failure of the registrar server, the binding is moved to another server
+#[derive(Debug)]
+enum ServerFailure {
+    Core(sip_core::Error),
+    Failed(StatusCode),
+    ServiceUnavailable { retry_after: Option<Duration> },
+}
+
+impl ServerFailure {
+    /// `Retry-After` of a 503 response, the server is blacklisted at least for this duration
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ServerFailure::ServiceUnavailable { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl<E> TryFrom<RegisterError<E>> for ServerFailure {
+    type Error = RegisterError<E>;
+
+    fn try_from(error: RegisterError<E>) -> Result<Self, Self::Error> {
+        match error {
+            RegisterError::Core(e) => Ok(ServerFailure::Core(e)),
+            RegisterError::Failed(code) if code.kind() == CodeKind::ServerFailure => {
+                Ok(ServerFailure::Failed(code))
+            }
+            RegisterError::ServiceUnavailable { retry_after } => {
+                Ok(ServerFailure::ServiceUnavailable { retry_after })
+            }
+            e => Err(e),
+        }
+    }
+}
+
+impl<E> From<ServerFailure> for RegisterError<E> {
+    fn from(failure: ServerFailure) -> Self {
+        match failure {
+            ServerFailure::Core(e) => RegisterError::Core(e),
+            ServerFailure::Failed(code) => RegisterError::Failed(code),
+            ServerFailure::ServiceUnavailable { retry_after } => {
+                RegisterError::ServiceUnavailable { retry_after }
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum FlowError {
+    #[error("keep-alive failed: {0}")]
+    KeepAlive(#[from] io::Error),
+    #[error("STUN keep-alive failed: {0}")]
+    Stun(#[from] StunError),
+    #[error("public address changed from {0} to {1}")]
+    AddressChanged(SocketAddr, SocketAddr),
+}
+
+/// Flow to the registrar maintained using keep-alives ([RFC5626 Section 4.4](https://datatracker.ietf.org/doc/html/rfc5626#section-4.4))
+struct Flow {
+    transport: TpHandle,
+    remote: SocketAddr,
+
+    /// Public address of the flow discovered using STUN, only used on unreliable transports
+    public_address: Option<SocketAddr>,
+
+    flow_timer: Option<Duration>,
+    keep_alive_interval: Interval,
+}
+
+impl Flow {
+    fn new(
+        registration: &RegistrationProto,
+        target_transport_info: &TargetTransportInfo,
+    ) -> Option<Self> {
+        let (transport, remote) = target_transport_info.transport.clone()?;
+
+        let flow_timer = registration.flow_timer();
+        let keep_alive_interval = create_keep_alive_interval(flow_timer, transport.reliable());
+
+        Some(Self {
+            transport,
+            remote,
+            public_address: None,
+            flow_timer,
+            keep_alive_interval,
+        })
+    }
+
+    /// Returns if the flow uses the transport of `target_transport_info`
+    fn uses(&self, target_transport_info: &TargetTransportInfo) -> bool {
+        target_transport_info
+            .transport
+            .as_ref()
+            .is_some_and(|(transport, remote)| {
+                *transport == self.transport && *remote == self.remote
+            })
+    }
+
+    /// Update the keep-alive interval if the registrar requested a different one
+    fn set_flow_timer(&mut self, flow_timer: Option<Duration>) {
+        if self.flow_timer != flow_timer {
+            self.flow_timer = flow_timer;
+            self.keep_alive_interval =
+                create_keep_alive_interval(flow_timer, self.transport.reliable());
+        }
+    }
+
+    async fn keep_alive(&mut self, endpoint: &Endpoint) -> Result<(), FlowError> {
+        if self.transport.reliable() {
+            endpoint
+                .send_keep_alive(&self.transport, self.remote)
+                .await?;
+        } else {
+            let public_address = endpoint
+                .discover_public_address(self.remote, &self.transport)
+                .await?;
+
+            // A changed address means the NAT binding was lost and the flow must be registered again
+            if let Some(previous) = self.public_address.replace(public_address)
+                && previous != public_address
+            {
+                return Err(FlowError::AddressChanged(previous, public_address));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Send keep-alives on the flow, returns once it failed. Never returns if there's no flow.
+async fn wait_for_flow_failure(endpoint: &Endpoint, flow: Option<&mut Flow>) -> FlowError {
+    let Some(flow) = flow else {
+        return pending().await;
+    };
+
+    loop {
+        flow.keep_alive_interval.tick().await;
+
+        if let Err(e) = flow.keep_alive(endpoint).await {
+            return e;
+        }
+    }
+}
+
+/// Create the interval keep-alives are sent in on a flow
+///
+/// Uses the `Flow-Timer` value of the registrar or the default of 120 seconds for connection oriented transports and
+/// 29 seconds for UDP. The interval is randomly chosen between 80% and 100% of that value (RFC5626 Section 4.4.1).
+fn create_keep_alive_interval(flow_timer: Option<Duration>, reliable: bool) -> Interval {
+    let period = keep_alive_period(flow_timer, reliable);
+
+    let mut interval = interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval
+}
+
+fn keep_alive_period(flow_timer: Option<Duration>, reliable: bool) -> Duration {
+    let base = flow_timer.unwrap_or(if reliable {
+        Duration::from_secs(120)
+    } else {
+        Duration::from_secs(29)
+    });
+
+    // Avoid zero duration intervals
+    base.max(Duration::from_secs(1))
+        .mul_f64(rand::random_range(0.8..=1.0))
+}
+
+/// Time to wait before retrying to create a flow after `consecutive_failures` failed attempts
+/// ([RFC5626 Section 4.5](https://datatracker.ietf.org/doc/html/rfc5626#section-4.5))
+fn flow_recovery_delay(consecutive_failures: u32) -> Duration {
+    const BASE_TIME: u64 = 30;
+    const MAX_TIME: u64 = 1800;
+
+    let wait_time = BASE_TIME
+        .saturating_mul(1 << consecutive_failures.min(16))
+        .min(MAX_TIME);
+
+    Duration::from_secs(wait_time).mul_f64(rand::random_range(0.5..=1.0))
 }
 
 /// Send a register request and handle authentication using the given session and credentials
@@ -338,7 +1109,7 @@ async fn register<A: ClientAuthenticator>(
             .send_request(request, target_transport_info)
             .await?;
 
-        let response = transaction.receive_final().await.unwrap();
+        let response = transaction.receive_final().await?;
 
         let response_code = response.line.code;
 
@@ -372,7 +1143,67 @@ async fn register<A: ClientAuthenticator>(
                     return Err(RegisterError::Failed(response_code));
                 }
             }
+            503 => {
+                let retry_after = response
+                    .headers
+                    .get_named::<RetryAfter>()
+                    .ok()
+                    .map(|retry_after| Duration::from_secs(retry_after.value.into()));
+
+                return Err(RegisterError::ServiceUnavailable { retry_after });
+            }
             _ => return Err(RegisterError::Failed(response_code)),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keep_alive_period_defaults() {
+        let reliable = keep_alive_period(None, true);
+        assert!(reliable >= Duration::from_secs(96) && reliable <= Duration::from_secs(120));
+
+        let unreliable = keep_alive_period(None, false);
+        assert!(
+            unreliable >= Duration::from_secs_f64(23.2) && unreliable <= Duration::from_secs(29)
+        );
+
+        let flow_timer = keep_alive_period(Some(Duration::from_secs(50)), true);
+        assert!(flow_timer >= Duration::from_secs(40) && flow_timer <= Duration::from_secs(50));
+    }
+
+    #[test]
+    fn flow_recovery_backoff() {
+        let first = flow_recovery_delay(0);
+        assert!(first >= Duration::from_secs(15) && first <= Duration::from_secs(30));
+
+        let third = flow_recovery_delay(2);
+        assert!(third >= Duration::from_secs(60) && third <= Duration::from_secs(120));
+
+        let capped = flow_recovery_delay(100);
+        assert!(capped >= Duration::from_secs(900) && capped <= Duration::from_secs(1800));
+    }
+
+    #[test]
+    fn server_failure_classification() {
+        let failure = ServerFailure::try_from(RegisterError::<()>::Failed(
+            StatusCode::SERVER_INTERNAL_ERROR,
+        ))
+        .unwrap();
+        assert_eq!(failure.retry_after(), None);
+
+        let failure = ServerFailure::try_from(RegisterError::<()>::ServiceUnavailable {
+            retry_after: Some(Duration::from_secs(120)),
+        })
+        .unwrap();
+        assert_eq!(failure.retry_after(), Some(Duration::from_secs(120)));
+
+        assert!(
+            ServerFailure::try_from(RegisterError::<()>::Failed(StatusCode::FORBIDDEN)).is_err()
+        );
+        assert!(ServerFailure::try_from(RegisterError::Auth(())).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! SIP-specific event notification ([RFC6665](https://datatracker.ietf.org/doc/html/rfc6665))
+//!
+//! Event packages (e.g. presence, dialog, message-summary or reg) are described by implementing
+//! [`EventPackage`]. A [`Subscription`] subscribes to the state of a remote resource and receives
+//! its NOTIFY requests, a [`Notifier`] serves a subscription which was received from a peer.
+//!
+//! The [`SubscriptionLayer`] must be added to the endpoint after the [`DialogLayer`](crate::dialog::DialogLayer).
+
+use crate::dialog::{ClientDialogBuilder, Usage};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use parking_lot as pl;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake};
+use sip_types::Method;
+use sip_types::header::typed::{ContentType, Event};
+use slotmap::{DefaultKey, SlotMap};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
+
+mod notifier;
+mod subscriber;
+mod unsolicited;
+
+pub use notifier::{AcceptSubscriptionError, Notifier, NotifierEvent};
+pub use subscriber::{Notification, SubscribeError, Subscription};
+pub use unsolicited::UnsolicitedNotifications;
+
+/// Describes an event package used with [`Subscription`] and [`Notifier`]
+pub trait EventPackage: Send + Sync + 'static {
+    /// Name of the event package used in the `Event` header, e.g. `presence`
+    const EVENT: &'static str;
+
+    /// Content type of the resource state carried in NOTIFY bodies
+    const CONTENT_TYPE: &'static str;
+
+    /// Subscription duration used when none is requested
+    const DEFAULT_EXPIRES: Duration = Duration::from_secs(3600);
+
+    /// Typed resource state
+    type State: Send + 'static;
+
+    /// Error returned when a NOTIFY body cannot be parsed
+    type Error: Error + Send + Sync + 'static;
+
+    /// Parse the body of a NOTIFY request
+    fn parse_state(body: &Bytes) -> Result<Self::State, Self::Error>;
+
+    /// Print the resource state into a NOTIFY body
+    fn print_state(state: &Self::State) -> Bytes;
+}
+
+/// Receives NOTIFY requests which are not matched by the [`DialogLayer`](crate::dialog::DialogLayer)
+///
+/// A notifier may send the first NOTIFY before its 2xx response arrives, in which case the dialog doesn't
+/// exist yet. These are delivered to the waiting [`Subscription`].
+///
+/// NOTIFY requests outside of any dialog are delivered to a matching [`UnsolicitedNotifications`].
+#[derive(Default)]
+pub struct SubscriptionLayer {
+    pending: pl::Mutex<HashMap<PendingKey, mpsc::Sender<IncomingRequest>>>,
+    unsolicited: pl::Mutex<SlotMap<DefaultKey, UnsolicitedListener>>,
+}
+
+struct UnsolicitedListener {
+    event: &'static str,
+    users: Vec<BytesStr>,
+    sink: mpsc::Sender<IncomingRequest>,
+}
+
+impl UnsolicitedListener {
+    fn matches(&self, request: &IncomingRequest) -> bool {
+        let is_event = request
+            .headers
+            .get_named::<Event>()
+            .is_ok_and(|event| event_package(&event) == self.event);
+
+        if !is_event {
+            return false;
+        }
+
+        // Match either the Request-URI (the Contact) or the To header (the AOR) of the NOTIFY
+        [
+            request.line.uri.user_part.user(),
+            request.base_headers.to.uri.uri.user_part.user(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|user| self.users.contains(user))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PendingKey {
+    call_id: BytesStr,
+    local_tag: BytesStr,
+}
+
+#[async_trait::async_trait]
+impl Layer for SubscriptionLayer {
+    fn name(&self) -> &'static str {
+        "subscription"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::SUBSCRIBE);
+        endpoint.add_allow(Method::NOTIFY);
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::NOTIFY {
+            return;
+        }
+
+        let sink = if let Some(local_tag) = request.base_headers.to.tag.clone() {
+            let key = PendingKey {
+                call_id: request.base_headers.call_id.0.clone(),
+                local_tag,
+            };
+
+            self.pending.lock().get(&key).cloned()
+        } else {
+            self.unsolicited
+                .lock()
+                .values()
+                .find(|listener| listener.matches(&request))
+                .map(|listener| listener.sink.clone())
+        };
+
+        let Some(sink) = sink else {
+            return;
+        };
+
+        if let Err(SendError(notify)) = sink.send(request.inner().take().unwrap()).await {
+            *request.inner() = Some(notify);
+        }
+    }
+}
+
+impl SubscriptionLayer {
+    fn add_pending(
+        &self,
+        endpoint: &Endpoint,
+        builder: &ClientDialogBuilder,
+        sink: mpsc::Sender<IncomingRequest>,
+    ) -> PendingSubscription {
+        let key = PendingKey {
+            call_id: builder.call_id.0.clone(),
+            local_tag: builder
+                .local_fromto
+                .tag
+                .clone()
+                .expect("builder always sets local tag"),
+        };
+
+        self.pending.lock().insert(key.clone(), sink);
+
+        PendingSubscription {
+            endpoint: endpoint.clone(),
+            key,
+        }
+    }
+
+    fn add_unsolicited(&self, listener: UnsolicitedListener) -> DefaultKey {
+        self.unsolicited.lock().insert(listener)
+    }
+
+    fn remove_unsolicited(&self, key: DefaultKey) {
+        self.unsolicited.lock().remove(key);
+    }
+}
+
+/// Removes the pending subscription from the [`SubscriptionLayer`] when dropped
+struct PendingSubscription {
+    endpoint: Endpoint,
+    key: PendingKey,
+}
+
+impl Drop for PendingSubscription {
+    fn drop(&mut self) {
+        self.endpoint
+            .layer::<SubscriptionLayer>()
+            .pending
+            .lock()
+            .remove(&self.key);
+    }
+}
+
+/// Dialog usage which forwards all requests with the given method
+struct SubscriptionUsage {
+    method: Method,
+    sink: mpsc::Sender<IncomingRequest>,
+}
+
+#[async_trait::async_trait]
+impl Usage for SubscriptionUsage {
+    fn name(&self) -> &'static str {
+        "subscription-usage"
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != self.method {
+            return;
+        }
+
+        if let Err(SendError(rejected)) = self.sink.send(request.inner().take().unwrap()).await {
+            *request.inner() = Some(rejected);
+        }
+    }
+}
+
+/// Returns the event package name of the `Event` header, without any parameters
+pub(crate) fn event_package(event: &Event) -> &str {
+    event.0.split(';').next().unwrap_or_default().trim()
+}
+
+/// Returns the `id` parameter of the `Event` header
+fn event_id(event: &Event) -> Option<&str> {
+    event.0.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+
+        name.trim()
+            .eq_ignore_ascii_case("id")
+            .then_some(value.trim())
+    })
+}
+
+/// Returns if both `Event` headers refer to the same subscription
+fn event_matches(a: &Event, b: &Event) -> bool {
+    event_package(a) == event_package(b) && event_id(a) == event_id(b)
+}
+
+/// Returns if the content type (ignoring its parameters) is the one of the event package
+fn is_package_content_type<P: EventPackage>(content_type: &ContentType) -> bool {
+    content_type
+        .0
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .eq_ignore_ascii_case(P::CONTENT_TYPE)
+}
@@ -0,0 +1,337 @@
+use super::{EventPackage, SubscriptionUsage, event_matches, event_package};
+use crate::dialog::{Dialog, UsageGuard};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use sip_core::{Endpoint, IncomingRequest, Result};
+use sip_types::header::typed::{
+    Contact, ContentType, Event, EventReasonValue, Expires, SubStateValue, SubscriptionState,
+};
+use sip_types::{CodeKind, Method, StatusCode};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep_until};
+
+/// Any errors that might be encountered while accepting a SUBSCRIBE request
+#[derive(Debug, thiserror::Error)]
+pub enum AcceptSubscriptionError {
+    #[error(transparent)]
+    Core(#[from] sip_core::Error),
+    #[error("SUBSCRIBE request is missing the Event header or has an unexpected event package")]
+    BadEvent,
+}
+
+/// Event returned by [`Notifier::receive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifierEvent {
+    /// The subscriber refreshed the subscription
+    Refreshed,
+
+    /// The subscription has been terminated, either because the subscriber unsubscribed or the
+    /// subscription expired. The final NOTIFY has already been sent.
+    Terminated,
+}
+
+/// Server side of a subscription using the event package `P`
+///
+/// After accepting the subscription an initial NOTIFY must be sent using [`Notifier::notify`] or
+/// [`Notifier::notify_pending`]. [`Notifier::receive`] must be polled to handle refreshes.
+///
+/// Dropping the notifier terminates the subscription with reason `noresource`.
+pub struct Notifier<P: EventPackage> {
+    dialog: Arc<Dialog>,
+    _usage_guard: UsageGuard,
+    subscribe_rx: mpsc::Receiver<IncomingRequest>,
+
+    /// `Event` header of the SUBSCRIBE request, which is repeated in every NOTIFY
+    event: Event,
+    expires_at: Instant,
+
+    /// Body of the last NOTIFY, sent again with the final NOTIFY
+    last_state: Option<Bytes>,
+    terminated: bool,
+
+    _package: PhantomData<fn(P)>,
+}
+
+impl<P: EventPackage> Notifier<P> {
+    /// Accept an incoming SUBSCRIBE request (outside of any dialog) by responding with `200 OK`
+    ///
+    /// SUBSCRIBE requests for other event packages are rejected with `489 Bad Event`.
+    pub async fn accept(
+        endpoint: Endpoint,
+        mut subscribe: IncomingRequest,
+        contact: Contact,
+    ) -> Result<Self, AcceptSubscriptionError> {
+        let event = match subscribe.headers.get_named::<Event>() {
+            Ok(event) if event_package(&event) == P::EVENT => event,
+            _ => {
+                let response = endpoint.create_response(&subscribe, StatusCode::BAD_EVENT, None);
+                endpoint
+                    .create_server_tsx(&mut subscribe)
+                    .respond(response)
+                    .await?;
+
+                return Err(AcceptSubscriptionError::BadEvent);
+            }
+        };
+
+        let expires = requested_expires::<P>(&subscribe);
+
+        let dialog = Dialog::new_server(endpoint.clone(), &subscribe, contact)
+            .map_err(sip_core::Error::from)?;
+
+        let (subscribe_sink, subscribe_rx) = mpsc::channel(4);
+        let usage_guard = dialog.register_usage(SubscriptionUsage {
+            method: Method::SUBSCRIBE,
+            sink: subscribe_sink,
+        });
+
+        let mut response = dialog.create_response(&subscribe, StatusCode::OK, None)?;
+        response
+            .msg
+            .headers
+            .insert_named(&Expires(expires.as_secs() as u32));
+
+        endpoint
+            .create_server_tsx(&mut subscribe)
+            .respond(response)
+            .await?;
+
+        Ok(Self {
+            dialog: Arc::new(dialog),
+            _usage_guard: usage_guard,
+            subscribe_rx,
+            event,
+            expires_at: Instant::now() + expires,
+            last_state: None,
+            terminated: false,
+            _package: PhantomData,
+        })
+    }
+
+    /// Returns the dialog of the subscription
+    pub fn dialog(&self) -> &Dialog {
+        &self.dialog
+    }
+
+    /// Returns if the subscription has been terminated
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Send a NOTIFY request containing the given resource state
+    ///
+    /// If the subscriber only fetched the state (`Expires: 0`) this terminates the subscription.
+    pub async fn notify(&mut self, state: &P::State) -> Result<()> {
+        let body = P::print_state(state);
+        self.last_state = Some(body.clone());
+
+        let subscription_state = match self.remaining() {
+            Some(remaining) => SubscriptionState::new(SubStateValue::Active)
+                .with_expires(remaining.as_secs() as u32),
+            None => SubscriptionState::new(SubStateValue::Terminated)
+                .with_reason(EventReasonValue::Timeout),
+        };
+
+        self.send_notify(subscription_state, Some(body)).await
+    }
+
+    /// Send a NOTIFY request without body, signaling that the subscription is pending authorization
+    pub async fn notify_pending(&mut self) -> Result<()> {
+        let remaining = self.remaining().unwrap_or_default();
+
+        self.send_notify(
+            SubscriptionState::new(SubStateValue::Pending).with_expires(remaining.as_secs() as u32),
+            None,
+        )
+        .await
+    }
+
+    /// Terminate the subscription with the given reason
+    pub async fn terminate(mut self, reason: EventReasonValue) -> Result<()> {
+        self.send_notify(
+            SubscriptionState::new(SubStateValue::Terminated).with_reason(reason),
+            None,
+        )
+        .await
+    }
+
+    /// Handle refreshing SUBSCRIBE requests until the subscription is terminated
+    pub async fn receive(&mut self) -> Result<NotifierEvent> {
+        loop {
+            if self.terminated {
+                return Ok(NotifierEvent::Terminated);
+            }
+
+            let subscribe = select! {
+                subscribe = self.subscribe_rx.recv() => subscribe,
+                _ = sleep_until(self.expires_at) => None,
+            };
+
+            let Some(subscribe) = subscribe else {
+                // Subscription expired without being refreshed
+                self.send_final_notify(EventReasonValue::Timeout).await?;
+                continue;
+            };
+
+            if let Some(event) = self.handle_subscribe(subscribe).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    async fn handle_subscribe(
+        &mut self,
+        mut subscribe: IncomingRequest,
+    ) -> Result<Option<NotifierEvent>> {
+        let transaction = self.dialog.endpoint.create_server_tsx(&mut subscribe);
+
+        let is_same_subscription = subscribe
+            .headers
+            .get_named::<Event>()
+            .is_ok_and(|event| event_matches(&event, &self.event));
+
+        if !is_same_subscription {
+            let response = self
+                .dialog
+                .create_response(&subscribe, StatusCode::BAD_EVENT, None)?;
+            transaction.respond(response).await?;
+
+            return Ok(None);
+        }
+
+        let expires = requested_expires::<P>(&subscribe);
+
+        let mut response = self
+            .dialog
+            .create_response(&subscribe, StatusCode::OK, None)?;
+        response
+            .msg
+            .headers
+            .insert_named(&Expires(expires.as_secs() as u32));
+        transaction.respond(response).await?;
+
+        if expires.is_zero() {
+            self.send_final_notify(EventReasonValue::Timeout).await?;
+
+            Ok(Some(NotifierEvent::Terminated))
+        } else {
+            self.expires_at = Instant::now() + expires;
+
+            Ok(Some(NotifierEvent::Refreshed))
+        }
+    }
+
+    /// Send the final NOTIFY including the last resource state
+    async fn send_final_notify(&mut self, reason: EventReasonValue) -> Result<()> {
+        let body = self.last_state.clone();
+
+        self.send_notify(
+            SubscriptionState::new(SubStateValue::Terminated).with_reason(reason),
+            body,
+        )
+        .await
+    }
+
+    async fn send_notify(
+        &mut self,
+        subscription_state: SubscriptionState,
+        body: Option<Bytes>,
+    ) -> Result<()> {
+        if self.terminated {
+            return Ok(());
+        }
+
+        if subscription_state.state == SubStateValue::Terminated {
+            self.terminated = true;
+        }
+
+        let accepted =
+            send_notify::<P>(&self.dialog, &self.event, subscription_state, body).await?;
+
+        if !accepted {
+            self.terminated = true;
+        }
+
+        Ok(())
+    }
+
+    /// Remaining duration of the subscription, `None` if it has already expired
+    fn remaining(&self) -> Option<Duration> {
+        let remaining = self.expires_at.saturating_duration_since(Instant::now());
+
+        (!remaining.is_zero()).then_some(remaining)
+    }
+}
+
+impl<P: EventPackage> Drop for Notifier<P> {
+    fn drop(&mut self) {
+        if self.terminated {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let dialog = self.dialog.clone();
+        let event = self.event.clone();
+
+        handle.spawn(async move {
+            let subscription_state = SubscriptionState::new(SubStateValue::Terminated)
+                .with_reason(EventReasonValue::NoResource);
+
+            if let Err(e) = send_notify::<P>(&dialog, &event, subscription_state, None).await {
+                log::warn!(
+                    "Failed to terminate dropped {} subscription {e:?}",
+                    P::EVENT
+                );
+            }
+        });
+    }
+}
+
+/// Send a NOTIFY request, returns if the subscriber accepted it
+async fn send_notify<P: EventPackage>(
+    dialog: &Dialog,
+    event: &Event,
+    subscription_state: SubscriptionState,
+    body: Option<Bytes>,
+) -> Result<bool> {
+    let mut notify = dialog.create_request(Method::NOTIFY);
+    notify.headers.insert_named(event);
+    notify.headers.insert_named(&subscription_state);
+    notify.headers.insert_named(&dialog.local_contact);
+
+    if let Some(body) = body {
+        notify
+            .headers
+            .insert_named(&ContentType(BytesStr::from_static(P::CONTENT_TYPE)));
+        notify.body = body;
+    }
+
+    let response = dialog.send_request(notify).await?;
+
+    if response.line.code.kind() != CodeKind::Success {
+        log::warn!(
+            "Subscriber responded to {} NOTIFY with {:?}, terminating subscription",
+            P::EVENT,
+            response.line.code
+        );
+
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+fn requested_expires<P: EventPackage>(subscribe: &IncomingRequest) -> Duration {
+    subscribe
+        .headers
+        .get_named::<Expires>()
+        .map(|expires| Duration::from_secs(expires.0.into()))
+        .unwrap_or(P::DEFAULT_EXPIRES)
+}
@@ -0,0 +1,506 @@
+use super::{
+    EventPackage, SubscriptionLayer, SubscriptionUsage, event_package, is_package_content_type,
+};
+use crate::dialog::{ClientDialogBuilder, Dialog, UsageGuard};
+use sip_auth::{ClientAuthenticator, RequestParts, ResponseParts};
+use sip_core::transaction::TsxResponse;
+use sip_core::{Endpoint, IncomingRequest, Request};
+use sip_types::header::HeaderError;
+use sip_types::header::typed::{
+    Accept, Contact, ContentType, Event, Expires, SubStateValue, SubscriptionState,
+};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{CodeKind, Headers, Method, Name, StatusCode};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Instant, sleep_until};
+
+/// Time to wait for the final NOTIFY after unsubscribing (64*T1)
+const FINAL_NOTIFY_TIMEOUT: Duration = Duration::from_secs(32);
+
+/// Any errors that might be encountered while subscribing to an event package
+#[derive(Debug, thiserror::Error)]
+pub enum SubscribeError<A> {
+    #[error(transparent)]
+    Core(#[from] sip_core::Error),
+    #[error("Authentication of SUBSCRIBE request failed")]
+    Auth(#[source] A),
+    #[error("Got response to SUBSCRIBE with unexpected status code {0:?}")]
+    Failed(StatusCode),
+}
+
+/// NOTIFY request received by a [`Subscription`]
+#[derive(Debug)]
+pub struct Notification<P: EventPackage> {
+    /// `Subscription-State` header of the NOTIFY request
+    pub subscription_state: SubscriptionState,
+
+    /// Resource state carried in the NOTIFY body, `None` if the NOTIFY had no body
+    pub state: Option<P::State>,
+}
+
+impl<P: EventPackage> Notification<P> {
+    /// Returns if the notifier has terminated the subscription with this notification
+    pub fn is_terminated(&self) -> bool {
+        self.subscription_state.state == SubStateValue::Terminated
+    }
+}
+
+/// Subscription to the state of a remote resource using the event package `P`
+///
+/// The subscription is refreshed in the background before it expires. Dropping this type will
+/// unsubscribe from the resource.
+pub struct Subscription<P: EventPackage> {
+    notifications: mpsc::Receiver<Notification<P>>,
+    terminate: Option<oneshot::Sender<()>>,
+}
+
+impl<P: EventPackage> Subscription<P> {
+    /// Send a SUBSCRIBE request to `target` and wait for it to be accepted
+    ///
+    /// `expires` overrides the [`EventPackage::DEFAULT_EXPIRES`] requested from the notifier.
+    pub async fn subscribe<A: ClientAuthenticator + Send + 'static>(
+        endpoint: Endpoint,
+        mut authenticator: A,
+        id: NameAddr,
+        contact: Contact,
+        target: SipUri,
+        expires: Option<Duration>,
+    ) -> Result<Self, SubscribeError<A::Error>> {
+        let expires = expires.unwrap_or(P::DEFAULT_EXPIRES);
+
+        let mut builder = ClientDialogBuilder::new(endpoint.clone(), id, contact, target);
+
+        // NOTIFY requests received before the dialog exists are routed here by the SubscriptionLayer
+        let (notify_sink, notify_rx) = mpsc::channel(4);
+        let pending = endpoint.layer::<SubscriptionLayer>().add_pending(
+            &endpoint,
+            &builder,
+            notify_sink.clone(),
+        );
+
+        let response = loop {
+            let mut request = builder.create_request(Method::SUBSCRIBE);
+            builder.local_cseq += 1;
+
+            add_subscribe_headers::<P>(&mut request.headers, expires);
+            authenticator.authorize_request(&mut request.headers);
+
+            let mut transaction = endpoint
+                .send_request(request, &mut builder.target_tp_info)
+                .await?;
+
+            let response = transaction.receive_final().await?;
+
+            // The transaction may have failed over to another server of the target
+            let parts = &transaction.request().parts;
+            builder.target_tp_info.transport = Some((parts.transport.clone(), parts.destination));
+
+            match response.line.code.into_u16() {
+                200..=299 => break response,
+                401 | 407 => {
+                    let request = transaction.request();
+
+                    authenticator
+                        .handle_rejection(
+                            RequestParts {
+                                line: &request.msg.line,
+                                headers: &request.msg.headers,
+                                body: &request.msg.body,
+                            },
+                            ResponseParts {
+                                line: &response.line,
+                                headers: &response.headers,
+                                body: &response.body,
+                            },
+                        )
+                        .map_err(SubscribeError::Auth)?;
+                }
+                _ => return Err(SubscribeError::Failed(response.line.code)),
+            }
+        };
+
+        if response.base_headers.to.tag.is_none() {
+            return Err(SubscribeError::Core(
+                HeaderError::malformed_adhoc(Name::TO, "missing tag parameter").into(),
+            ));
+        }
+
+        let granted = granted_expires(&response).unwrap_or(expires);
+
+        let dialog = builder
+            .create_dialog_from_response(&response)
+            .map_err(sip_core::Error::from)?;
+
+        let usage_guard = dialog.register_usage(SubscriptionUsage {
+            method: Method::NOTIFY,
+            sink: notify_sink,
+        });
+
+        // From now on all NOTIFY requests are matched by the dialog
+        drop(pending);
+
+        let (notifications_sink, notifications) = mpsc::channel(4);
+        let (terminate, terminate_rx) = oneshot::channel();
+
+        tokio::spawn(subscription_task::<P, A>(
+            SubscriptionTask {
+                dialog,
+                _usage_guard: usage_guard,
+                authenticator,
+                requested_expires: expires,
+                notify_rx,
+                notifications: notifications_sink,
+                terminate: terminate_rx,
+            },
+            granted,
+        ));
+
+        Ok(Self {
+            notifications,
+            terminate: Some(terminate),
+        })
+    }
+
+    /// Wait for the next NOTIFY request
+    ///
+    /// Returns `None` once the subscription has been terminated and no more notifications will be received.
+    pub async fn receive(&mut self) -> Option<Notification<P>> {
+        self.notifications.recv().await
+    }
+
+    /// Unsubscribe from the resource by sending a SUBSCRIBE request with `Expires: 0`
+    ///
+    /// The final NOTIFY sent by the notifier can still be received using [`Subscription::receive`].
+    pub fn terminate(&mut self) {
+        // Dropping the sender signals the background task to unsubscribe
+        self.terminate.take();
+    }
+}
+
+struct SubscriptionTask<P: EventPackage, A> {
+    dialog: Dialog,
+    _usage_guard: UsageGuard,
+    authenticator: A,
+    requested_expires: Duration,
+    notify_rx: mpsc::Receiver<IncomingRequest>,
+    notifications: mpsc::Sender<Notification<P>>,
+    terminate: oneshot::Receiver<()>,
+}
+
+async fn subscription_task<P: EventPackage, A: ClientAuthenticator>(
+    mut task: SubscriptionTask<P, A>,
+    granted: Duration,
+) {
+    let mut refresh_at = Instant::now() + refresh_interval(granted);
+    let mut final_notify_deadline = None;
+
+    loop {
+        select! {
+            notify = task.notify_rx.recv() => {
+                let Some(notify) = notify else {
+                    return;
+                };
+
+                let notification = match handle_notify::<P>(&task.dialog, notify).await {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        log::warn!("Failed to respond to NOTIFY request, {e:?}");
+                        continue;
+                    }
+                };
+
+                let terminated = notification.is_terminated();
+
+                // The notifier may shorten the subscription duration
+                if let Some(expires) = notification.subscription_state.expires {
+                    refresh_at = refresh_at
+                        .min(Instant::now() + refresh_interval(Duration::from_secs(expires.into())));
+                }
+
+                let _ = task.notifications.send(notification).await;
+
+                if terminated {
+                    return;
+                }
+            }
+            _ = sleep_until(refresh_at), if final_notify_deadline.is_none() => {
+                match send_subscribe::<P, A>(&task.dialog, &mut task.authenticator, task.requested_expires).await {
+                    // The notifier ends the subscription and sends the final NOTIFY
+                    Some(granted) if granted.is_zero() => {
+                        final_notify_deadline = Some(Instant::now() + FINAL_NOTIFY_TIMEOUT);
+                    }
+                    Some(granted) => refresh_at = Instant::now() + refresh_interval(granted),
+                    None => return,
+                }
+            }
+            _ = &mut task.terminate, if final_notify_deadline.is_none() => {
+                if send_subscribe::<P, A>(&task.dialog, &mut task.authenticator, Duration::ZERO).await.is_none() {
+                    return;
+                }
+
+                final_notify_deadline = Some(Instant::now() + FINAL_NOTIFY_TIMEOUT);
+            }
+            _ = sleep_until(final_notify_deadline.unwrap_or(refresh_at)), if final_notify_deadline.is_some() => {
+                log::debug!("Did not receive final NOTIFY after unsubscribing");
+                return;
+            }
+        }
+    }
+}
+
+/// Respond to a NOTIFY request received inside the subscription's dialog
+///
+/// Returns `None` if the NOTIFY was rejected
+async fn handle_notify<P: EventPackage>(
+    dialog: &Dialog,
+    mut notify: IncomingRequest,
+) -> sip_core::Result<Option<Notification<P>>> {
+    // NOTIFY requests routed here before the dialog was created may originate from another fork
+    let notification = if notify.base_headers.from.tag == dialog.peer_fromto.tag {
+        parse_notify::<P>(&notify, None)
+    } else {
+        Err(StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST)
+    };
+
+    let mut response =
+        dialog.create_response(&notify, notify_response_code(&notification), None)?;
+    add_notify_response_headers::<P>(&mut response.msg.headers, &notification);
+
+    let transaction = dialog.endpoint.create_server_tsx(&mut notify);
+    transaction.respond(response).await?;
+
+    Ok(notification.ok())
+}
+
+/// Parse a NOTIFY request of the event package `P`
+///
+/// `default_state` is used when the request has no `Subscription-State` header, if `None` the header is required.
+pub(super) fn parse_notify<P: EventPackage>(
+    notify: &IncomingRequest,
+    default_state: Option<SubscriptionState>,
+) -> Result<Notification<P>, StatusCode> {
+    let event = notify
+        .headers
+        .get_named::<Event>()
+        .map_err(|_| StatusCode::BAD_EVENT)?;
+
+    if event_package(&event) != P::EVENT {
+        return Err(StatusCode::BAD_EVENT);
+    }
+
+    let subscription_state = match notify.headers.get_named::<SubscriptionState>() {
+        Ok(subscription_state) => subscription_state,
+        Err(_) => default_state.ok_or(StatusCode::BAD_REQUEST)?,
+    };
+
+    if notify.body.is_empty() {
+        return Ok(Notification {
+            subscription_state,
+            state: None,
+        });
+    }
+
+    let is_package_content_type = notify
+        .headers
+        .get_named::<ContentType>()
+        .is_ok_and(|content_type| is_package_content_type::<P>(&content_type));
+
+    if !is_package_content_type {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    match P::parse_state(&notify.body) {
+        Ok(state) => Ok(Notification {
+            subscription_state,
+            state: Some(state),
+        }),
+        Err(e) => {
+            log::warn!("Failed to parse {} NOTIFY body, {e}", P::EVENT);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+pub(super) fn notify_response_code<P: EventPackage>(
+    notification: &Result<Notification<P>, StatusCode>,
+) -> StatusCode {
+    match notification {
+        Ok(..) => StatusCode::OK,
+        Err(code) => *code,
+    }
+}
+
+pub(super) fn add_notify_response_headers<P: EventPackage>(
+    headers: &mut Headers,
+    notification: &Result<Notification<P>, StatusCode>,
+) {
+    if notification.as_ref().err() == Some(&StatusCode::UNSUPPORTED_MEDIA_TYPE) {
+        headers.insert_named(&Accept(P::CONTENT_TYPE.into()));
+    }
+}
+
+/// Send a SUBSCRIBE request inside the dialog to refresh or terminate (`expires` = 0) the subscription
+///
+/// Returns the subscription duration granted by the notifier, which is zero if the subscription is being terminated
+/// and the final NOTIFY is yet to be received. Returns `None` if the subscription has ended.
+async fn send_subscribe<P: EventPackage, A: ClientAuthenticator>(
+    dialog: &Dialog,
+    authenticator: &mut A,
+    expires: Duration,
+) -> Option<Duration> {
+    loop {
+        let mut request = dialog.create_request(Method::SUBSCRIBE);
+        request.headers.insert_named(&dialog.local_contact);
+
+        add_subscribe_headers::<P>(&mut request.headers, expires);
+        authenticator.authorize_request(&mut request.headers);
+
+        let (request, response) = match send_request(dialog, request).await {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("Failed to send SUBSCRIBE request, {e}");
+                return None;
+            }
+        };
+
+        match response.line.code.kind() {
+            CodeKind::Success if expires.is_zero() => return Some(Duration::ZERO),
+            CodeKind::Success => return Some(granted_expires(&response).unwrap_or(expires)),
+            _ if response.line.code == StatusCode::UNAUTHORIZED
+                || response.line.code == StatusCode::PROXY_AUTHENTICATION_REQUIRED =>
+            {
+                let result = authenticator.handle_rejection(
+                    RequestParts {
+                        line: &request.line,
+                        headers: &request.headers,
+                        body: &request.body,
+                    },
+                    ResponseParts {
+                        line: &response.line,
+                        headers: &response.headers,
+                        body: &response.body,
+                    },
+                );
+
+                if let Err(e) = result {
+                    log::warn!("Authentication of SUBSCRIBE request failed, {e}");
+                    return None;
+                }
+            }
+            _ => {
+                log::warn!(
+                    "Got response to SUBSCRIBE with unexpected status code {:?}, subscription ended",
+                    response.line.code
+                );
+                return None;
+            }
+        }
+    }
+}
+
+async fn send_request(
+    dialog: &Dialog,
+    request: Request,
+) -> sip_core::Result<(Request, TsxResponse)> {
+    let mut target_tp_info = dialog.target_tp_info.lock().await;
+
+    let mut transaction = dialog
+        .endpoint
+        .send_request(request, &mut target_tp_info)
+        .await?;
+
+    drop(target_tp_info);
+
+    let response = transaction.receive_final().await?;
+
+    Ok((transaction.request().msg.clone(), response))
+}
+
+fn add_subscribe_headers<P: EventPackage>(headers: &mut Headers, expires: Duration) {
+    headers.insert_named(&Event::new(P::EVENT));
+    headers.insert_named(&Expires(expires.as_secs() as u32));
+    headers.insert_named(&Accept(P::CONTENT_TYPE.into()));
+}
+
+fn granted_expires(response: &TsxResponse) -> Option<Duration> {
+    response
+        .headers
+        .get_named::<Expires>()
+        .ok()
+        .map(|expires| Duration::from_secs(expires.0.into()))
+}
+
+/// Refresh the subscription before the notifier considers it expired
+fn refresh_interval(expires: Duration) -> Duration {
+    if expires > Duration::from_secs(64) {
+        expires - Duration::from_secs(32)
+    } else {
+        expires / 2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::subscription::{Notifier, NotifierEvent};
+    use crate::test_util::{TakeRequests, authenticator, contact, endpoint, within};
+    use crate::{MessageSummary, MessageSummaryPackage};
+
+    #[tokio::test]
+    async fn final_notify_after_unsubscribe() {
+        let (subscribes, mut incoming) = TakeRequests::new(Method::SUBSCRIBE);
+
+        let (alice_endpoint, alice) = endpoint("alice", |builder| {
+            builder.add_layer(SubscriptionLayer::default());
+        })
+        .await;
+        let (bob_endpoint, bob) = endpoint("bob", |builder| {
+            builder.add_layer(SubscriptionLayer::default());
+            builder.add_layer(subscribes);
+        })
+        .await;
+
+        let bob_contact = contact(&bob);
+
+        let notifier = tokio::spawn(async move {
+            let subscribe = incoming.recv().await.unwrap();
+
+            let mut notifier =
+                Notifier::<MessageSummaryPackage>::accept(bob_endpoint, subscribe, bob_contact)
+                    .await
+                    .unwrap();
+
+            let summary: MessageSummary = "Messages-Waiting: yes\r\n".parse().unwrap();
+            notifier.notify(&summary).await.unwrap();
+
+            notifier.receive().await.unwrap()
+        });
+
+        let mut subscription = within(Subscription::<MessageSummaryPackage>::subscribe(
+            alice_endpoint,
+            authenticator(),
+            NameAddr::uri(alice.clone()),
+            contact(&alice),
+            bob,
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let notification = within(subscription.receive()).await.unwrap();
+        assert!(!notification.is_terminated());
+        assert!(notification.state.unwrap().messages_waiting);
+
+        subscription.terminate();
+
+        let notification = within(subscription.receive()).await.unwrap();
+        assert!(notification.is_terminated());
+        assert!(notification.state.is_some());
+
+        assert!(within(subscription.receive()).await.is_none());
+        assert_eq!(within(notifier).await.unwrap(), NotifierEvent::Terminated);
+    }
+}
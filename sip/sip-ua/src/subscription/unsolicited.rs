@@ -0,0 +1,90 @@
+use super::subscriber::{add_notify_response_headers, notify_response_code, parse_notify};
+use super::{EventPackage, Notification, SubscriptionLayer, UnsolicitedListener};
+use bytesstr::BytesStr;
+use sip_core::{Endpoint, IncomingRequest};
+use sip_types::header::typed::{SubStateValue, SubscriptionState};
+use slotmap::DefaultKey;
+use tokio::sync::mpsc;
+
+/// Receives NOTIFY requests of the event package `P` which are sent outside of any subscription
+///
+/// Many PBXes send e.g. message-summary NOTIFYs to registered users without ever receiving a SUBSCRIBE.
+/// Requests are matched by the user part of their Request-URI or `To` header.
+pub struct UnsolicitedNotifications<P: EventPackage> {
+    endpoint: Endpoint,
+    key: DefaultKey,
+    notifications: mpsc::Receiver<Notification<P>>,
+}
+
+impl<P: EventPackage> UnsolicitedNotifications<P> {
+    /// Start receiving unsolicited NOTIFY requests addressed to any of the given `users`
+    pub fn new(endpoint: Endpoint, users: Vec<BytesStr>) -> Self {
+        let (sink, notify_rx) = mpsc::channel(4);
+        let (notifications_sink, notifications) = mpsc::channel(4);
+
+        let key = endpoint
+            .layer::<SubscriptionLayer>()
+            .add_unsolicited(UnsolicitedListener {
+                event: P::EVENT,
+                users,
+                sink,
+            });
+
+        // Respond in a separate task, so no NOTIFY is left unanswered when `receive` is cancelled
+        tokio::spawn(respond_task::<P>(
+            endpoint.clone(),
+            notify_rx,
+            notifications_sink,
+        ));
+
+        Self {
+            endpoint,
+            key,
+            notifications,
+        }
+    }
+
+    /// Wait for the next unsolicited NOTIFY request
+    ///
+    /// This function is cancel safe.
+    pub async fn receive(&mut self) -> Option<Notification<P>> {
+        self.notifications.recv().await
+    }
+}
+
+/// Respond to all NOTIFY requests and forward the valid ones, until the listener is removed
+async fn respond_task<P: EventPackage>(
+    endpoint: Endpoint,
+    mut notify_rx: mpsc::Receiver<IncomingRequest>,
+    notifications: mpsc::Sender<Notification<P>>,
+) {
+    while let Some(mut notify) = notify_rx.recv().await {
+        // Unsolicited NOTIFYs usually come without Subscription-State
+        let notification =
+            parse_notify::<P>(&notify, Some(SubscriptionState::new(SubStateValue::Active)));
+
+        let mut response =
+            endpoint.create_response(&notify, notify_response_code(&notification), None);
+        add_notify_response_headers::<P>(&mut response.msg.headers, &notification);
+
+        let transaction = endpoint.create_server_tsx(&mut notify);
+
+        if let Err(e) = transaction.respond(response).await {
+            log::warn!("Failed to respond to unsolicited NOTIFY request, {e:?}");
+        }
+
+        if let Ok(notification) = notification
+            && notifications.send(notification).await.is_err()
+        {
+            return;
+        }
+    }
+}
+
+impl<P: EventPackage> Drop for UnsolicitedNotifications<P> {
+    fn drop(&mut self) {
+        self.endpoint
+            .layer::<SubscriptionLayer>()
+            .remove_unsolicited(self.key);
+    }
+}
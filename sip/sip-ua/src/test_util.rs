@@ -0,0 +1,272 @@
+//! Utilities for tests running multiple endpoints against each other over UDP on localhost
+
+use crate::dialog::DialogLayer;
+use crate::invite::InviteLayer;
+use crate::{Call, CallEvent, InboundCall, MediaBackend, OutboundCall};
+use bytesstr::BytesStr;
+use sdp_types::{Direction, SessionDescription};
+use sip_auth::{DigestAuthenticator, DigestCredentials};
+use sip_core::transaction::TsxResponse;
+use sip_core::transport::TargetTransportInfo;
+use sip_core::transport::udp::Udp;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake, Request};
+use sip_types::header::typed::{CSeq, CallID, Contact, FromTo};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Method, Name};
+use std::future::pending;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+/// Create an endpoint with dialog and INVITE support listening on a random UDP port
+///
+/// Returns the endpoint and the uri of the given `user` at the endpoint's address.
+pub(crate) async fn endpoint(
+    user: &str,
+    layers: impl FnOnce(&mut EndpointBuilder),
+) -> (Endpoint, SipUri) {
+    let mut builder = Endpoint::builder();
+
+    builder.add_layer(DialogLayer::default());
+    builder.add_layer(InviteLayer::default());
+
+    layers(&mut builder);
+
+    let transport = Udp::spawn(&mut builder, "127.0.0.1:0").await.unwrap();
+
+    let uri = format!("sip:{user}@{}", transport.bound()).parse().unwrap();
+
+    (builder.build(), uri)
+}
+
+pub(crate) fn contact(uri: &SipUri) -> Contact {
+    Contact::new(NameAddr::uri(uri.clone()))
+}
+
+pub(crate) fn authenticator() -> DigestAuthenticator {
+    DigestAuthenticator::new(DigestCredentials::new())
+}
+
+/// Create a request outside of any dialog from `from` to `to`
+pub(crate) fn request(method: Method, from: &SipUri, to: &SipUri) -> Request {
+    let mut request = Request::new(method.clone(), to.clone());
+
+    request.headers.insert_type(
+        Name::FROM,
+        &FromTo::new(NameAddr::uri(from.clone()), Some("from-tag".into())),
+    );
+    request
+        .headers
+        .insert_type(Name::TO, &FromTo::new(NameAddr::uri(to.clone()), None));
+    request
+        .headers
+        .insert_named(&CallID::new(format!("{}-{method}", rand::random::<u32>())));
+    request.headers.insert_named(&CSeq::new(1, method));
+
+    request
+}
+
+/// Send the request in a new transaction and wait for the final response
+pub(crate) async fn send(endpoint: &Endpoint, request: Request) -> TsxResponse {
+    within(async {
+        let mut transaction = endpoint
+            .send_request(request, &mut TargetTransportInfo::default())
+            .await
+            .unwrap();
+
+        transaction.receive_final().await.unwrap()
+    })
+    .await
+}
+
+/// Wait for the future to complete, panics if it takes longer than 5 seconds
+pub(crate) async fn within<F: Future>(future: F) -> F::Output {
+    timeout(Duration::from_secs(5), future)
+        .await
+        .expect("timed out")
+}
+
+/// Layer taking all requests with the given method and passing them to the test
+pub(crate) struct TakeRequests {
+    method: Method,
+    sender: mpsc::UnboundedSender<IncomingRequest>,
+}
+
+impl TakeRequests {
+    pub(crate) fn new(method: Method) -> (Self, mpsc::UnboundedReceiver<IncomingRequest>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        (Self { method, sender }, receiver)
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for TakeRequests {
+    fn name(&self) -> &'static str {
+        "take-requests"
+    }
+
+    async fn receive(&self, _: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method == self.method {
+            let _ = self.sender.send(request.take());
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("offer rejected by test media")]
+pub(crate) struct TestMediaError;
+
+/// Media backend creating SDP with a single audio stream
+#[derive(Default)]
+pub(crate) struct TestMedia {
+    pub(crate) hold: bool,
+    /// Don't send an SDP offer in the INVITE
+    pub(crate) no_offer: bool,
+    /// Return an error when receiving an SDP offer
+    pub(crate) reject_offers: bool,
+    pub(crate) offers: Vec<SessionDescription>,
+    pub(crate) answers: Vec<SessionDescription>,
+}
+
+impl TestMedia {
+    fn sdp(&self) -> SessionDescription {
+        let direction = if self.hold {
+            Direction::SendOnly
+        } else {
+            Direction::SendRecv
+        };
+
+        let sdp = format!(
+            "v=0\r\n\
+            o=- 1 1 IN IP4 127.0.0.1\r\n\
+            s=-\r\n\
+            c=IN IP4 127.0.0.1\r\n\
+            t=0 0\r\n\
+            m=audio 4000 RTP/AVP 0\r\n\
+            a={direction}\r\n"
+        );
+
+        SessionDescription::parse(&BytesStr::from(sdp)).unwrap()
+    }
+}
+
+impl MediaBackend for TestMedia {
+    type Error = TestMediaError;
+    type Event = ();
+
+    fn has_media(&self) -> bool {
+        !self.no_offer
+    }
+
+    async fn create_sdp_offer(&mut self) -> Result<SessionDescription, Self::Error> {
+        Ok(self.sdp())
+    }
+
+    async fn receive_sdp_answer(&mut self, sdp: SessionDescription) -> Result<(), Self::Error> {
+        self.answers.push(sdp);
+        Ok(())
+    }
+
+    async fn receive_sdp_offer(
+        &mut self,
+        sdp: SessionDescription,
+    ) -> Result<SessionDescription, Self::Error> {
+        if self.reject_offers {
+            return Err(TestMediaError);
+        }
+
+        self.offers.push(sdp);
+        Ok(self.sdp())
+    }
+
+    fn set_hold(&mut self, hold: bool) {
+        self.hold = hold;
+    }
+
+    async fn run(&mut self) -> Result<Self::Event, Self::Error> {
+        pending().await
+    }
+}
+
+/// Two endpoints with an established call between them
+pub(crate) struct CallPair {
+    pub(crate) alice: Call<TestMedia>,
+    pub(crate) bob: Call<TestMedia>,
+    // Keep the endpoints alive for the duration of the test
+    pub(crate) _endpoints: [Endpoint; 2],
+    /// INVITE requests outside of a dialog received by bob's endpoint after the call was established
+    pub(crate) invites: mpsc::UnboundedReceiver<IncomingRequest>,
+}
+
+/// Establish a call from alice to bob
+pub(crate) async fn call_pair() -> CallPair {
+    let (invites, mut incoming) = TakeRequests::new(Method::INVITE);
+
+    let (alice_endpoint, alice) = endpoint("alice", |_| {}).await;
+    let (bob_endpoint, bob) = endpoint("bob", |builder| builder.add_layer(invites)).await;
+
+    let accept = {
+        let bob_endpoint = bob_endpoint.clone();
+        let bob = bob.clone();
+
+        tokio::spawn(async move {
+            let invite = incoming.recv().await.unwrap();
+
+            let call = InboundCall::from_invite(bob_endpoint, invite, contact(&bob))
+                .unwrap()
+                .with_media(TestMedia::default())
+                .accept()
+                .await
+                .unwrap();
+
+            (call, incoming)
+        })
+    };
+
+    let outbound_call = OutboundCall::make(
+        alice_endpoint.clone(),
+        authenticator(),
+        NameAddr::uri(alice.clone()),
+        contact(&alice),
+        bob,
+        TestMedia::default(),
+    );
+
+    let alice = within(async {
+        let mut outbound_call = outbound_call.await.unwrap();
+
+        let call = outbound_call.wait_for_completion().await.unwrap();
+        call.finish().await.unwrap()
+    })
+    .await;
+
+    let (bob, invites) = within(accept).await.unwrap();
+
+    CallPair {
+        alice,
+        bob,
+        _endpoints: [alice_endpoint, bob_endpoint],
+        invites,
+    }
+}
+
+/// Run the call, handling internal events, until an event is returned which matches `f`
+pub(crate) async fn next_event<M: MediaBackend, T>(
+    call: &mut Call<M>,
+    mut f: impl FnMut(CallEvent<M>) -> Option<T>,
+) -> T {
+    within(async {
+        loop {
+            match call.run().await.unwrap() {
+                CallEvent::Internal(event) => call.handle_internal_event(event).await.unwrap(),
+                event => {
+                    if let Some(t) = f(event) {
+                        return t;
+                    }
+                }
+            }
+        }
+    })
+    .await
+}
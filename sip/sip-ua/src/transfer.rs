@@ -0,0 +1,313 @@
+//! Call transfer using REFER ([RFC3515](https://datatracker.ietf.org/doc/html/rfc3515)) and Replaces
+//! ([RFC3891](https://datatracker.ietf.org/doc/html/rfc3891))
+
+use crate::dialog::Dialog;
+use crate::invite::session::{NotifyReceived, ReferReceived};
+use crate::subscription::event_package;
+use bytesstr::BytesStr;
+use sip_core::{IncomingRequest, Result};
+use sip_types::header::typed::{
+    ContentType, Event, EventReasonValue, ReferTo, ReferredBy, SubStateValue, SubscriptionState,
+};
+use sip_types::msg::StatusLine;
+use sip_types::uri::SipUri;
+use sip_types::uri::params::Params;
+use sip_types::{CodeKind, Headers, Method, StatusCode};
+use std::sync::Arc;
+
+pub(crate) const CONTENT_TYPE_SIPFRAG: ContentType =
+    ContentType(BytesStr::from_static("message/sipfrag;version=2.0"));
+
+/// Progress of a transfer initiated using [`Call::transfer_to`](crate::Call::transfer_to) or
+/// [`Call::transfer_attended`](crate::Call::transfer_attended), reported by the peer using NOTIFY requests.
+#[derive(Debug, Clone)]
+pub struct TransferProgress {
+    /// Status of the peer's request to the transfer target
+    ///
+    /// `None` if the NOTIFY didn't contain a valid `message/sipfrag` body
+    pub status: Option<StatusLine>,
+
+    /// The implicit subscription has been terminated, no further progress will be reported
+    pub terminated: bool,
+}
+
+impl TransferProgress {
+    pub(crate) fn from_notify(notify: &IncomingRequest) -> Self {
+        let is_sipfrag = notify
+            .headers
+            .get_named::<ContentType>()
+            .is_ok_and(|c| c.0.starts_with("message/sipfrag"));
+
+        let status = if is_sipfrag {
+            std::str::from_utf8(&notify.body)
+                .ok()
+                .and_then(|body| body.lines().next())
+                .and_then(|line| line.trim().parse::<StatusLine>().ok())
+        } else {
+            None
+        };
+
+        let terminated = notify
+            .headers
+            .get_named::<SubscriptionState>()
+            .is_ok_and(|state| state.state == SubStateValue::Terminated);
+
+        Self { status, terminated }
+    }
+}
+
+/// REFER request received inside a [`Call`](crate::Call), asking to transfer the call to another target
+///
+/// Must be either accepted or rejected. Dropping it will respond with `603 Decline`.
+pub struct IncomingRefer {
+    dialog: Arc<Dialog>,
+    event: Option<ReferReceived>,
+    refer_to: ReferTo,
+    referred_by: Option<ReferredBy>,
+}
+
+impl IncomingRefer {
+    /// Returns `Err` with the event when it doesn't contain a valid `Refer-To` header
+    pub(crate) fn new(
+        dialog: Arc<Dialog>,
+        event: ReferReceived,
+    ) -> Result<Self, Box<(ReferReceived, StatusCode)>> {
+        let refer_to = match event.refer.headers.get_named::<Vec<ReferTo>>() {
+            Ok(mut refer_to) if refer_to.len() == 1 => refer_to.remove(0),
+            _ => return Err(Box::new((event, StatusCode::BAD_REQUEST))),
+        };
+
+        let referred_by = event.refer.headers.get_named::<ReferredBy>().ok();
+
+        Ok(Self {
+            dialog,
+            event: Some(event),
+            refer_to,
+            referred_by,
+        })
+    }
+
+    /// The received REFER request
+    pub fn request(&self) -> &IncomingRequest {
+        &self.event.as_ref().expect("only taken when consumed").refer
+    }
+
+    /// The `Refer-To` header of the REFER request
+    pub fn refer_to(&self) -> &ReferTo {
+        &self.refer_to
+    }
+
+    /// The `Referred-By` header of the REFER request
+    pub fn referred_by(&self) -> Option<&ReferredBy> {
+        self.referred_by.as_ref()
+    }
+
+    /// The URI which must be called to complete the transfer
+    pub fn target(&self) -> SipUri {
+        target(&self.refer_to)
+    }
+
+    /// Headers which must be added to the INVITE sent to [`IncomingRefer::target`]
+    ///
+    /// Contains the `Replaces` header for attended transfers and the `Referred-By` header, if any.
+    pub fn invite_headers(&self) -> Headers {
+        invite_headers(&self.refer_to, self.referred_by.as_ref())
+    }
+
+    /// Accept the REFER request with `202 Accepted` and report `100 Trying` to the peer
+    ///
+    /// The returned [`ReferNotifier`] must be used to report the outcome of the transfer.
+    pub async fn accept(mut self) -> Result<ReferNotifier> {
+        let event = self.event.take().expect("only taken when consumed");
+
+        let response = self
+            .dialog
+            .create_response(&event.refer, StatusCode::ACCEPTED, None)?;
+
+        event.transaction.respond(response).await?;
+
+        let mut notifier = ReferNotifier {
+            dialog: self.dialog.clone(),
+            event_id: event.refer.base_headers.cseq.cseq,
+            refer_to: self.refer_to.clone(),
+            referred_by: self.referred_by.take(),
+            terminated: false,
+        };
+
+        notifier.notify(StatusCode::TRYING).await?;
+
+        Ok(notifier)
+    }
+
+    /// Reject the REFER request with the given status code
+    pub async fn reject(mut self, code: StatusCode) -> Result<()> {
+        let event = self.event.take().expect("only taken when consumed");
+
+        respond(&self.dialog, event, code).await
+    }
+}
+
+impl Drop for IncomingRefer {
+    fn drop(&mut self) {
+        let Some(event) = self.event.take() else {
+            return;
+        };
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let dialog = self.dialog.clone();
+
+        handle.spawn(async move {
+            if let Err(e) = respond(&dialog, event, StatusCode::DECLINE).await {
+                log::warn!("Failed to decline dropped REFER request {e:?}");
+            }
+        });
+    }
+}
+
+/// Reports the progress of an accepted REFER to the peer using NOTIFY requests
+pub struct ReferNotifier {
+    dialog: Arc<Dialog>,
+    event_id: u32,
+    refer_to: ReferTo,
+    referred_by: Option<ReferredBy>,
+    terminated: bool,
+}
+
+impl ReferNotifier {
+    /// The URI which must be called to complete the transfer
+    pub fn target(&self) -> SipUri {
+        target(&self.refer_to)
+    }
+
+    /// Headers which must be added to the INVITE sent to [`ReferNotifier::target`]
+    ///
+    /// Contains the `Replaces` header for attended transfers and the `Referred-By` header, if any.
+    pub fn invite_headers(&self) -> Headers {
+        invite_headers(&self.refer_to, self.referred_by.as_ref())
+    }
+
+    /// Returns if a final status has been reported and the subscription is terminated
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Report the status of the INVITE sent to the transfer target
+    ///
+    /// Reporting a final status code terminates the implicit subscription.
+    pub async fn notify(&mut self, code: StatusCode) -> Result<()> {
+        if self.terminated {
+            return Ok(());
+        }
+
+        let subscription_state = if code.kind() == CodeKind::Provisional {
+            SubscriptionState::new(SubStateValue::Active)
+        } else {
+            self.terminated = true;
+
+            SubscriptionState::new(SubStateValue::Terminated)
+                .with_reason(EventReasonValue::NoResource)
+        };
+
+        let mut notify = self.dialog.create_request(Method::NOTIFY);
+        notify
+            .headers
+            .insert_named(&Event::new(format!("refer;id={}", self.event_id)));
+        notify.headers.insert_named(&subscription_state);
+        notify.headers.insert_named(&self.dialog.local_contact);
+        notify.headers.insert_named(&CONTENT_TYPE_SIPFRAG);
+        notify.body = StatusLine {
+            code,
+            reason: code.text().map(BytesStr::from_static),
+        }
+        .to_string()
+        .into();
+
+        let response = self.dialog.send_request(notify).await?;
+
+        if response.line.code.kind() != CodeKind::Success {
+            log::warn!(
+                "Peer responded to REFER NOTIFY with {:?}, terminating subscription",
+                response.line.code
+            );
+
+            self.terminated = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ReferNotifier {
+    fn drop(&mut self) {
+        if self.terminated {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let mut this = ReferNotifier {
+            dialog: self.dialog.clone(),
+            event_id: self.event_id,
+            refer_to: self.refer_to.clone(),
+            referred_by: self.referred_by.take(),
+            terminated: false,
+        };
+
+        self.terminated = true;
+
+        handle.spawn(async move {
+            if let Err(e) = this.notify(StatusCode::SERVICE_UNAVAILABLE).await {
+                log::warn!("Failed to terminate REFER subscription {e:?}");
+            }
+        });
+    }
+}
+
+/// Respond to a received NOTIFY of the REFER subscription and extract the transfer progress
+pub(crate) async fn handle_notify(
+    dialog: &Dialog,
+    event: NotifyReceived,
+) -> Result<TransferProgress> {
+    let progress = TransferProgress::from_notify(&event.notify);
+
+    let response = dialog.create_response(&event.notify, StatusCode::OK, None)?;
+    event.transaction.respond(response).await?;
+
+    Ok(progress)
+}
+
+pub(crate) async fn respond(dialog: &Dialog, event: ReferReceived, code: StatusCode) -> Result<()> {
+    let response = dialog.create_response(&event.refer, code, None)?;
+
+    event.transaction.respond(response).await
+}
+
+fn target(refer_to: &ReferTo) -> SipUri {
+    let mut uri = refer_to.uri.uri.clone();
+    uri.header_params = Params::new();
+    uri
+}
+
+fn invite_headers(refer_to: &ReferTo, referred_by: Option<&ReferredBy>) -> Headers {
+    let mut headers = Headers::new();
+
+    if let Some(Ok(replaces)) = refer_to.replaces() {
+        headers.insert_named(&replaces);
+    }
+
+    if let Some(referred_by) = referred_by {
+        headers.insert_named(referred_by);
+    }
+
+    headers
+}
+
+// Helper to check if the Event header value is the refer event package
+pub(crate) fn is_refer_event(event: &Event) -> bool {
+    event_package(event) == "refer"
+}
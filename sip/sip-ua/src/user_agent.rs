@@ -0,0 +1,537 @@
+//! Multiple accounts on one endpoint with routing of incoming requests to them
+
+use crate::message::SendMessageError;
+use crate::mwi::MwiSubscription;
+use crate::outbound_call::{MakeCallError, OutboundCall};
+use crate::subscription::SubscribeError;
+use crate::{
+    InboundCall, IncomingMessage, MediaBackend, NoMedia, RegisterError, RegistrarConfig,
+    Registration,
+};
+use bytes::Bytes;
+use parking_lot as pl;
+use sip_auth::ClientAuthenticator;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Contact, ContentType, Event, FromTo};
+use sip_types::host::Host;
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Headers, Method, StatusCode};
+use slotmap::{DefaultKey, SlotMap};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Request received for an [`Account`]
+#[allow(clippy::large_enum_variant)]
+pub enum AccountEvent {
+    /// Incoming INVITE, must be accepted or declined
+    IncomingCall(InboundCall<NoMedia>),
+
+    /// MESSAGE request outside of any dialog, it has already been answered with `200 OK`
+    Message(IncomingMessage),
+
+    /// NOTIFY request outside of any subscription, it has already been answered with `200 OK`
+    Notify(IncomingNotify),
+}
+
+/// NOTIFY request received outside of any subscription (e.g. unsolicited message-summary or check-sync events)
+#[derive(Debug)]
+pub struct IncomingNotify {
+    /// `Event` header of the NOTIFY request
+    pub event: Event,
+
+    /// `From` header of the sender
+    pub from: FromTo,
+
+    /// `To` header of the NOTIFY request
+    pub to: FromTo,
+
+    /// Request-URI of the NOTIFY request
+    pub uri: SipUri,
+
+    /// Content type of the body, if there is one
+    pub content_type: Option<ContentType>,
+
+    /// The NOTIFY body
+    pub body: Bytes,
+
+    /// All headers of the NOTIFY request
+    pub headers: Headers,
+}
+
+/// Manages multiple [`Account`]s on one endpoint
+///
+/// Incoming INVITE, MESSAGE and NOTIFY requests outside of any dialog are matched to an account by their
+/// Request-URI (against the Contact and the address-of-record of the account) or their `To` header and delivered
+/// to it as [`AccountEvent`]s.
+///
+/// Requires the [`UserAgentLayer`] to be added to the endpoint.
+#[derive(Clone)]
+pub struct UserAgent {
+    endpoint: Endpoint,
+}
+
+impl UserAgent {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Register a new account using the provided config
+    ///
+    /// `make_authenticator` creates the authenticator used for every request sent by this account, including the
+    /// REGISTER request.
+    pub async fn add_account<F, A>(
+        &self,
+        config: RegistrarConfig,
+        make_authenticator: F,
+    ) -> Result<Account<F>, RegisterError<A::Error>>
+    where
+        F: Fn() -> A + Send + Sync + 'static,
+        A: ClientAuthenticator + Send + 'static,
+    {
+        let registration =
+            Registration::register(self.endpoint.clone(), config, make_authenticator()).await?;
+
+        let (sink, events) = mpsc::channel(8);
+
+        let key = self
+            .endpoint
+            .layer::<UserAgentLayer>()
+            .accounts
+            .lock()
+            .insert(AccountEntry {
+                id: registration.id().uri.clone(),
+                contact: registration.dialog_contact().uri.uri.clone(),
+                sink,
+            });
+
+        Ok(Account {
+            endpoint: self.endpoint.clone(),
+            key,
+            registration,
+            make_authenticator,
+            events,
+        })
+    }
+}
+
+/// An account managed by a [`UserAgent`]
+///
+/// Dropping the account removes its registration and rejects further requests addressed to it.
+pub struct Account<F> {
+    endpoint: Endpoint,
+    key: DefaultKey,
+    registration: Registration,
+    make_authenticator: F,
+    events: mpsc::Receiver<AccountEvent>,
+}
+
+impl<F, A> Account<F>
+where
+    F: Fn() -> A + Send + Sync + 'static,
+    A: ClientAuthenticator + Send + 'static,
+{
+    /// The registration of this account
+    pub fn registration(&mut self) -> &mut Registration {
+        &mut self.registration
+    }
+
+    /// Wait for the next request addressed to this account
+    pub async fn recv(&mut self) -> Option<AccountEvent> {
+        self.events.recv().await
+    }
+
+    /// Make a call to the target using this account, see [`Registration::make_call`]
+    pub async fn make_call<M: MediaBackend>(
+        &self,
+        target: String,
+        media: M,
+    ) -> Result<OutboundCall<M>, MakeCallError<M::Error, A::Error>> {
+        self.registration
+            .make_call(target, (self.make_authenticator)(), media)
+            .await
+    }
+
+    /// Make a call to the target uri using this account, see [`Registration::make_call_to_uri`]
+    pub async fn make_call_to_uri<M: MediaBackend>(
+        &self,
+        target: SipUri,
+        media: M,
+    ) -> Result<OutboundCall<M>, MakeCallError<M::Error, A::Error>> {
+        self.registration
+            .make_call_to_uri(target, (self.make_authenticator)(), media)
+            .await
+    }
+
+    /// Send a MESSAGE request using this account, see [`Registration::send_message`]
+    pub async fn send_message(
+        &self,
+        target: String,
+        content_type: ContentType,
+        body: impl Into<Bytes>,
+    ) -> Result<(), SendMessageError<A::Error>> {
+        self.registration
+            .send_message(target, content_type, body, (self.make_authenticator)())
+            .await
+    }
+
+    /// Subscribe to message waiting indications of this account, see [`Registration::subscribe_mwi`]
+    pub async fn subscribe_mwi(&self) -> Result<MwiSubscription, SubscribeError<A::Error>> {
+        self.registration
+            .subscribe_mwi((self.make_authenticator)())
+            .await
+    }
+}
+
+impl<F> Drop for Account<F> {
+    fn drop(&mut self) {
+        self.endpoint
+            .layer::<UserAgentLayer>()
+            .accounts
+            .lock()
+            .remove(self.key);
+    }
+}
+
+struct AccountEntry {
+    /// Address-of-record of the account
+    id: SipUri,
+    /// Contact URI of the account
+    contact: SipUri,
+    sink: mpsc::Sender<AccountEvent>,
+}
+
+/// Routes incoming requests outside of any dialog to the accounts of a [`UserAgent`]
+///
+/// Must be added after the [`DialogLayer`](crate::dialog::DialogLayer), [`InviteLayer`](crate::invite::InviteLayer)
+/// and [`SubscriptionLayer`](crate::subscription::SubscriptionLayer) if used. It handles MESSAGE requests for its
+/// accounts, so a [`MessageLayer`](crate::MessageLayer) added before it would receive them instead.
+#[derive(Default)]
+pub struct UserAgentLayer {
+    accounts: pl::Mutex<SlotMap<DefaultKey, AccountEntry>>,
+}
+
+impl UserAgentLayer {
+    /// Returns the event sink of the account which matches the request best
+    fn find_account(
+        &self,
+        request: &IncomingRequest,
+    ) -> Option<(mpsc::Sender<AccountEvent>, NameAddr)> {
+        let accounts = self.accounts.lock();
+
+        accounts
+            .values()
+            .filter_map(|account| {
+                let score = match_score(
+                    &request.line.uri,
+                    &request.base_headers.to.uri.uri,
+                    &account.id,
+                    &account.contact,
+                )?;
+
+                Some((score, account))
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, account)| (account.sink.clone(), NameAddr::uri(account.contact.clone())))
+    }
+
+    async fn respond(endpoint: &Endpoint, mut request: IncomingRequest, code: StatusCode) {
+        let response = endpoint.create_response(&request, code, None);
+
+        if let Err(e) = endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+        {
+            log::warn!(
+                "Failed to respond to {} request, {e:?}",
+                request.line.method
+            );
+        }
+    }
+
+    async fn handle_invite(
+        endpoint: &Endpoint,
+        sink: mpsc::Sender<AccountEvent>,
+        contact: NameAddr,
+        invite: IncomingRequest,
+    ) {
+        let contact = Contact::new(contact);
+
+        let call = match InboundCall::from_invite(endpoint.clone(), invite, contact) {
+            Ok(call) => call,
+            Err(e) => {
+                let (invite, e) = *e;
+                log::warn!("Failed to create inbound call from INVITE, {e}");
+
+                Self::respond(endpoint, invite, StatusCode::BAD_REQUEST).await;
+                return;
+            }
+        };
+
+        let (event, code) = match sink.try_send(AccountEvent::IncomingCall(call)) {
+            Ok(()) => return,
+            Err(TrySendError::Full(event)) => (event, StatusCode::BUSY_HERE),
+            Err(TrySendError::Closed(event)) => (event, StatusCode::TEMPORARILY_UNAVAILABLE),
+        };
+
+        if let AccountEvent::IncomingCall(call) = event
+            && let Err(e) = call.decline(code, None).await
+        {
+            log::warn!("Failed to decline INVITE the account cannot receive, {e:?}");
+        }
+    }
+
+    /// Pass the event to the account without waiting, returns the status code of the response to the request
+    fn deliver(sink: &mpsc::Sender<AccountEvent>, event: AccountEvent) -> StatusCode {
+        // Never wait for the application here, as it would block the endpoint's receive path
+        match sink.try_send(event) {
+            Ok(()) => StatusCode::OK,
+            Err(TrySendError::Full(_)) => StatusCode::BUSY_HERE,
+            Err(TrySendError::Closed(_)) => StatusCode::TEMPORARILY_UNAVAILABLE,
+        }
+    }
+
+    async fn handle_message(
+        endpoint: &Endpoint,
+        sink: mpsc::Sender<AccountEvent>,
+        request: IncomingRequest,
+    ) {
+        let Ok(content_type) = request.headers.get_named::<ContentType>() else {
+            Self::respond(endpoint, request, StatusCode::UNSUPPORTED_MEDIA_TYPE).await;
+            return;
+        };
+
+        let message = IncomingMessage {
+            from: request.base_headers.from.clone(),
+            to: request.base_headers.to.clone(),
+            uri: request.line.uri.clone(),
+            content_type,
+            body: request.body.clone(),
+            headers: request.headers.clone(),
+        };
+
+        let code = Self::deliver(&sink, AccountEvent::Message(message));
+
+        Self::respond(endpoint, request, code).await;
+    }
+
+    async fn handle_notify(
+        endpoint: &Endpoint,
+        sink: mpsc::Sender<AccountEvent>,
+        request: IncomingRequest,
+    ) {
+        let Ok(event) = request.headers.get_named::<Event>() else {
+            Self::respond(endpoint, request, StatusCode::BAD_EVENT).await;
+            return;
+        };
+
+        let notify = IncomingNotify {
+            event,
+            from: request.base_headers.from.clone(),
+            to: request.base_headers.to.clone(),
+            uri: request.line.uri.clone(),
+            content_type: request.headers.get_named().ok(),
+            body: request.body.clone(),
+            headers: request.headers.clone(),
+        };
+
+        let code = Self::deliver(&sink, AccountEvent::Notify(notify));
+
+        Self::respond(endpoint, request, code).await;
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for UserAgentLayer {
+    fn name(&self) -> &'static str {
+        "user-agent"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::MESSAGE);
+        endpoint.add_allow(Method::NOTIFY);
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        let method = request.line.method.clone();
+
+        // Only handle requests outside of a dialog
+        if request.base_headers.to.tag.is_some()
+            || !(method == Method::INVITE || method == Method::MESSAGE || method == Method::NOTIFY)
+        {
+            return;
+        }
+
+        let Some((sink, contact)) = self.find_account(&request) else {
+            return;
+        };
+
+        let request = request.take();
+
+        if method == Method::INVITE {
+            Self::handle_invite(endpoint, sink, contact, request).await;
+        } else if method == Method::MESSAGE {
+            Self::handle_message(endpoint, sink, request).await;
+        } else {
+            Self::handle_notify(endpoint, sink, request).await;
+        }
+    }
+}
+
+/// How well a request matches an account, higher is better. `None` if it doesn't match at all.
+///
+/// In order of preference the Request-URI must match the account's Contact, the Request-URI must match the
+/// account's address-of-record, the `To` header must match the address-of-record or the Request-URI must contain
+/// the user of either at the host of either (e.g. the address-of-record's user at the Contact's host).
+fn match_score(request_uri: &SipUri, to: &SipUri, id: &SipUri, contact: &SipUri) -> Option<u8> {
+    if user_and_host_match(request_uri, contact) {
+        Some(3)
+    } else if user_and_host_match(request_uri, id) {
+        Some(2)
+    } else if user_and_host_match(to, id) {
+        Some(1)
+    } else {
+        let user = request_uri.user_part.user()?;
+
+        let user_matches = [contact, id]
+            .into_iter()
+            .any(|uri| uri.user_part.user() == Some(user));
+        let host_matches = [contact, id]
+            .into_iter()
+            .any(|uri| hosts_match(request_uri, uri));
+
+        (user_matches && host_matches).then_some(0)
+    }
+}
+
+fn user_and_host_match(a: &SipUri, b: &SipUri) -> bool {
+    hosts_match(a, b) && a.user_part.user().is_some() && a.user_part.user() == b.user_part.user()
+}
+
+fn hosts_match(a: &SipUri, b: &SipUri) -> bool {
+    match (&a.host_port.host, &b.host_port.host) {
+        (Host::Name(a), Host::Name(b)) => a.eq_ignore_ascii_case(b),
+        (a, b) => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{endpoint, request, send};
+    use bytesstr::BytesStr;
+
+    fn uri(uri: &str) -> SipUri {
+        uri.parse().unwrap()
+    }
+
+    #[test]
+    fn match_contact() {
+        let score = match_score(
+            &uri("sip:alice@192.0.2.1:5060"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:alice@192.0.2.1:5060"),
+        );
+
+        assert_eq!(score, Some(3));
+    }
+
+    #[test]
+    fn match_to_header() {
+        let score = match_score(
+            &uri("sip:+4930123@192.0.2.1"),
+            &uri("sip:alice@EXAMPLE.org"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:alice@192.0.2.2"),
+        );
+
+        assert_eq!(score, Some(1));
+    }
+
+    #[test]
+    fn match_user_at_other_host() {
+        let score = match_score(
+            &uri("sip:alice@192.0.2.2"),
+            &uri("sip:someone@example.com"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:4f3a9c@192.0.2.2"),
+        );
+
+        assert_eq!(score, Some(0));
+    }
+
+    #[test]
+    fn no_match_of_user_at_unknown_host() {
+        let score = match_score(
+            &uri("sip:alice@192.0.2.3"),
+            &uri("sip:someone@example.com"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:alice@192.0.2.2"),
+        );
+
+        assert_eq!(score, None);
+    }
+
+    #[test]
+    fn no_match() {
+        let score = match_score(
+            &uri("sip:bob@192.0.2.1"),
+            &uri("sip:bob@example.org"),
+            &uri("sip:alice@example.org"),
+            &uri("sip:alice@192.0.2.1"),
+        );
+
+        assert_eq!(score, None);
+    }
+
+    #[tokio::test]
+    async fn reject_when_account_cannot_receive() {
+        let (alice_endpoint, alice) = endpoint("alice", |builder| {
+            builder.add_layer(UserAgentLayer::default());
+        })
+        .await;
+        let (client, bob) = endpoint("bob", |_| {}).await;
+
+        let (sink, mut events) = mpsc::channel(1);
+
+        alice_endpoint
+            .layer::<UserAgentLayer>()
+            .accounts
+            .lock()
+            .insert(AccountEntry {
+                id: alice.clone(),
+                contact: alice.clone(),
+                sink,
+            });
+
+        let message = || {
+            let mut message = request(Method::MESSAGE, &bob, &alice);
+            message
+                .headers
+                .insert_named(&ContentType(BytesStr::from_static("text/plain")));
+            message.body = Bytes::from_static(b"hello");
+            message
+        };
+
+        let response = send(&client, message()).await;
+        assert_eq!(response.line.code, StatusCode::OK);
+
+        // The account hasn't received the first message yet
+        let response = send(&client, message()).await;
+        assert_eq!(response.line.code, StatusCode::BUSY_HERE);
+
+        assert!(matches!(
+            events.recv().await,
+            Some(AccountEvent::Message(_))
+        ));
+        drop(events);
+
+        let response = send(&client, message()).await;
+        assert_eq!(response.line.code, StatusCode::TEMPORARILY_UNAVAILABLE);
+    }
+}